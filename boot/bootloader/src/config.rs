@@ -0,0 +1,69 @@
+//! Reads the kernel command line from the configuration sector (see `common::command_line`), and
+//! puts it in the [`BootInfo`].
+//!
+//! The command line has to fit in the boot info, so only the first sector is read. It's read with
+//! the BIOS, before the kernel, into the same buffer the kernel's file goes through.
+
+use {
+	crate::load::FILE_SEGMENT,
+	common::{
+		boot_info::{BootInfo, COMMAND_LINE_LEN},
+		command_line,
+		disks::{self, DiskError},
+		stages::Stage,
+	},
+	core::slice,
+};
+
+/// Something went wrong while reading the configuration sector.
+#[derive(Debug)]
+pub enum ConfigError {
+	/// Reading the sector from the disk failed.
+	#[allow(dead_code)] // Only read when the error gets printed
+	Disk(DiskError),
+	/// The command line isn't valid UTF-8.
+	InvalidUtf8,
+	/// The command line is longer than [`COMMAND_LINE_LEN`].
+	TooLong,
+}
+impl From<DiskError> for ConfigError {
+	fn from(err: DiskError) -> Self {
+		Self::Disk(err)
+	}
+}
+
+/// Reads the kernel command line from the boot partition, which starts at `partition_start`. If
+/// there's no configuration sector, the command line is left empty.
+pub fn load(boot_info: &mut BootInfo, partition_start: u64) -> Result<(), ConfigError> {
+	let drive = boot_info.boot_drive;
+	let location =
+		match disks::stage_location(drive, partition_start, Stage::Config, FILE_SEGMENT, 0) {
+			Ok(location) => location,
+			Err(DiskError::MissingStage) => return Ok(()),
+			Err(err) => return Err(err.into()),
+		};
+
+	let sectors = (COMMAND_LINE_LEN as u32)
+		.div_ceil(disks::SECTOR_SIZE as u32)
+		.min(location.sectors);
+	disks::read_sectors(
+		drive,
+		partition_start + location.lba as u64,
+		sectors,
+		FILE_SEGMENT,
+		0,
+	)?;
+
+	let config = unsafe {
+		slice::from_raw_parts(
+			((FILE_SEGMENT as usize) << 4) as *const u8,
+			sectors as usize * disks::SECTOR_SIZE as usize,
+		)
+	};
+	let command_line = command_line::from_config(config).ok_or(ConfigError::InvalidUtf8)?;
+	if !boot_info.set_command_line(command_line) {
+		return Err(ConfigError::TooLong);
+	}
+
+	Ok(())
+}
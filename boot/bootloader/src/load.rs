@@ -0,0 +1,273 @@
+//! Reads the rest of BS from the boot partition: the ELF loader, the kernel, and the initial
+//! ramdisk.
+//!
+//! Each one is a stage in the boot partition (see `common::stages`). If the boot drive is on the
+//! IDE controller, they're read with the ata crate, the same way they can be once the BIOS is gone.
+//! Otherwise, they're read with the BIOS, a chunk at a time, through a buffer in conventional
+//! memory (int 13h can only read below 1mib).
+//!
+//! Everything past 1mib is written from unreal mode (see `crate::unreal_mode`), since real mode's
+//! segments can't reach it.
+//!
+//! The ELF loader is read to [`ELF_LOADER_ADDRESS`], where it's linked to run. The kernel's file is
+//! read into frames from the [`FrameAllocator`], so it can be any size, and where it is goes in the
+//! [`BootInfo`]. It's still just a file at this point: the ELF loader loads it, after the
+//! bootloader's entered 64-bit mode.
+//!
+//! The initial ramdisk (initrd) is an archive the kernel can use as a filesystem, before it has
+//! disk drivers. It's optional, and stored after the kernel as the [`Stage::Initrd`] stage. It's
+//! read into frames the same way, and just left there; its location goes in the [`BootInfo`] too.
+
+use {
+	ata::{AtaError, IdeChannel, IdeController, IdeDisk, SECTOR_SIZE},
+	common::{
+		boot_info::BootInfo,
+		disks::{self, DiskError},
+		mbr::{MBR_ADDRESS, PARTITION_TABLE_OFFSET},
+		paging::*,
+		stages::{
+			Stage, StageLocation, StageTable, ELF_LOADER_ADDRESS, ELF_LOADER_MAX_SIZE,
+			STAGE_TABLE_LBA,
+		},
+	},
+	core::slice,
+};
+
+/// The real-mode segment files are read to when they're read with the BIOS. This is 0x2_0000 in
+/// memory, past the end of the bootloader.
+pub const FILE_SEGMENT: u16 = 0x2000;
+/// How much is read to [`FILE_SEGMENT`] at once, so it fits in conventional memory (it ends at
+/// 0x9_0000, before the EBDA).
+const BIOS_CHUNK_SIZE: usize = 0x7_0000;
+
+/// Something went wrong while reading a stage.
+#[derive(Debug)]
+pub enum LoadError {
+	/// Reading the stage from the disk with the BIOS failed.
+	#[allow(dead_code)] // Only read when the error gets printed
+	Disk(DiskError),
+	/// Reading the stage from the disk with the IDE controller failed.
+	#[allow(dead_code)] // Only read when the error gets printed
+	Ata(AtaError),
+	/// There weren't enough free frames to read the stage into.
+	OutOfMemory,
+	/// The ELF loader is bigger than [`ELF_LOADER_MAX_SIZE`].
+	TooBig,
+}
+impl From<DiskError> for LoadError {
+	fn from(err: DiskError) -> Self {
+		Self::Disk(err)
+	}
+}
+impl From<AtaError> for LoadError {
+	fn from(err: AtaError) -> Self {
+		Self::Ata(err)
+	}
+}
+
+/// Reads the ELF loader from the boot partition (which starts at `partition_start`) to
+/// [`ELF_LOADER_ADDRESS`], returning its size in bytes. If the boot drive is on the IDE controller,
+/// `boot_disk` is where (see [`boot_disk`]), and the ELF loader is read through it.
+///
+/// The caller has to make sure nothing else is using the memory at [`ELF_LOADER_ADDRESS`].
+pub fn load_elf_loader(
+	boot_info: &BootInfo,
+	partition_start: u64,
+	mut boot_disk: Option<&mut BootDisk>,
+) -> Result<u64, LoadError> {
+	let drive = boot_info.boot_drive;
+	let location = stage_location(
+		drive,
+		partition_start,
+		Stage::ElfLoader,
+		boot_disk.as_deref_mut(),
+	)?;
+	let len = location.sectors as usize * SECTOR_SIZE;
+	if len as u64 > ELF_LOADER_MAX_SIZE {
+		return Err(LoadError::TooBig);
+	}
+
+	// Paging is off and the bootloader's in unreal mode, so it can be written directly
+	let buffer = unsafe { slice::from_raw_parts_mut(ELF_LOADER_ADDRESS as usize as *mut u8, len) };
+	let lba = partition_start + location.lba as u64;
+	read(drive, lba, buffer, boot_disk)?;
+
+	Ok(len as u64)
+}
+
+/// Reads the kernel's ELF file from the boot partition into frames from `frames`, and records
+/// where it is in `boot_info`. `partition_start` and `boot_disk` are the same as for
+/// [`load_elf_loader`].
+///
+/// The file's frames are never freed, since the ELF loader loads the kernel from it.
+pub fn load_kernel(
+	boot_info: &mut BootInfo,
+	partition_start: u64,
+	frames: &mut FrameAllocator,
+	boot_disk: Option<&mut BootDisk>,
+) -> Result<(), LoadError> {
+	let file = read_stage(
+		boot_info.boot_drive,
+		partition_start,
+		Stage::Kernel,
+		frames,
+		boot_disk,
+	)?;
+
+	boot_info.kernel_file_start = file.start;
+	boot_info.kernel_file_len = file.len;
+	Ok(())
+}
+
+/// Reads the initial ramdisk from the boot partition into frames from `frames`, and records where
+/// it is in `boot_info`. The initrd is optional, so if it isn't on the disk, this does nothing.
+/// `partition_start` and `boot_disk` are the same as for [`load_elf_loader`].
+///
+/// The initrd's frames are never freed, since the kernel uses it.
+pub fn load_initrd(
+	boot_info: &mut BootInfo,
+	partition_start: u64,
+	frames: &mut FrameAllocator,
+	boot_disk: Option<&mut BootDisk>,
+) -> Result<(), LoadError> {
+	let file = match read_stage(
+		boot_info.boot_drive,
+		partition_start,
+		Stage::Initrd,
+		frames,
+		boot_disk,
+	) {
+		Ok(file) => file,
+		Err(LoadError::Disk(DiskError::MissingStage)) => return Ok(()),
+		Err(err) => return Err(err),
+	};
+
+	boot_info.initrd_start = file.start;
+	boot_info.initrd_len = file.len;
+	Ok(())
+}
+
+/// The IDE disk BS was booted from.
+pub struct BootDisk {
+	channel: IdeChannel,
+	disk: IdeDisk,
+}
+
+/// Finds which of the IDE controller's disks is the boot drive, by looking for the one with the
+/// same partition table as the MBR the BIOS loaded. Returns `None` if it isn't on the controller.
+pub fn boot_disk(controller: IdeController) -> Option<BootDisk> {
+	let mbr = unsafe { slice::from_raw_parts(MBR_ADDRESS as *const u8, SECTOR_SIZE) };
+	let IdeController {
+		primary_channel,
+		secondary_channel,
+	} = controller;
+
+	for mut channel in [primary_channel, secondary_channel] {
+		for disk in [IdeDisk::Primary, IdeDisk::Secondary] {
+			let mut sector = [0; SECTOR_SIZE];
+			// Empty slots just error
+			if channel.read_sectors(disk, 0, &mut sector).is_ok()
+				&& sector[PARTITION_TABLE_OFFSET..] == mbr[PARTITION_TABLE_OFFSET..]
+			{
+				return Some(BootDisk { channel, disk });
+			}
+		}
+	}
+
+	None
+}
+
+/// A stage's file, after it's been read into frames.
+struct StageFile {
+	/// The first frame the file was read into.
+	start: PhysAddr,
+	/// The file's size in bytes. It's always a whole number of sectors.
+	len: u64,
+}
+
+/// Reads `stage` from the boot partition into frames from `frames`. It's read through the IDE
+/// controller if the boot drive is on it (`boot_disk`), and with the BIOS from `drive` otherwise.
+fn read_stage(
+	drive: u8,
+	partition_start: u64,
+	stage: Stage,
+	frames: &mut FrameAllocator,
+	mut boot_disk: Option<&mut BootDisk>,
+) -> Result<StageFile, LoadError> {
+	let location = stage_location(drive, partition_start, stage, boot_disk.as_deref_mut())?;
+
+	let len = location.sectors as usize * SECTOR_SIZE;
+	let count = (len as u64).div_ceil(PAGE_SIZE) as usize;
+	let start = frames
+		.allocate_contiguous(count, PAGE_SIZE)
+		.ok_or(LoadError::OutOfMemory)?;
+	// Paging is off and the bootloader's in unreal mode, so the frames can be written directly
+	let buffer = unsafe { slice::from_raw_parts_mut(start as usize as *mut u8, len) };
+
+	let lba = partition_start + location.lba as u64;
+	if let Err(err) = read(drive, lba, buffer, boot_disk) {
+		frames.deallocate_contiguous(start, count);
+		return Err(err);
+	}
+
+	Ok(StageFile {
+		start,
+		len: len as u64,
+	})
+}
+
+/// Finds where `stage` is in the boot partition, by reading the stage table - through the IDE
+/// controller if the boot drive is on it (`boot_disk`), and with the BIOS from `drive` otherwise.
+fn stage_location(
+	drive: u8,
+	partition_start: u64,
+	stage: Stage,
+	boot_disk: Option<&mut BootDisk>,
+) -> Result<StageLocation, LoadError> {
+	let location = match boot_disk {
+		Some(BootDisk { channel, disk }) => {
+			let mut sector = [0; SECTOR_SIZE];
+			channel.read_sectors(*disk, partition_start + STAGE_TABLE_LBA, &mut sector)?;
+			StageTable::from_sector(&sector)
+				.get(stage)
+				.ok_or(DiskError::MissingStage)?
+		}
+		None => disks::stage_location(drive, partition_start, stage, FILE_SEGMENT, 0)?,
+	};
+
+	Ok(location)
+}
+
+/// Reads sectors, starting at `lba`, until `buffer` is full. They're read through the IDE
+/// controller if the boot drive is on it (`boot_disk`), and with the BIOS from `drive` otherwise.
+fn read(
+	drive: u8,
+	lba: u64,
+	buffer: &mut [u8],
+	boot_disk: Option<&mut BootDisk>,
+) -> Result<(), LoadError> {
+	match boot_disk {
+		Some(BootDisk { channel, disk }) => channel
+			.read_sectors(*disk, lba, buffer)
+			.map_err(LoadError::from),
+		None => read_with_bios(drive, lba, buffer).map_err(LoadError::from),
+	}
+}
+
+/// Reads sectors from `drive` with the BIOS, starting at `lba`, until `buffer` is full. The BIOS can
+/// only read to conventional memory, so they're read to [`FILE_SEGMENT`] in chunks of
+/// [`BIOS_CHUNK_SIZE`], then copied to `buffer`.
+fn read_with_bios(drive: u8, lba: u64, buffer: &mut [u8]) -> Result<(), DiskError> {
+	let bounce = ((FILE_SEGMENT as usize) << 4) as *const u8;
+	let mut lba = lba;
+	for chunk in buffer.chunks_mut(BIOS_CHUNK_SIZE) {
+		let sectors = chunk.len().div_ceil(SECTOR_SIZE) as u32;
+		disks::read_sectors(drive, lba, sectors, FILE_SEGMENT, 0)?;
+		// `buffer` is usually past 1mib, and the BIOS call could've left unreal mode
+		crate::unreal_mode();
+		chunk.copy_from_slice(unsafe { slice::from_raw_parts(bounce, chunk.len()) });
+		lba += sectors as u64;
+	}
+
+	Ok(())
+}
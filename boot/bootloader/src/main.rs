@@ -1,86 +1,227 @@
 #![no_std]
 #![no_main]
 
+mod config;
+mod load;
+
 use {
-	acpi::{rsdp::Rsdp, rsdt::Rsdt},
+	acpi::{
+		mcfg::Mcfg,
+		rsdp::{RootPointer, Rsdp},
+		rsdt::{Rsdt, Sdt, ToPtr, Xsdt},
+	},
 	ata::IdeController,
-	common::{gdt::*, paging::*, printing::Printer, *},
+	common::{
+		boot_info::BootInfo,
+		gdt::*,
+		mbr::{PartitionTable, MBR_ADDRESS},
+		memory_map::RegionKind,
+		paging::*,
+		printing::Printer,
+		stages::{BOOT_PROGRAMS_END, ELF_LOADER_ADDRESS},
+		*,
+	},
 	core::{
-		arch::asm,
-		mem::{ManuallyDrop, MaybeUninit},
+		arch::{asm, global_asm},
+		ptr,
 	},
 	pci::{
 		classification::{Class, HeaderType, MassStorageControllerSubclass},
-		PciDevice,
+		ecam::Ecam,
+		ConfigAccess, PciDevice,
 	},
 };
 
+/// The bootloader's entry point. The bootstrapper passes the drive BS was booted from; the
+/// bootloader sets up the [`BootInfo`] and fills it in.
 #[no_mangle]
 #[link_section = ".boot-program-main"]
-fn main() {
-	Printer::get_global().clear();
+extern "C" fn main(drive: u8) {
+	// Make sure BS can actually run on this CPU before doing anything else, so a 32-bit-only CPU
+	// gets an error message instead of a triple fault when the bootloader tries to enter 64-bit mode
+	if let Err(missing) = check_cpu() {
+		unsupported_cpu(missing);
+	}
+
+	{
+		// The bootstrapper may have switched text modes
+		let mut printer = Printer::get_global();
+		printer.detect_mode();
+		printer.clear();
+	}
 	// For some reason QEMU cuts off the first 2 lines of the console on my mac; seeing this
 	// message just confirms prints aren't getting cut off.
 	println!("\n\nhewwo");
 
+	// Set up the boot info for the later stages, and get the memory map while BIOS calls still work
+	let boot_info = unsafe { BootInfo::init(BootInfo::ADDRESS as *mut BootInfo, drive) };
+	// The map's left as-is: normalizing it takes up too much code for 16-bit mode, so the ELF loader
+	// does it
+	let map = &mut boot_info.memory_map;
+	if !memory_map::read_e820(map) {
+		println!("The BIOS doesn't support E820, so the memory map is unknown");
+	} else {
+		println!(
+			"Read {} memory map entries from the BIOS",
+			map.entries().len()
+		);
+	}
+
+	// The rest of BS and its configuration are in the boot partition, the active one in the MBR's
+	// partition table. The bootstrapper left the MBR at `MBR_ADDRESS`.
+	let partitions = unsafe { PartitionTable::from_mbr(MBR_ADDRESS as *const u8) };
+	let Some(partition) = partitions.active() else {
+		panic!("The MBR doesn't have an active partition");
+	};
+	let partition_start = partition.start_lba as u64;
+
+	match config::load(boot_info, partition_start) {
+		Ok(()) => println!("Command line: {}", boot_info.command_line()),
+		Err(err) => println!("Couldn't read the command line: {err:?}"),
+	}
+
+	// QEMU enables A20 by default, but real hardware often doesn't - and without it, every odd
+	// megabyte of memory mirrors the one below it. `a20::enable` tries the BIOS, then the keyboard
+	// controller, then port 0x92, and checks that memory stopped wrapping after each one.
+	match a20::enable() {
+		Some(method) => println!("A20 enabled ({method:?})"),
+		None => panic!("Couldn't enable the A20 line"),
+	}
+
+	// Everything from here on touches memory past 1mib - ACPI tables, PCI's ECAM, the ELF loader,
+	// and frames - which real mode's 64kib segments can't reach
+	unreal_mode();
+
+	// The RSDP is in the first mib of memory, but the tables it points to usually aren't.
+	let Some(root_pointer) = (unsafe { Rsdp::find() }) else {
+		panic!("Failed to find RSDP");
+	};
+	boot_info.rsdp_address = root_pointer.rsdp() as *const Rsdp as u64;
+
 	// Eventually this PCI code is going to get put in its own crate/boot program.
-	// Right now it's here as a POC.
+	// Right now it's here as a POC - and to find the IDE controller, so the rest of BS can be read
+	// without the BIOS.
 	println!("PCI");
-	pci();
+	let ide = pci(root_pointer);
 	println!("ICP");
 
-	// TODO: Enable A20 line - https://wiki.osdev.org/A20_Line
-	// QEMU has it enabled by default, so we don't need it for now.
+	// Frames for the kernel's file, the initrd, and the page tables. The first mib has the BIOS'
+	// data and the boot programs, and the ELF loader goes in the mib after it, so they're left
+	// alone.
+	let mut frames = FrameAllocator::new(
+		unsafe { &mut *ptr::addr_of_mut!(FRAME_BITMAP) },
+		boot_info.memory_map.usable(),
+	);
+	frames.reserve(MemoryRegion {
+		start: 0,
+		len: BOOT_PROGRAMS_END,
+	});
+	// The map isn't normalized, so usable regions can overlap ones that aren't
+	for entry in boot_info.memory_map.entries() {
+		if entry.kind != RegionKind::Usable {
+			frames.reserve(entry.region());
+		}
+	}
 
-	// Enable 64-bit mode
+	// If the boot drive's on the IDE controller, everything's read through it
+	let mut boot_disk = ide.and_then(load::boot_disk);
+	match load::load_elf_loader(boot_info, partition_start, boot_disk.as_mut()) {
+		Ok(len) => println!("Loaded the ELF loader ({} kib)", len / 1024),
+		Err(err) => panic!("Failed to load the ELF loader: {err:?}"),
+	}
+	if let Err(err) = load::load_kernel(boot_info, partition_start, &mut frames, boot_disk.as_mut())
+	{
+		panic!("Failed to load the kernel: {err:?}");
+	}
+	if let Some(file) = boot_info.kernel_file() {
+		println!(
+			"Read the kernel's file ({} kib) to {:#x}",
+			file.len / 1024,
+			file.start
+		);
+	}
+	if let Err(err) = load::load_initrd(boot_info, partition_start, &mut frames, boot_disk.as_mut())
+	{
+		panic!("Failed to load the initrd: {err:?}");
+	}
+	if let Some(initrd) = boot_info.initrd() {
+		println!(
+			"Loaded the initrd ({} kib) at {:#x}",
+			initrd.len / 1024,
+			initrd.start
+		);
+	}
+
+	// Enable 64-bit mode. `check_cpu` already made sure the CPU supports it.
 	// https://wiki.osdev.org/Entering_Long_Mode_Directly
 	// https://forum.osdev.org/viewtopic.php?f=1&t=11093&sid=e95191d8cf1676df0e60df6853b220d3
 
-	// Structs we need to enter 64-bit mode
-	let gdt_descriptor = build_gdt();
-	let page_map_level_4 = build_page_tables();
+	// The kernel doesn't use SSE, but user-mode programs (and any float code) will. This only
+	// touches CR0 and CR4, so it carries over into 64-bit mode.
+	match unsafe { fpu::enable() } {
+		Some(features) => println!("Enabled the FPU and SSE ({features:?})"),
+		None => println!("This CPU doesn't support SSE, leaving it off"),
+	}
+
+	// The stack the ELF loader runs on, since the bootloader's stack (below `LOAD_ADDRESS`) is
+	// tiny.
+	let Some(stack) = frames.allocate_contiguous(ELF_LOADER_STACK_FRAMES, PAGE_SIZE) else {
+		panic!("Out of memory for the ELF loader's stack");
+	};
+	unsafe { ELF_LOADER_STACK_TOP = stack + ELF_LOADER_STACK_FRAMES as u64 * PAGE_SIZE };
+
+	// This is the last BIOS call, since nothing printed after it shows up on the screen. The ELF
+	// loader maps the framebuffer for the kernel.
+	#[cfg(feature = "framebuffer")]
+	{
+		set_video_mode(boot_info);
+		unreal_mode();
+	}
+
+	// Structs we need to enter 64-bit mode. Without the no-execute bit, every page is executable.
+	let no_execute = cpuid::Feature::Nx.supported();
+	let page_map_level_4 = build_page_tables(boot_info, &mut frames, no_execute);
+
+	// That's everything the bootloader allocates. The ELF loader makes its own frame allocator from
+	// the memory map, so it needs to know what's taken.
+	boot_info.memory_map.mark_allocated(&frames);
+
+	// BIOS calls can turn interrupts back on, and there's no IDT for 64-bit mode yet, so an
+	// interrupt during the switch would triple fault
+	unsafe { asm!("cli") }
 
 	// Sets the PAE bit/enables PAE. PAE: Physical Address Extension, allowing access to >4gb of memory.
-	// This is required to enter 64-bit mode.
-	// TODO: Investigate: PAE seems to break under QEMU, but OSDev Wiki claims it's needed for 64-bit mode.
-	// println!("Enabling PAE & PGE");
-	// unsafe {
-	// 	asm!(
-	// 		"mov eax, cr4",
-	// 		"or eax, (1 << 5)",
-	// 		"mov cr4, eax",
-	// 		out("eax") _
-	// 	)
-	// }
+	// This is required to enter 64-bit mode: 64-bit page tables are an extension of PAE's, and
+	// enabling paging with LME set but PAE clear faults.
+	println!("Enabling PAE");
+	unsafe {
+		asm!(
+			"mov eax, cr4",
+			"or eax, (1 << 5)",
+			"mov cr4, eax",
+			out("eax") _
+		)
+	}
 
 	// Load the page map level 4 (PML4)
 	// The PML4 is the top-level page table, and its entries point to lower level page tables
 	// Thus this implicitly loads all our page tables
 	println!("Loading PML4");
-	unsafe { asm!("mov cr3, eax", in("eax") (page_map_level_4.ptr() as u32)) }
+	unsafe { Cr3::new(page_map_level_4).write() }
 
-	// Set the EFER MSR's LME bit.
-	// MSR: Model-specific registers - registers that can change between CPU models. Technically you should
-	//      check if an MSR is available with CPUID before using them, but BS only supports x86_64 processors,
-	//      and this MSR in particular is always present for those.
+	// Set the EFER MSR's LME bit (and NXE bit, if the CPU has it).
+	// MSR: Model-specific registers - registers that can change between CPU models. EFER is always present
+	//      on CPUs that support 64-bit mode, which was checked above.
 	// EFER: An MSR with lots of settings related to 64-bit mode, syscalls, and more.
 	// LME: Long Mode Enable. The bit in the EFER register that enables long mode (aka 64-bit mode).
-	//
-	// MSRs are all identified by specific numbers. To read an MSR, call `rdmsr` and provide the MSR's number
-	// in ECX. The value will be read into EAX. To write an MSR, call `wrmsr` with the MSR's number in ECX and
-	// the value to write in EAX.
+	// NXE: No-Execute Enable. Makes the no-execute bit in the page tables work; without it, that bit is
+	//      reserved and using it faults.
 	println!("Setting LME");
-	unsafe {
-		asm!(
-			"mov ecx, 0xC0000080", // The EFER MSR's number
-			"rdmsr",
-			"or eax, 1 << 8", // The LME bit
-			"wrmsr",
-			// Tell rust we use these registers
-			out("eax") _,
-			out("ecx") _
-		)
+	let mut efer = msr::Efer::read().with(msr::Efer::LONG_MODE_ENABLE);
+	if no_execute {
+		efer = efer.with(msr::Efer::NO_EXECUTE_ENABLE);
 	}
+	unsafe { efer.write() }
 
 	// Enable paging and protected mode simultaneously
 	// This, combined with what we did above, jumps straight from real/16-bit mode into 64-bit mode
@@ -101,201 +242,388 @@ fn main() {
 	// The GDT is the legacy way for defining memory permissions, from before paging was invented
 	// The CPU will actually ignore this in 64-bit mode and use pages instead
 	// However, it's still required to set up a GDT to leave 16-bit mode
+	//
+	// `unreal_mode` already loaded it, but BIOS calls since then could've replaced it.
+	//
+	// Loading the GDT doesn't change CS, so the CPU keeps running this code in compatibility mode. CS gets
+	// reloaded when we far jump into 64-bit code (see `gdt::far_jump`).
 	println!("Loading GDT");
-	unsafe { asm!("lgdt [{}]", in(reg) &gdt_descriptor) }
+	unsafe { GDT.load() }
+
+	// Far jump to `enter_elf_loader`, which is the first code that actually runs in 64-bit mode.
+	// It finishes setting up 64-bit mode, then calls the ELF loader.
+	println!("Jumping to the ELF loader");
+	unsafe { far_jump(CODE_SELECTOR, enter_elf_loader as *const () as usize as u32) }
 }
 
-/// Builds and sets a GDT with 3 entries: null, all memory read/write, all memory executable.
+/// The biggest resolution [`set_video_mode`] switches to.
+#[cfg(feature = "framebuffer")]
+const MAX_RESOLUTION: (u16, u16) = (1024, 768);
+
+/// Switches to the biggest VBE mode that fits in [`MAX_RESOLUTION`] (see [`vbe::find_mode`]), and
+/// records its framebuffer in `boot_info`. If there isn't one, or it can't be set, this stays in
+/// text mode.
+#[cfg(feature = "framebuffer")]
+fn set_video_mode(boot_info: &mut BootInfo) {
+	let Some(mode) = vbe::find_mode(MAX_RESOLUTION.0, MAX_RESOLUTION.1) else {
+		println!("No usable VBE mode, staying in text mode");
+		return;
+	};
+
+	let framebuffer = mode.framebuffer();
+	println!(
+		"Switching to {}x{} ({}-bit), framebuffer at {:#x}",
+		framebuffer.width,
+		framebuffer.height,
+		framebuffer.bytes_per_pixel * 8,
+		framebuffer.address
+	);
+	if mode.set() {
+		boot_info.framebuffer = framebuffer.into();
+	} else {
+		println!(
+			"Couldn't set VBE mode {:#x}, staying in text mode",
+			mode.number
+		);
+	}
+}
+
+/// Checks the CPU has everything needed for 64-bit mode. If it doesn't, returns the first thing
+/// that's missing.
+fn check_cpu() -> Result<(), &'static str> {
+	if !cpuid::cpuid_supported() {
+		return Err("the cpuid instruction");
+	}
+	if !cpuid::Feature::Pae.supported() {
+		return Err("PAE");
+	}
+	// This is in extended leaf 0x8000_0001. `supported` checks the highest extended leaf first,
+	// since older CPUs don't have that one.
+	if !cpuid::Feature::LongMode.supported() {
+		return Err("64-bit mode");
+	}
+
+	Ok(())
+}
+
+/// Says the CPU can't run BS, because it's `missing` something, then halts forever.
+///
+/// This uses BIOS teletype output (int 10h, AH=0x0E), like the bootstrapper's errors, so the message
+/// shows up no matter what the screen's doing.
+fn unsupported_cpu(missing: &str) -> ! {
+	let message = "\r\nThis CPU cannot run BS (64-bit required). It doesn't have ";
+	for byte in message.bytes().chain(missing.bytes()) {
+		unsafe {
+			asm!(
+				"push bx",
+				"xor bx, bx",
+				"int 0x10",
+				"pop bx",
+				inout("ax") 0x0E00 | byte as u16 => _,
+			)
+		}
+	}
+
+	loop {
+		unsafe { asm!("cli", "hlt") }
+	}
+}
+
+/// The bootloader only uses frames below this (64mib), so the frame bitmap stays small. That's
+/// plenty for the kernel's file, the initrd, and the page tables.
+const BOOT_MEMORY_LIMIT: PhysAddr = 0x400_0000;
+/// The [`FrameAllocator`]'s bitmap.
+static mut FRAME_BITMAP: [u64; FrameAllocator::bitmap_len(BOOT_MEMORY_LIMIT)] =
+	[0; FrameAllocator::bitmap_len(BOOT_MEMORY_LIMIT)];
+
+/// How many frames the ELF loader's stack takes up (64kib).
+const ELF_LOADER_STACK_FRAMES: usize = 16;
+
+/// The top of the ELF loader's stack, for `enter_elf_loader`. It's a static because 16-bit code
+/// can't put a 64-bit address in a register. It's identity-mapped.
+#[no_mangle]
+static mut ELF_LOADER_STACK_TOP: u64 = 0;
+
+extern "C" {
+	/// The 64-bit trampoline: reloads the data segments with [`DATA_SELECTOR`], switches to the ELF
+	/// loader's stack ([`ELF_LOADER_STACK_TOP`]), then calls the ELF loader (at
+	/// [`ELF_LOADER_ADDRESS`]) with the [`BootInfo`]. This is 64-bit code, so it can only be
+	/// reached with [`far_jump`].
+	fn enter_elf_loader() -> !;
+}
+global_asm! {
+	".section .text.enter_elf_loader, \"ax\"",
+	".code64",
+	"enter_elf_loader:",
+	// The data segments still have their real-mode values
+	"mov ax, {data}",
+	"mov ds, ax",
+	"mov es, ax",
+	"mov fs, ax",
+	"mov gs, ax",
+	"mov ss, ax",
+	// The stack is 16-byte aligned, so it's aligned the way the ELF loader expects after `call`
+	"mov rsp, qword ptr [ELF_LOADER_STACK_TOP]",
+	// Frame-pointer stack traces stop at a null frame pointer
+	"xor ebp, ebp",
+	// The ELF loader's first argument is the boot info
+	"mov edi, {boot_info}",
+	"mov eax, {elf_loader}",
+	"call rax",
+	"2:",
+	"cli",
+	"hlt",
+	"jmp 2b",
+	// Back to 16-bit code for everything after this
+	".code16",
+	data = const DATA_SELECTOR,
+	boot_info = const BootInfo::ADDRESS,
+	elf_loader = const ELF_LOADER_ADDRESS,
+}
+
+/// The selector for [`GDT`]'s code segment.
+const CODE_SELECTOR: u16 = Gdt::<3>::selector(1);
+/// The selector for [`GDT`]'s data segment.
+const DATA_SELECTOR: u16 = Gdt::<3>::selector(2);
+
+/// A GDT with 3 entries: null, all memory executable, all memory read/write.
 /// If that sounds unsafe, the real memory permissions will be configured later with paging. x86_64
 /// actually doesn't support any other GDT configuration, since it's deprecated and paging is used instead,
 /// but we still have to make a GDT to enable it. See the gdt.rs docs for more info.
 ///
-/// This uses `ManuallyDrop` to leak the GDT and prevent it from ever getting destructed.
-fn build_gdt() -> ManuallyDrop<GdtDescriptor> {
-	let gdt = ManuallyDrop::new([
-		[0, 0, 0, 0, 0, 0, 0, 0],
-		SegmentDescriptorBuilder {
-			base: 0,
-			limit: gdt::U20_MAX,
-			flags: SegmentFlagsBuilder {
-				paged_limit: true,
-				protected: false,
-				long: true,
-			},
-			access: SegmentAccessBuilder {
-				present: true,
-				privilege: 0,
-				non_system: true,
-				executable: true,
-				direction_conforming: false,
-				read_write: true,
-				accessed: true,
-			},
-		}
-		.build(),
-		SegmentDescriptorBuilder {
-			base: 0,
-			limit: gdt::U20_MAX,
-			flags: SegmentFlagsBuilder {
-				paged_limit: true,
-				protected: false,
-				long: true,
-			},
-			access: SegmentAccessBuilder {
-				present: true,
-				privilege: 0,
-				non_system: true,
-				executable: false,
-				direction_conforming: false,
-				read_write: true,
-				accessed: true,
-			},
-		}
-		.build(),
-	]);
+/// This is a static so it's never destructed, and stays loaded after the bootloader returns.
+static GDT: Gdt<3> = {
+	let mut gdt = Gdt::new();
+	gdt.add(SegmentDescriptorBuilder::kernel_code64().build());
+	gdt.add(SegmentDescriptorBuilder::kernel_data().build());
+	gdt
+};
 
-	ManuallyDrop::new(GdtDescriptor {
-		size: ((8 * gdt.len()) - 1) as u16,
-		offset: &gdt as *const _ as u64,
-	})
+/// Loads [`GDT`] and enters unreal mode with its data segment (see [`enter_unreal_mode`]), so the
+/// bootloader can read and write memory past 1mib without leaving 16-bit mode. BIOS calls can take
+/// the bootloader back out of it, so this gets called again after the ones that run later.
+fn unreal_mode() {
+	unsafe {
+		GDT.load();
+		enter_unreal_mode(DATA_SELECTOR);
+	}
 }
 
-/// Identity-maps 2mib of memory with RWX permissions. This is temporary, just enough to get our kernel booted.
+/// Builds the page tables the ELF loader starts with, returning the physical address of the page
+/// map level 4. Usable memory (from the memory map) is identity-mapped, up to
+/// [`IDENTITY_MAP_LIMIT`], so the ELF loader can reach everything the bootloader loaded. Everything
+/// below [`BOOT_PROGRAMS_END`] is executable, since the boot programs run from it; the rest isn't.
+/// Reserved memory isn't mapped at all, except for the VGA text buffer.
+///
+/// Without `no_execute` (see [`cpuid::Feature::Nx`]), everything is executable.
 ///
-/// This uses `ManuallyDrop` to leak the pages and prevent them from ever getting destructed.
-fn build_page_tables() -> ManuallyDrop<PageMap<PageMapLevel4Entry>> {
-	let mut page_table = ManuallyDrop::new(PageMap::<PageTableEntry>::new());
-	let mut address = 0;
-	for entry in page_table.iter_mut() {
-		entry
-			.set_present(true)
-			.set_writable(true)
-			.set_address(address);
-		address += 0x1000;
+/// The ELF loader builds the kernel's page tables itself. These are in frames from `frames`, so
+/// they're never freed.
+fn build_page_tables(
+	boot_info: &BootInfo,
+	frames: &mut FrameAllocator,
+	no_execute: bool,
+) -> PhysAddr {
+	let Some(pml4_address) = frames.allocate_frame() else {
+		panic!("Out of memory for the page tables");
+	};
+	let pml4 = pml4_address as usize as *mut PageMap<PageMapLevel4Entry>;
+	// Paging is still off and the bootloader's in unreal mode, so every table can be edited at its
+	// physical address. An empty table is all zeroes; zeroing it in place keeps a 4kib table off
+	// the bootloader's small stack.
+	unsafe { pml4.write_bytes(0, 1) };
+	let mut mapper = unsafe { Mapper::new(&mut *pml4, frames, 0) };
+
+	let flags = |executable: bool| PageFlags {
+		writable: true,
+		executable: executable || !no_execute,
+		..Default::default()
+	};
+	let mut result = Ok(());
+	let mut map = |start: PhysAddr, end: PhysAddr, flags: PageFlags| {
+		if start < end && result.is_ok() {
+			result = mapper.map_range(start, start, end - start, flags);
+		}
+	};
+
+	for region in boot_info.memory_map.usable() {
+		// Only whole pages are usable
+		let start = region.start.next_multiple_of(PAGE_SIZE);
+		let end = (region.end().min(IDENTITY_MAP_LIMIT) / PAGE_SIZE) * PAGE_SIZE;
+		let boot_programs_end = end.min(BOOT_PROGRAMS_END);
+
+		map(start, boot_programs_end, flags(true));
+		map(start.max(boot_programs_end), end, flags(false));
+	}
+	map(VGA_BUFFER.start, VGA_BUFFER.end(), flags(false));
+	if let Err(err) = result {
+		panic!("Failed to build the page tables: {err:?}");
 	}
 
-	let mut page_directory = ManuallyDrop::new(PageMap::<PageDirectoryEntry>::new());
-	page_directory[0]
-		.set_present(true)
-		.set_writable(true)
-		.set_address(page_table.ptr() as _);
-
-	let mut page_directory_pointer_table =
-		ManuallyDrop::new(PageMap::<PageDirectoryPointerTableEntry>::new());
-	page_directory_pointer_table[0]
-		.set_present(true)
-		.set_writable(true)
-		.set_address(page_directory.ptr() as _);
-
-	let mut page_map_level_4 = ManuallyDrop::new(PageMap::<PageMapLevel4Entry>::new());
-	page_map_level_4[0]
-		.set_present(true)
-		.set_writable(true)
-		.set_address(page_directory_pointer_table.ptr() as _);
-
-	page_map_level_4
+	pml4_address
 }
 
+/// The bootloader only identity-maps memory below this (4gib). It can't reach past that anyways,
+/// and mapping all of a big computer's memory with 4kib pages would take too many page tables.
+const IDENTITY_MAP_LIMIT: PhysAddr = 0x1_0000_0000;
+/// The VGA text buffer, which is reserved memory, but gets mapped so the ELF loader can print.
+const VGA_BUFFER: MemoryRegion = MemoryRegion {
+	start: 0xB8000,
+	len: 0x8000,
+};
+
 // PCI will eventually be put in its own boot program so the bootstrapper can use it to read from
 // disk. Right now it's here as a POC.
-fn pci() {
-	let mut address = 0;
-	let mut maybe_rsdp = None;
-
-	while address < 0xFFFFF {
-		let rsdp = unsafe { Rsdp::try_from_raw(address as _) };
-		if let Ok(rsdp) = rsdp {
-			maybe_rsdp = Some(rsdp);
-			break;
+/// Scans the PCI bus, returning the first IDE controller it finds.
+fn pci(root_pointer: RootPointer) -> Option<IdeController> {
+	let rsdp = root_pointer.rsdp();
+	println!("Found RSDP at {:#x}", rsdp as *const Rsdp as usize);
+
+	// ACPI 2+ systems have an XSDP, which points to the XSDT. The XSDT is identical to the RSDT,
+	// except it uses 64-bit pointers. If the XSDT can't be used, we fall back to the RSDT, which
+	// ACPI 2+ systems are still required to have.
+	if let RootPointer::Xsdp(xsdp) = root_pointer {
+		let address = xsdp.xsd_address;
+
+		// We're in real mode, so we can't follow pointers past 4gib.
+		match usize::try_from(address) {
+			Ok(ptr) => match unsafe { Xsdt::try_from_raw(ptr as _) } {
+				Ok(xsdt) => {
+					println!("Found XSDT at {address:#x}");
+					return pci_from_sdt(&xsdt);
+				}
+				Err(err) => {
+					println!("XSDT at {address:#x} is invalid ({err}), falling back to RSDT")
+				}
+			},
+			Err(_) => println!("XSDT at {address:#x} is unreachable, falling back to RSDT"),
 		}
-
-		address += 16;
 	}
-	let Some(rsdp) = maybe_rsdp else {
-		panic!("Failed to find RSDP");
-	};
-	// TODO: Handle XSDP (Extended System Descriptor Pointer)
-	// Can use: `if let Ok(xsdp: &Xsdp) = rsdp.try_into() {}`
-	// Then need to follow XSDP pointer instead of RSDP pointer
 
-	println!("Found RSDP at {address:#x}",);
-	let rsdt = unsafe { Rsdt::try_from_raw(rsdp.rsdt_address as _).unwrap() };
 	let address = rsdp.rsdt_address;
+	let rsdt = match unsafe { Rsdt::try_from_raw(address as _) } {
+		Ok(rsdt) => rsdt,
+		Err(err) => panic!("RSDT at {address:#x} is invalid: {err}"),
+	};
 	println!("Found RSDT at {address:#x}");
-	for table in rsdt.tables {
-		let rsdt = unsafe { Rsdt::try_from_raw(*table as _).unwrap() };
+	pci_from_sdt(&rsdt)
+}
+
+/// Finds PCI devices using the tables in an [`Rsdt`] or [`Xsdt`], returning the first IDE
+/// controller.
+fn pci_from_sdt<PtrSize: ToPtr>(sdt: &Sdt<PtrSize>) -> Option<IdeController> {
+	for table in sdt.tables_typed() {
 		println!(
-			"    Table in RSDT: {}",
-			core::str::from_utf8(&rsdt.descriptor.signature).unwrap()
+			"    Table in SDT: {}",
+			core::str::from_utf8(&table.descriptor().signature).unwrap()
 		);
 	}
 
-	// If the system supports PCIe, there will be an MCFG table. Otherwise, we fall back to using regular PCI.
-	if let Some(_mcfg) = rsdt.find_table("MCFG") {
-		todo!("PCIe")
-	} else {
-		println!("No PCIe detected, falling back on PCI...");
+	// If the system supports PCIe, there will be an MCFG table, and devices should be found through
+	// its memory-mapped configuration spaces - some machines (like QEMU's q35) only have some devices
+	// there. Otherwise, we fall back to using regular PCI.
+	let access = match sdt.find::<Mcfg>().and_then(|mcfg| ecam_from_mcfg(&mcfg)) {
+		Some(ecam) => {
+			let base_address = ecam.base_address;
+			println!(
+				"Found PCIe, configuration spaces for buses {}-{} at {base_address:#x}",
+				ecam.start_bus, ecam.end_bus
+			);
+			ConfigAccess::Ecam(ecam)
+		}
+		None => {
+			println!("No PCIe detected, falling back on PCI...");
+			ConfigAccess::Ports
+		}
+	};
 
-		// PCI bus 0, device 0, fn 0 is the root PCI bridge
-		let Some(root) = PciDevice::new(0, 0, 0) else {
-			panic!("Failed to initialise PCI :c")
-		};
+	// PCI bus 0, device 0, fn 0 is the root PCI bridge
+	let Some(root) = PciDevice::open(access, 0, 0, 0) else {
+		panic!("Failed to initialise PCI :c")
+	};
 
-		handle_pci_bridge(root);
+	handle_pci_bridge(access, root)
+}
+
+/// Finds the MCFG allocation for bus 0 in segment group 0, which has the root bridge. Returns
+/// `None` if there isn't one, or it's past 4gib, where the bootloader can't reach it.
+fn ecam_from_mcfg(mcfg: &Mcfg) -> Option<Ecam> {
+	let allocation = mcfg
+		.allocations
+		.iter()
+		.find(|allocation| allocation.segment_group == 0 && allocation.start_bus == 0)?;
+	let base_address = allocation.base_address;
+	// The whole range has to be below 4gib, not just the start
+	let end = base_address + ((allocation.end_bus as u64 + 1) << 20);
+	if end > IDENTITY_MAP_LIMIT {
+		println!("PCIe configuration spaces at {base_address:#x} are unreachable");
+		return None;
 	}
+
+	Some(Ecam::new(
+		base_address,
+		allocation.segment_group,
+		allocation.start_bus,
+		allocation.end_bus,
+	))
 }
 
-fn handle_pci_bridge(mut bridge: PciDevice) {
+fn handle_pci_bridge(access: ConfigAccess, mut bridge: PciDevice) -> Option<IdeController> {
 	let header = bridge.header().unwrap();
 
 	if header.multi_function {
 		let bus = bridge.bus();
 		let device = bridge.device();
 		let mut function = 0;
-		while let Some(mut bridge) = PciDevice::new(bus, device, function) {
+		let mut ide = None;
+		while let Some(mut bridge) = PciDevice::open(access, bus, device, function) {
 			let register = bridge.read_register(6).unwrap();
 			let bus = register[1];
-			handle_pci_bus(bus);
+			ide = ide.or(handle_pci_bus(access, bus));
 
 			function += 1;
 		}
+		ide
 	} else {
 		let register = bridge.read_register(6).unwrap();
 		let bus = register[2];
-		handle_pci_bus(bus);
+		handle_pci_bus(access, bus)
 	}
 }
 
-fn handle_pci_bus(bus: u8) {
+fn handle_pci_bus(access: ConfigAccess, bus: u8) -> Option<IdeController> {
+	let mut ide = None;
 	for device_id in 0..32 {
-		if let Some(mut device) = PciDevice::new(bus, device_id, 0) {
+		if let Some(mut device) = PciDevice::open(access, bus, device_id, 0) {
 			let header = device.header().unwrap();
 
 			if header.kind == HeaderType::PciToPci {
 				println!("PCI bridge at {bus}.{device_id}");
-				handle_pci_bridge(device);
+				ide = ide.or(handle_pci_bridge(access, device));
 			} else if header.multi_function {
 				let bus = device.bus();
 				let device = device.device();
 				let mut function = 0;
-				while let Some(mut device) = PciDevice::new(bus, device, function) {
-					handle_pci_device(&mut device);
+				while let Some(mut device) = PciDevice::open(access, bus, device, function) {
+					ide = ide.or(handle_pci_device(&mut device));
 					function += 1;
 				}
 			} else {
-				handle_pci_device(&mut device);
+				ide = ide.or(handle_pci_device(&mut device));
 			}
 		}
 	}
+
+	ide
 }
 
-fn handle_pci_device(device: &mut PciDevice) {
+fn handle_pci_device(device: &mut PciDevice) -> Option<IdeController> {
 	println!("Found PCI device with class: {:?}", device.class());
 	if device.class()
 		== Some(Class::MassStorageController(
 			MassStorageControllerSubclass::Ide,
 		)) {
-		let mut controller = IdeController::from_pci(device).unwrap();
+		let controller = IdeController::from_pci(device).unwrap();
 		controller.primary_channel.set_interrupts(false);
 		controller.secondary_channel.set_interrupts(false);
 		println!(
@@ -303,23 +631,8 @@ fn handle_pci_device(device: &mut PciDevice) {
 			device.programming_interface().unwrap()
 		);
 
-		controller.primary_channel.set_disk(ata::IdeDisk::Primary);
-		controller
-			.primary_channel
-			.send_command(ata::AtaCommand::ReadPio, 0, 0)
-			.unwrap();
-		let mut output: [u16; 256] = [0; 256];
-		for part in output.iter_mut() {
-			*part = controller
-				.primary_channel
-				.read_register(ata::AtaRegister::Data);
-		}
-		print!("First sector on drive: [");
-		for word in output {
-			for byte in word.to_ne_bytes() {
-				print!("{byte:02x}, ")
-			}
-		}
-		println!("]")
+		return Some(controller);
 	}
+
+	None
 }
@@ -0,0 +1,47 @@
+//! Prints errors on the screen, so boot failures on real hardware can be diagnosed without a
+//! debugger.
+//!
+//! This uses BIOS teletype output (int 10h, AH=0x0E) instead of the VGA printer in
+//! `common::printing`, since it's much smaller and works no matter what mode the screen is in.
+//! There's only room for a short message, like:
+//!
+//! ```text
+//! BS error: disk read
+//! ```
+//!
+//! Everything after this, including telling apart why a disk read failed, is up to the bootloader.
+//!
+//! Resources:
+//! - https://en.wikipedia.org/wiki/INT_10H
+
+use core::arch::asm;
+
+/// Prints `message` as an error, then halts forever.
+pub fn fail(message: &str) -> ! {
+	print("\r\nBS error: ");
+	print(message);
+	halt()
+}
+
+/// Prints `message` with BIOS teletype output (int 10h, AH=0x0E). The BIOS handles `\r` and `\n`,
+/// and scrolls the screen when it fills up.
+pub fn print(message: &str) {
+	for byte in message.bytes() {
+		unsafe {
+			asm!(
+				"push bx",
+				"xor bx, bx",
+				"int 0x10",
+				"pop bx",
+				inout("ax") 0x0E00 | byte as u16 => _,
+			)
+		}
+	}
+}
+
+/// Halts the CPU forever.
+pub fn halt() -> ! {
+	loop {
+		unsafe { asm!("hlt") }
+	}
+}
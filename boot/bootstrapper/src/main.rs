@@ -1,17 +1,20 @@
 #![no_std]
 #![no_main]
 
+mod error;
+
 use {
-	common::printing::Printer,
+	common::{
+		disks::DiskAddressPacket,
+		mbr::{BOOTLOADER_LOCATION_OFFSET, MBR_ADDRESS},
+		stages::{StageLocation, LOAD_ADDRESS, STACK_TOP},
+	},
 	core::{
 		arch::{asm, global_asm},
-		fmt::Write,
 		mem,
 	},
 };
 
-mod disk;
-
 // This is where BS starts. It's written in AT&T syntax because for some reason I
 // can't correctly make a long jump in Intel syntax. The rest of the project is in
 // the much saner Intel syntax.
@@ -22,16 +25,6 @@ r#"
 .code16
 
 asm_main:
-    /*
-        Clear CS segment
-        CS stores the base address of code in memory. We want it to be 0 so it's
-        simpler to work with addresses and don't have to worry about a weird offset
-        that the BIOS randomly set here. The only way to clear CS is by performing a
-        long jump.
-    */
-    ljmp $0, $call_bootloader
-
-call_bootloader:
     /*
         Clear direction flag
         If the direction flag is set, the CPU reads strings backwards in memory. We
@@ -51,7 +44,7 @@ call_bootloader:
         Segment registers describe the base of some segment of memory - a code segment,
         data segment, etc. These have random values from the BIOS. For simplicity, BS
         sets them all to 0 so everything has the same address it'd have in actual memory.
-        The CS (code segment) register was cleared above.
+        The CS (code segment) register is cleared below.
     */
     mov $0, %ax
     mov %ax, %ds
@@ -60,8 +53,30 @@ call_bootloader:
     mov %ax, %fs
     mov %ax, %gs
 
+    /*
+        Move the MBR out of the way
+        The BIOS loaded the MBR to 0x7C00, but the bootloader gets loaded over that, so the
+        bootstrapper copies itself (and the partition table) lower down first. It's linked
+        to run there. Until the long jump below, this is still running from 0x7C00, so it
+        can't use any absolute addresses.
+    */
+    mov $0x7C00, %si
+    mov ${mbr}, %di
+    mov $256, %cx
+    rep movsw
+
+    /*
+        Clear CS segment
+        CS stores the base address of code in memory. We want it to be 0 so it's
+        simpler to work with addresses and don't have to worry about a weird offset
+        that the BIOS randomly set here. The only way to clear CS is by performing a
+        long jump - which also jumps into the bootstrapper's copy.
+    */
+    ljmp $0, $call_bootloader
+
+call_bootloader:
     /* Set up the stack */
-    mov $0x7C00, %sp
+    mov ${stack}, %sp
 
 	/* Jump to Rust, passing dx as an argument (the `drive` argument in `loader`) */
     push %dx
@@ -71,6 +86,8 @@ call_bootloader:
     push %dx
     call loader
 "#,
+mbr = const MBR_ADDRESS,
+stack = const STACK_TOP,
 // We actually need this because you can't do long jumps correctly in the intel
 // syntax for some reason
 options(att_syntax)
@@ -78,40 +95,66 @@ options(att_syntax)
 
 #[no_mangle]
 extern "C" fn loader(drive: u16) -> ! {
-	// Load bootloader into memory
-	// It returns the last read sector, aka the end of the bootloader program
-	let _end_of_bootloader = disk::load_program(1, drive);
-
-	// Call bootloader
-	let main = 0x7E00 as *const ();
-	let main: fn() = unsafe { mem::transmute(main) };
-	main();
-
-	// We're now in 64-bit mode and can't use BIOS calls, since they're 16-bit
-	// TODO: Write a PCI IDE driver, which can read from disk, and use that to read
-	// from disk instead of BIOS. This will give us more control and let us load the
-	// ELF loader into memory here.
-
-	loop {
-		unsafe { asm!("hlt") }
+	// Switch to 80x50 text mode, by loading the BIOS' 8x8 font (int 10h, AX=0x1112). This has to
+	// happen now, since BIOS calls don't work after leaving real mode. The bootloader picks up the
+	// new size with `Printer::detect_mode`.
+	#[cfg(feature = "text-80x50")]
+	unsafe {
+		asm!("pusha", "mov ax, 0x1112", "xor bl, bl", "int 0x10", "popa");
+	}
+
+	// The build wrote where the bootloader is into the MBR, which the BIOS loaded along with the
+	// bootstrapper, so it's already in memory. Finding the boot partition is left to the bootloader.
+	let location = unsafe { *((MBR_ADDRESS + BOOTLOADER_LOCATION_OFFSET) as *const StageLocation) };
+
+	// Load bootloader into memory, with one EDD read (int 13h, AH=0x42). The bootloader always ends
+	// by 0x10000 (see `boot-program.ld`), so it's never more sectors than one read can handle. Falling
+	// back to CHS, and retrying failed reads, is left to the bootloader (see `common::disks`) - there
+	// isn't room for it here.
+	let packet = DiskAddressPacket::new(
+		location.lba as u64,
+		location.sectors as u16,
+		0,
+		LOAD_ADDRESS,
+	);
+	let carry: u16;
+	unsafe {
+		asm!(
+			"push si",
+			"mov si, {packet:x}",
+			"int 0x13",
+			"pop si",
+			"sbb {carry:x}, {carry:x}",
+			packet = in(reg) &packet,
+			carry = lateout(reg) carry,
+			inout("ax") 0x4200u16 => _,
+			in("dx") drive,
+		);
+	}
+	if carry != 0 {
+		error::fail("disk read");
 	}
+
+	// Call bootloader, which sets up the boot info
+	let main = LOAD_ADDRESS as *const ();
+	let main: extern "C" fn(u8) = unsafe { mem::transmute(main) };
+	main(drive as u8);
+
+	// The bootloader enters 64-bit mode and jumps to the ELF loader, so it shouldn't come back
+
+	error::halt()
 }
 
 #[cfg(not(test))]
 mod panic {
-	use core::{arch::asm, fmt::Write, panic::PanicInfo};
+	use {crate::error, core::panic::PanicInfo};
 
+	/// Failures the bootstrapper expects go through `error::fail` instead, which says what went
+	/// wrong. This is for everything else.
 	#[panic_handler]
 	fn kys(_info: &PanicInfo) -> ! {
 		// QEMU cuts off the top 2 lines of the console on my mac so we
-		common::printing::Printer::get_global()
-			.write_str("\n\nBOOTSTRAPPER PANIC")
-			.unwrap();
-
-		loop {
-			unsafe {
-				asm!("hlt");
-			}
-		}
+		error::print("\r\n\r\nBOOTSTRAPPER PANIC");
+		error::halt()
 	}
 }
@@ -1,12 +1,13 @@
 use std::env;
 
 fn main() {
-	// Make rust compile the binary with our link script
+	// Make rust compile the binary with our link script. The ELF loader has its own, since it's
+	// loaded somewhere else than the other boot programs.
 	let root = env::var("CARGO_MANIFEST_DIR").unwrap();
 	let root = std::path::Path::new(&root);
 
 	println!(
 		"cargo:rustc-link-arg-bins=--script={}",
-		root.parent().unwrap().join("boot-program.ld").display()
+		root.join("link.ld").display()
 	);
 }
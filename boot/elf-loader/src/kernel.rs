@@ -0,0 +1,176 @@
+//! Loads the kernel.
+//!
+//! The kernel is a position-independent ELF. The bootloader already read its file into memory (see
+//! [`BootInfo::kernel_file`]); Frieren copies its `PT_LOAD` segments into frames from the
+//! [`FrameAllocator`], zeroes their `.bss`, and applies its relocations.
+//!
+//! The kernel is loaded at [`KERNEL_BASE`], in the higher half - but it isn't mapped there yet, so
+//! the segments are written through their physical addresses (the bootloader identity-mapped all
+//! usable memory). [`LoadedKernel`] remembers where each segment went, so the page tables can map
+//! them at their virtual addresses afterwards.
+//!
+//! If the command line has the `kaslr` flag, the kernel's moved up from [`KERNEL_BASE`] by a random
+//! multiple of [`KASLR_ALIGN`] (kernel address space layout randomization), so its addresses can't
+//! be guessed ahead of time. It's position-independent, so that's just a different base for its
+//! relocations. The kernel's code model needs it to stay in the top 2gib, so it can move up to
+//! [`KASLR_RANGE`] - which leaves the kernel the other 1gib.
+//!
+//! [`BootInfo::kernel_file`]: common::boot_info::BootInfo::kernel_file
+
+use {
+	common::{boot_info::BootInfo, command_line::CommandLine, paging::*, random},
+	core::{ops::Range, slice},
+	frieren::{Elf, ElfError, ProgramHeader, SegmentWriter},
+};
+
+/// The most `PT_LOAD` segments the kernel can have.
+pub const MAX_SEGMENTS: usize = 8;
+/// How far KASLR can move the kernel up from [`KERNEL_BASE`] (1gib). See the module-level docs.
+const KASLR_RANGE: u64 = 0x4000_0000;
+/// KASLR moves the kernel by a multiple of this (2mib), so its segments keep their alignment - even
+/// if they're ever mapped with huge pages.
+const KASLR_ALIGN: u64 = 0x20_0000;
+
+/// Something went wrong while loading the kernel.
+#[derive(Debug)]
+pub enum KernelError {
+	/// The kernel has more than [`MAX_SEGMENTS`] `PT_LOAD` segments.
+	TooManySegments,
+	/// Frieren couldn't load the kernel. This includes running out of frames for its segments.
+	#[allow(dead_code)] // Only read when the error gets printed
+	Elf(ElfError),
+}
+impl From<ElfError> for KernelError {
+	fn from(err: ElfError) -> Self {
+		Self::Elf(err)
+	}
+}
+
+/// One of the kernel's segments, after it's been loaded.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct KernelSegment {
+	/// Where the segment goes in virtual memory.
+	pub virt: u64,
+	/// Where the segment was copied to in physical memory. This has the same offset into its page
+	/// as `virt`.
+	pub phys: PhysAddr,
+	/// How many bytes the segment takes up in memory.
+	pub len: u64,
+	/// If the kernel can write to the segment.
+	pub writable: bool,
+	/// If the segment has code in it.
+	pub executable: bool,
+}
+impl KernelSegment {
+	/// The frames the segment was copied to.
+	pub fn frames(&self) -> Range<PhysAddr> {
+		let start = self.phys - self.phys % PAGE_SIZE;
+		let end = (self.phys + self.len).next_multiple_of(PAGE_SIZE);
+
+		start..end
+	}
+}
+
+/// Where the kernel ended up. See the module-level docs.
+pub struct LoadedKernel {
+	segments: [KernelSegment; MAX_SEGMENTS],
+	segment_count: usize,
+	/// The address the kernel was loaded at. This is [`KERNEL_BASE`], unless KASLR moved it.
+	pub base: u64,
+	/// The relocated address of the kernel's entry point.
+	pub entry: u64,
+}
+impl LoadedKernel {
+	/// The kernel's loaded segments.
+	pub fn segments(&self) -> &[KernelSegment] {
+		&self.segments[..self.segment_count]
+	}
+}
+
+/// Loads the kernel from its ELF `file` at [`KERNEL_BASE`] (or a random spot above it, if
+/// `command_line` turns on KASLR), using frames from `frames` for its segments.
+pub fn load(
+	file: &[u8],
+	command_line: &CommandLine,
+	frames: &mut FrameAllocator,
+) -> Result<LoadedKernel, KernelError> {
+	let base = if command_line.flag("kaslr") {
+		KERNEL_BASE + (random::entropy() % (KASLR_RANGE / KASLR_ALIGN)) * KASLR_ALIGN
+	} else {
+		KERNEL_BASE
+	};
+
+	let elf = Elf::parse(file)?;
+	let mut writer = KernelWriter {
+		frames,
+		kernel: LoadedKernel {
+			segments: [KernelSegment::default(); MAX_SEGMENTS],
+			segment_count: 0,
+			base,
+			entry: 0,
+		},
+		too_many_segments: false,
+	};
+	let loaded = elf.load(base, &mut writer);
+	if writer.too_many_segments {
+		return Err(KernelError::TooManySegments);
+	}
+	loaded?;
+
+	let mut kernel = writer.kernel;
+	kernel.entry = elf.entry_point::<&BootInfo>(base)?.address();
+
+	Ok(kernel)
+}
+
+/// Gives Frieren physical memory for each of the kernel's segments, and records where they went.
+struct KernelWriter<'a, 'f> {
+	frames: &'a mut FrameAllocator<'f>,
+	kernel: LoadedKernel,
+	/// Set if the kernel had more than [`MAX_SEGMENTS`] segments, since Frieren only knows that a
+	/// segment couldn't be loaded.
+	too_many_segments: bool,
+}
+impl SegmentWriter for KernelWriter<'_, '_> {
+	fn segment_memory(&mut self, segment: &ProgramHeader) -> Option<&mut [u8]> {
+		if self.kernel.segment_count == MAX_SEGMENTS {
+			self.too_many_segments = true;
+			return None;
+		}
+
+		// The segment has to start at the same spot in its page as it will in virtual memory
+		let page_offset = segment.address % PAGE_SIZE;
+		let frames = (page_offset + segment.memory_size).div_ceil(PAGE_SIZE);
+		let phys = self
+			.frames
+			.allocate_contiguous(frames as usize, PAGE_SIZE)?
+			+ page_offset;
+
+		self.kernel.segments[self.kernel.segment_count] = KernelSegment {
+			virt: self.kernel.base + segment.address,
+			phys,
+			len: segment.memory_size,
+			writable: segment.writable(),
+			executable: segment.executable(),
+		};
+		self.kernel.segment_count += 1;
+
+		// Usable memory is identity-mapped, so the frames can be written at their physical address
+		Some(unsafe {
+			slice::from_raw_parts_mut(phys as usize as *mut u8, segment.memory_size as usize)
+		})
+	}
+
+	fn relocate(&mut self, address: u64, value: u64) -> Option<()> {
+		let virt = self.kernel.base.checked_add(address)?;
+		let segment = self
+			.kernel
+			.segments()
+			.iter()
+			.find(|segment| segment.virt <= virt && virt - segment.virt < segment.len)?;
+
+		let phys = segment.phys + (virt - segment.virt);
+		unsafe { (phys as usize as *mut u64).write_unaligned(value) };
+		Some(())
+	}
+}
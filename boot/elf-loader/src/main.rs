@@ -1,8 +1,19 @@
 #![no_std]
 #![no_main]
 
-use common::*;
-use core::arch::{asm, global_asm};
+mod kernel;
+
+use {
+	common::{
+		boot_info::BootInfo, command_line::CommandLine, gdt::*, memory_map::RegionKind, paging::*,
+		stages::BOOT_PROGRAMS_END, *,
+	},
+	core::{
+		arch::{asm, global_asm},
+		ptr, slice,
+	},
+	kernel::{KernelSegment, LoadedKernel},
+};
 
 global_asm! {
 r#"
@@ -10,13 +21,255 @@ r#"
 .global asm_main
 
 asm_main:
+    /* The boot info pointer is already in rdi, which is `main`'s first argument */
     call main
 "#
 }
 
+/// The ELF loader's GDT. It's the same as the bootloader's, but the bootloader's GDT is in memory the ELF loader
+/// doesn't own.
+static GDT: Gdt<3> = {
+	let mut gdt = Gdt::new();
+	gdt.add(SegmentDescriptorBuilder::kernel_code64().build());
+	gdt.add(SegmentDescriptorBuilder::kernel_data().build());
+	gdt
+};
+
+/// The ELF loader's entry point. The bootloader passes the [`BootInfo`], with the kernel's file
+/// already read into memory. The ELF loader loads the kernel, then passes the boot info on to it.
 #[no_mangle]
-extern "C" fn main() -> ! {
+extern "C" fn main(boot_info: *mut BootInfo) -> ! {
+	unsafe {
+		GDT.load();
+		reload_segments(Gdt::<3>::selector(1), Gdt::<3>::selector(2));
+	}
+
 	println!("\n\nInside 64-bit ELF loader :3");
-	unsafe { asm!("hlt") }
-	unreachable!()
+	let Some(boot_info) = (unsafe { BootInfo::from_ptr_mut(boot_info) }) else {
+		panic!("The ELF loader wasn't given valid boot info");
+	};
+	// The bootloader left the memory map the way the BIOS reported it (plus what it allocated), since
+	// sorting it out takes too much code for 16-bit mode
+	boot_info.memory_map.normalize();
+	println!(
+		"Booted from drive {:#x}, with {} kib of usable memory",
+		boot_info.boot_drive,
+		boot_info.memory_map.usable_bytes() / 1024
+	);
+
+	// The bootloader marked everything it allocated in the memory map, so every usable frame is
+	// actually free
+	let mut frames = FrameAllocator::new(
+		unsafe { &mut *ptr::addr_of_mut!(FRAME_BITMAP) },
+		boot_info.memory_map.usable(),
+	);
+
+	let Some(file) = boot_info.kernel_file() else {
+		panic!("The bootloader didn't read the kernel");
+	};
+	// The bootloader identity-mapped everything it loaded
+	let file =
+		unsafe { slice::from_raw_parts(file.start as usize as *const u8, file.len as usize) };
+	let command_line = CommandLine::new(boot_info.command_line());
+	let kernel = match kernel::load(file, &command_line, &mut frames) {
+		Ok(kernel) => kernel,
+		Err(err) => panic!("Failed to load the kernel: {err:?}"),
+	};
+	println!(
+		"Loaded the kernel ({} segments) at {:#x}, entry point at {:#x}",
+		kernel.segments().len(),
+		kernel.base,
+		kernel.entry
+	);
+	// The kernel needs this to symbolize its backtraces
+	boot_info.kernel_slide = kernel.base - KERNEL_BASE;
+
+	let no_execute = cpuid::Feature::Nx.supported();
+	let page_map_level_4 = build_page_tables(boot_info, &kernel, &mut frames, no_execute);
+
+	println!(
+		"Mapped the kernel's stack at {:#x}..{:#x}, with a guard page at {:#x}",
+		KERNEL_STACK.bottom, KERNEL_STACK.top, KERNEL_STACK.guard
+	);
+
+	// That's everything the ELF loader allocates, so the kernel knows what's in use
+	boot_info.memory_map.mark_allocated(&frames);
+	boot_info.memory_map.normalize();
+
+	// These map everything the ELF loader's using, so it keeps running after the switch
+	println!("Switching to the kernel's page tables");
+	unsafe { Cr3::new(page_map_level_4).write() }
+
+	// The kernel gets its own stack, since the ELF loader's is in memory the bootloader allocated,
+	// which the kernel will eventually want back
+	println!("Jumping to the kernel");
+	unsafe { enter_kernel(boot_info, kernel.entry, KERNEL_STACK.top) }
 }
+
+/// Switches to the kernel's stack (`stack_top`, which has to be 16-byte aligned), then calls the
+/// kernel's entry point (`entry`) with the [`BootInfo`]. If the kernel returns, this halts forever.
+///
+/// # Safety
+/// `entry` has to be the kernel's entry point, and the stack has to be mapped.
+unsafe fn enter_kernel(boot_info: *mut BootInfo, entry: u64, stack_top: u64) -> ! {
+	unsafe {
+		asm!(
+			// The stack is 16-byte aligned, so it's aligned the way the kernel expects after `call`
+			"mov rsp, {stack_top}",
+			// Frame-pointer stack traces stop at a null frame pointer
+			"xor ebp, ebp",
+			"call {entry}",
+			"2:",
+			"cli",
+			"hlt",
+			"jmp 2b",
+			stack_top = in(reg) stack_top,
+			entry = in(reg) entry,
+			// The kernel's first argument is the boot info
+			in("rdi") boot_info,
+			options(noreturn)
+		)
+	}
+}
+
+/// The ELF loader only uses frames below this (64mib), so the frame bitmap stays small. That's
+/// plenty for the kernel, its stack, and its page tables.
+const BOOT_MEMORY_LIMIT: PhysAddr = 0x400_0000;
+/// The [`FrameAllocator`]'s bitmap.
+static mut FRAME_BITMAP: [u64; FrameAllocator::bitmap_len(BOOT_MEMORY_LIMIT)] =
+	[0; FrameAllocator::bitmap_len(BOOT_MEMORY_LIMIT)];
+
+/// Builds the page tables the kernel starts with, returning the physical address of the page map
+/// level 4:
+/// - Usable memory, and memory the boot programs allocated (see [`RegionKind::BootAllocated`]), is
+///   identity-mapped, up to [`IDENTITY_MAP_LIMIT`]. Everything below [`BOOT_PROGRAMS_END`] is
+///   executable, since the boot programs run from it; the rest isn't. Reserved memory isn't mapped
+///   at all, except for the VGA text buffer and the framebuffer (if there is one). Neither are the
+///   frames the kernel was loaded into, so its code doesn't have a writable alias.
+/// - The kernel's segments are mapped at their virtual addresses, with the permissions in its ELF
+///   (so its code isn't writable, and its data isn't executable).
+/// - The kernel's stack ([`KERNEL_STACK`]) is mapped to new frames, with its guard page left
+///   unmapped, so overflowing it page faults instead of corrupting whatever's below.
+///
+/// Without `no_execute` (see [`cpuid::Feature::Nx`]), everything is executable.
+///
+/// The page tables and the stack are in frames from `frames`, so they're never freed.
+fn build_page_tables(
+	boot_info: &BootInfo,
+	kernel: &LoadedKernel,
+	frames: &mut FrameAllocator,
+	no_execute: bool,
+) -> PhysAddr {
+	let Some(pml4_address) = frames.allocate_frame() else {
+		panic!("Out of memory for the page tables");
+	};
+	let pml4 = pml4_address as usize as *mut PageMap<PageMapLevel4Entry>;
+	// The bootloader's page tables identity-map every frame, so every table can be edited at its
+	// physical address
+	unsafe { pml4.write(PageMap::new()) };
+	let mut mapper = unsafe { Mapper::new(&mut *pml4, frames, 0) };
+
+	let flags = |writable: bool, executable: bool| PageFlags {
+		writable,
+		executable: executable || !no_execute,
+		..Default::default()
+	};
+	let mut result = Ok(());
+	let mut map = |virt: u64, phys: PhysAddr, len: u64, flags: PageFlags| {
+		// Ranges don't have to start on a page, but mappings do
+		let page_offset = virt % PAGE_SIZE;
+		if result.is_ok() {
+			result = mapper.map_range(
+				virt - page_offset,
+				phys - page_offset,
+				len + page_offset,
+				flags,
+			);
+		}
+	};
+
+	for region in boot_info.memory_map.entries() {
+		if !matches!(region.kind, RegionKind::Usable | RegionKind::BootAllocated) {
+			continue;
+		}
+
+		// Only whole pages are usable
+		let mut start = region.start.next_multiple_of(PAGE_SIZE);
+		let end = (region.end().min(IDENTITY_MAP_LIMIT) / PAGE_SIZE) * PAGE_SIZE;
+
+		// The kernel's frames are left out, since a writable alias of its code would undo the
+		// permissions its segments get below
+		while start < end {
+			let next_segment = kernel
+				.segments()
+				.iter()
+				.map(KernelSegment::frames)
+				.filter(|frames| frames.end > start && frames.start < end)
+				.min_by_key(|frames| frames.start);
+			let (piece_end, next_start) = match next_segment {
+				Some(frames) => (frames.start.max(start), frames.end),
+				None => (end, end),
+			};
+
+			let boot_programs_end = piece_end.min(BOOT_PROGRAMS_END);
+			if start < boot_programs_end {
+				map(start, start, boot_programs_end - start, flags(true, true));
+			}
+			let piece_start = start.max(boot_programs_end);
+			if piece_start < piece_end {
+				map(
+					piece_start,
+					piece_start,
+					piece_end - piece_start,
+					flags(true, false),
+				);
+			}
+
+			start = next_start;
+		}
+	}
+	map(
+		VGA_BUFFER.start,
+		VGA_BUFFER.start,
+		VGA_BUFFER.len,
+		flags(true, false),
+	);
+	if let Some(framebuffer) = boot_info.framebuffer() {
+		map(
+			framebuffer.address,
+			framebuffer.address,
+			(framebuffer.pitch * framebuffer.height) as u64,
+			flags(true, false),
+		);
+	}
+
+	for segment in kernel.segments() {
+		map(
+			segment.virt,
+			segment.phys,
+			segment.len,
+			flags(segment.writable, segment.executable),
+		);
+	}
+	if let Err(err) = result {
+		panic!("Failed to build the page tables: {err:?}");
+	}
+
+	let guard_pages = (KERNEL_STACK.bottom - KERNEL_STACK.guard) / PAGE_SIZE;
+	let stack_pages = KERNEL_STACK.size() / PAGE_SIZE;
+	if let Err(err) = mapper.map_guarded_stack(KERNEL_STACK.guard, guard_pages, stack_pages) {
+		panic!("Failed to map the kernel's stack: {err:?}");
+	}
+
+	pml4_address
+}
+
+/// The ELF loader only identity-maps memory below this (4gib), like the bootloader. Mapping all of
+/// a big computer's memory with 4kib pages would take too many page tables, so the kernel maps the
+/// rest itself.
+const IDENTITY_MAP_LIMIT: PhysAddr = 0x1_0000_0000;
+/// The VGA text buffer, which is reserved memory, but gets mapped so the kernel can print.
+const VGA_BUFFER: MemoryRegion = MemoryRegion {
+	start: 0xB8000,
+	len: 0x8000,
+};
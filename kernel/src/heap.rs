@@ -0,0 +1,207 @@
+//! The kernel heap, so the kernel can use `alloc` (`Box`, `Vec`, `String`, `BTreeMap`, etc).
+//!
+//! The heap is [`HEAP_SIZE`] bytes of virtual memory at [`KERNEL_HEAP_START`], backed by frames
+//! from [`memory::FRAMES`]. It's mapped all at once by [`init`]; until then, every allocation
+//! fails.
+//!
+//! It's managed by a linked-list allocator: every free block stores its size and a pointer to the
+//! next free block inside itself, so the list doesn't need any memory of its own. The list is
+//! sorted by address. Allocating takes the first block that's big enough (first fit) and gives
+//! back whatever's left over on either side of the allocation. Freeing puts the block back in
+//! order, and merges it with the blocks next to it, so the heap doesn't break up into pieces too
+//! small to use.
+//!
+//! Every block is a multiple of [`BLOCK_ALIGN`], and starts on one, so whatever's left over after
+//! an allocation is always big enough to hold a [`FreeBlock`].
+//!
+//! Resources:
+//! - https://os.phil-opp.com/allocator-designs/#linked-list-allocator
+//! - https://wiki.osdev.org/Memory_Allocation
+
+use {
+	crate::memory,
+	common::{cpuid, paging::*, sync::Spinlock},
+	core::{
+		alloc::{GlobalAlloc, Layout},
+		mem, ptr,
+	},
+};
+
+/// The size of the kernel heap (4mib).
+pub const HEAP_SIZE: u64 = 0x40_0000;
+/// Every block in the heap starts on a multiple of this, and is a multiple of this in size. It's
+/// the size of a [`FreeBlock`], so any block can go back in the free list.
+const BLOCK_ALIGN: usize = 16;
+const _: () = assert!(mem::size_of::<FreeBlock>() <= BLOCK_ALIGN);
+
+/// The kernel's allocator. See the module-level docs.
+#[global_allocator]
+static HEAP: Heap = Heap(Spinlock::new(LinkedListAllocator::new()));
+
+/// Maps the heap at [`KERNEL_HEAP_START`], then gives it to the allocator. [`memory::init`] has
+/// to be called first.
+pub fn init() -> Result<(), MapError> {
+	// Without the no-execute bit, every page is executable
+	let flags = PageFlags {
+		writable: true,
+		executable: !cpuid::Feature::Nx.supported(),
+		..Default::default()
+	};
+	let end = KERNEL_HEAP_START + HEAP_SIZE;
+	memory::with_mapper(|mapper| -> Result<(), MapError> {
+		for page in (KERNEL_HEAP_START..end).step_by(PAGE_SIZE as usize) {
+			let frame = mapper
+				.frames()
+				.allocate_frame()
+				.ok_or(MapError::OutOfFrames)?;
+			mapper.map_to(page, frame, flags)?;
+		}
+
+		Ok(())
+	})?;
+
+	// The heap was just mapped, and nothing else uses it
+	let (start, size) = (KERNEL_HEAP_START as usize, HEAP_SIZE as usize);
+	unsafe { HEAP.0.lock().add_region(start, size) };
+	Ok(())
+}
+
+/// The [`GlobalAlloc`] for the [`LinkedListAllocator`]. The lock is a [`Spinlock`], so interrupt
+/// handlers can allocate.
+struct Heap(Spinlock<LinkedListAllocator>);
+unsafe impl GlobalAlloc for Heap {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		self.0.lock().allocate(layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		unsafe { self.0.lock().deallocate(ptr, layout) }
+	}
+}
+
+/// A free block of heap memory. It's stored at the start of the block itself.
+struct FreeBlock {
+	/// The size of the whole block, in bytes, including this.
+	size: usize,
+	/// The next free block, which is always at a higher address. Null if this is the last one.
+	next: *mut FreeBlock,
+}
+
+/// A first-fit allocator with a sorted free list. See the module-level docs.
+pub struct LinkedListAllocator {
+	/// The free block with the lowest address, or null if there's no free memory.
+	head: *mut FreeBlock,
+}
+// The free blocks are only reachable through the allocator
+unsafe impl Send for LinkedListAllocator {}
+impl LinkedListAllocator {
+	/// An allocator with no memory to hand out.
+	pub const fn new() -> Self {
+		Self {
+			head: ptr::null_mut(),
+		}
+	}
+
+	/// Gives the allocator the `size` bytes at `start` to hand out. Bytes that don't fit in a whole
+	/// block are left unused.
+	///
+	/// # Safety
+	/// The memory has to be mapped and writable, and nothing else can use it afterwards.
+	pub unsafe fn add_region(&mut self, start: usize, size: usize) {
+		let aligned = start.next_multiple_of(BLOCK_ALIGN);
+		let end = start.saturating_add(size) / BLOCK_ALIGN * BLOCK_ALIGN;
+		if aligned < end {
+			unsafe { self.insert(aligned, end - aligned) };
+		}
+	}
+
+	/// Allocates memory for `layout`. Returns null if there isn't a free block big enough.
+	pub fn allocate(&mut self, layout: Layout) -> *mut u8 {
+		let (size, align) = block_layout(layout);
+
+		// The pointer to the block being looked at, so it can be unlinked
+		let mut link: *mut *mut FreeBlock = &mut self.head;
+		unsafe {
+			while let Some(block) = (*link).as_mut() {
+				let start = block as *mut FreeBlock as usize;
+				let end = start + block.size;
+				let allocation = start.next_multiple_of(align);
+				let allocation_end = allocation.saturating_add(size);
+				if allocation_end > end {
+					link = &mut block.next;
+					continue;
+				}
+
+				// Take the whole block out of the list, then give back what the allocation didn't
+				// use. Everything's a multiple of `BLOCK_ALIGN`, so the leftovers are valid blocks.
+				*link = block.next;
+				if allocation > start {
+					self.insert(start, allocation - start);
+				}
+				if end > allocation_end {
+					self.insert(allocation_end, end - allocation_end);
+				}
+
+				return allocation as *mut u8;
+			}
+		}
+
+		ptr::null_mut()
+	}
+
+	/// Frees memory from [`LinkedListAllocator::allocate`].
+	///
+	/// # Safety
+	/// `ptr` has to have been allocated by this allocator, with the same `layout`, and it can't be
+	/// used afterwards.
+	pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+		let (size, _) = block_layout(layout);
+		unsafe { self.insert(ptr as usize, size) };
+	}
+
+	/// Puts the `size` bytes at `start` in the free list, in order, merging them with the free
+	/// blocks on either side if they touch.
+	///
+	/// # Safety
+	/// The memory has to be mapped, writable, and unused. `start` and `size` have to be multiples
+	/// of [`BLOCK_ALIGN`].
+	unsafe fn insert(&mut self, start: usize, size: usize) {
+		let mut previous: *mut FreeBlock = ptr::null_mut();
+		let mut next = self.head;
+		while !next.is_null() && (next as usize) < start {
+			previous = next;
+			next = unsafe { (*next).next };
+		}
+
+		let block = start as *mut FreeBlock;
+		unsafe {
+			block.write(FreeBlock { size, next });
+
+			if !next.is_null() && start + size == next as usize {
+				(*block).size += (*next).size;
+				(*block).next = (*next).next;
+			}
+
+			if previous.is_null() {
+				self.head = block;
+			} else if previous as usize + (*previous).size == start {
+				(*previous).size += (*block).size;
+				(*previous).next = (*block).next;
+			} else {
+				(*previous).next = block;
+			}
+		}
+	}
+}
+impl Default for LinkedListAllocator {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// The size and alignment of the block for `layout`: both are rounded up to [`BLOCK_ALIGN`].
+fn block_layout(layout: Layout) -> (usize, usize) {
+	let size = layout.size().max(1).next_multiple_of(BLOCK_ALIGN);
+	let align = layout.align().max(BLOCK_ALIGN);
+	(size, align)
+}
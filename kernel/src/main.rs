@@ -1,11 +1,67 @@
 #![no_std]
 #![no_main]
 
-use common::*;
+extern crate alloc;
 
+mod heap;
+mod memory;
+
+use {
+	alloc::vec::Vec,
+	common::{
+		boot_info::BootInfo,
+		command_line::CommandLine,
+		paging::{KERNEL_HEAP_START, KERNEL_STACK},
+		*,
+	},
+};
+
+/// The kernel's entry point. The ELF loader passes the [`BootInfo`] the boot programs filled in.
 #[no_mangle]
-extern "C" fn main() {
+extern "C" fn main(boot_info: *const BootInfo) {
 	// Kernel just has a hello world for now; when I see this message I'll know
 	// Frieren is working her magic.
 	println!("HALLO FROM KERNEL");
+
+	let Some(boot_info) = (unsafe { BootInfo::from_ptr(boot_info) }) else {
+		println!("No boot info :(");
+		return;
+	};
+	// The ELF loader left a guard page below the stack, so overflowing it gets reported as one
+	interrupts::register_stack("kernel", KERNEL_STACK);
+	println!(
+		"{} kib of usable memory, command line: {:?}",
+		boot_info.memory_map.usable_bytes() / 1024,
+		boot_info.command_line()
+	);
+
+	// Everything after this can allocate
+	memory::init(boot_info);
+	if let Err(err) = heap::init() {
+		panic!("Failed to map the kernel heap: {err:?}");
+	}
+
+	let command_line = CommandLine::new(boot_info.command_line());
+	if command_line.flag("debug") {
+		println!("Kernel loaded at {:#x}", boot_info.kernel_base());
+		println!(
+			"Kernel stack at {:#x}..{:#x}",
+			KERNEL_STACK.bottom, KERNEL_STACK.top
+		);
+		println!(
+			"Kernel heap at {:#x} ({} kib)",
+			KERNEL_HEAP_START,
+			heap::HEAP_SIZE / 1024
+		);
+		let options: Vec<_> = command_line.options().collect();
+		println!("Command line options: {options:?}");
+		for entry in boot_info.memory_map.entries() {
+			println!(
+				"    {:#x}..{:#x}: {:?}",
+				entry.start,
+				entry.end(),
+				entry.kind
+			);
+		}
+	}
 }
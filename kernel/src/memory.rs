@@ -0,0 +1,48 @@
+//! The kernel's physical memory: a frame allocator over the memory the boot programs left free,
+//! and a [`Mapper`] for the page tables the ELF loader built.
+//!
+//! The boot programs mark everything they allocated in the memory map (see
+//! `RegionKind::BootAllocated`), so every usable region really is free. The ELF loader
+//! identity-maps usable memory below 4gib, so frames (and new page tables) can be edited at their
+//! physical addresses - which is why the frame allocator stops at [`FRAME_LIMIT`], for now.
+
+use {
+	common::{boot_info::BootInfo, paging::*, sync::*},
+	core::ptr,
+};
+
+/// The kernel's frame allocator. It's set up by [`init`].
+pub static FRAMES: Once<Spinlock<FrameAllocator<'static>>> = Once::new();
+
+/// The frame allocator only hands out frames below this (4gib), since that's all the ELF loader
+/// identity-mapped.
+const FRAME_LIMIT: PhysAddr = 0x1_0000_0000;
+/// [`FRAMES`]' bitmap.
+static mut FRAME_BITMAP: [u64; FrameAllocator::bitmap_len(FRAME_LIMIT)] =
+	[0; FrameAllocator::bitmap_len(FRAME_LIMIT)];
+
+/// Sets up [`FRAMES`] from the memory map in `boot_info`. Only the first call does anything.
+pub fn init(boot_info: &BootInfo) -> &'static Spinlock<FrameAllocator<'static>> {
+	FRAMES.call_once(|| {
+		// `call_once` only runs this once, so nothing else has the bitmap
+		let bitmap = unsafe { &mut *ptr::addr_of_mut!(FRAME_BITMAP) };
+		Spinlock::new(FrameAllocator::new(bitmap, boot_info.memory_map.usable()))
+	})
+}
+
+/// Runs `f` with a [`Mapper`] for the current page tables, which gets new page tables from
+/// [`FRAMES`]. The frame allocator stays locked until `f` returns.
+///
+/// Panics if [`init`] hasn't been called yet.
+pub fn with_mapper<T>(f: impl FnOnce(&mut Mapper) -> T) -> T {
+	let Some(frames) = FRAMES.get() else {
+		panic!("The frame allocator hasn't been set up yet");
+	};
+	let mut frames = frames.lock();
+
+	let pml4 = Cr3::read().address() as usize as *mut PageMap<PageMapLevel4Entry>;
+	// Page tables and frames are identity-mapped (see the module-level docs), and the frame
+	// allocator's lock keeps anything else from using a mapper at the same time
+	let mut mapper = unsafe { Mapper::new(&mut *pml4, &mut frames, 0) };
+	f(&mut mapper)
+}
@@ -0,0 +1,228 @@
+//! A (very) minimal parser for AML, the ACPI Machine Language. The DSDT and SSDTs don't store
+//! data in a fixed layout like the other tables; instead, they store AML bytecode, which the OS
+//! is supposed to run in an interpreter to build up a namespace of objects describing the system.
+//!
+//! A full AML interpreter is a huge project. Fortunately, some important objects are just plain
+//! data that can be found without running any code. The main one BS needs is `\_S5`, a package
+//! holding the values that have to be written to the PM1 control registers to shut down the
+//! computer. This module can find named objects like that one and read integers out of packages.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/AML
+//! - https://forum.osdev.org/viewtopic.php?t=16990 (the classic "shutdown without an AML interpreter" post)
+//! - https://uefi.org/htmlspecs/ACPI_Spec_6_4_html/20_AML_Specification/AML_Specification.html
+
+use {
+	crate::rsdt::*,
+	core::{mem, slice},
+};
+
+/// A DSDT (Differentiated System Description Table) or SSDT (Secondary System Description Table).
+/// Both are a [`SystemDescriptor`] followed by AML bytecode. The DSDT is pointed to by the FADT,
+/// while SSDTs are pointed to by the RSDT/XSDT.
+pub struct DefinitionBlock<'a> {
+	pub descriptor: &'a SystemDescriptor,
+	/// The AML bytecode in this table.
+	pub aml: &'a [u8],
+}
+impl<'a> DefinitionBlock<'a> {
+	/// What the DSDT's [`SystemDescriptor::signature`] should be set to.
+	pub const DSDT_SIGNATURE: [u8; 4] = *b"DSDT";
+	/// What an SSDT's [`SystemDescriptor::signature`] should be set to.
+	pub const SSDT_SIGNATURE: [u8; 4] = *b"SSDT";
+
+	/// Takes a possible pointer to a DSDT or SSDT and ensures it's a valid [`DefinitionBlock`].
+	///
+	/// # Safety
+	/// - `ptr` must be a non-null, aligned pointer
+	/// - `ptr` must live for at least `'a`
+	pub unsafe fn try_from_raw(
+		ptr: *const SystemDescriptor,
+	) -> Result<Self, SystemDescriptorError> {
+		let descriptor = SystemDescriptor::try_from_raw(ptr)?;
+
+		if descriptor.signature != Self::DSDT_SIGNATURE
+			&& descriptor.signature != Self::SSDT_SIGNATURE
+		{
+			return Err(SystemDescriptorError::Signature {
+				expected: Self::DSDT_SIGNATURE,
+				found: descriptor.signature,
+			});
+		}
+
+		let aml = unsafe {
+			slice::from_raw_parts(
+				(ptr as usize + mem::size_of::<SystemDescriptor>()) as *const u8,
+				descriptor.len as usize - mem::size_of::<SystemDescriptor>(),
+			)
+		};
+
+		Ok(Self { descriptor, aml })
+	}
+
+	/// Finds a named object (`Name(XXXX, ...)` in ASL) and returns the bytecode of its value.
+	/// `name` is the object's 4-character name; names shorter than 4 characters are padded with
+	/// underscores, so `\_S5` is `_S5_`.
+	///
+	/// This doesn't actually interpret the AML - it looks for the bytes of a `NameOp` followed by
+	/// `name`. That's enough to find objects at a fixed, well-known path, like `\_S5`.
+	pub fn find_name(&self, name: [u8; 4]) -> Option<&'a [u8]> {
+		let aml = self.aml;
+
+		for (idx, window) in aml.windows(4).enumerate() {
+			if window != name {
+				continue;
+			}
+
+			// The name may have a root prefix (`\`) between it and the `NameOp`
+			let before = &aml[..idx];
+			let is_named_object = matches!(
+				before,
+				[.., opcode::NAME, opcode::ROOT_PREFIX] | [.., opcode::NAME]
+			);
+			if is_named_object {
+				return Some(&aml[idx + 4..]);
+			}
+		}
+
+		None
+	}
+}
+
+/// The AML opcodes this parser understands.
+mod opcode {
+	pub const ZERO: u8 = 0x00;
+	pub const ONE: u8 = 0x01;
+	pub const NAME: u8 = 0x08;
+	pub const BYTE_PREFIX: u8 = 0x0A;
+	pub const WORD_PREFIX: u8 = 0x0B;
+	pub const DWORD_PREFIX: u8 = 0x0C;
+	pub const QWORD_PREFIX: u8 = 0x0E;
+	pub const PACKAGE: u8 = 0x12;
+	pub const ROOT_PREFIX: u8 = b'\\';
+	pub const ONES: u8 = 0xFF;
+}
+
+/// Parses an AML integer (`ZeroOp`, `OneOp`, `OnesOp`, or a byte/word/dword/qword constant) from
+/// the start of `aml`. Returns the integer and the number of bytes it took up.
+pub fn parse_integer(aml: &[u8]) -> Option<(u64, usize)> {
+	/// Reads a `N`-byte little-endian integer after the prefix byte.
+	fn constant<const N: usize>(aml: &[u8]) -> Option<(u64, usize)> {
+		let bytes = aml.get(1..1 + N)?;
+		let mut value = [0; 8];
+		value[..N].copy_from_slice(bytes);
+		Some((u64::from_le_bytes(value), 1 + N))
+	}
+
+	match *aml.first()? {
+		opcode::ZERO => Some((0, 1)),
+		opcode::ONE => Some((1, 1)),
+		opcode::ONES => Some((u64::MAX, 1)),
+		opcode::BYTE_PREFIX => constant::<1>(aml),
+		opcode::WORD_PREFIX => constant::<2>(aml),
+		opcode::DWORD_PREFIX => constant::<4>(aml),
+		opcode::QWORD_PREFIX => constant::<8>(aml),
+		_ => None,
+	}
+}
+
+/// Parses a `PkgLength` from the start of `aml`. Returns the length and the number of bytes the
+/// `PkgLength` itself took up. Note that the length includes the `PkgLength` bytes.
+///
+/// The top two bits of the first byte are how many bytes follow it. If no bytes follow, the
+/// length is the bottom 6 bits of the first byte. Otherwise, the length is the bottom 4 bits of
+/// the first byte, followed by the other bytes.
+pub fn parse_pkg_length(aml: &[u8]) -> Option<(usize, usize)> {
+	let lead = *aml.first()?;
+	let following = (lead >> 6) as usize;
+
+	if following == 0 {
+		return Some(((lead & 0b0011_1111) as usize, 1));
+	}
+
+	let mut len = (lead & 0b0000_1111) as usize;
+	for (idx, byte) in aml.get(1..1 + following)?.iter().enumerate() {
+		len |= (*byte as usize) << (4 + 8 * idx);
+	}
+
+	Some((len, 1 + following))
+}
+
+/// An AML package (`Package() {...}` in ASL) - essentially, an array.
+pub struct Package<'a> {
+	/// How many elements the package has.
+	pub num_elements: u8,
+	/// The bytecode of the elements.
+	elements: &'a [u8],
+}
+impl<'a> Package<'a> {
+	/// Parses a package from the start of `aml`.
+	pub fn parse(aml: &'a [u8]) -> Option<Self> {
+		if *aml.first()? != opcode::PACKAGE {
+			return None;
+		}
+
+		let (len, len_size) = parse_pkg_length(&aml[1..])?;
+		// The length covers everything after the `PackageOp`, including the `PkgLength` itself
+		let body = aml.get(1..1 + len)?;
+		let num_elements = *body.get(len_size)?;
+		let elements = &body[len_size + 1..];
+
+		Some(Self {
+			num_elements,
+			elements,
+		})
+	}
+
+	/// Iterates over the package's elements, parsing each one as an integer. Stops at the first
+	/// element that isn't an integer, since this parser can't tell how long other objects are.
+	pub fn integers(&self) -> impl Iterator<Item = u64> + 'a {
+		let mut elements = self.elements;
+		let mut remaining = self.num_elements;
+
+		core::iter::from_fn(move || {
+			if remaining == 0 {
+				return None;
+			}
+
+			let (value, size) = parse_integer(elements)?;
+			elements = &elements[size..];
+			remaining -= 1;
+			Some(value)
+		})
+	}
+}
+
+/// The values to write to the `SLP_TYP` field of the PM1a and PM1b control registers to enter a
+/// sleep state.
+#[derive(Clone, Copy, Debug)]
+pub struct SleepType {
+	/// The `SLP_TYP` value for the PM1a control register.
+	pub a: u16,
+	/// The `SLP_TYP` value for the PM1b control register.
+	pub b: u16,
+}
+impl SleepType {
+	/// Finds the sleep type for the S5 (soft off) state, by reading the `\_S5` package from the
+	/// DSDT and SSDTs. Returns `None` if none of them define it.
+	pub fn s5<'a>(tables: impl IntoIterator<Item = &'a DefinitionBlock<'a>>) -> Option<Self> {
+		Self::find(tables, *b"_S5_")
+	}
+
+	/// Finds the sleep type for a sleep state, by reading its package (eg `\_S3` or `\_S5`) from
+	/// the DSDT and SSDTs.
+	pub fn find<'a>(
+		tables: impl IntoIterator<Item = &'a DefinitionBlock<'a>>,
+		name: [u8; 4],
+	) -> Option<Self> {
+		tables.into_iter().find_map(|table| {
+			let package = Package::parse(table.find_name(name)?)?;
+			let mut values = package.integers();
+			let a = values.next()? as u16;
+			// Some firmware only puts one value in the package
+			let b = values.next().unwrap_or(0) as u16;
+
+			Some(Self { a, b })
+		})
+	}
+}
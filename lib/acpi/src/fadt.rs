@@ -0,0 +1,243 @@
+//! Defines the Fixed ACPI Description Table (FADT). Its signature is `FACP`, which is a bit
+//! confusing, but the table is always called the FADT. The FADT stores the locations of ACPI's
+//! fixed hardware registers - the ones used for things like shutting down or rebooting the
+//! computer - and points to the DSDT, which holds the AML code describing the rest of the system.
+//!
+//! The FADT has grown with every ACPI version, and firmware is allowed to give us an older,
+//! shorter version of it. Fields that were added in later versions are accessed through methods
+//! that check the table's length first, so we never read past the end of the table.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/FADT
+//! - https://uefi.org/htmlspecs/ACPI_Spec_6_4_html/05_ACPI_Software_Programming_Model/ACPI_Software_Programming_Model.html#fixed-acpi-description-table-fadt
+
+use {
+	crate::{generic_address::GenericAddress, rsdt::*},
+	core::mem::{self, offset_of},
+};
+
+/// The Fixed ACPI Description Table. See the module-level docs.
+#[repr(C, packed)]
+pub struct Fadt {
+	pub descriptor: SystemDescriptor,
+	/// The 32-bit physical address of the FACS (Firmware ACPI Control Structure).
+	pub firmware_ctrl: u32,
+	/// The 32-bit physical address of the DSDT. Use [`Fadt::dsdt_address`] instead of reading this
+	/// directly, since ACPI 2+ systems may store a 64-bit address elsewhere.
+	pub dsdt: u32,
+	/// Only used in ACPI 1; reserved afterwards.
+	pub reserved: u8,
+	/// What kind of machine the OEM thinks this is (desktop, laptop, server, etc).
+	pub preferred_power_management_profile: u8,
+	/// The legacy (8259 PIC) interrupt the SCI, or System Control Interrupt, is wired to. ACPI
+	/// uses the SCI to notify the OS about events like the power button being pressed.
+	pub sci_interrupt: u16,
+	/// The I/O port of the System Management Mode command port. Writing [`Fadt::acpi_enable`] to
+	/// this port switches the system from legacy mode into ACPI mode.
+	pub smi_command_port: u32,
+	/// The value to write to [`Fadt::smi_command_port`] to enable ACPI mode.
+	pub acpi_enable: u8,
+	/// The value to write to [`Fadt::smi_command_port`] to disable ACPI mode.
+	pub acpi_disable: u8,
+	pub s4bios_req: u8,
+	pub pstate_control: u8,
+	/// I/O port of the PM1a event register block.
+	pub pm1a_event_block: u32,
+	/// I/O port of the PM1b event register block. 0 if not supported.
+	pub pm1b_event_block: u32,
+	/// I/O port of the PM1a control register block. This is the register used for shutting down.
+	pub pm1a_control_block: u32,
+	/// I/O port of the PM1b control register block. 0 if not supported.
+	pub pm1b_control_block: u32,
+	pub pm2_control_block: u32,
+	pub pm_timer_block: u32,
+	pub gpe0_block: u32,
+	pub gpe1_block: u32,
+	pub pm1_event_length: u8,
+	/// The size of the PM1 control blocks, in bytes.
+	pub pm1_control_length: u8,
+	pub pm2_control_length: u8,
+	pub pm_timer_length: u8,
+	pub gpe0_length: u8,
+	pub gpe1_length: u8,
+	pub gpe1_base: u8,
+	pub c_state_control: u8,
+	pub worst_c2_latency: u16,
+	pub worst_c3_latency: u16,
+	pub flush_size: u16,
+	pub flush_stride: u16,
+	pub duty_offset: u8,
+	pub duty_width: u8,
+	/// The index of the RTC's day-of-month alarm in CMOS memory. 0 if not supported.
+	pub day_alarm: u8,
+	/// The index of the RTC's month alarm in CMOS memory. 0 if not supported.
+	pub month_alarm: u8,
+	/// The index of the RTC's century register in CMOS memory. Use [`Fadt::century()`] instead of
+	/// reading this directly.
+	pub century: u8,
+	/// Flags describing legacy hardware on PCs (ACPI 2+). See [`Fadt::BOOT_ARCH_8042`] and friends.
+	pub boot_architecture_flags: u16,
+	pub reserved2: u8,
+	/// Fixed feature flags. See [`Fadt::FLAG_RESET_REG_SUP`] and friends.
+	pub flags: u32,
+	/// The register to write [`Fadt::reset_value`] to to reset the computer (ACPI 2+). Use
+	/// [`Fadt::reset_register`] instead of reading this directly.
+	pub reset_register: GenericAddress,
+	/// The value to write to [`Fadt::reset_register`] to reset the computer.
+	pub reset_value: u8,
+	pub arm_boot_architecture_flags: u16,
+	pub minor_version: u8,
+	/// The 64-bit physical address of the FACS (ACPI 2+).
+	pub x_firmware_ctrl: u64,
+	/// The 64-bit physical address of the DSDT (ACPI 2+).
+	pub x_dsdt: u64,
+	pub x_pm1a_event_block: GenericAddress,
+	pub x_pm1b_event_block: GenericAddress,
+	pub x_pm1a_control_block: GenericAddress,
+	pub x_pm1b_control_block: GenericAddress,
+	pub x_pm2_control_block: GenericAddress,
+	pub x_pm_timer_block: GenericAddress,
+	pub x_gpe0_block: GenericAddress,
+	pub x_gpe1_block: GenericAddress,
+	pub sleep_control_register: GenericAddress,
+	pub sleep_status_register: GenericAddress,
+	pub hypervisor_vendor_id: u64,
+}
+impl Fadt {
+	/// What the FADT's [`SystemDescriptor::signature`] should be set to.
+	pub const SIGNATURE: [u8; 4] = *b"FACP";
+	/// The size of the original ACPI 1 FADT. Every FADT should be at least this long.
+	pub const MIN_LEN: usize = offset_of!(Fadt, reset_register);
+
+	/// Set in [`Fadt::flags`] if [`Fadt::reset_register`] is supported.
+	pub const FLAG_RESET_REG_SUP: u32 = 1 << 10;
+	/// Set in [`Fadt::flags`] if the system is hardware-reduced, meaning it has none of the fixed
+	/// hardware registers (including the PM1 control blocks).
+	pub const FLAG_HW_REDUCED_ACPI: u32 = 1 << 20;
+
+	/// Set in [`Fadt::boot_architecture_flags`] if the system has legacy devices (eg a parallel
+	/// port).
+	pub const BOOT_ARCH_LEGACY_DEVICES: u16 = 1 << 0;
+	/// Set in [`Fadt::boot_architecture_flags`] if the system has an 8042 PS/2 controller.
+	pub const BOOT_ARCH_8042: u16 = 1 << 1;
+	/// Set in [`Fadt::boot_architecture_flags`] if the system does *not* have a VGA controller.
+	pub const BOOT_ARCH_NO_VGA: u16 = 1 << 2;
+	/// Set in [`Fadt::boot_architecture_flags`] if the CMOS RTC is *not* present.
+	pub const BOOT_ARCH_NO_CMOS_RTC: u16 = 1 << 5;
+
+	/// Takes a possible pointer to a FADT and ensures it's a valid [`Fadt`].
+	///
+	/// # Safety
+	/// - `ptr` must be a non-null, aligned pointer
+	/// - `ptr` must live for at least `'a`
+	pub unsafe fn try_from_raw<'a>(ptr: *const Self) -> Result<&'a Self, SystemDescriptorError> {
+		let descriptor = SystemDescriptor::try_from_raw(ptr.cast())?;
+
+		if descriptor.signature != Self::SIGNATURE {
+			return Err(SystemDescriptorError::Signature {
+				expected: Self::SIGNATURE,
+				found: descriptor.signature,
+			});
+		}
+		if (descriptor.len as usize) < Self::MIN_LEN {
+			return Err(SystemDescriptorError::Length {
+				expected: Self::MIN_LEN,
+				found: descriptor.len,
+			});
+		}
+
+		Ok(unsafe { &*ptr })
+	}
+
+	/// If this FADT is long enough to contain a field ending at `end`. Fields added in newer
+	/// ACPI versions may not be present on older systems.
+	fn has(&self, end: usize) -> bool {
+		self.descriptor.len as usize >= end
+	}
+
+	/// The physical address of the DSDT. Prefers the 64-bit address if it's present.
+	pub fn dsdt_address(&self) -> u64 {
+		if self.has(offset_of!(Fadt, x_dsdt) + mem::size_of::<u64>()) && self.x_dsdt != 0 {
+			self.x_dsdt
+		} else {
+			self.dsdt as u64
+		}
+	}
+
+	/// The PM1a control register block. Prefers the extended register if it's present. Returns
+	/// `None` on hardware-reduced systems, which don't have one.
+	pub fn pm1a_control_block(&self) -> Option<GenericAddress> {
+		let extended = if self
+			.has(offset_of!(Fadt, x_pm1a_control_block) + mem::size_of::<GenericAddress>())
+		{
+			Some(self.x_pm1a_control_block)
+		} else {
+			None
+		};
+		self.pm1_control_block(extended, self.pm1a_control_block)
+	}
+	/// The PM1b control register block. Prefers the extended register if it's present. Returns
+	/// `None` if the system doesn't have one (most systems only have PM1a).
+	pub fn pm1b_control_block(&self) -> Option<GenericAddress> {
+		let extended = if self
+			.has(offset_of!(Fadt, x_pm1b_control_block) + mem::size_of::<GenericAddress>())
+		{
+			Some(self.x_pm1b_control_block)
+		} else {
+			None
+		};
+		self.pm1_control_block(extended, self.pm1b_control_block)
+	}
+	fn pm1_control_block(
+		&self,
+		extended: Option<GenericAddress>,
+		legacy: u32,
+	) -> Option<GenericAddress> {
+		if let Some(extended) = extended.filter(|extended| !extended.is_null()) {
+			Some(extended)
+		} else if legacy != 0 {
+			// The length is in bytes, and firmware can report one too big to fit in bits
+			let bit_width = (self.pm1_control_length as u16 * 8).min(u8::MAX as u16) as u8;
+			Some(GenericAddress::io_port(legacy as u16, bit_width))
+		} else {
+			None
+		}
+	}
+
+	/// The CMOS index of the RTC's century register, or `None` if the RTC doesn't have one.
+	pub fn century(&self) -> Option<u8> {
+		match self.century {
+			0 => None,
+			idx => Some(idx),
+		}
+	}
+
+	/// The register and value to write to it to reset the computer, or `None` if the FADT is too
+	/// old or the firmware doesn't support the reset register.
+	pub fn reset_register(&self) -> Option<(GenericAddress, u8)> {
+		if !self.has(offset_of!(Fadt, reset_value) + mem::size_of::<u8>())
+			|| self.flags & Self::FLAG_RESET_REG_SUP == 0
+			|| self.reset_register.is_null()
+		{
+			return None;
+		}
+
+		Some((self.reset_register, self.reset_value))
+	}
+
+	/// The legacy-PC boot architecture flags, or `None` if the FADT predates them (ACPI 1).
+	pub fn boot_architecture_flags(&self) -> Option<u16> {
+		if self.descriptor.revision < 2 {
+			return None;
+		}
+
+		Some(self.boot_architecture_flags)
+	}
+}
+impl<'a> AcpiTable<'a> for &'a Fadt {
+	const SIGNATURE: [u8; 4] = Fadt::SIGNATURE;
+
+	unsafe fn try_from_raw(ptr: *const SystemDescriptor) -> Result<Self, SystemDescriptorError> {
+		unsafe { Fadt::try_from_raw(ptr.cast()) }
+	}
+}
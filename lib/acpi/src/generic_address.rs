@@ -0,0 +1,166 @@
+//! Defines the Generic Address Structure (GAS). ACPI uses this structure whenever it needs to
+//! point to a register, since registers can live in several different address spaces (regular
+//! memory, CPU I/O ports, PCI configuration space, etc).
+//!
+//! Resources:
+//! - https://uefi.org/htmlspecs/ACPI_Spec_6_4_html/05_ACPI_Software_Programming_Model/ACPI_Software_Programming_Model.html#generic-address-structure-gas
+
+use core::ptr;
+
+/// Describes the location of a register. See the module-level docs.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+pub struct GenericAddress {
+	/// The address space the register is in. See [`AddressSpace`].
+	pub address_space: u8,
+	/// The size of the register, in bits.
+	pub bit_width: u8,
+	/// The bit offset of the register at the given address.
+	pub bit_offset: u8,
+	/// The size of each access to the register. 0 is undefined (for legacy reasons), 1 is a byte,
+	/// 2 is a word, 3 is a dword, and 4 is a qword.
+	pub access_size: u8,
+	/// The address of the register in its address space.
+	pub address: u64,
+}
+impl GenericAddress {
+	/// Creates a [`GenericAddress`] for a register in the CPU's I/O port address space. Older ACPI
+	/// tables store registers as plain port numbers; this lets them be used like newer registers.
+	pub const fn io_port(port: u16, bit_width: u8) -> Self {
+		Self {
+			address_space: AddressSpace::SystemIo as u8,
+			bit_width,
+			bit_offset: 0,
+			access_size: 0,
+			address: port as u64,
+		}
+	}
+
+	/// Attempts to identify this register's address space. Returns `None` for reserved and
+	/// OEM-defined address spaces.
+	pub fn address_space(&self) -> Option<AddressSpace> {
+		self.address_space.try_into().ok()
+	}
+
+	/// ACPI uses an address of 0 to mark a register as not present.
+	pub fn is_null(&self) -> bool {
+		self.address == 0
+	}
+
+	/// The size of each access to the register, in bytes. Falls back to the register's size when
+	/// the access size is undefined.
+	fn access_bytes(&self) -> u8 {
+		match self.access_size {
+			1..=4 => 1 << (self.access_size - 1),
+			_ => self.bit_width.div_ceil(8).next_power_of_two().clamp(1, 8),
+		}
+	}
+
+	/// Reads the register. Returns `None` if the register is in an address space BS doesn't
+	/// support yet. Only system memory and system I/O registers are supported for now.
+	///
+	/// # Safety
+	/// Reading hardware registers can have side effects. For registers in system memory, the
+	/// address must be mapped.
+	pub unsafe fn read(&self) -> Option<u64> {
+		let value = match (self.address_space()?, self.access_bytes()) {
+			(AddressSpace::SystemMemory, bytes) => unsafe {
+				let ptr = self.address as usize;
+				match bytes {
+					1 => ptr::read_volatile(ptr as *const u8) as u64,
+					2 => ptr::read_volatile(ptr as *const u16) as u64,
+					4 => ptr::read_volatile(ptr as *const u32) as u64,
+					_ => ptr::read_volatile(ptr as *const u64),
+				}
+			},
+			(AddressSpace::SystemIo, bytes) => unsafe {
+				let port = self.address as u16;
+				match bytes {
+					1 => portio::read_u8(port) as u64,
+					2 => portio::read_u16(port) as u64,
+					// There's no 64-bit port I/O
+					_ => portio::read_u32(port) as u64,
+				}
+			},
+			_ => return None,
+		};
+
+		Some(value >> self.bit_offset)
+	}
+
+	/// Writes to the register. Returns `None` if the register is in an address space BS doesn't
+	/// support yet. Only system memory and system I/O registers are supported for now.
+	///
+	/// # Safety
+	/// Writing to hardware registers can have all sorts of side effects (that's kinda the point).
+	/// For registers in system memory, the address must be mapped.
+	pub unsafe fn write(&self, value: u64) -> Option<()> {
+		let value = value << self.bit_offset;
+
+		match (self.address_space()?, self.access_bytes()) {
+			(AddressSpace::SystemMemory, bytes) => unsafe {
+				let ptr = self.address as usize;
+				match bytes {
+					1 => ptr::write_volatile(ptr as *mut u8, value as u8),
+					2 => ptr::write_volatile(ptr as *mut u16, value as u16),
+					4 => ptr::write_volatile(ptr as *mut u32, value as u32),
+					_ => ptr::write_volatile(ptr as *mut u64, value),
+				}
+			},
+			(AddressSpace::SystemIo, bytes) => unsafe {
+				let port = self.address as u16;
+				match bytes {
+					1 => portio::write_u8(port, value as u8),
+					2 => portio::write_u16(port, value as u16),
+					_ => portio::write_u32(port, value as u32),
+				}
+			},
+			_ => return None,
+		}
+
+		Some(())
+	}
+}
+
+/// The address spaces a [`GenericAddress`] can point into.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressSpace {
+	/// Regular memory.
+	SystemMemory = 0,
+	/// CPU I/O ports.
+	SystemIo = 1,
+	/// PCI configuration space.
+	PciConfiguration = 2,
+	EmbeddedController = 3,
+	SmBus = 4,
+	SystemCmos = 5,
+	PciBarTarget = 6,
+	Ipmi = 7,
+	GeneralPurposeIo = 8,
+	GenericSerialBus = 9,
+	PlatformCommunicationsChannel = 0x0A,
+	/// Registers that are specific to the CPU, like MSRs.
+	FunctionalFixedHardware = 0x7F,
+}
+impl TryFrom<u8> for AddressSpace {
+	type Error = ();
+
+	fn try_from(value: u8) -> Result<Self, Self::Error> {
+		Ok(match value {
+			0 => Self::SystemMemory,
+			1 => Self::SystemIo,
+			2 => Self::PciConfiguration,
+			3 => Self::EmbeddedController,
+			4 => Self::SmBus,
+			5 => Self::SystemCmos,
+			6 => Self::PciBarTarget,
+			7 => Self::Ipmi,
+			8 => Self::GeneralPurposeIo,
+			9 => Self::GenericSerialBus,
+			0x0A => Self::PlatformCommunicationsChannel,
+			0x7F => Self::FunctionalFixedHardware,
+			_ => return Err(()),
+		})
+	}
+}
@@ -0,0 +1,83 @@
+//! Defines the HPET description table. The HPET, or High Precision Event Timer, is a timer
+//! that's much more precise than the legacy PIT. This table just says where the HPET's registers
+//! are in memory and gives some basic information about it; actually using the timer is done
+//! through those registers.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/HPET
+//! - https://www.intel.com/content/dam/www/public/us/en/documents/technical-specifications/software-developers-hpet-spec-1-0a.pdf
+
+use crate::{generic_address::GenericAddress, rsdt::*};
+
+/// The HPET description table. See the module-level docs.
+#[repr(C, packed)]
+pub struct Hpet {
+	pub descriptor: SystemDescriptor,
+	/// Copied from the HPET's capabilities register. Use the methods on [`Hpet`] to read this.
+	pub event_timer_block_id: u32,
+	/// Where the HPET's registers are. This should always be in system memory.
+	pub base_address: GenericAddress,
+	/// Which HPET this table describes, when the system has multiple.
+	pub hpet_number: u8,
+	/// The minimum number of clock ticks that can be set in periodic mode without losing
+	/// interrupts.
+	pub minimum_tick: u16,
+	/// The page protection the HPET's register block has, and any OEM attributes.
+	pub page_protection: u8,
+}
+impl Hpet {
+	/// What the HPET table's [`SystemDescriptor::signature`] should be set to.
+	pub const SIGNATURE: [u8; 4] = *b"HPET";
+
+	/// Takes a possible pointer to an HPET table and ensures it's a valid [`Hpet`].
+	///
+	/// # Safety
+	/// - `ptr` must be a non-null, aligned pointer
+	/// - `ptr` must live for at least `'a`
+	pub unsafe fn try_from_raw<'a>(ptr: *const Self) -> Result<&'a Self, SystemDescriptorError> {
+		let descriptor = SystemDescriptor::try_from_raw(ptr.cast())?;
+
+		if descriptor.signature != Self::SIGNATURE {
+			return Err(SystemDescriptorError::Signature {
+				expected: Self::SIGNATURE,
+				found: descriptor.signature,
+			});
+		}
+		if (descriptor.len as usize) < core::mem::size_of::<Self>() {
+			return Err(SystemDescriptorError::Length {
+				expected: core::mem::size_of::<Self>(),
+				found: descriptor.len,
+			});
+		}
+
+		Ok(unsafe { &*ptr })
+	}
+
+	/// The hardware revision of the HPET.
+	pub fn hardware_revision(&self) -> u8 {
+		self.event_timer_block_id as u8
+	}
+	/// How many comparators (individual timers) the HPET has.
+	pub fn comparators(&self) -> u8 {
+		(((self.event_timer_block_id >> 8) & 0b1_1111) + 1) as u8
+	}
+	/// If the HPET's main counter is 64 bits wide. If false, it's 32 bits wide.
+	pub fn counter_is_64_bit(&self) -> bool {
+		self.event_timer_block_id & (1 << 13) != 0
+	}
+	/// If the HPET can replace the PIT and RTC interrupts (IRQ 0 and IRQ 8).
+	pub fn legacy_replacement_capable(&self) -> bool {
+		self.event_timer_block_id & (1 << 15) != 0
+	}
+	/// The PCI vendor ID of the HPET's manufacturer.
+	pub fn pci_vendor_id(&self) -> u16 {
+		(self.event_timer_block_id >> 16) as u16
+	}
+}
+impl<'a> AcpiTable<'a> for &'a Hpet {
+	const SIGNATURE: [u8; 4] = Hpet::SIGNATURE;
+
+	unsafe fn try_from_raw(ptr: *const SystemDescriptor) -> Result<Self, SystemDescriptorError> {
+		unsafe { Hpet::try_from_raw(ptr.cast()) }
+	}
+}
@@ -1,4 +1,12 @@
 #![no_std]
 
+pub mod aml;
+pub mod fadt;
+pub mod generic_address;
+pub mod hpet;
+pub mod madt;
+pub mod mcfg;
+pub mod power;
 pub mod rsdp;
 pub mod rsdt;
+pub mod srat;
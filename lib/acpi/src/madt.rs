@@ -0,0 +1,261 @@
+//! Defines the MADT, or Multiple APIC Description Table. Its signature is `APIC`. The MADT lists
+//! the interrupt controllers in the system: every CPU's local APIC, every I/O APIC, and how legacy
+//! (8259 PIC) IRQs are wired to the I/O APICs.
+//!
+//! The MADT is a [`SystemDescriptor`], the local APIC's address, some flags, and then a list of
+//! variable-length entries. Each entry starts with a type byte and a length byte.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/MADT
+//! - https://uefi.org/htmlspecs/ACPI_Spec_6_4_html/05_ACPI_Software_Programming_Model/ACPI_Software_Programming_Model.html#multiple-apic-description-table-madt
+
+use {
+	crate::rsdt::*,
+	core::{mem, slice},
+};
+
+/// The MADT. See the module-level docs.
+pub struct Madt<'a> {
+	pub descriptor: &'a SystemDescriptor,
+	/// The physical address of every CPU's local APIC. This can be overridden by a
+	/// [`LocalApicAddressOverride`] entry.
+	pub local_apic_address: u32,
+	/// See [`Madt::FLAG_PCAT_COMPAT`].
+	pub flags: u32,
+	/// The raw bytes of all the entries in the MADT. Use [`Madt::entries`] to parse them.
+	entries: &'a [u8],
+}
+impl<'a> Madt<'a> {
+	/// What the MADT's [`SystemDescriptor::signature`] should be set to.
+	pub const SIGNATURE: [u8; 4] = *b"APIC";
+	/// Set in [`Madt::flags`] if the system also has dual 8259 PICs, which must be disabled before
+	/// using the APICs.
+	pub const FLAG_PCAT_COMPAT: u32 = 1;
+
+	/// Takes a possible pointer to an MADT and ensures it's a valid [`Madt`].
+	///
+	/// # Safety
+	/// - `ptr` must be a non-null, aligned pointer
+	/// - `ptr` must live for at least `'a`
+	pub unsafe fn try_from_raw(
+		ptr: *const SystemDescriptor,
+	) -> Result<Self, SystemDescriptorError> {
+		let descriptor = SystemDescriptor::try_from_raw(ptr)?;
+
+		if descriptor.signature != Self::SIGNATURE {
+			return Err(SystemDescriptorError::Signature {
+				expected: Self::SIGNATURE,
+				found: descriptor.signature,
+			});
+		}
+		let header_len = mem::size_of::<SystemDescriptor>() + 8;
+		if (descriptor.len as usize) < header_len {
+			return Err(SystemDescriptorError::Length {
+				expected: header_len,
+				found: descriptor.len,
+			});
+		}
+
+		let fields = (ptr as usize + mem::size_of::<SystemDescriptor>()) as *const [u32; 2];
+		let [local_apic_address, flags] = unsafe { fields.read_unaligned() };
+		let entries = unsafe {
+			slice::from_raw_parts(
+				(ptr as usize + header_len) as *const u8,
+				descriptor.len as usize - header_len,
+			)
+		};
+
+		Ok(Self {
+			descriptor,
+			local_apic_address,
+			flags,
+			entries,
+		})
+	}
+
+	/// Iterates over the entries in the MADT.
+	pub fn entries(&self) -> impl Iterator<Item = MadtEntry<'a>> {
+		Records::new(self.entries).map(|(kind, body)| MadtEntry::parse(kind, body))
+	}
+
+	/// The physical address of the local APICs, taking any [`LocalApicAddressOverride`] into
+	/// account.
+	pub fn local_apic_address(&self) -> u64 {
+		for entry in self.entries() {
+			if let MadtEntry::LocalApicAddressOverride(address_override) = entry {
+				return address_override.address;
+			}
+		}
+
+		self.local_apic_address as u64
+	}
+}
+
+impl<'a> AcpiTable<'a> for Madt<'a> {
+	const SIGNATURE: [u8; 4] = Madt::SIGNATURE;
+
+	unsafe fn try_from_raw(ptr: *const SystemDescriptor) -> Result<Self, SystemDescriptorError> {
+		unsafe { Self::try_from_raw(ptr) }
+	}
+}
+
+/// An entry in the [`Madt`].
+pub enum MadtEntry<'a> {
+	LocalApic(&'a LocalApic),
+	IoApic(&'a IoApic),
+	InterruptSourceOverride(&'a InterruptSourceOverride),
+	NmiSource(&'a NmiSource),
+	LocalApicNmi(&'a LocalApicNmi),
+	LocalApicAddressOverride(&'a LocalApicAddressOverride),
+	LocalX2Apic(&'a LocalX2Apic),
+	/// An entry type BS doesn't parse yet, or an entry that was too short for its type.
+	Unknown {
+		kind: u8,
+		body: &'a [u8],
+	},
+}
+impl<'a> MadtEntry<'a> {
+	fn parse(kind: u8, body: &'a [u8]) -> Self {
+		/// Casts `body` to `T` if it's long enough. All of the entry types are packed, so
+		/// alignment isn't a concern.
+		fn cast<T>(body: &[u8]) -> Option<&T> {
+			(body.len() >= mem::size_of::<T>()).then(|| unsafe { &*body.as_ptr().cast() })
+		}
+
+		let entry = match kind {
+			0 => cast(body).map(Self::LocalApic),
+			1 => cast(body).map(Self::IoApic),
+			2 => cast(body).map(Self::InterruptSourceOverride),
+			3 => cast(body).map(Self::NmiSource),
+			4 => cast(body).map(Self::LocalApicNmi),
+			5 => cast(body).map(Self::LocalApicAddressOverride),
+			9 => cast(body).map(Self::LocalX2Apic),
+			_ => None,
+		};
+
+		entry.unwrap_or(Self::Unknown { kind, body })
+	}
+}
+
+/// A CPU and its local APIC.
+#[repr(C, packed)]
+pub struct LocalApic {
+	pub acpi_processor_id: u8,
+	pub apic_id: u8,
+	/// Bit 0: The CPU is enabled. Bit 1: The CPU can be enabled, if it isn't already.
+	pub flags: u32,
+}
+impl LocalApic {
+	/// If this CPU is usable - either it's enabled already, or it can be enabled.
+	pub fn usable(&self) -> bool {
+		self.flags & 0b11 != 0
+	}
+}
+
+/// An I/O APIC, which routes hardware interrupts to local APICs.
+#[repr(C, packed)]
+pub struct IoApic {
+	pub io_apic_id: u8,
+	pub reserved: u8,
+	/// The physical address of the I/O APIC's registers.
+	pub address: u32,
+	/// The first Global System Interrupt this I/O APIC handles. The number of interrupts it
+	/// handles is stored in one of its registers.
+	pub global_system_interrupt_base: u32,
+}
+
+/// Describes how a legacy (8259 PIC) IRQ is wired to the I/O APICs, if it's different from the
+/// default mapping (where IRQ N is Global System Interrupt N).
+#[repr(C, packed)]
+pub struct InterruptSourceOverride {
+	/// Always 0, for ISA.
+	pub bus: u8,
+	/// The legacy IRQ.
+	pub source: u8,
+	/// The Global System Interrupt the IRQ is actually wired to.
+	pub global_system_interrupt: u32,
+	/// The polarity and trigger mode of the interrupt. See [`InterruptSourceOverride::polarity`]
+	/// and [`InterruptSourceOverride::trigger_mode`].
+	pub flags: u16,
+}
+impl InterruptSourceOverride {
+	pub fn polarity(&self) -> Polarity {
+		Polarity::from_flags(self.flags)
+	}
+	pub fn trigger_mode(&self) -> TriggerMode {
+		TriggerMode::from_flags(self.flags)
+	}
+}
+
+/// A Global System Interrupt that should be a non-maskable interrupt.
+#[repr(C, packed)]
+pub struct NmiSource {
+	/// Same as [`InterruptSourceOverride::flags`].
+	pub flags: u16,
+	pub global_system_interrupt: u32,
+}
+
+/// Which LINT pin of a local APIC is wired to the NMI line.
+#[repr(C, packed)]
+pub struct LocalApicNmi {
+	/// The CPU this applies to, or `0xFF` for every CPU.
+	pub acpi_processor_id: u8,
+	/// Same as [`InterruptSourceOverride::flags`].
+	pub flags: u16,
+	/// 0 for LINT0, 1 for LINT1.
+	pub lint: u8,
+}
+
+/// Overrides [`Madt::local_apic_address`] with a 64-bit address.
+#[repr(C, packed)]
+pub struct LocalApicAddressOverride {
+	pub reserved: u16,
+	pub address: u64,
+}
+
+/// A CPU and its local x2APIC. Used instead of [`LocalApic`] for CPUs whose APIC ID doesn't fit
+/// in a byte.
+#[repr(C, packed)]
+pub struct LocalX2Apic {
+	pub reserved: u16,
+	pub x2apic_id: u32,
+	/// Same as [`LocalApic::flags`].
+	pub flags: u32,
+	pub acpi_processor_uid: u32,
+}
+
+/// The polarity of an interrupt, from the flags in MADT entries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Polarity {
+	/// Use the default polarity for the bus. ISA interrupts are active high.
+	BusDefault,
+	ActiveHigh,
+	ActiveLow,
+}
+impl Polarity {
+	fn from_flags(flags: u16) -> Self {
+		match flags & 0b11 {
+			0b01 => Self::ActiveHigh,
+			0b11 => Self::ActiveLow,
+			_ => Self::BusDefault,
+		}
+	}
+}
+
+/// The trigger mode of an interrupt, from the flags in MADT entries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerMode {
+	/// Use the default trigger mode for the bus. ISA interrupts are edge-triggered.
+	BusDefault,
+	Edge,
+	Level,
+}
+impl TriggerMode {
+	fn from_flags(flags: u16) -> Self {
+		match (flags >> 2) & 0b11 {
+			0b01 => Self::Edge,
+			0b11 => Self::Level,
+			_ => Self::BusDefault,
+		}
+	}
+}
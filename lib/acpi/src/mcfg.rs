@@ -0,0 +1,128 @@
+//! Defines the MCFG table, which describes where PCIe's configuration space is. Regular PCI uses
+//! CPU I/O ports to access each device's configuration space; PCIe instead maps every device's
+//! configuration space into memory. This is called ECAM, or the "Enhanced Configuration Access
+//! Mechanism".
+//!
+//! The MCFG is a [`SystemDescriptor`] followed by 8 reserved bytes, and then a list of
+//! [`ConfigSpaceAllocation`]s. Each allocation maps the configuration spaces of a range of PCI
+//! buses in one PCI segment group (most systems only have segment group 0).
+//!
+//! Resources:
+//! - https://wiki.osdev.org/PCI_Express
+//! - https://wiki.osdev.org/PCI#Enhanced_Configuration_Mechanism
+
+use {
+	crate::rsdt::*,
+	core::{mem, slice},
+};
+
+/// The MCFG table. See the module-level docs.
+pub struct Mcfg<'a> {
+	pub descriptor: &'a SystemDescriptor,
+	/// The memory-mapped configuration spaces described by this table.
+	pub allocations: &'a [ConfigSpaceAllocation],
+}
+impl<'a> Mcfg<'a> {
+	/// What the MCFG's [`SystemDescriptor::signature`] should be set to.
+	pub const SIGNATURE: [u8; 4] = *b"MCFG";
+	/// The MCFG has 8 reserved bytes between the [`SystemDescriptor`] and the allocations.
+	const RESERVED_LEN: usize = 8;
+
+	/// Takes a possible pointer to an MCFG and ensures it's a valid [`Mcfg`].
+	///
+	/// # Safety
+	/// - `ptr` must be a non-null, aligned pointer
+	/// - `ptr` must live for at least `'a`
+	pub unsafe fn try_from_raw(
+		ptr: *const SystemDescriptor,
+	) -> Result<Self, SystemDescriptorError> {
+		let descriptor = SystemDescriptor::try_from_raw(ptr)?;
+
+		if descriptor.signature != Self::SIGNATURE {
+			return Err(SystemDescriptorError::Signature {
+				expected: Self::SIGNATURE,
+				found: descriptor.signature,
+			});
+		}
+		let header_len = mem::size_of::<SystemDescriptor>() + Self::RESERVED_LEN;
+		if (descriptor.len as usize) < header_len {
+			return Err(SystemDescriptorError::Length {
+				expected: header_len,
+				found: descriptor.len,
+			});
+		}
+
+		let allocations_addr = (ptr as usize) + header_len;
+		let allocations_len = descriptor.len as usize - header_len;
+		let num_allocations = allocations_len / mem::size_of::<ConfigSpaceAllocation>();
+		let allocations = unsafe {
+			slice::from_raw_parts(
+				allocations_addr as *const ConfigSpaceAllocation,
+				num_allocations,
+			)
+		};
+
+		Ok(Self {
+			descriptor,
+			allocations,
+		})
+	}
+
+	/// Finds the physical address of a PCIe function's configuration space. Returns `None` if no
+	/// allocation in this table covers that segment group and bus.
+	pub fn config_space_address(
+		&self,
+		segment_group: u16,
+		bus: u8,
+		device: u8,
+		function: u8,
+	) -> Option<u64> {
+		self.allocations
+			.iter()
+			.find_map(|allocation| allocation.address_of(segment_group, bus, device, function))
+	}
+}
+
+impl<'a> AcpiTable<'a> for Mcfg<'a> {
+	const SIGNATURE: [u8; 4] = Mcfg::SIGNATURE;
+
+	unsafe fn try_from_raw(ptr: *const SystemDescriptor) -> Result<Self, SystemDescriptorError> {
+		unsafe { Self::try_from_raw(ptr) }
+	}
+}
+
+/// A "Configuration Space Base Address Allocation Structure". Maps the configuration spaces of
+/// every device on a range of PCI buses into memory.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+pub struct ConfigSpaceAllocation {
+	/// The physical address of the configuration space for [`Self::start_bus`].
+	pub base_address: u64,
+	/// The PCI segment group these buses are in.
+	pub segment_group: u16,
+	/// The first PCI bus mapped by this allocation.
+	pub start_bus: u8,
+	/// The last PCI bus mapped by this allocation (inclusive).
+	pub end_bus: u8,
+	pub reserved: u32,
+}
+impl ConfigSpaceAllocation {
+	/// Finds the physical address of a PCIe function's configuration space. Returns `None` if it
+	/// isn't mapped by this allocation.
+	///
+	/// Each bus gets 1mib of configuration space, each device on the bus gets 32kib of that, and
+	/// each function on the device gets 4kib of that.
+	pub fn address_of(&self, segment_group: u16, bus: u8, device: u8, function: u8) -> Option<u64> {
+		if segment_group != self.segment_group
+			|| bus < self.start_bus
+			|| bus > self.end_bus
+			|| device >= 32
+			|| function >= 8
+		{
+			return None;
+		}
+
+		let bus = (bus - self.start_bus) as u64;
+		Some(self.base_address + (bus << 20) + ((device as u64) << 15) + ((function as u64) << 12))
+	}
+}
@@ -0,0 +1,175 @@
+//! Turning the computer off and restarting it.
+//!
+//! Shutting down is done by writing to the PM1 control registers, which are listed in the FADT.
+//! The value to write (`SLP_TYP`) isn't in the FADT, though - it's in the `\_S5` package in the
+//! DSDT, which is why this needs the [AML parser](crate::aml).
+//!
+//! Restarting is simpler, and has a couple of fallbacks since not every system supports the ACPI
+//! reset register:
+//! 1. Write [`Fadt::reset_value`] to [`Fadt::reset_register`]
+//! 2. Tell the PS/2 keyboard controller to pulse the CPU's reset line
+//! 3. Triple fault the CPU
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Shutdown
+//! - https://wiki.osdev.org/Reboot
+//! - https://uefi.org/htmlspecs/ACPI_Spec_6_4_html/04_ACPI_Hardware_Specification/ACPI_Hardware_Specification.html#pm1-control-registers
+
+use {
+	crate::{
+		aml::{DefinitionBlock, SleepType},
+		fadt::Fadt,
+		generic_address::GenericAddress,
+		rsdt::SystemDescriptorError,
+	},
+	core::arch::asm,
+};
+
+/// The `SCI_EN` bit in the PM1 control registers. If it's set, the system is in ACPI mode.
+const SCI_EN: u64 = 1 << 0;
+/// The `SLP_TYP` field in the PM1 control registers.
+const SLP_TYP_SHIFT: u64 = 10;
+const SLP_TYP_MASK: u64 = 0b111 << SLP_TYP_SHIFT;
+/// The `SLP_EN` bit in the PM1 control registers. Setting it enters the sleep state in `SLP_TYP`.
+const SLP_EN: u64 = 1 << 13;
+
+/// Errors that can stop [`shutdown`] from turning off the computer.
+#[derive(Debug)]
+pub enum PowerError {
+	/// The DSDT is at an address that doesn't fit in a pointer.
+	DsdtAddress,
+	/// The DSDT couldn't be parsed.
+	Dsdt(SystemDescriptorError),
+	/// The DSDT doesn't have an `\_S5` package.
+	NoS5,
+	/// The FADT doesn't list a PM1a control register. Hardware-reduced systems don't have one.
+	NoPm1aControlBlock,
+	/// A register is in an address space BS doesn't support yet.
+	UnsupportedRegister,
+	/// The firmware never switched the system into ACPI mode.
+	AcpiEnable,
+	/// The registers were written, but the computer is still on.
+	StillRunning,
+}
+
+/// Turns off the computer by entering the S5 ("soft off") sleep state. This only returns if
+/// shutting down fails.
+///
+/// # Safety
+/// The FADT and DSDT must be identity-mapped, along with any memory-mapped PM1 registers.
+pub unsafe fn shutdown(fadt: &Fadt) -> PowerError {
+	let Ok(dsdt_address) = usize::try_from(fadt.dsdt_address()) else {
+		return PowerError::DsdtAddress;
+	};
+	let dsdt = match unsafe { DefinitionBlock::try_from_raw(dsdt_address as *const _) } {
+		Ok(dsdt) => dsdt,
+		Err(err) => return PowerError::Dsdt(err),
+	};
+	let Some(sleep_type) = SleepType::s5([&dsdt]) else {
+		return PowerError::NoS5;
+	};
+
+	unsafe { enter_sleep_state(fadt, sleep_type) }
+}
+
+/// Enters a sleep state by writing `sleep_type` to the PM1 control registers. Use [`shutdown`] to
+/// turn off the computer; this is for when the [`SleepType`] has already been found (eg, from an
+/// SSDT). This only returns if entering the sleep state fails.
+///
+/// # Safety
+/// Any memory-mapped PM1 registers must be identity-mapped. Entering a sleep state other than S5
+/// without saving the system's state first will lose that state.
+pub unsafe fn enter_sleep_state(fadt: &Fadt, sleep_type: SleepType) -> PowerError {
+	let Some(pm1a) = fadt.pm1a_control_block() else {
+		return PowerError::NoPm1aControlBlock;
+	};
+	let pm1b = fadt.pm1b_control_block();
+
+	if let Err(err) = unsafe { enable_acpi_mode(fadt, &pm1a) } {
+		return err;
+	}
+
+	unsafe {
+		if write_sleep_type(&pm1a, sleep_type.a).is_none() {
+			return PowerError::UnsupportedRegister;
+		}
+		if let Some(pm1b) = pm1b {
+			if write_sleep_type(&pm1b, sleep_type.b).is_none() {
+				return PowerError::UnsupportedRegister;
+			}
+		}
+	}
+
+	PowerError::StillRunning
+}
+
+/// Switches the system from legacy mode into ACPI mode, if it isn't already. The PM1 control
+/// registers are ignored until this happens.
+unsafe fn enable_acpi_mode(fadt: &Fadt, pm1a: &GenericAddress) -> Result<(), PowerError> {
+	let Some(control) = (unsafe { pm1a.read() }) else {
+		return Err(PowerError::UnsupportedRegister);
+	};
+	// Already in ACPI mode, or the system doesn't support legacy mode
+	if control & SCI_EN != 0 || fadt.smi_command_port == 0 || fadt.acpi_enable == 0 {
+		return Ok(());
+	}
+
+	unsafe { portio::write_u8(fadt.smi_command_port as u16, fadt.acpi_enable) };
+
+	// The firmware can take a moment to switch modes
+	for _ in 0..1_000_000 {
+		if unsafe { pm1a.read() }.is_some_and(|control| control & SCI_EN != 0) {
+			return Ok(());
+		}
+		core::hint::spin_loop();
+	}
+
+	Err(PowerError::AcpiEnable)
+}
+
+/// Writes `SLP_TYP` and `SLP_EN` to a PM1 control register, leaving its other bits untouched.
+unsafe fn write_sleep_type(register: &GenericAddress, sleep_type: u16) -> Option<()> {
+	let control = unsafe { register.read() }?;
+	let control = (control & !SLP_TYP_MASK)
+		| (((sleep_type as u64) << SLP_TYP_SHIFT) & SLP_TYP_MASK)
+		| SLP_EN;
+
+	unsafe { register.write(control) }
+}
+
+/// Restarts the computer. Tries the FADT's reset register if there is one, then the keyboard
+/// controller, and finally triple faults the CPU.
+///
+/// # Safety
+/// This doesn't give anything a chance to save its state. The reset register must be
+/// identity-mapped if it's in memory.
+pub unsafe fn reset(fadt: Option<&Fadt>) -> ! {
+	if let Some((register, value)) = fadt.and_then(Fadt::reset_register) {
+		unsafe { register.write(value as u64) };
+	}
+
+	unsafe {
+		// Wait for the keyboard controller's input buffer to be empty, then send the reset command
+		for _ in 0..1_000_000 {
+			if portio::read_u8(0x64) & 0b10 == 0 {
+				break;
+			}
+			core::hint::spin_loop();
+		}
+		portio::write_u8(0x64, 0xFE);
+	}
+
+	// Load an empty IDT and trigger an interrupt. The CPU won't be able to find a handler for the
+	// interrupt, or for the fault that causes, or for the double fault that causes, so it resets.
+	let empty_idt = [0u8; 10];
+	unsafe {
+		asm!(
+			"lidt [{}]",
+			"int3",
+			in(reg) &empty_idt,
+		);
+	}
+
+	#[allow(clippy::empty_loop)]
+	loop {}
+}
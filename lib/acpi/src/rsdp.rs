@@ -6,7 +6,13 @@
 //! Resources:
 //! - https://wiki.osdev.org/RSDP
 
-use core::mem;
+use {
+	crate::rsdt::{checksum, Signature},
+	core::{
+		fmt::{self, Display, Formatter},
+		mem, ptr,
+	},
+};
 
 /// The "Root System Description Pointer".
 #[repr(packed)]
@@ -37,20 +43,77 @@ impl Rsdp {
 		let rsdp = unsafe { &*ptr };
 
 		if rsdp.signature != Self::SIGNATURE {
-			return Err(RsdpXsdpError::Signature);
+			return Err(RsdpXsdpError::Signature(rsdp.signature));
 		}
 
-		let mut checksum: u8 = 0;
 		let bytes: &[u8; mem::size_of::<Rsdp>()] = unsafe { &*ptr.cast() };
-		for byte in bytes {
-			checksum = checksum.wrapping_add(*byte);
-		}
-		if checksum != 0 {
-			return Err(RsdpXsdpError::Checksum);
+		let sum = checksum(bytes);
+		if sum != 0 {
+			return Err(RsdpXsdpError::Checksum {
+				expected: rsdp.checksum.wrapping_sub(sum),
+				found: rsdp.checksum,
+			});
 		}
 
 		Ok(rsdp)
 	}
+
+	/// Searches memory for the RSDP. Per the ACPI spec, the RSDP is either in the first 1kib of
+	/// the EBDA (Extended BIOS Data Area), or somewhere in the BIOS ROM area (`0xE0000-0xFFFFF`).
+	/// Either way, it'll be aligned to 16 bytes.
+	///
+	/// If the RSDP is also a valid [`Xsdp`], this returns the [`Xsdp`] instead.
+	///
+	/// # Safety
+	/// The first mib of physical memory must be readable at its physical address (ie, identity
+	/// mapped). This is always the case in real mode.
+	pub unsafe fn find<'a>() -> Option<RootPointer<'a>> {
+		// The BIOS Data Area stores the EBDA's real-mode segment at 0x40E
+		let ebda_segment = unsafe { ptr::read_volatile(Self::EBDA_SEGMENT_PTR) };
+		let ebda = (ebda_segment as usize) << 4;
+
+		unsafe { Self::search(ebda, ebda + 1024).or_else(|| Self::search(0xE0000, 0x100000)) }
+	}
+	/// Where the BIOS Data Area stores the EBDA's segment.
+	const EBDA_SEGMENT_PTR: *const u16 = 0x40E as _;
+
+	/// Searches for the RSDP on every 16-byte boundary from `start` (inclusive) to `end`
+	/// (exclusive).
+	///
+	/// # Safety
+	/// All memory from `start` to `end` must be readable.
+	unsafe fn search<'a>(start: usize, end: usize) -> Option<RootPointer<'a>> {
+		let mut address = start.next_multiple_of(16);
+
+		while address < end {
+			if let Ok(rsdp) = unsafe { Self::try_from_raw(address as *const Self) } {
+				return Some(match rsdp.try_into() {
+					Ok(xsdp) => RootPointer::Xsdp(xsdp),
+					Err(_) => RootPointer::Rsdp(rsdp),
+				});
+			}
+
+			address += 16;
+		}
+
+		None
+	}
+}
+
+/// The result of [`Rsdp::find`]. ACPI 1 systems only have an [`Rsdp`], while ACPI 2+ systems have
+/// an [`Xsdp`].
+pub enum RootPointer<'a> {
+	Rsdp(&'a Rsdp),
+	Xsdp(&'a Xsdp),
+}
+impl<'a> RootPointer<'a> {
+	/// Gets the [`Rsdp`]. Every [`Xsdp`] starts with an [`Rsdp`], so this always succeeds.
+	pub fn rsdp(&self) -> &'a Rsdp {
+		match self {
+			Self::Rsdp(rsdp) => rsdp,
+			Self::Xsdp(xsdp) => &xsdp.rsdp,
+		}
+	}
 }
 
 /// The "eXtended System Description Pointer". This is used instead of the
@@ -63,7 +126,7 @@ pub struct Xsdp {
 	pub len: u32,
 	/// The location of the Extended System Descriptor.
 	pub xsd_address: u64,
-	/// The checksum of the extended fields; does not include fields in the RSDP.
+	/// The checksum of the whole XSDP, including the fields in the RSDP.
 	pub extended_checksum: u8,
 	pub reserved: [u8; 3],
 }
@@ -91,16 +154,17 @@ impl<'a> TryFrom<&'a Rsdp> for &'a Xsdp {
 
 		let xsdp: &'a Xsdp = unsafe { mem::transmute(rsdp) };
 		if xsdp.len as usize != mem::size_of::<Xsdp>() {
-			return Err(RsdpXsdpError::Length);
+			return Err(RsdpXsdpError::Length(xsdp.len));
 		}
 
-		let mut checksum: u8 = 0;
+		// The extended checksum covers the whole XSDP, including the RSDP fields
 		let bytes: &[u8; mem::size_of::<Xsdp>()] = unsafe { mem::transmute(xsdp) };
-		for byte in &bytes[mem::size_of::<Rsdp>() + 1..] {
-			checksum = checksum.wrapping_add(*byte);
-		}
-		if checksum != 0 {
-			return Err(RsdpXsdpError::Checksum);
+		let sum = checksum(bytes);
+		if sum != 0 {
+			return Err(RsdpXsdpError::ExtendedChecksum {
+				expected: xsdp.extended_checksum.wrapping_sub(sum),
+				found: xsdp.extended_checksum,
+			});
 		}
 
 		Ok(xsdp)
@@ -110,14 +174,46 @@ impl<'a> TryFrom<&'a Rsdp> for &'a Xsdp {
 #[derive(Debug)]
 /// An error while verifying an [`Rsdp`] or an [`Xsdp`].
 pub enum RsdpXsdpError {
-	/// The signature wasn't `RSD PTR `.
-	Signature,
+	/// The signature wasn't `RSD PTR `. Holds the signature that was found instead.
+	Signature([u8; 8]),
 	/// BS only supports revision 2 XSDPs. These should be present on ACPI 2+ systems.
 	Revision(u8),
-	/// Checksum verification failed.
-	Checksum,
-	/// Extended checksum verification failed.
-	ExtendedChecksum,
-	/// The XSDP's length didn't match the size of [`Xsdp`].
-	Length,
+	/// Checksum verification failed. `expected` is the checksum that would've made the RSDP's
+	/// bytes add up to 0, and `found` is the checksum it actually has.
+	Checksum { expected: u8, found: u8 },
+	/// Extended checksum verification failed. Same fields as [`RsdpXsdpError::Checksum`].
+	ExtendedChecksum { expected: u8, found: u8 },
+	/// The XSDP's length didn't match the size of [`Xsdp`]. Holds the length that was found.
+	Length(u32),
+}
+impl Display for RsdpXsdpError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Signature(found) => write!(
+				f,
+				"wrong signature (expected `{}`, found `{}`)",
+				Signature(&Rsdp::SIGNATURE),
+				Signature(found)
+			),
+			Self::Revision(revision) => {
+				write!(
+					f,
+					"unsupported XSDP revision (expected 2, found {revision})"
+				)
+			}
+			Self::Checksum { expected, found } => write!(
+				f,
+				"invalid checksum (expected {expected:#04X}, found {found:#04X})"
+			),
+			Self::ExtendedChecksum { expected, found } => write!(
+				f,
+				"invalid extended checksum (expected {expected:#04X}, found {found:#04X})"
+			),
+			Self::Length(found) => write!(
+				f,
+				"wrong XSDP length (expected {} bytes, found {found})",
+				mem::size_of::<Xsdp>()
+			),
+		}
+	}
 }
@@ -6,7 +6,14 @@
 //! - https://wiki.osdev.org/RSDT
 //! - https://wiki.osdev.org/XSDT
 
-use core::{mem, slice};
+use {
+	crate::{fadt::Fadt, hpet::Hpet, madt::Madt, mcfg::Mcfg, srat::Srat},
+	core::{
+		ascii,
+		fmt::{self, Display, Formatter},
+		mem, slice,
+	},
+};
 
 /// The SDT/System Descriptor Table. Essentially used as a basis
 /// for all the other tables here.
@@ -38,15 +45,18 @@ impl SystemDescriptor {
 		let descriptor = unsafe { &*ptr };
 
 		if descriptor.len < mem::size_of::<SystemDescriptor>() as u32 {
-			return Err(SystemDescriptorError::Length);
+			return Err(SystemDescriptorError::Length {
+				expected: mem::size_of::<SystemDescriptor>(),
+				found: descriptor.len,
+			});
 		}
 		let bytes = unsafe { slice::from_raw_parts(ptr.cast::<u8>(), descriptor.len as _) };
-		let mut checksum: u8 = 0;
-		for byte in bytes {
-			checksum = checksum.wrapping_add(*byte);
-		}
-		if checksum != 0 {
-			return Err(SystemDescriptorError::Checksum);
+		let sum = checksum(bytes);
+		if sum != 0 {
+			return Err(SystemDescriptorError::Checksum {
+				expected: descriptor.checksum.wrapping_sub(sum),
+				found: descriptor.checksum,
+			});
 		}
 
 		Ok(descriptor)
@@ -56,10 +66,68 @@ impl SystemDescriptor {
 /// Errors while verifying a [`SystemDescriptor`].
 #[derive(Debug)]
 pub enum SystemDescriptorError {
-	/// The bytes of the descriptor added together didn't equal 0.
-	Checksum,
-	/// The length field of the descriptor was less than the size of a descriptor.
-	Length,
+	/// The bytes of the descriptor added together didn't equal 0. `expected` is the checksum that
+	/// would've made them add up to 0, and `found` is the checksum the table actually has.
+	Checksum { expected: u8, found: u8 },
+	/// The descriptor's signature didn't match the table it was being parsed as.
+	Signature { expected: [u8; 4], found: [u8; 4] },
+	/// The length field of the descriptor was less than the size of a descriptor, or less than
+	/// the minimum size of the table it was being parsed as. `expected` is that minimum size.
+	Length { expected: usize, found: u32 },
+}
+impl Display for SystemDescriptorError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Checksum { expected, found } => write!(
+				f,
+				"invalid checksum (expected {expected:#04X}, found {found:#04X})"
+			),
+			Self::Signature { expected, found } => write!(
+				f,
+				"wrong signature (expected `{}`, found `{}`)",
+				Signature(expected),
+				Signature(found)
+			),
+			Self::Length { expected, found } => write!(
+				f,
+				"table too short (expected at least {expected} bytes, found {found})"
+			),
+		}
+	}
+}
+
+/// Adds up every byte in `bytes`. ACPI structures are valid if this is 0.
+pub(crate) fn checksum(bytes: &[u8]) -> u8 {
+	bytes.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Displays a table signature as text, escaping any bytes that aren't printable ASCII. Corrupt
+/// tables can have anything in their signature.
+pub(crate) struct Signature<'a>(pub &'a [u8]);
+impl Display for Signature<'_> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		for byte in self.0 {
+			write!(f, "{}", ascii::escape_default(*byte))?;
+		}
+
+		Ok(())
+	}
+}
+
+/// A table that can be found by its signature with [`Sdt::find`]. This is implemented for every
+/// table type that's pointed to by the [`Rsdt`]/[`Xsdt`]. Some tables are parsed into a struct that
+/// borrows the table (like [`Madt`]), while others are just a reference to the table itself (like
+/// [`Fadt`]), so this is implemented for `Madt<'a>` but `&'a Fadt`.
+pub trait AcpiTable<'a>: Sized {
+	/// What the table's [`SystemDescriptor::signature`] should be set to.
+	const SIGNATURE: [u8; 4];
+
+	/// Takes a possible pointer to the table and ensures it's valid.
+	///
+	/// # Safety
+	/// - `ptr` must be a non-null, aligned pointer
+	/// - `ptr` must live for at least `'a`
+	unsafe fn try_from_raw(ptr: *const SystemDescriptor) -> Result<Self, SystemDescriptorError>;
 }
 
 /// Abstracts over number types that can be converted to pointers.
@@ -106,22 +174,112 @@ impl<'a, PtrSize: ToPtr> Sdt<'a, PtrSize> {
 	/// Find a table pointed to by this [`Rsdt`]/[`Xsdt`]. Both tables store a list of pointers
 	/// that point to other tables. Those tables all start with a [`SystemDescriptor`], and can be
 	/// identified by their 4-byte signature.
-	pub fn find_table(&self, name: &str) -> Option<&SystemDescriptor> {
-		let name = name.as_bytes();
-		if name.len() != 4 {
-			return None;
-		}
+	///
+	/// The table type determines which signature to look for, and the table is validated before
+	/// it's returned. Tables with the right signature that fail validation are skipped.
+	///
+	/// ```ignore
+	/// let mcfg = xsdt.find::<Mcfg>();
+	/// let fadt = xsdt.find::<&Fadt>();
+	/// ```
+	pub fn find<T: AcpiTable<'a>>(&self) -> Option<T> {
+		self.tables.iter().find_map(|table| {
+			let ptr = table.to_ptr::<SystemDescriptor>();
+			let descriptor = unsafe { SystemDescriptor::try_from_raw(ptr) }.ok()?;
+			if descriptor.signature != T::SIGNATURE {
+				return None;
+			}
 
-		for table in self.tables.iter() {
-			let descriptor = unsafe { SystemDescriptor::try_from_raw(table.to_ptr()) };
-			if let Ok(descriptor) = descriptor {
-				if descriptor.signature == name {
-					return Some(descriptor);
-				}
+			unsafe { T::try_from_raw(ptr) }.ok()
+		})
+	}
+
+	/// Iterates over the tables pointed to by this [`Rsdt`]/[`Xsdt`], identifying each one by its
+	/// signature. Tables that fail validation are skipped.
+	pub fn tables_typed(&self) -> impl Iterator<Item = KnownTable<'a>> + 'a {
+		self.tables
+			.iter()
+			.filter_map(|table| unsafe { KnownTable::try_from_raw(table.to_ptr()) })
+	}
+}
+
+/// A table pointed to by an [`Rsdt`]/[`Xsdt`], identified by its signature. See
+/// [`Sdt::tables_typed`].
+pub enum KnownTable<'a> {
+	Madt(Madt<'a>),
+	Fadt(&'a Fadt),
+	Hpet(&'a Hpet),
+	Mcfg(Mcfg<'a>),
+	Srat(Srat<'a>),
+	/// A table BS doesn't parse yet, or a table that didn't match its signature's layout.
+	Unknown(&'a SystemDescriptor),
+}
+impl<'a> KnownTable<'a> {
+	/// Takes a possible pointer to an SDT and parses it as whichever table its signature says it
+	/// is. Returns `None` if the [`SystemDescriptor`] itself isn't valid.
+	///
+	/// # Safety
+	/// - `ptr` must be a non-null, aligned pointer
+	/// - `ptr` must live for at least `'a`
+	pub unsafe fn try_from_raw(ptr: *const SystemDescriptor) -> Option<Self> {
+		let descriptor = unsafe { SystemDescriptor::try_from_raw(ptr) }.ok()?;
+
+		let table = unsafe {
+			match descriptor.signature {
+				Madt::SIGNATURE => Madt::try_from_raw(ptr).ok().map(Self::Madt),
+				Fadt::SIGNATURE => Fadt::try_from_raw(ptr.cast()).ok().map(Self::Fadt),
+				Hpet::SIGNATURE => Hpet::try_from_raw(ptr.cast()).ok().map(Self::Hpet),
+				Mcfg::SIGNATURE => Mcfg::try_from_raw(ptr).ok().map(Self::Mcfg),
+				Srat::SIGNATURE => Srat::try_from_raw(ptr).ok().map(Self::Srat),
+				_ => None,
 			}
+		};
+
+		Some(table.unwrap_or(Self::Unknown(descriptor)))
+	}
+
+	/// The [`SystemDescriptor`] at the start of this table.
+	pub fn descriptor(&self) -> &'a SystemDescriptor {
+		match self {
+			Self::Madt(madt) => madt.descriptor,
+			Self::Fadt(fadt) => &fadt.descriptor,
+			Self::Hpet(hpet) => &hpet.descriptor,
+			Self::Mcfg(mcfg) => mcfg.descriptor,
+			Self::Srat(srat) => srat.descriptor,
+			Self::Unknown(descriptor) => descriptor,
+		}
+	}
+}
+
+/// Several tables (like the MADT) end in a list of variable-length records, where each record
+/// starts with a type byte and a length byte (which includes those two bytes). This iterates over
+/// those records, yielding each one's type and the bytes after its two-byte header.
+pub(crate) struct Records<'a> {
+	bytes: &'a [u8],
+}
+impl<'a> Records<'a> {
+	pub(crate) fn new(bytes: &'a [u8]) -> Self {
+		Self { bytes }
+	}
+}
+impl<'a> Iterator for Records<'a> {
+	type Item = (u8, &'a [u8]);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let [kind, len, ..] = *self.bytes else {
+			return None;
+		};
+		let len = len as usize;
+		// A record shorter than its own header would loop forever; a record longer than the
+		// remaining bytes is truncated. Either way the table is corrupt, so stop here.
+		if len < 2 || len > self.bytes.len() {
+			self.bytes = &[];
+			return None;
 		}
 
-		None
+		let body = &self.bytes[2..len];
+		self.bytes = &self.bytes[len..];
+		Some((kind, body))
 	}
 }
 
@@ -0,0 +1,202 @@
+//! Defines the SRAT, or System Resource Affinity Table. The SRAT describes the system's NUMA
+//! topology: it groups CPUs and ranges of memory into "proximity domains", where CPUs can access
+//! memory in their own domain faster than memory in other domains.
+//!
+//! BS doesn't do anything NUMA-aware, but the memory affinity entries are still useful, since
+//! they list every range of physical memory (including hot-pluggable memory) the firmware knows
+//! about.
+//!
+//! Like the MADT, the SRAT is a [`SystemDescriptor`], some reserved bytes, and then a list of
+//! variable-length entries that start with a type byte and a length byte.
+//!
+//! Resources:
+//! - https://uefi.org/htmlspecs/ACPI_Spec_6_4_html/05_ACPI_Software_Programming_Model/ACPI_Software_Programming_Model.html#system-resource-affinity-table-srat
+//! - https://wiki.osdev.org/SRAT
+
+use {
+	crate::rsdt::*,
+	core::{mem, slice},
+};
+
+/// The SRAT. See the module-level docs.
+pub struct Srat<'a> {
+	pub descriptor: &'a SystemDescriptor,
+	/// The raw bytes of all the entries in the SRAT. Use [`Srat::entries`] to parse them.
+	entries: &'a [u8],
+}
+impl<'a> Srat<'a> {
+	/// What the SRAT's [`SystemDescriptor::signature`] should be set to.
+	pub const SIGNATURE: [u8; 4] = *b"SRAT";
+	/// The SRAT has 12 reserved bytes between the [`SystemDescriptor`] and the entries.
+	const RESERVED_LEN: usize = 12;
+
+	/// Takes a possible pointer to an SRAT and ensures it's a valid [`Srat`].
+	///
+	/// # Safety
+	/// - `ptr` must be a non-null, aligned pointer
+	/// - `ptr` must live for at least `'a`
+	pub unsafe fn try_from_raw(
+		ptr: *const SystemDescriptor,
+	) -> Result<Self, SystemDescriptorError> {
+		let descriptor = SystemDescriptor::try_from_raw(ptr)?;
+
+		if descriptor.signature != Self::SIGNATURE {
+			return Err(SystemDescriptorError::Signature {
+				expected: Self::SIGNATURE,
+				found: descriptor.signature,
+			});
+		}
+		let header_len = mem::size_of::<SystemDescriptor>() + Self::RESERVED_LEN;
+		if (descriptor.len as usize) < header_len {
+			return Err(SystemDescriptorError::Length {
+				expected: header_len,
+				found: descriptor.len,
+			});
+		}
+
+		let entries = unsafe {
+			slice::from_raw_parts(
+				(ptr as usize + header_len) as *const u8,
+				descriptor.len as usize - header_len,
+			)
+		};
+
+		Ok(Self {
+			descriptor,
+			entries,
+		})
+	}
+
+	/// Iterates over the entries in the SRAT.
+	pub fn entries(&self) -> impl Iterator<Item = SratEntry<'a>> {
+		Records::new(self.entries).map(|(kind, body)| SratEntry::parse(kind, body))
+	}
+
+	/// Iterates over the enabled memory ranges in the SRAT.
+	pub fn memory_ranges(&self) -> impl Iterator<Item = &'a MemoryAffinity> {
+		self.entries().filter_map(|entry| match entry {
+			SratEntry::MemoryAffinity(memory) if memory.enabled() => Some(memory),
+			_ => None,
+		})
+	}
+}
+
+impl<'a> AcpiTable<'a> for Srat<'a> {
+	const SIGNATURE: [u8; 4] = Srat::SIGNATURE;
+
+	unsafe fn try_from_raw(ptr: *const SystemDescriptor) -> Result<Self, SystemDescriptorError> {
+		unsafe { Self::try_from_raw(ptr) }
+	}
+}
+
+/// An entry in the [`Srat`].
+pub enum SratEntry<'a> {
+	LocalApicAffinity(&'a LocalApicAffinity),
+	MemoryAffinity(&'a MemoryAffinity),
+	LocalX2ApicAffinity(&'a LocalX2ApicAffinity),
+	/// An entry type BS doesn't parse yet, or an entry that was too short for its type.
+	Unknown {
+		kind: u8,
+		body: &'a [u8],
+	},
+}
+impl<'a> SratEntry<'a> {
+	fn parse(kind: u8, body: &'a [u8]) -> Self {
+		/// Casts `body` to `T` if it's long enough. All of the entry types are packed, so
+		/// alignment isn't a concern.
+		fn cast<T>(body: &[u8]) -> Option<&T> {
+			(body.len() >= mem::size_of::<T>()).then(|| unsafe { &*body.as_ptr().cast() })
+		}
+
+		let entry = match kind {
+			0 => cast(body).map(Self::LocalApicAffinity),
+			1 => cast(body).map(Self::MemoryAffinity),
+			2 => cast(body).map(Self::LocalX2ApicAffinity),
+			_ => None,
+		};
+
+		entry.unwrap_or(Self::Unknown { kind, body })
+	}
+}
+
+/// Set in the `flags` of every SRAT entry if the entry should be used. Disabled entries should be
+/// ignored.
+const FLAG_ENABLED: u32 = 1 << 0;
+
+/// Which proximity domain a CPU (identified by its local APIC ID) is in.
+#[repr(C, packed)]
+pub struct LocalApicAffinity {
+	/// Bits 0-7 of the proximity domain. See [`LocalApicAffinity::proximity_domain`].
+	pub proximity_domain_low: u8,
+	pub apic_id: u8,
+	pub flags: u32,
+	/// Only used on Itanium.
+	pub local_sapic_eid: u8,
+	/// Bits 8-31 of the proximity domain. See [`LocalApicAffinity::proximity_domain`].
+	pub proximity_domain_high: [u8; 3],
+	pub clock_domain: u32,
+}
+impl LocalApicAffinity {
+	/// The full proximity domain this CPU is in.
+	pub fn proximity_domain(&self) -> u32 {
+		let [a, b, c] = self.proximity_domain_high;
+		u32::from_le_bytes([self.proximity_domain_low, a, b, c])
+	}
+	/// If this entry should be used.
+	pub fn enabled(&self) -> bool {
+		self.flags & FLAG_ENABLED != 0
+	}
+}
+
+/// Which proximity domain a range of physical memory is in.
+#[repr(C, packed)]
+pub struct MemoryAffinity {
+	pub proximity_domain: u32,
+	pub reserved: u16,
+	/// The physical address the memory range starts at.
+	pub base_address: u64,
+	/// The length of the memory range, in bytes.
+	pub length: u64,
+	pub reserved2: u32,
+	/// See [`MemoryAffinity::FLAG_HOT_PLUGGABLE`] and [`MemoryAffinity::FLAG_NON_VOLATILE`].
+	pub flags: u32,
+	pub reserved3: u64,
+}
+impl MemoryAffinity {
+	/// Set in [`MemoryAffinity::flags`] if the memory can be added or removed while the system is
+	/// running. Hot-pluggable ranges may not have any memory in them at boot.
+	pub const FLAG_HOT_PLUGGABLE: u32 = 1 << 1;
+	/// Set in [`MemoryAffinity::flags`] if the memory is non-volatile.
+	pub const FLAG_NON_VOLATILE: u32 = 1 << 2;
+
+	/// If this entry should be used.
+	pub fn enabled(&self) -> bool {
+		self.flags & FLAG_ENABLED != 0
+	}
+	/// If this memory can be added or removed while the system is running.
+	pub fn hot_pluggable(&self) -> bool {
+		self.flags & Self::FLAG_HOT_PLUGGABLE != 0
+	}
+	/// The physical address just past the end of this memory range.
+	pub fn end_address(&self) -> u64 {
+		self.base_address.saturating_add(self.length)
+	}
+}
+
+/// Which proximity domain a CPU (identified by its x2APIC ID) is in. Used instead of
+/// [`LocalApicAffinity`] for CPUs whose APIC ID doesn't fit in a byte.
+#[repr(C, packed)]
+pub struct LocalX2ApicAffinity {
+	pub reserved: u16,
+	pub proximity_domain: u32,
+	pub x2apic_id: u32,
+	pub flags: u32,
+	pub clock_domain: u32,
+	pub reserved2: u32,
+}
+impl LocalX2ApicAffinity {
+	/// If this entry should be used.
+	pub fn enabled(&self) -> bool {
+		self.flags & FLAG_ENABLED != 0
+	}
+}
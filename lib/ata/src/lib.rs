@@ -1,16 +1,24 @@
 #![no_std]
 
 use {
-	core::arch::asm,
 	pci::{
 		classification::{Class, MassStorageControllerSubclass},
 		PciDevice,
 	},
+	portio::PortSize,
 };
 
 mod enums;
 pub use enums::*;
 
+/// How many bytes are in a sector. Drives can technically have other sector sizes, but this is what
+/// [`IdeChannel::read_sectors`] assumes.
+pub const SECTOR_SIZE: usize = 512;
+/// The most sectors one PIO read command can transfer (a sector count of 0 means 256).
+const MAX_PIO_SECTORS: usize = 256;
+/// The first sector 28-bit LBAs can't reach. Reads past this use 48-bit LBAs.
+const LBA28_LIMIT: u64 = 1 << 28;
+
 /// Represents an IDE controller on the PCI bus. Each controller has two channels, which can each hold two drives.
 pub struct IdeController {
 	/// The first channel on this controller.
@@ -101,6 +109,96 @@ impl IdeChannel {
 		self.write_register(AtaRegister::Command, cmd as u8)
 	}
 
+	/// Reads sectors from `disk` into `buffer` with PIO, starting at `lba`. `buffer`'s length has to
+	/// be a multiple of [`SECTOR_SIZE`]; that's how many sectors get read. This makes `disk` the
+	/// active disk.
+	///
+	/// Reads that go past what 28-bit LBAs can reach use the 48-bit ("extended") read command.
+	/// Big reads are split into several commands, since one command can only read 256 sectors with
+	/// a 28-bit LBA.
+	pub fn read_sectors(
+		&mut self,
+		disk: IdeDisk,
+		mut lba: u64,
+		buffer: &mut [u8],
+	) -> Result<(), AtaError> {
+		assert!(
+			buffer.len().is_multiple_of(SECTOR_SIZE),
+			"ATA reads must be a whole number of sectors"
+		);
+
+		for chunk in buffer.chunks_mut(MAX_PIO_SECTORS * SECTOR_SIZE) {
+			let sectors = chunk.len() / SECTOR_SIZE;
+			let extended = lba + sectors as u64 > LBA28_LIMIT;
+			let bytes = lba.to_le_bytes();
+			// Bit 4 selects the drive, and bit 6 means LBA addressing. 28-bit LBAs put their top 4
+			// bits in the drive select register.
+			let disk_bit = match disk {
+				IdeDisk::Primary => 0,
+				IdeDisk::Secondary => 1 << 4,
+			};
+
+			if extended {
+				self.write_register(AtaRegister::DriveSelect, 0x40 | disk_bit)?;
+				// 48-bit values are written in 2 halves: high bytes, then low bytes
+				self.write_register(AtaRegister::SectorCount, (sectors >> 8) as u8)?;
+				self.write_register(AtaRegister::Lba0, bytes[3])?;
+				self.write_register(AtaRegister::Lba1, bytes[4])?;
+				self.write_register(AtaRegister::Lba2, bytes[5])?;
+			} else {
+				let lba_high = bytes[3] & 0x0F;
+				self.write_register(AtaRegister::DriveSelect, 0xE0 | disk_bit | lba_high)?;
+			}
+			self.active_disk = disk;
+			// 256 sectors wraps around to 0, which means 256
+			self.write_register(AtaRegister::SectorCount, sectors as u8)?;
+			self.write_register(AtaRegister::Lba0, bytes[0])?;
+			self.write_register(AtaRegister::Lba1, bytes[1])?;
+			self.write_register(AtaRegister::Lba2, bytes[2])?;
+
+			let command = if extended {
+				AtaCommand::ReadPioExtended
+			} else {
+				AtaCommand::ReadPio
+			};
+			self.write_register(AtaRegister::Command, command as u8)?;
+
+			for sector in chunk.as_chunks_mut::<SECTOR_SIZE>().0 {
+				self.wait_for_data()?;
+				for word in sector.as_chunks_mut::<2>().0 {
+					let data: u16 = self.read_register(AtaRegister::Data);
+					*word = data.to_le_bytes();
+				}
+			}
+
+			lba += sectors as u64;
+		}
+
+		Ok(())
+	}
+
+	/// Waits until the active drive has a sector of data ready to read, or errors.
+	fn wait_for_data(&self) -> Result<(), AtaError> {
+		loop {
+			let status: u8 = self.read_register(AtaRegister::Status);
+
+			if status & AtaStatus::Error as u8 != 0 {
+				let err_reg: u8 = self.read_register(AtaRegister::Error);
+				return Err(AtaError::VARIANTS
+					.into_iter()
+					.find(|err| err_reg & *err as u8 != 0)
+					.unwrap_or(AtaError::Unknown));
+			}
+			if status & AtaStatus::DeviceFault as u8 != 0 {
+				return Err(AtaError::Unknown);
+			}
+
+			if status & AtaStatus::Busy as u8 == 0 && status & AtaStatus::DataRequest as u8 != 0 {
+				return Ok(());
+			}
+		}
+	}
+
 	/// Enable or disable interrupt requests from the active drive on this channel.
 	pub fn set_interrupts(&self, enabled: bool) {
 		let mut val: u8 = self.read_register(AtaRegister::AltControl);
@@ -148,7 +246,7 @@ impl IdeChannel {
 		};
 		let register: u16 = register.into();
 
-		S::read(base_port + register)
+		unsafe { S::read(base_port + register) }
 	}
 	/// Write to one of the active disk's registers. This function works with
 	/// both 8-bit and 16-bit registers via generics, but it doesn't check that
@@ -170,7 +268,7 @@ impl IdeChannel {
 		};
 		let register: u16 = register.into();
 
-		S::write(base_port + register, data);
+		unsafe { S::write(base_port + register, data) };
 
 		// https://wiki.osdev.org/ATA_PIO_Mode#400ns_delays
 		for _ in 0..15 {
@@ -196,35 +294,3 @@ impl IdeChannel {
 		Ok(())
 	}
 }
-
-/// This trait allows functions that work with CPU ports to work
-/// with ports of different sizes. The idea is that a function
-/// can take or return a [`PortSize`] as a generic, and use that
-/// generic to read from/write to a CPU port. The generic will
-/// then handle the port's size (8 bits, 16 bits, etc) automatically.
-pub trait PortSize {
-	/// Read from a CPU port.
-	fn read(port: u16) -> Self;
-	/// Write to a CPU port.
-	fn write(port: u16, data: Self);
-}
-impl PortSize for u8 {
-	fn read(port: u16) -> Self {
-		let val;
-		unsafe { asm!("in al, dx", in("dx") port, out("al") val) }
-		val
-	}
-	fn write(port: u16, data: Self) {
-		unsafe { asm!("out dx, al", in("dx") port, in("al") data) }
-	}
-}
-impl PortSize for u16 {
-	fn read(port: u16) -> Self {
-		let val;
-		unsafe { asm!("in ax, dx", in("dx") port, out("ax") val) }
-		val
-	}
-	fn write(port: u16, data: Self) {
-		unsafe { asm!("out dx, ax", in("dx") port, in("ax") data) }
-	}
-}
@@ -0,0 +1,152 @@
+//! Enables the A20 line, so memory past 1mib can be used.
+//!
+//! The original 8086 only had 20 address lines, so addresses past 1mib wrapped back around to 0.
+//! Some old software relied on that, so later CPUs kept the wrapping by masking off the 21st
+//! address line (A20) - and it's still masked on some computers at boot. Until it's enabled, every
+//! odd megabyte of memory is a mirror of the one below it.
+//!
+//! There's several ways to enable it, and which ones work depends on the computer, so [`enable`]
+//! tries them in order from safest to riskiest, and checks if A20 is enabled after each one:
+//! 1. Asking the BIOS (int 15h, AX=0x2401)
+//! 2. The keyboard controller, which (for historical reasons) has the A20 gate on an output pin
+//! 3. "Fast A20", through system control port A (port 0x92)
+//!
+//! This uses BIOS calls and real-mode segments, so it's only available in the 16-bit boot
+//! programs.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/A20_Line
+
+use core::arch::asm;
+
+/// The keyboard controller's data port.
+const KEYBOARD_DATA: u16 = 0x60;
+/// The keyboard controller's status port (when read) and command port (when written).
+const KEYBOARD_COMMAND: u16 = 0x64;
+/// System control port A, for Fast A20.
+const SYSTEM_CONTROL_A: u16 = 0x92;
+
+/// How A20 got enabled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum A20Method {
+	/// It was already enabled, by the BIOS or an earlier boot program.
+	AlreadyEnabled,
+	/// The BIOS enabled it (int 15h, AX=0x2401).
+	Bios,
+	/// The keyboard controller's output port.
+	KeyboardController,
+	/// Fast A20, through port 0x92.
+	FastA20,
+}
+
+/// Tries to enable A20 with each method, in order (see the module-level docs). Returns the method
+/// that worked, or `None` if none of them did.
+pub fn enable() -> Option<A20Method> {
+	if enabled() {
+		return Some(A20Method::AlreadyEnabled);
+	}
+
+	let methods: [(A20Method, fn()); 3] = [
+		(A20Method::Bios, enable_bios),
+		(A20Method::KeyboardController, enable_keyboard_controller),
+		(A20Method::FastA20, enable_fast_a20),
+	];
+	for (method, enable) in methods {
+		enable();
+		// The keyboard controller can take a while to change the A20 gate, so check a few times
+		if (0..1_000).any(|_| enabled()) {
+			return Some(method);
+		}
+	}
+
+	None
+}
+
+/// If A20 is enabled. This checks if memory wraps around: if writing to 0xFFFF:0x0510 (1mib past
+/// 0x0000:0x0500) changes 0x0000:0x0500, A20 is disabled. Both bytes are restored afterwards.
+pub fn enabled() -> bool {
+	let wrapped: u8;
+	unsafe {
+		asm!(
+			"push ds",
+			"push es",
+			"push si",
+			"xor ax, ax",
+			"mov es, ax",
+			"not ax",
+			"mov ds, ax",
+			"mov si, 0x0510",
+			// Save the old bytes
+			"mov al, byte ptr es:[0x0500]",
+			"mov ah, byte ptr ds:[si]",
+			"mov byte ptr es:[0x0500], 0x00",
+			"mov byte ptr ds:[si], 0xFF",
+			"cmp byte ptr es:[0x0500], 0xFF",
+			// Restore them (`mov` doesn't change the flags)
+			"mov byte ptr ds:[si], ah",
+			"mov byte ptr es:[0x0500], al",
+			"sete {wrapped}",
+			"pop si",
+			"pop es",
+			"pop ds",
+			wrapped = out(reg_byte) wrapped,
+			out("ax") _,
+		);
+	}
+
+	wrapped == 0
+}
+
+/// Asks the BIOS to enable A20 (int 15h, AX=0x2401). Not every BIOS supports this.
+pub fn enable_bios() {
+	unsafe { asm!("int 0x15", inout("ax") 0x2401u16 => _) };
+}
+
+/// Enables A20 through the keyboard controller, by setting bit 1 of its output port.
+pub fn enable_keyboard_controller() {
+	unsafe {
+		// Disable the keyboard, so a keypress can't get mixed up with the output port
+		keyboard_command(0xAD);
+		// Read the output port
+		keyboard_command(0xD0);
+		while portio::read_u8(KEYBOARD_COMMAND) & 1 == 0 {}
+		let output = portio::read_u8(KEYBOARD_DATA);
+
+		// Write it back, with A20 enabled
+		keyboard_command(0xD1);
+		keyboard_wait();
+		portio::write_u8(KEYBOARD_DATA, output | 0b10);
+
+		// Enable the keyboard again
+		keyboard_command(0xAE);
+		keyboard_wait();
+	}
+}
+
+/// Enables A20 with Fast A20 (bit 1 of port 0x92). This isn't supported everywhere, and on some
+/// computers that port does something else entirely, which is why it's tried last.
+pub fn enable_fast_a20() {
+	unsafe {
+		let value = portio::read_u8(SYSTEM_CONTROL_A);
+		if value & 0b10 == 0 {
+			// Bit 0 resets the computer, so make sure it's clear
+			portio::write_u8(SYSTEM_CONTROL_A, (value | 0b10) & !1);
+		}
+	}
+}
+
+/// Waits for the keyboard controller to be ready for a command, then sends it.
+///
+/// # Safety
+/// `command` has to be a valid keyboard controller command.
+unsafe fn keyboard_command(command: u8) {
+	unsafe {
+		keyboard_wait();
+		portio::write_u8(KEYBOARD_COMMAND, command);
+	}
+}
+
+/// Waits until the keyboard controller's input buffer is empty, so it can take another byte.
+fn keyboard_wait() {
+	while unsafe { portio::read_u8(KEYBOARD_COMMAND) } & 0b10 != 0 {}
+}
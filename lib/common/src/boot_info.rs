@@ -0,0 +1,262 @@
+//! [`BootInfo`], which is how the boot programs tell each other (and eventually the kernel) what
+//! they found.
+//!
+//! It lives at a fixed address, [`BootInfo::ADDRESS`], in free memory right after the MBR. The
+//! bootloader sets it up, reads the memory map (while BIOS calls still work), and fills in the rest.
+//! From there it's passed along by pointer: the bootloader hands it to the ELF loader, which loads
+//! the kernel and hands it on.
+//!
+//! Since the boot programs are 16-bit and the ELF loader and kernel are 64-bit, the layout can't
+//! depend on the pointer size - so there's no `usize`s or pointers in it, and every `u64` is 8-byte
+//! aligned (16-bit code only aligns them to 4 bytes by default). The size is checked at compile
+//! time, so a layout mismatch won't build.
+//!
+//! It starts with [`BootInfo::MAGIC`] and [`BootInfo::VERSION`], so later stages can check they
+//! were actually given a `BootInfo`, and that it's the version they were built for. The version has
+//! to be bumped whenever the layout changes.
+//!
+//! Resources:
+//! - https://github.com/rust-osdev/bootloader/blob/main/api/src/info.rs
+//! - https://www.gnu.org/software/grub/manual/multiboot2/multiboot.html#Boot-information-format
+
+use {
+	crate::{
+		memory_map::MemoryMap,
+		paging::{MemoryRegion, PhysAddr, KERNEL_BASE},
+		printing::framebuffer::{FramebufferInfo, PixelFormat},
+	},
+	core::mem,
+};
+
+/// The longest kernel command line that fits in a [`BootInfo`].
+pub const COMMAND_LINE_LEN: usize = 256;
+
+/// Everything the boot programs found out about the computer. See the module-level docs.
+#[repr(C, align(8))]
+#[derive(Clone, Copy)]
+pub struct BootInfo {
+	/// Always [`BootInfo::MAGIC`].
+	pub magic: u64,
+	/// Always [`BootInfo::VERSION`], for the version of BS that made this.
+	pub version: u32,
+	/// The size of this struct, in bytes.
+	pub size: u32,
+	/// The physical address of the RSDP (or XSDP), or 0 if it wasn't found. See [`BootInfo::rsdp`].
+	pub rsdp_address: u64,
+	/// The physical address of the initial ramdisk, or 0 if there isn't one. See
+	/// [`BootInfo::initrd`].
+	pub initrd_start: u64,
+	/// The size of the initial ramdisk, in bytes.
+	pub initrd_len: u64,
+	/// The linear framebuffer, if there is one. See [`BootInfo::framebuffer`].
+	pub framebuffer: BootFramebuffer,
+	/// The physical memory map.
+	pub memory_map: MemoryMap,
+	/// How many bytes of `command_line` are used.
+	pub command_line_len: u32,
+	/// The BIOS drive number BS was booted from (eg 0x80 for the first hard drive).
+	pub boot_drive: u8,
+	_reserved: [u8; 3],
+	/// The kernel command line, as UTF-8. See [`BootInfo::command_line`].
+	pub command_line: [u8; COMMAND_LINE_LEN],
+	/// How far past [`KERNEL_BASE`] the kernel was loaded, if KASLR moved it. See
+	/// [`BootInfo::kernel_base`].
+	pub kernel_slide: u64,
+	/// The physical address of the kernel's ELF file, as it was read from the disk, or 0 if it
+	/// wasn't. See [`BootInfo::kernel_file`].
+	pub kernel_file_start: u64,
+	/// The size of the kernel's ELF file, in bytes.
+	pub kernel_file_len: u64,
+}
+impl BootInfo {
+	/// The first 8 bytes of every [`BootInfo`].
+	pub const MAGIC: u64 = u64::from_le_bytes(*b"BSBOOTIN");
+	/// The layout version. Bump this whenever the layout changes.
+	pub const VERSION: u32 = 3;
+	/// Where the boot info is in memory. This is free conventional memory, between the MBR (see
+	/// [`crate::mbr::MBR_ADDRESS`]) and the bottom of the 16-bit boot programs' stack (see
+	/// [`crate::stages::STACK_TOP`]).
+	pub const ADDRESS: usize = 0x800;
+
+	/// Empty boot info for `boot_drive`, with an empty memory map and no framebuffer, RSDP, initrd,
+	/// kernel file, or command line.
+	pub const fn new(boot_drive: u8) -> Self {
+		Self {
+			magic: Self::MAGIC,
+			version: Self::VERSION,
+			size: mem::size_of::<Self>() as u32,
+			rsdp_address: 0,
+			initrd_start: 0,
+			initrd_len: 0,
+			framebuffer: BootFramebuffer::NONE,
+			memory_map: MemoryMap::new(),
+			command_line_len: 0,
+			boot_drive,
+			_reserved: [0; 3],
+			command_line: [0; COMMAND_LINE_LEN],
+			kernel_slide: 0,
+			kernel_file_start: 0,
+			kernel_file_len: 0,
+		}
+	}
+
+	/// Sets up empty boot info at `ptr`, like [`BootInfo::new`]. This zeroes it and sets a few
+	/// fields, instead of copying a whole `BootInfo` there, which takes up a lot less code in 16-bit
+	/// mode.
+	///
+	/// # Safety
+	/// `ptr` has to be valid for writing a `BootInfo`, and nothing else can be using that memory.
+	pub unsafe fn init(ptr: *mut Self, boot_drive: u8) -> &'static mut Self {
+		unsafe {
+			// An all-zero `BootInfo` is valid: every enum's first variant is 0
+			ptr.write_bytes(0, 1);
+			let this = &mut *ptr;
+			this.magic = Self::MAGIC;
+			this.version = Self::VERSION;
+			this.size = mem::size_of::<Self>() as u32;
+			this.boot_drive = boot_drive;
+
+			this
+		}
+	}
+
+	/// Gets the boot info at `ptr`. Returns `None` if it's null, doesn't start with
+	/// [`BootInfo::MAGIC`], or is from a different [`BootInfo::VERSION`].
+	///
+	/// # Safety
+	/// If `ptr` isn't null, it has to be mapped and readable for at least 8 bytes - and, if it
+	/// starts with the magic, for a whole `BootInfo`.
+	pub unsafe fn from_ptr(ptr: *const Self) -> Option<&'static Self> {
+		let this = unsafe { ptr.as_ref()? };
+		(this.magic == Self::MAGIC
+			&& this.version == Self::VERSION
+			&& this.size as usize == mem::size_of::<Self>())
+		.then_some(this)
+	}
+
+	/// [`BootInfo::from_ptr`], but mutable, for the stages that fill in the boot info.
+	///
+	/// # Safety
+	/// The same as [`BootInfo::from_ptr`]. Nothing else can be using the boot info while the
+	/// reference exists.
+	pub unsafe fn from_ptr_mut(ptr: *mut Self) -> Option<&'static mut Self> {
+		unsafe {
+			Self::from_ptr(ptr)?;
+			Some(&mut *ptr)
+		}
+	}
+
+	/// The physical address of the RSDP, if it was found.
+	pub fn rsdp(&self) -> Option<PhysAddr> {
+		match self.rsdp_address {
+			0 => None,
+			address => Some(address),
+		}
+	}
+
+	/// Where the initial ramdisk is, if there is one.
+	pub fn initrd(&self) -> Option<MemoryRegion> {
+		match self.initrd_start {
+			0 => None,
+			start => Some(MemoryRegion {
+				start,
+				len: self.initrd_len,
+			}),
+		}
+	}
+
+	/// Where the kernel's ELF file is, if the bootloader read it. The ELF loader loads the kernel
+	/// from it; it's left in memory afterwards, so the kernel can read its own symbols.
+	pub fn kernel_file(&self) -> Option<MemoryRegion> {
+		match self.kernel_file_start {
+			0 => None,
+			start => Some(MemoryRegion {
+				start,
+				len: self.kernel_file_len,
+			}),
+		}
+	}
+
+	/// Where the kernel was actually loaded. Addresses in the kernel's ELF are relative to this, so
+	/// backtraces can be symbolized by subtracting it.
+	pub fn kernel_base(&self) -> u64 {
+		KERNEL_BASE + self.kernel_slide
+	}
+
+	/// The linear framebuffer, if there is one.
+	pub fn framebuffer(&self) -> Option<FramebufferInfo> {
+		self.framebuffer.info()
+	}
+
+	/// The kernel command line. It's empty if there isn't one, or if it isn't valid UTF-8.
+	pub fn command_line(&self) -> &str {
+		let len = (self.command_line_len as usize).min(COMMAND_LINE_LEN);
+		core::str::from_utf8(&self.command_line[..len]).unwrap_or("")
+	}
+
+	/// Sets the kernel command line. Returns `false` (and leaves it unchanged) if it's longer than
+	/// [`COMMAND_LINE_LEN`].
+	pub fn set_command_line(&mut self, command_line: &str) -> bool {
+		let Some(slot) = self.command_line.get_mut(..command_line.len()) else {
+			return false;
+		};
+
+		slot.copy_from_slice(command_line.as_bytes());
+		self.command_line_len = command_line.len() as u32;
+		true
+	}
+}
+
+// Catch layout differences between the 16-bit and 64-bit builds. Update this (and bump
+// `BootInfo::VERSION`) when adding fields.
+const _: () = assert!(mem::size_of::<BootInfo>() == 1904);
+const _: () = assert!(mem::offset_of!(BootInfo, memory_map) == 72);
+
+/// A [`FramebufferInfo`], with fixed-size fields so it can go in a [`BootInfo`].
+#[repr(C, align(8))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BootFramebuffer {
+	/// The physical address of the top-left pixel, or 0 if there's no framebuffer.
+	pub address: u64,
+	pub width: u32,
+	pub height: u32,
+	pub pitch: u32,
+	pub bytes_per_pixel: u32,
+	pub format: PixelFormat,
+}
+impl BootFramebuffer {
+	/// No framebuffer.
+	pub const NONE: Self = Self {
+		address: 0,
+		width: 0,
+		height: 0,
+		pitch: 0,
+		bytes_per_pixel: 0,
+		format: PixelFormat::Bgr,
+	};
+
+	/// The framebuffer, or `None` if there isn't one. The address is physical, so it has to be
+	/// identity-mapped (or changed to a virtual address) before the framebuffer's used.
+	pub fn info(&self) -> Option<FramebufferInfo> {
+		(self.address != 0).then_some(FramebufferInfo {
+			address: self.address,
+			width: self.width as usize,
+			height: self.height as usize,
+			pitch: self.pitch as usize,
+			bytes_per_pixel: self.bytes_per_pixel as usize,
+			format: self.format,
+		})
+	}
+}
+impl From<FramebufferInfo> for BootFramebuffer {
+	fn from(info: FramebufferInfo) -> Self {
+		Self {
+			address: info.address,
+			width: info.width as u32,
+			height: info.height as u32,
+			pitch: info.pitch as u32,
+			bytes_per_pixel: info.bytes_per_pixel as u32,
+			format: info.format,
+		}
+	}
+}
@@ -0,0 +1,98 @@
+//! The kernel command line, which lets BS be configured without rebuilding it.
+//!
+//! The command line is stored on the disk in the configuration sector, as the [`Stage::Config`]
+//! stage in the boot partition. It's plain UTF-8 text, padded to a whole sector with zeroes; the
+//! bootloader reads it with [`from_config`] and puts it in the [`BootInfo`]. The sector's optional,
+//! so the command line is empty if it isn't there.
+//!
+//! The command line is a list of options, separated by whitespace. Each option is either a flag
+//! (like `debug`) or a key and a value (like `console=serial`). Values can't have whitespace in
+//! them. For example:
+//!
+//! ```text
+//! debug console=serial
+//! ```
+//!
+//! [`CommandLine`] parses it, so the kernel can look options up by name.
+//! If an option's given more than once, the last one wins, so options can be overridden by
+//! appending to the command line.
+//!
+//! [`Stage::Config`]: crate::stages::Stage::Config
+//! [`BootInfo`]: crate::boot_info::BootInfo
+//!
+//! Resources:
+//! - https://www.kernel.org/doc/html/latest/admin-guide/kernel-parameters.html
+
+/// One option on the command line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommandLineOption<'a> {
+	/// The option's name; everything before the `=`.
+	pub key: &'a str,
+	/// Everything after the `=`, or `None` if the option is a flag.
+	pub value: Option<&'a str>,
+}
+impl<'a> CommandLineOption<'a> {
+	/// Parses one option.
+	pub fn parse(option: &'a str) -> Self {
+		match option.split_once('=') {
+			Some((key, value)) => Self {
+				key,
+				value: Some(value),
+			},
+			None => Self {
+				key: option,
+				value: None,
+			},
+		}
+	}
+}
+
+/// A parsed kernel command line. See the module-level docs.
+#[derive(Clone, Copy, Debug)]
+pub struct CommandLine<'a> {
+	text: &'a str,
+}
+impl<'a> CommandLine<'a> {
+	/// Parses `text` as a command line.
+	pub const fn new(text: &'a str) -> Self {
+		Self { text }
+	}
+
+	/// The command line, as it was given.
+	pub fn as_str(&self) -> &'a str {
+		self.text
+	}
+
+	/// Every option on the command line, in order.
+	pub fn options(&self) -> impl Iterator<Item = CommandLineOption<'a>> {
+		self.text.split_whitespace().map(CommandLineOption::parse)
+	}
+
+	/// The last option named `key`.
+	pub fn option(&self, key: &str) -> Option<CommandLineOption<'a>> {
+		self.options().filter(|option| option.key == key).last()
+	}
+
+	/// The value of the last `key=value` option named `key`. Returns `None` if there isn't one,
+	/// or if it's a flag.
+	pub fn get(&self, key: &str) -> Option<&'a str> {
+		self.option(key)?.value
+	}
+
+	/// If the flag `key` is set. `key=false`, `key=no`, `key=off`, and `key=0` unset it; any other
+	/// value sets it.
+	pub fn flag(&self, key: &str) -> bool {
+		self.option(key)
+			.is_some_and(|option| !matches!(option.value, Some("false" | "no" | "off" | "0")))
+	}
+}
+
+/// Gets the command line from the configuration sector(s). The zeroes at the end are cut off,
+/// along with any whitespace around the command line. Returns `None` if it isn't valid UTF-8.
+pub fn from_config(config: &[u8]) -> Option<&str> {
+	let len = config
+		.iter()
+		.rposition(|byte| *byte != 0)
+		.map_or(0, |last| last + 1);
+	core::str::from_utf8(&config[..len]).ok().map(str::trim)
+}
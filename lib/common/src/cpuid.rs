@@ -0,0 +1,224 @@
+//! Asks the CPU what it is, and what it supports, with the `cpuid` instruction.
+//!
+//! `cpuid` takes a "leaf" number in EAX (and sometimes a "subleaf" in ECX), and fills EAX, EBX,
+//! ECX, and EDX with information about the CPU. Leaves from 0x8000_0000 up are "extended" leaves,
+//! which is where most 64-bit features live. Not every CPU has every leaf - leaf 0 and leaf
+//! 0x8000_0000 give the highest normal and extended leaf - so [`Feature::supported`] checks the
+//! leaf exists before reading it.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/CPUID
+//! - https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html (vol 2, "CPUID")
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::{__cpuid_count, CpuidResult};
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::{__cpuid_count, CpuidResult};
+
+/// The first extended leaf.
+const EXTENDED: u32 = 0x8000_0000;
+
+/// If the CPU has the `cpuid` instruction at all. Every 64-bit CPU does, but 32-bit CPUs from
+/// before the Pentium don't - this checks if the ID bit in EFLAGS can be flipped.
+#[cfg(target_arch = "x86")]
+pub fn cpuid_supported() -> bool {
+	/// The ID bit in EFLAGS.
+	const ID: u32 = 1 << 21;
+
+	let (original, flipped): (u32, u32);
+	unsafe {
+		core::arch::asm!(
+			"pushfd",
+			"pop {original}",
+			"mov {flipped}, {original}",
+			"xor {flipped}, {id}",
+			"push {flipped}",
+			"popfd",
+			// Read it back, to see if the CPU kept the change
+			"pushfd",
+			"pop {flipped}",
+			// Then put EFLAGS back how it was
+			"push {original}",
+			"popfd",
+			original = out(reg) original,
+			flipped = out(reg) flipped,
+			id = const ID,
+		)
+	};
+
+	(original ^ flipped) & ID != 0
+}
+/// If the CPU has the `cpuid` instruction at all. Every 64-bit CPU does.
+#[cfg(target_arch = "x86_64")]
+pub fn cpuid_supported() -> bool {
+	true
+}
+
+/// Runs `cpuid` for `leaf` and `subleaf`, or returns `None` if the CPU doesn't have that leaf (or
+/// doesn't have `cpuid`, see [`cpuid_supported`]).
+pub fn cpuid(leaf: u32, subleaf: u32) -> Option<CpuidResult> {
+	#[cfg(target_arch = "x86")]
+	if !cpuid_supported() {
+		return None;
+	}
+
+	// Leaf 0 and the first extended leaf say what the highest leaf in their range is
+	let max_leaf = __cpuid_count(leaf & EXTENDED, 0).eax;
+	(leaf <= max_leaf).then(|| __cpuid_count(leaf, subleaf))
+}
+
+/// The CPU's vendor, like `GenuineIntel` or `AuthenticAMD`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Vendor([u8; 12]);
+impl Vendor {
+	/// Reads the vendor from leaf 0.
+	pub fn read() -> Self {
+		let leaf = __cpuid_count(0, 0);
+		let mut vendor = [0; 12];
+		// It's stored in EBX, EDX, ECX - not in alphabetical order
+		vendor[..4].copy_from_slice(&leaf.ebx.to_le_bytes());
+		vendor[4..8].copy_from_slice(&leaf.edx.to_le_bytes());
+		vendor[8..].copy_from_slice(&leaf.ecx.to_le_bytes());
+
+		Self(vendor)
+	}
+
+	/// The vendor string. Virtual machines sometimes use non-ASCII bytes, so this returns `"?"` if
+	/// it isn't valid UTF-8.
+	pub fn as_str(&self) -> &str {
+		core::str::from_utf8(&self.0).unwrap_or("?")
+	}
+
+	/// If this is an Intel CPU.
+	pub fn is_intel(&self) -> bool {
+		&self.0 == b"GenuineIntel"
+	}
+
+	/// If this is an AMD CPU.
+	pub fn is_amd(&self) -> bool {
+		&self.0 == b"AuthenticAMD"
+	}
+}
+impl core::fmt::Debug for Vendor {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+/// Which CPU model this is, from leaf 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Version {
+	/// The CPU family - eg, 6 for most Intel CPUs, or 0x19 for AMD Zen 3 and 4.
+	pub family: u16,
+	/// The model, within the family.
+	pub model: u8,
+	/// The revision of the model.
+	pub stepping: u8,
+}
+impl Version {
+	/// Reads the version from leaf 1.
+	pub fn read() -> Self {
+		let eax = __cpuid_count(1, 0).eax;
+		let stepping = (eax & 0xF) as u8;
+		let base_model = ((eax >> 4) & 0xF) as u8;
+		let base_family = ((eax >> 8) & 0xF) as u16;
+		let extended_model = ((eax >> 16) & 0xF) as u8;
+		let extended_family = ((eax >> 20) & 0xFF) as u16;
+
+		// The extended fields only count for newer families
+		let family = match base_family {
+			0xF => base_family + extended_family,
+			_ => base_family,
+		};
+		let model = match base_family {
+			0x6 | 0xF => (extended_model << 4) | base_model,
+			_ => base_model,
+		};
+
+		Self {
+			family,
+			model,
+			stepping,
+		}
+	}
+}
+
+/// A CPU feature that can be checked for with [`Feature::supported`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feature {
+	/// 64-bit mode.
+	LongMode,
+	/// Physical Address Extension, which 64-bit mode needs.
+	Pae,
+	/// The no-execute bit in page table entries.
+	Nx,
+	/// SSE instructions.
+	Sse,
+	/// SSE2 instructions.
+	Sse2,
+	/// The `fxsave` and `fxrstor` instructions, which save and restore the FPU and SSE registers.
+	Fxsr,
+	/// The `xsave` family of instructions, and the XCR0 register. See [`crate::fpu`].
+	Xsave,
+	/// AVX instructions. They also need XSAVE, to save the bigger registers.
+	Avx,
+	/// A local APIC. See [`crate::interrupts::apic`].
+	Apic,
+	/// The local APIC's x2APIC mode.
+	X2Apic,
+	/// 1gib pages, mapped by page directory pointer table entries.
+	GiantPages,
+	/// The `rdrand` instruction, for random numbers.
+	Rdrand,
+	/// 5-level paging. See [`crate::paging::level5`].
+	La57,
+	/// Process-context identifiers, which tag TLB entries with an address space.
+	Pcid,
+	/// The machine-check exception.
+	MachineCheck,
+	/// The machine-check architecture (the machine-check banks).
+	MachineCheckArchitecture,
+}
+impl Feature {
+	/// If the CPU supports this feature.
+	pub fn supported(self) -> bool {
+		let (leaf, subleaf, register, bit) = self.location();
+		let Some(result) = cpuid(leaf, subleaf) else {
+			return false;
+		};
+
+		let value = match register {
+			Register::Ecx => result.ecx,
+			Register::Edx => result.edx,
+		};
+		value & (1 << bit) != 0
+	}
+
+	/// Where this feature's bit is: its leaf, subleaf, register, and bit.
+	fn location(self) -> (u32, u32, Register, u8) {
+		match self {
+			Self::LongMode => (EXTENDED + 1, 0, Register::Edx, 29),
+			Self::Pae => (1, 0, Register::Edx, 6),
+			Self::Nx => (EXTENDED + 1, 0, Register::Edx, 20),
+			Self::Sse => (1, 0, Register::Edx, 25),
+			Self::Sse2 => (1, 0, Register::Edx, 26),
+			Self::Fxsr => (1, 0, Register::Edx, 24),
+			Self::Xsave => (1, 0, Register::Ecx, 26),
+			Self::Avx => (1, 0, Register::Ecx, 28),
+			Self::Apic => (1, 0, Register::Edx, 9),
+			Self::X2Apic => (1, 0, Register::Ecx, 21),
+			Self::GiantPages => (EXTENDED + 1, 0, Register::Edx, 26),
+			Self::Rdrand => (1, 0, Register::Ecx, 30),
+			Self::La57 => (7, 0, Register::Ecx, 16),
+			Self::Pcid => (1, 0, Register::Ecx, 17),
+			Self::MachineCheck => (1, 0, Register::Edx, 7),
+			Self::MachineCheckArchitecture => (1, 0, Register::Edx, 14),
+		}
+	}
+}
+
+/// The registers feature bits are in.
+enum Register {
+	Ecx,
+	Edx,
+}
@@ -0,0 +1,520 @@
+//! Works with BIOS disk services (int 13h) to read and write data on disks. This only works in real mode,
+//! so it's only available in the 16-bit boot programs.
+//!
+//! Modern BIOSes have the "enhanced disk drive" (EDD) extensions, which read disks with LBA
+//! (Logical Block Addressing). Some old BIOSes, and some USB-boot emulation modes, don't - so
+//! [`read_sectors`] (and [`write_sectors`]) fall back to CHS (Cylinder-Head-Sector) addressing, using the drive's
+//! [`Geometry`].
+//!
+//! The BIOS reports errors with the carry flag, and puts a status code in AH. Those are turned into
+//! a [`DiskError`]. Floppies and old drives sometimes fail a read just because the motor was still
+//! spinning up, so failed transfers are retried a few times after resetting the drive.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Disk_access_using_the_BIOS_(INT_13h)
+//! - https://en.wikipedia.org/wiki/INT_13H
+//! - https://en.wikipedia.org/wiki/Logical_block_addressing#CHS_conversion
+
+use {
+	crate::stages::{Stage, StageLocation, StageTable, STAGE_TABLE_LBA},
+	core::{arch::asm, mem::size_of},
+};
+
+/// How many bytes are in a sector.
+pub const SECTOR_SIZE: u16 = 512;
+/// The most sectors one int 13h call transfers. Some BIOSes can't do more than 127, so bigger
+/// transfers are split into pieces this size.
+const MAX_SECTORS: u16 = 127;
+/// The end of the memory real mode can reach, with segment:offset addressing (and A20 disabled).
+const REAL_MODE_MEMORY_END: u32 = 0x10_0000;
+/// How many times [`read_sectors`] and [`write_sectors`] retry a failed transfer before giving up.
+pub const RETRIES: usize = 3;
+
+/// An error from the BIOS' disk services.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiskError {
+	/// The BIOS doesn't support the function (status 0x01).
+	InvalidCommand,
+	/// The sector couldn't be found (status 0x02 or 0x04).
+	SectorNotFound,
+	/// The disk is write-protected (status 0x03).
+	WriteProtected,
+	/// Resetting the drive failed (status 0x05).
+	ResetFailed,
+	/// The disk was changed or removed (status 0x06 or 0x31).
+	MediaChanged,
+	/// The read would cross a 64kib boundary, which DMA can't do (status 0x09).
+	DmaBoundary,
+	/// The data was corrupted on the disk (status 0x0A, 0x0B, or 0x10).
+	BadSector,
+	/// The drive didn't respond (status 0x80 or 0xAA).
+	Timeout,
+	/// The controller or drive failed (status 0x20, 0x40, or 0xCC).
+	DriveFailure,
+	/// Any other status code.
+	Other(u8),
+	/// The BIOS doesn't support LBA, and couldn't give the drive's geometry for CHS addressing.
+	NoGeometry,
+	/// The sector is past the end of the drive.
+	OutOfRange,
+	/// There's no stage table, or it doesn't have the stage that was being loaded. See
+	/// [`crate::stages`].
+	MissingStage,
+	/// The transfer would go past the memory real mode can reach (the first mib).
+	OutOfMemory,
+}
+impl DiskError {
+	/// Converts a BIOS status code (from AH) to an error.
+	pub fn from_status(status: u8) -> Self {
+		match status {
+			0x01 => Self::InvalidCommand,
+			0x02 | 0x04 => Self::SectorNotFound,
+			0x03 => Self::WriteProtected,
+			0x05 => Self::ResetFailed,
+			0x06 | 0x31 => Self::MediaChanged,
+			0x09 => Self::DmaBoundary,
+			0x0A | 0x0B | 0x10 => Self::BadSector,
+			0x80 | 0xAA => Self::Timeout,
+			0x20 | 0x40 | 0xCC => Self::DriveFailure,
+			other => Self::Other(other),
+		}
+	}
+
+	/// If trying again (after resetting the drive) might work.
+	pub fn retryable(&self) -> bool {
+		!matches!(
+			self,
+			Self::InvalidCommand
+				| Self::WriteProtected
+				| Self::DmaBoundary
+				| Self::NoGeometry
+				| Self::OutOfRange
+				| Self::MissingStage
+				| Self::OutOfMemory
+		)
+	}
+}
+
+/// Turns the result of an int 13h call into a `Result`. `carry` is non-zero if the carry flag
+/// was set, and `ax` is AX after the call.
+fn status(carry: u16, ax: u16) -> Result<(), DiskError> {
+	match carry {
+		0 => Ok(()),
+		_ => Err(DiskError::from_status((ax >> 8) as u8)),
+	}
+}
+
+/// Finds where `stage` is, by reading the stage table at the start of the boot partition (see
+/// [`crate::stages`]), which starts at `partition_start`. The table is read to `segment:offset`, so
+/// that has to be a free sector of memory. The location is relative to `partition_start`.
+pub fn stage_location(
+	drive: u8,
+	partition_start: u64,
+	stage: Stage,
+	segment: u16,
+	offset: u16,
+) -> Result<StageLocation, DiskError> {
+	read_sectors(drive, partition_start + STAGE_TABLE_LBA, 1, segment, offset)?;
+	let address = ((segment as usize) << 4) + offset as usize;
+	let table = unsafe { &*(address as *const StageTable) };
+
+	table.get(stage).ok_or(DiskError::MissingStage)
+}
+
+/// Reads `sectors` sectors from `drive`, starting at `lba`, to `segment:offset` in memory. Uses
+/// the EDD extensions if the BIOS has them, and CHS addressing otherwise. Failed reads are retried
+/// up to [`RETRIES`] times, resetting the drive in between.
+///
+/// Any number of sectors can be read at once; they're split into pieces the BIOS can handle (see
+/// [`transfer`]).
+pub fn read_sectors(
+	drive: u8,
+	lba: u64,
+	sectors: u32,
+	segment: u16,
+	offset: u16,
+) -> Result<(), DiskError> {
+	transfer(Transfer::Read, drive, lba, sectors, segment, offset)
+}
+
+/// Writes `sectors` sectors from `segment:offset` in memory to `drive`, starting at `lba`. Works
+/// just like [`read_sectors`]. If `verify` is set, the BIOS reads the sectors back to check they
+/// were written correctly.
+pub fn write_sectors(
+	drive: u8,
+	lba: u64,
+	sectors: u32,
+	segment: u16,
+	offset: u16,
+	verify: bool,
+) -> Result<(), DiskError> {
+	transfer(
+		Transfer::Write { verify },
+		drive,
+		lba,
+		sectors,
+		segment,
+		offset,
+	)
+}
+
+/// Which way [`transfer`] moves data.
+#[derive(Clone, Copy)]
+enum Transfer {
+	Read,
+	Write { verify: bool },
+}
+
+/// Reads or writes sectors, retrying failed transfers. See [`read_sectors`].
+///
+/// Some BIOSes can't transfer more than [`MAX_SECTORS`] at once. A 16-bit offset can only reach
+/// 64kib past its segment, and DMA can't cross a 64kib boundary either. So big transfers are split
+/// up into pieces of at most [`MAX_SECTORS`], and at each 64kib boundary, with the segment moved
+/// forward for each piece.
+fn transfer(
+	direction: Transfer,
+	drive: u8,
+	mut lba: u64,
+	mut sectors: u32,
+	segment: u16,
+	offset: u16,
+) -> Result<(), DiskError> {
+	let mut address = ((segment as u32) << 4) + offset as u32;
+	if address as u64 + sectors as u64 * SECTOR_SIZE as u64 > REAL_MODE_MEMORY_END as u64 {
+		return Err(DiskError::OutOfMemory);
+	}
+
+	while sectors > 0 {
+		// Always transfer at least one sector, even if it crosses the boundary; the BIOS will say
+		// if that's a problem
+		let to_boundary = (0x1_0000 - (address & 0xFFFF)) / SECTOR_SIZE as u32;
+		let count = sectors.min(MAX_SECTORS as u32).min(to_boundary.max(1)) as u16;
+
+		// Use the highest segment possible, so the offset can't overflow
+		let (segment, offset) = ((address >> 4) as u16, (address & 0xF) as u16);
+		let mut attempts = 0;
+		loop {
+			match try_transfer(direction, drive, lba, count, segment, offset) {
+				Err(err) if err.retryable() && attempts < RETRIES => {
+					attempts += 1;
+					// If the reset fails, the retry will too
+					let _ = reset(drive);
+				}
+				result => break result?,
+			}
+		}
+
+		address += count as u32 * SECTOR_SIZE as u32;
+		lba += count as u64;
+		sectors -= count as u32;
+	}
+
+	Ok(())
+}
+
+/// [`transfer`], without retrying or splitting the transfer.
+fn try_transfer(
+	direction: Transfer,
+	drive: u8,
+	lba: u64,
+	sectors: u16,
+	segment: u16,
+	offset: u16,
+) -> Result<(), DiskError> {
+	if extensions_supported(drive) {
+		// AH = 0x42 (read) or 0x43 (write). For writes, AL = 2 means verify the write.
+		let function: u16 = match direction {
+			Transfer::Read => 0x4200,
+			Transfer::Write { verify: false } => 0x4300,
+			Transfer::Write { verify: true } => 0x4302,
+		};
+
+		let dap = DiskAddressPacket::new(lba, sectors, segment, offset);
+		let (carry, ax): (u16, u16);
+		unsafe {
+			asm!(
+				"push si",
+				"mov si, {dap:x}",
+				"int 0x13",
+				"pop si",
+				"sbb {carry:x}, {carry:x}",
+				dap = in(reg) &dap,
+				carry = lateout(reg) carry,
+				inout("ax") function => ax,
+				in("dx") drive as u16,
+			);
+		}
+
+		return status(carry, ax);
+	}
+
+	// AH = 2 (read), 3 (write), or 4 (verify), and AL = 1 sector. Some BIOSes can't transfer
+	// across tracks, so CHS transfers go one sector at a time.
+	let functions: &[u16] = match direction {
+		Transfer::Read => &[0x0201],
+		Transfer::Write { verify: false } => &[0x0301],
+		Transfer::Write { verify: true } => &[0x0301, 0x0401],
+	};
+
+	let geometry = geometry(drive).map_err(|_| DiskError::NoGeometry)?;
+	for sector in 0..sectors {
+		let (cylinder, head, sector_number) = geometry
+			.chs(lba + sector as u64)
+			.ok_or(DiskError::OutOfRange)?;
+
+		for function in functions {
+			let (carry, ax): (u16, u16);
+			unsafe {
+				asm!(
+					"push es",
+					"mov es, {segment:x}",
+					"int 0x13",
+					"pop es",
+					"sbb {carry:x}, {carry:x}",
+					segment = in(reg) segment,
+					carry = lateout(reg) carry,
+					inout("ax") *function => ax,
+					in("bx") offset + sector * SECTOR_SIZE,
+					// CH = low 8 bits of the cylinder, CL = high 2 bits of the cylinder, then the
+					// sector
+					in("cx") (cylinder << 8) | ((cylinder >> 2) & 0xC0) | sector_number as u16,
+					in("dx") ((head as u16) << 8) | drive as u16,
+				);
+			}
+			status(carry, ax)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Resets `drive` (int 13h, AH=0), which recalibrates it after a failed read.
+pub fn reset(drive: u8) -> Result<(), DiskError> {
+	let (carry, ax): (u16, u16);
+	unsafe {
+		asm!(
+			"int 0x13",
+			"sbb {carry:x}, {carry:x}",
+			carry = out(reg) carry,
+			inout("ax") 0u16 => ax,
+			in("dx") drive as u16,
+		);
+	}
+
+	status(carry, ax)
+}
+
+/// If the BIOS supports the EDD extensions for `drive`, which let it read with LBA (int 13h,
+/// AH=0x41).
+pub fn extensions_supported(drive: u8) -> bool {
+	let (carry, signature, features): (u16, u16, u16);
+	unsafe {
+		asm!(
+			"int 0x13",
+			// Non-zero if the carry flag (which means an error) is set
+			"sbb {carry:x}, {carry:x}",
+			carry = out(reg) carry,
+			inout("ax") 0x4100u16 => _,
+			inout("bx") 0x55AAu16 => signature,
+			out("cx") features,
+			in("dx") drive as u16,
+		);
+	}
+
+	// The signature gets swapped if the extensions are installed, and bit 0 of the features means
+	// the DAP-based functions work
+	carry == 0 && signature == 0xAA55 && features & 1 != 0
+}
+
+/// A drive's geometry, for CHS addressing.
+#[derive(Clone, Copy, Debug)]
+pub struct Geometry {
+	/// How many cylinders the drive has.
+	pub cylinders: u16,
+	/// How many heads the drive has.
+	pub heads: u16,
+	/// How many sectors are in each track. Sectors are numbered from 1.
+	pub sectors_per_track: u8,
+}
+impl Geometry {
+	/// How many sectors CHS addressing can reach.
+	pub fn sectors(&self) -> u64 {
+		self.cylinders as u64 * self.heads as u64 * self.sectors_per_track as u64
+	}
+
+	/// Converts `lba` to `(cylinder, head, sector)`. Returns `None` if `lba` is past the end of the
+	/// drive, as far as CHS can see.
+	pub fn chs(&self, lba: u64) -> Option<(u16, u8, u8)> {
+		let sectors_per_track = self.sectors_per_track as u64;
+		let track = lba / sectors_per_track;
+		let cylinder = track / self.heads as u64;
+		if cylinder >= self.cylinders as u64 {
+			return None;
+		}
+
+		Some((
+			cylinder as u16,
+			(track % self.heads as u64) as u8,
+			(lba % sectors_per_track) as u8 + 1,
+		))
+	}
+}
+
+/// Gets `drive`'s geometry from the BIOS (int 13h, AH=0x08).
+pub fn geometry(drive: u8) -> Result<Geometry, DiskError> {
+	legacy_parameters(drive).map(|(geometry, _)| geometry)
+}
+
+/// How many hard drives the BIOS found (int 13h, AH=0x08). They're numbered from 0x80.
+pub fn drive_count() -> Result<u8, DiskError> {
+	legacy_parameters(0x80).map(|(_, count)| count)
+}
+
+/// Gets `drive`'s geometry, and how many drives of its type (floppy or hard drive) there are (int
+/// 13h, AH=0x08).
+fn legacy_parameters(drive: u8) -> Result<(Geometry, u8), DiskError> {
+	let (carry, ax, cx, dx): (u16, u16, u16, u16);
+	unsafe {
+		asm!(
+			// Some BIOSes need ES:DI to be 0, and it gets overwritten with a pointer to a floppy
+			// parameter table
+			"push es",
+			"push di",
+			"xor di, di",
+			"mov es, di",
+			"int 0x13",
+			"pop di",
+			"pop es",
+			"sbb {carry:x}, {carry:x}",
+			carry = out(reg) carry,
+			inout("ax") 0x0800u16 => ax,
+			out("bx") _,
+			out("cx") cx,
+			inout("dx") drive as u16 => dx,
+		);
+	}
+
+	status(carry, ax)?;
+	let sectors_per_track = (cx & 0x3F) as u8;
+	if sectors_per_track == 0 {
+		return Err(DiskError::NoGeometry);
+	}
+
+	// These are all the highest valid number, not how many there are
+	let geometry = Geometry {
+		cylinders: ((cx >> 8) | ((cx & 0xC0) << 2)) + 1,
+		heads: (dx >> 8) + 1,
+		sectors_per_track,
+	};
+
+	Ok((geometry, dx as u8))
+}
+
+/// Everything the BIOS knows about a drive's size. See [`drive_parameters`].
+#[derive(Clone, Copy, Debug)]
+pub struct DriveParameters {
+	/// The drive's geometry, for CHS addressing.
+	pub geometry: Geometry,
+	/// How many bytes are in a sector. Usually [`SECTOR_SIZE`], but CDs have 2048-byte sectors.
+	pub sector_size: u16,
+	/// How many sectors the drive has - the first LBA that can't be read.
+	pub sectors: u64,
+}
+
+/// Gets `drive`'s size. Uses the EDD extensions (int 13h, AH=0x48) if the BIOS has them, and
+/// otherwise works it out from the drive's [`Geometry`], assuming 512-byte sectors.
+pub fn drive_parameters(drive: u8) -> Result<DriveParameters, DiskError> {
+	if !extensions_supported(drive) {
+		let geometry = geometry(drive)?;
+		return Ok(DriveParameters {
+			geometry,
+			sector_size: SECTOR_SIZE,
+			sectors: geometry.sectors(),
+		});
+	}
+
+	let mut parameters = ExtendedDriveParameters {
+		size: size_of::<ExtendedDriveParameters>() as u16,
+		..Default::default()
+	};
+	let (carry, ax): (u16, u16);
+	unsafe {
+		asm!(
+			"push si",
+			"mov si, {parameters:x}",
+			"int 0x13",
+			"pop si",
+			"sbb {carry:x}, {carry:x}",
+			parameters = in(reg) &mut parameters,
+			carry = lateout(reg) carry,
+			inout("ax") 0x4800u16 => ax,
+			in("dx") drive as u16,
+		);
+	}
+	status(carry, ax)?;
+
+	// Bit 1 of the flags means the geometry is valid. Otherwise it's probably a removable drive,
+	// which only has a geometry while there's media in it.
+	let geometry = if parameters.flags & 0b10 != 0 {
+		Geometry {
+			cylinders: parameters.cylinders.min(u16::MAX as u32) as u16,
+			heads: parameters.heads.min(u16::MAX as u32) as u16,
+			sectors_per_track: parameters.sectors_per_track.min(u8::MAX as u32) as u8,
+		}
+	} else {
+		geometry(drive)?
+	};
+
+	Ok(DriveParameters {
+		geometry,
+		sector_size: parameters.sector_size,
+		sectors: parameters.sectors,
+	})
+}
+
+/// The buffer int 13h AH=0x48 fills in.
+#[repr(C, packed)]
+#[derive(Default)]
+struct ExtendedDriveParameters {
+	/// The size of this buffer.
+	size: u16,
+	flags: u16,
+	cylinders: u32,
+	heads: u32,
+	sectors_per_track: u32,
+	/// How many sectors the drive has.
+	sectors: u64,
+	sector_size: u16,
+}
+
+/// Used in LBA addressing to specify a part of a disk to read or write, and where it is in memory.
+#[repr(C, packed)]
+pub struct DiskAddressPacket {
+	/// The size of this packet. Should be 16, for 16 bytes.
+	pub size: u8,
+	/// A reserved byte - always 0
+	pub reserved: u8,
+	/// How many sectors to read from the disk - some BIOSes cap this to 127, so [`read_sectors`]
+	/// never uses more than that
+	pub sectors: u16,
+	/// An offset, starting at <segment>, to the memory address the disk data should be loaded to.
+	pub offset: u16,
+	/// A memory segment where the disk data will be loaded to. It'll specifically be loaded to <segment> + <offset>.
+	pub segment: u16,
+	/// The LBA to read - AKA, the sector to read. This is a 48-bit value, but has padding after it so it
+	/// ends up being 8 bytes.
+	pub lba: u64,
+}
+impl DiskAddressPacket {
+	/// A packet for transferring `sectors` sectors, starting at `lba`, to or from `segment:offset`.
+	pub fn new(lba: u64, sectors: u16, segment: u16, offset: u16) -> Self {
+		Self {
+			size: 16,
+			reserved: 0,
+			sectors,
+			offset,
+			segment,
+			lba,
+		}
+	}
+}
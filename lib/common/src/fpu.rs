@@ -0,0 +1,115 @@
+//! Turns on the FPU and SSE, so code can use floating point and SIMD instructions.
+//!
+//! Every x86_64 CPU has an x87 FPU and SSE, but the OS has to opt in to SSE before its instructions
+//! work (otherwise they fault with an invalid opcode), by promising it saves the SSE registers on
+//! context switches:
+//! - CR0: EM (emulate the FPU) has to be clear, and MP (monitor coprocessor) set, so FPU
+//!   instructions actually run; TS (task switched) is cleared so they don't fault yet. NE makes
+//!   FPU errors raise exception 16, instead of the legacy external interrupt.
+//! - CR4: OSFXSR says the OS uses `fxsave`/`fxrstor`, which turns on SSE. OSXMMEXCPT says it
+//!   handles SIMD floating point exceptions (exception 19).
+//!
+//! If the CPU has XSAVE, [`enable`] also sets CR4.OSXSAVE and turns on the x87 and SSE state in
+//! XCR0 (plus AVX, if the CPU has it), so `xsave`/`xrstor` can save everything at once.
+//!
+//! The kernel itself is built without SSE (`x86_64-unknown-none` uses soft floats), but user-mode
+//! programs expect it.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/SSE
+//! - https://wiki.osdev.org/FPU
+//! - https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html (vol 3, section 13.1)
+
+use {crate::cpuid::Feature, core::arch::asm};
+
+/// The monitor coprocessor bit in CR0.
+const CR0_MP: usize = 1 << 1;
+/// The FPU emulation bit in CR0.
+const CR0_EM: usize = 1 << 2;
+/// The task switched bit in CR0.
+const CR0_TS: usize = 1 << 3;
+/// The numeric error bit in CR0.
+const CR0_NE: usize = 1 << 5;
+/// The OSFXSR bit in CR4.
+const CR4_OSFXSR: usize = 1 << 9;
+/// The OSXMMEXCPT bit in CR4.
+const CR4_OSXMMEXCPT: usize = 1 << 10;
+/// The OSXSAVE bit in CR4.
+const CR4_OSXSAVE: usize = 1 << 18;
+
+/// The x87 state in XCR0. It always has to be set.
+const XCR0_X87: u64 = 1 << 0;
+/// The SSE state in XCR0.
+const XCR0_SSE: u64 = 1 << 1;
+/// The AVX state in XCR0. It needs the SSE state too.
+const XCR0_AVX: u64 = 1 << 2;
+
+/// What [`enable`] turned on, besides the FPU and SSE.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FpuFeatures {
+	/// The `xsave` instructions, and XCR0.
+	pub xsave: bool,
+	/// AVX, which needs XSAVE.
+	pub avx: bool,
+}
+
+/// If the CPU has SSE, and the `fxsave` instructions the OS needs to save its registers (CPUID leaf
+/// 1, EDX bits 24 and 25).
+pub fn sse_supported() -> bool {
+	Feature::Fxsr.supported() && Feature::Sse.supported()
+}
+
+/// Turns on the FPU and SSE, and XSAVE and AVX if the CPU has them (see the module-level docs).
+/// Returns `None`, without changing anything, if the CPU doesn't have SSE (see
+/// [`sse_supported`]).
+///
+/// # Safety
+/// Once this is done, FPU and SSE registers can change, so anything that switches between threads
+/// has to save and restore them.
+pub unsafe fn enable() -> Option<FpuFeatures> {
+	if !sse_supported() {
+		return None;
+	}
+
+	let xsave = Feature::Xsave.supported();
+	let avx = xsave && Feature::Avx.supported();
+	let mut cr4_flags = CR4_OSFXSR | CR4_OSXMMEXCPT;
+	if xsave {
+		cr4_flags |= CR4_OSXSAVE;
+	}
+
+	unsafe {
+		asm!(
+			"mov {0}, cr0",
+			"and {0}, {1}",
+			"or {0}, {2}",
+			"mov cr0, {0}",
+			"mov {0}, cr4",
+			"or {0}, {3}",
+			"mov cr4, {0}",
+			"fninit",
+			out(reg) _,
+			in(reg) !(CR0_EM | CR0_TS),
+			in(reg) CR0_MP | CR0_NE,
+			in(reg) cr4_flags,
+			options(nostack)
+		);
+
+		if xsave {
+			let mut xcr0 = XCR0_X87 | XCR0_SSE;
+			if avx {
+				xcr0 |= XCR0_AVX;
+			}
+			// XCR0 is extended control register 0, written from EDX:EAX
+			asm!(
+				"xsetbv",
+				in("ecx") 0,
+				in("eax") xcr0 as u32,
+				in("edx") (xcr0 >> 32) as u32,
+				options(nomem, nostack, preserves_flags)
+			);
+		}
+	}
+
+	Some(FpuFeatures { xsave, avx })
+}
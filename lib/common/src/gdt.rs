@@ -11,10 +11,29 @@
 //! The GDT is not stored directly in x86. Instead, the GDTR register stores a *GDT Descriptor*, which stores the size and
 //! location of the GDT.
 //!
+//! If the kernel uses `syscall`/`sysret`, the 64-bit segments have to be in a specific order, because `sysret` doesn't
+//! read the GDT - it just assumes the user segments come right after each other:
+//!
+//! ```txt
+//! | null | kernel code | kernel data | user data | user code |
+//! ```
+//!
+//! The STAR MSR then gets the kernel code selector (for `syscall`) and the kernel data selector (`sysret` adds 8 to get
+//! the user data selector and 16 to get the user code selector). Data comes before code for user mode, which is the
+//! opposite of the kernel segments.
+//!
+//! In 64-bit mode, the GDT also holds the Task State Segment (TSS), through a special 16-byte system segment descriptor.
+//! The TSS used to be for hardware task switching, but now it just stores stack pointers: the stacks the CPU switches to
+//! when an interrupt arrives from a lower privilege level, and the Interrupt Stack Table (IST), which interrupts can use
+//! to always get a known-good stack (even if the current one overflowed).
+//!
 //! Resources:
 //! - https://wiki.osdev.org/Global_Descriptor_Table
 //! - https://wiki.osdev.org/GDT_Tutorial
 //! - https://www.cs.bham.ac.uk/~exr/lectures/opsys/10_11/lectures/os-dev.pdf (the "Entering 32-bit Protected Mode" chapter)
+//! - https://wiki.osdev.org/Task_State_Segment
+
+use core::arch::asm;
 
 /// For whatever reason, some values in the GDT are u20s. Since there's no u20 type, a u32 is used instead, and verified
 /// as a u20 by making sure it's less than this.
@@ -24,6 +43,7 @@ pub const U20_MAX: u32 = 0b0000_0000_0000_1111_1111_1111_1111_1111;
 pub type SegmentDescriptor = [u8; 8];
 
 /// The GDT is made up of Segment Descriptors, 8-byte structures that describe & configure a segment of memory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct SegmentDescriptorBuilder {
 	/// The minimum address for this region of memory.
 	pub base: u32,
@@ -37,29 +57,88 @@ pub struct SegmentDescriptorBuilder {
 	pub access: SegmentAccessBuilder,
 }
 impl SegmentDescriptorBuilder {
+	/// A 64-bit code segment for the kernel (ring 0).
+	pub const fn kernel_code64() -> Self {
+		Self::flat64(0, true)
+	}
+
+	/// A data segment for the kernel (ring 0).
+	pub const fn kernel_data() -> Self {
+		Self::flat64(0, false)
+	}
+
+	/// A 64-bit code segment for user mode (ring 3). See the module-level docs for where it has to go in the GDT.
+	pub const fn user_code64() -> Self {
+		Self::flat64(3, true)
+	}
+
+	/// A data segment for user mode (ring 3). See the module-level docs for where it has to go in the GDT.
+	pub const fn user_data() -> Self {
+		Self::flat64(3, false)
+	}
+
+	/// A segment for 64-bit mode, spanning all memory. The CPU ignores the base and limit in 64-bit mode, but they're
+	/// set to cover everything anyways.
+	const fn flat64(privilege: u8, executable: bool) -> Self {
+		Self {
+			base: 0,
+			limit: U20_MAX,
+			flags: SegmentFlagsBuilder {
+				paged_limit: true,
+				// Data segments ignore the long flag, and code segments can't have both
+				protected: !executable,
+				long: executable,
+			},
+			access: SegmentAccessBuilder {
+				present: true,
+				privilege,
+				non_system: true,
+				executable,
+				direction_conforming: false,
+				read_write: true,
+				// Set ahead of time, so the CPU doesn't try to write it if the GDT is in read-only memory
+				accessed: true,
+			},
+		}
+	}
+
+	/// Decodes an 8-byte segment descriptor. The opposite of [`SegmentDescriptorBuilder::build`].
+	pub const fn decode(descriptor: SegmentDescriptor) -> Self {
+		let [limit0, limit1, base0, base1, base2, access, flags, base3] = descriptor;
+
+		Self {
+			base: u32::from_le_bytes([base0, base1, base2, base3]),
+			limit: u32::from_le_bytes([limit0, limit1, flags & 0x0F, 0]),
+			flags: SegmentFlagsBuilder::decode(flags),
+			access: SegmentAccessBuilder::decode(access),
+		}
+	}
+
 	/// Builds an 8-byte segment descriptor.
 	pub const fn build(self) -> SegmentDescriptor {
 		if self.limit > U20_MAX {
 			panic!("A memory segment's limit must fit in a u20");
 		}
 
-		let limit = self.limit >> 4;
-		let limit = limit.to_ne_bytes();
-		let base = self.base.to_ne_bytes();
+		// The limit and base are split up across the descriptor:
+		// | limit 0..16 | base 0..24 | access | flags, limit 16..20 | base 24..32 |
+		let limit = self.limit.to_le_bytes();
+		let base = self.base.to_le_bytes();
 		[
+			limit[0],
+			limit[1],
 			base[0],
-			self.flags.build() | limit[0],
-			self.access.build(),
 			base[1],
 			base[2],
+			self.access.build(),
+			self.flags.build() | limit[2],
 			base[3],
-			limit[1],
-			limit[2],
 		]
 	}
 }
 
 /// The segment's access byte controls permissions for this memory segment.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct SegmentAccessBuilder {
 	/// If this segment is in-memory.
 	pub present: bool,
@@ -92,6 +171,19 @@ pub struct SegmentAccessBuilder {
 	pub accessed: bool,
 }
 impl SegmentAccessBuilder {
+	/// Decodes an access byte. The opposite of [`SegmentAccessBuilder::build`].
+	pub const fn decode(access: u8) -> Self {
+		Self {
+			present: access & 0b1000_0000 != 0,
+			privilege: (access >> 5) & 0b11,
+			non_system: access & 0b0001_0000 != 0,
+			executable: access & 0b0000_1000 != 0,
+			direction_conforming: access & 0b0000_0100 != 0,
+			read_write: access & 0b0000_0010 != 0,
+			accessed: access & 0b0000_0001 != 0,
+		}
+	}
+
 	/// Builds the actual, byte-sized access flags struct.
 	pub const fn build(self) -> u8 {
 		let mut result = 0;
@@ -129,6 +221,7 @@ impl SegmentAccessBuilder {
 }
 
 /// The segment's flags configure if the segment limit is in bytes or pages and the segment's bitness.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct SegmentFlagsBuilder {
 	/// When true, the segment limit is evaluated in 4kib pages. When false, it's evaluated in bytes.
 	pub paged_limit: bool,
@@ -139,6 +232,16 @@ pub struct SegmentFlagsBuilder {
 	pub long: bool,
 }
 impl SegmentFlagsBuilder {
+	/// Decodes the segment flags, from the byte they share with the limit. The opposite of
+	/// [`SegmentFlagsBuilder::build`].
+	pub const fn decode(flags: u8) -> Self {
+		Self {
+			paged_limit: flags & 0b1000_0000 != 0,
+			protected: flags & 0b0100_0000 != 0,
+			long: flags & 0b0010_0000 != 0,
+		}
+	}
+
 	/// Builds the 4-bit-sized segment flags struct.
 	pub const fn build(self) -> u8 {
 		let mut result = 0;
@@ -161,8 +264,155 @@ impl SegmentFlagsBuilder {
 	}
 }
 
+/// The Task State Segment for 64-bit mode. See the module-level docs.
+#[repr(C, packed(4))]
+#[derive(Clone, Copy, Debug)]
+pub struct Tss {
+	_reserved1: u32,
+	/// The stack pointers the CPU switches to when an interrupt changes the privilege level to 0, 1, or 2 (RSP0-2).
+	/// Taking interrupts from ring 3 needs at least RSP0.
+	pub privilege_stacks: [u64; 3],
+	_reserved2: u64,
+	/// The Interrupt Stack Table (IST1-7). Interrupt descriptors can pick one of these stacks to always switch to,
+	/// by its index plus 1 (since 0 means no IST stack).
+	pub interrupt_stacks: [u64; 7],
+	_reserved3: u64,
+	_reserved4: u16,
+	/// The offset from the start of the TSS to the I/O permission bitmap (IOPB). If it's at least the TSS' limit,
+	/// there's no bitmap, and user mode can't use any I/O ports. See [`TssWithIoBitmap`].
+	pub iopb_offset: u16,
+}
+impl Tss {
+	/// Creates a TSS with no stacks and no I/O permission bitmap.
+	pub const fn new() -> Self {
+		Self {
+			_reserved1: 0,
+			privilege_stacks: [0; 3],
+			_reserved2: 0,
+			interrupt_stacks: [0; 7],
+			_reserved3: 0,
+			_reserved4: 0,
+			iopb_offset: size_of::<Self>() as u16,
+		}
+	}
+}
+impl Default for Tss {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// A TSS followed by an I/O permission bitmap, which controls which I/O ports user mode can use. Each bit is a port;
+/// the port is allowed if the bit is 0. Ports past the end of the bitmap are never allowed. Use
+/// [`TssDescriptorBuilder::with_io_bitmap`] to make its descriptor.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TssWithIoBitmap<const BYTES: usize> {
+	pub tss: Tss,
+	/// One bit per port, starting at port 0.
+	pub io_bitmap: [u8; BYTES],
+	/// The CPU may read a byte past the end of the bitmap, which has to be all 1s.
+	terminator: u8,
+}
+impl<const BYTES: usize> TssWithIoBitmap<BYTES> {
+	/// Creates a TSS with no stacks, and a bitmap that doesn't allow any ports.
+	pub const fn new() -> Self {
+		// The bitmap comes right after the TSS, which is where `Tss::new` already points the offset
+		Self {
+			tss: Tss::new(),
+			io_bitmap: [0xFF; BYTES],
+			terminator: 0xFF,
+		}
+	}
+
+	/// Lets user mode use (or stops it from using) `port`. Panics if `port` is past the end of the bitmap.
+	pub fn set_port_allowed(&mut self, port: u16, allowed: bool) {
+		let byte = &mut self.io_bitmap[port as usize / 8];
+		let bit = 1 << (port % 8);
+		if allowed {
+			*byte &= !bit;
+		} else {
+			*byte |= bit;
+		}
+	}
+}
+impl<const BYTES: usize> Default for TssWithIoBitmap<BYTES> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// System segment descriptors (like the TSS descriptor) are 16 bytes in 64-bit mode, so they take up 2 slots in the
+/// GDT.
+pub type SystemSegmentDescriptor = [SegmentDescriptor; 2];
+
+/// Builds the GDT entry for a [`Tss`].
+pub struct TssDescriptorBuilder {
+	/// The address of the TSS.
+	pub base: u64,
+	/// The size of the TSS (including any I/O permission bitmap) in bytes, minus 1.
+	pub limit: u32,
+	/// The privilege level needed to access this descriptor. Should normally be 0.
+	pub privilege: u8,
+}
+impl TssDescriptorBuilder {
+	/// Describes `tss`, without an I/O permission bitmap.
+	pub fn new(tss: &'static Tss) -> Self {
+		Self {
+			base: tss as *const Tss as u64,
+			limit: size_of::<Tss>() as u32 - 1,
+			privilege: 0,
+		}
+	}
+
+	/// Describes `tss` and its I/O permission bitmap.
+	pub fn with_io_bitmap<const BYTES: usize>(tss: &'static TssWithIoBitmap<BYTES>) -> Self {
+		Self {
+			base: tss as *const _ as u64,
+			limit: size_of::<TssWithIoBitmap<BYTES>>() as u32 - 1,
+			privilege: 0,
+		}
+	}
+
+	/// Builds the 16-byte system segment descriptor.
+	pub const fn build(self) -> SystemSegmentDescriptor {
+		if self.limit > U20_MAX {
+			panic!("A memory segment's limit must fit in a u20");
+		}
+
+		let access = SegmentAccessBuilder {
+			present: true,
+			privilege: self.privilege,
+			non_system: false,
+			// System segments reuse the low 4 bits of the access byte as their type; 0b1001 is an available 64-bit TSS
+			executable: true,
+			direction_conforming: false,
+			read_write: false,
+			accessed: true,
+		}
+		.build();
+
+		let limit = self.limit.to_le_bytes();
+		let base = self.base.to_le_bytes();
+		[
+			[
+				limit[0], limit[1], base[0], base[1], base[2], access, limit[2], base[3],
+			],
+			[base[4], base[5], base[6], base[7], 0, 0, 0, 0],
+		]
+	}
+}
+
+/// Loads the TSS whose descriptor is at `selector` in the GDT (its byte offset into the GDT) into the task register.
+///
+/// # Safety
+/// `selector` must point to a valid TSS descriptor in the loaded GDT, and the TSS must live forever.
+pub unsafe fn load_tss(selector: u16) {
+	unsafe { asm!("ltr {0:x}", in(reg) selector, options(nostack, preserves_flags)) };
+}
+
 /// Metadata about the GDT. This struct is what is actually stored in x86, instead of the GDT being stored directly.
-#[repr(packed)]
+#[repr(C, packed)]
 pub struct GdtDescriptor {
 	/// The size of the GDT in bytes, minus 1. The subtraction occurs because the max value of a u16 is 1 less than
 	/// the maximum possible size of the GDT. I think this happens because the GDT always has to have at least 1 value,
@@ -171,3 +421,213 @@ pub struct GdtDescriptor {
 	/// The address of the GDT. This is a u32 on 32-bit systems and a u64 on 64-bit systems.
 	pub offset: u64,
 }
+
+impl GdtDescriptor {
+	/// Reads the GDT descriptor that's currently loaded, with `sgdt`. Useful for finding out what the firmware or an
+	/// earlier boot stage left loaded.
+	pub fn current() -> Self {
+		let mut descriptor = Self { size: 0, offset: 0 };
+		// In 32-bit mode, this only writes the low 4 bytes of the offset, so the rest stays 0
+		unsafe { asm!("sgdt [{}]", in(reg) &mut descriptor, options(nostack, preserves_flags)) };
+
+		descriptor
+	}
+
+	/// The segment descriptors in the GDT this describes.
+	///
+	/// # Safety
+	/// The GDT has to be readable at its offset, and can't change while the slice exists.
+	pub unsafe fn entries<'a>(&self) -> &'a [SegmentDescriptor] {
+		let len = (self.size as usize + 1) / size_of::<SegmentDescriptor>();
+		unsafe {
+			core::slice::from_raw_parts(self.offset as usize as *const SegmentDescriptor, len)
+		}
+	}
+
+	/// Decodes every segment descriptor in the GDT this describes. System segment descriptors (like the TSS' 16-byte
+	/// descriptor) aren't decoded specially, so they show up as their raw halves.
+	///
+	/// # Safety
+	/// See [`GdtDescriptor::entries`].
+	pub unsafe fn decode<'a>(&self) -> impl Iterator<Item = SegmentDescriptorBuilder> + 'a {
+		unsafe { self.entries() }
+			.iter()
+			.map(|descriptor| SegmentDescriptorBuilder::decode(*descriptor))
+	}
+}
+
+/// A GDT that can hold up to `N` segment descriptors, including the null descriptor (and 2 for each system segment
+/// descriptor). It can be built in a `static`:
+///
+/// ```ignore
+/// static GDT: Gdt<3> = {
+///     let mut gdt = Gdt::new();
+///     gdt.add(SegmentDescriptorBuilder::kernel_code64().build());
+///     gdt.add(SegmentDescriptorBuilder::kernel_data().build());
+///     gdt
+/// };
+/// ```
+#[repr(C, align(8))]
+pub struct Gdt<const N: usize> {
+	entries: [SegmentDescriptor; N],
+	len: usize,
+}
+impl<const N: usize> Gdt<N> {
+	/// Creates a GDT with just the null descriptor.
+	pub const fn new() -> Self {
+		if N == 0 || N > 8192 {
+			panic!("A GDT must have between 1 and 8192 entries");
+		}
+
+		Self {
+			entries: [[0; 8]; N],
+			len: 1,
+		}
+	}
+
+	/// Adds a segment descriptor, returning its selector. Panics if the GDT is full.
+	pub const fn add(&mut self, descriptor: SegmentDescriptor) -> u16 {
+		if self.len >= N {
+			panic!("The GDT is full");
+		}
+
+		self.entries[self.len] = descriptor;
+		self.len += 1;
+
+		Self::selector(self.len - 1)
+	}
+
+	/// Adds a system segment descriptor (like a TSS descriptor), returning its selector. Panics if the GDT is full.
+	pub const fn add_system(&mut self, descriptor: SystemSegmentDescriptor) -> u16 {
+		let selector = self.add(descriptor[0]);
+		self.add(descriptor[1]);
+
+		selector
+	}
+
+	/// The selector for the descriptor at `index`: its offset in bytes from the start of the GDT.
+	pub const fn selector(index: usize) -> u16 {
+		(index * size_of::<SegmentDescriptor>()) as u16
+	}
+
+	/// The descriptors in this GDT.
+	pub fn entries(&self) -> &[SegmentDescriptor] {
+		&self.entries[..self.len]
+	}
+
+	/// The descriptor to give `lgdt` for this GDT.
+	pub fn descriptor(&'static self) -> GdtDescriptor {
+		GdtDescriptor {
+			size: (self.len * size_of::<SegmentDescriptor>() - 1) as u16,
+			offset: self.entries.as_ptr() as u64,
+		}
+	}
+
+	/// Loads this GDT with `lgdt`. The segment registers keep using the old segments until they're reloaded (see
+	/// [`reload_segments`]).
+	///
+	/// # Safety
+	/// The GDT must stay valid for as long as it's loaded, and the segments the segment registers will get reloaded
+	/// with must be valid.
+	pub unsafe fn load(&'static self) {
+		let descriptor = self.descriptor();
+		unsafe {
+			asm!("lgdt [{}]", in(reg) &descriptor, options(readonly, nostack, preserves_flags))
+		};
+	}
+}
+impl<const N: usize> Default for Gdt<N> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// Reloads every segment register, so they use the segments in the currently loaded GDT. CS can only be changed with
+/// a far jump or return, so this does a far return to the next instruction with `code` as the new CS. The other
+/// segment registers get `data`.
+///
+/// # Safety
+/// `code` has to be a 64-bit code segment and `data` has to be a data segment in the loaded GDT, both for ring 0.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn reload_segments(code: u16, data: u16) {
+	unsafe {
+		asm!(
+			"push {code}",
+			"lea {tmp}, [rip + 2f]",
+			"push {tmp}",
+			"retfq",
+			"2:",
+			"mov ds, {data:x}",
+			"mov es, {data:x}",
+			"mov fs, {data:x}",
+			"mov gs, {data:x}",
+			"mov ss, {data:x}",
+			code = in(reg) code as u64,
+			data = in(reg) data,
+			tmp = out(reg) _,
+			options(preserves_flags)
+		)
+	};
+}
+
+/// Enters unreal mode: real mode, but with DS, ES, FS, and GS using `data`'s limit (usually all 4gib of memory), so
+/// 16-bit code can reach memory past 1mib with 32-bit addresses. Their bases are set back to 0 afterwards. The CPU only
+/// reads a segment's limit from the GDT when the segment is loaded in protected mode, so this switches to protected
+/// mode just long enough to load `data`, then switches back.
+///
+/// Loading a segment register in real mode keeps the limit, but BIOS calls that use protected mode themselves can
+/// reset it, so this should be called again after BIOS calls.
+///
+/// # Safety
+/// `data` has to be a data segment in the loaded GDT.
+#[cfg(target_arch = "x86")]
+pub unsafe fn enter_unreal_mode(data: u16) {
+	unsafe {
+		// Interrupts would use the real-mode IVT while protected mode is on, so they're held off until it's off again.
+		// CS isn't reloaded, so this keeps running as 16-bit code the whole time.
+		asm!(
+			"pushfd",
+			"cli",
+			"mov {tmp:e}, cr0",
+			"or {tmp:e}, 1",
+			"mov cr0, {tmp:e}",
+			"mov ds, {data:x}",
+			"mov es, {data:x}",
+			"mov fs, {data:x}",
+			"mov gs, {data:x}",
+			"and {tmp:e}, -2",
+			"mov cr0, {tmp:e}",
+			"xor {tmp:e}, {tmp:e}",
+			"mov ds, {tmp:x}",
+			"mov es, {tmp:x}",
+			"mov fs, {tmp:x}",
+			"mov gs, {tmp:x}",
+			"popfd",
+			data = in(reg) data,
+			tmp = out(reg) _,
+		)
+	}
+}
+
+/// Jumps to 64-bit code at `address`, loading `code` into CS. From 16- or 32-bit code, this is the only way to
+/// actually start running in 64-bit mode after enabling it; until CS is reloaded, the CPU keeps running the old
+/// code in compatibility mode. The code at `address` should reload the other segment registers with
+/// [`reload_segments`].
+///
+/// # Safety
+/// 64-bit mode must be enabled, `code` has to be a 64-bit code segment in the loaded GDT, and `address` has to be
+/// 64-bit code that never returns.
+#[cfg(target_arch = "x86")]
+pub unsafe fn far_jump(code: u16, address: u32) -> ! {
+	unsafe {
+		// The return has to pop a 32-bit address and CS, even from 16-bit code, so the operand sizes are spelled out
+		asm!(
+			"pushl {code}",
+			"pushl {address}",
+			"lretl",
+			code = in(reg) code as u32,
+			address = in(reg) address,
+			options(att_syntax, noreturn)
+		)
+	}
+}
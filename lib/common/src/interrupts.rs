@@ -1,22 +1,98 @@
 //! Types for interrupt handling. Interrupts are given to the CPU when
 //! certain events happen, like a key being pressed or a click ticking.
-//! This is currently incomplete.
+//! Each interrupt has a vector (0-255), and the Interrupt Descriptor Table
+//! (IDT) has a descriptor for each vector, pointing to the function that
+//! handles it.
+//!
+//! CPU exceptions, and their default handlers, are in [`exceptions`] (only in 64-bit mode, since
+//! the stack frame is different in 32-bit mode). Hardware errors (NMIs and machine checks) are in
+//! [`machine_check`].
+//!
+//! Hardware interrupts come from an interrupt controller - [`pic`] is the legacy one, and [`apic`]
+//! replaces it on modern hardware (with [`ioapic`] routing hardware interrupts to it). They only
+//! arrive while interrupts are enabled (see [`enable`]), and drivers claim them through [`irq`].
+//! [`pit`] is the legacy timer, which also has a busy-wait sleep for before there's a scheduler.
+//!
+//! Stacks mapped with guard pages (see [`crate::paging::guard`]) can be registered with
+//! [`register_stack`], so the page fault handler can tell a stack overflow apart from any other
+//! page fault with [`stack_overflow`]. Overflows usually end up as double faults, since the page
+//! fault can't be pushed onto the stack that overflowed, so the double fault handler checks too. [`ist`] uses them for the interrupts that need their own
+//! stack, like double faults.
 //!
 //! Resources:
 //! - https://wiki.osdev.org/Interrupt_Descriptor_Table
 //! - https://wiki.osdev.org/Interrupt_Service_Routines
 
+pub mod apic;
+#[cfg(target_arch = "x86_64")]
+pub mod exceptions;
+pub mod ioapic;
+#[cfg(target_arch = "x86_64")]
+pub mod irq;
+pub mod ist;
+#[cfg(target_arch = "x86_64")]
+pub mod machine_check;
+pub mod pic;
+pub mod pit;
+
+use {
+	crate::{
+		paging::GuardedStack,
+		sync::{InterruptGuard, Spinlock},
+	},
+	core::arch::asm,
+};
+
 /// The Interrupt Descriptor Table. Stores a list of interrupt descriptors,
-/// which all store handlers for interrupts.
-#[repr(transparent)]
+/// which all store handlers for interrupts. The interrupt's vector is its
+/// index in the table.
+#[repr(C, align(16))]
 pub struct Idt<const LEN: usize> {
 	/// All the interrupts in this IDT.
 	pub interrupts: [InterruptDescriptor; LEN],
 }
+impl<const LEN: usize> Idt<LEN> {
+	/// Creates an IDT where every interrupt is missing. Panics if `LEN` is
+	/// more than 256, since there are only 256 interrupt vectors.
+	pub const fn new() -> Self {
+		if LEN > 256 {
+			panic!("An IDT can have at most 256 entries");
+		}
+
+		Self {
+			interrupts: [InterruptDescriptor::NULL; LEN],
+		}
+	}
+
+	/// The descriptor to give `lidt` for this IDT.
+	pub fn descriptor(&'static self) -> IdtDescriptor {
+		IdtDescriptor {
+			size: (size_of::<Self>() - 1) as u16,
+			offset: self.interrupts.as_ptr() as u64,
+		}
+	}
+
+	/// Loads this IDT with `lidt`.
+	///
+	/// # Safety
+	/// Every present descriptor must point to a valid interrupt handler, and
+	/// the IDT must stay valid for as long as it's loaded.
+	pub unsafe fn load(&'static self) {
+		let descriptor = self.descriptor();
+		unsafe {
+			asm!("lidt [{}]", in(reg) &descriptor, options(readonly, nostack, preserves_flags))
+		};
+	}
+}
+impl<const LEN: usize> Default for Idt<LEN> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
 
 /// Describes a handler for a specific CPU interrupt.
-#[repr(packed)]
-#[derive(Clone)]
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
 pub struct InterruptDescriptor {
 	/// An offset to an Interrupt Service Routine, which is the function
 	/// that gets called to handle this interrupt.
@@ -44,12 +120,195 @@ impl InterruptDescriptor {
 		offset3: 0,
 		_reserved: 0,
 	};
+
+	/// Creates a descriptor for the interrupt handler at `handler`, which
+	/// runs in the code segment `segment` (a selector in the GDT).
+	pub const fn new(handler: u64, segment: u16, options: InterruptOptions) -> Self {
+		Self {
+			offset1: handler as u16,
+			segment,
+			stack_table: options.stack,
+			attributes: options.build(),
+			offset2: (handler >> 16) as u16,
+			offset3: (handler >> 32) as u32,
+			_reserved: 0,
+		}
+	}
+
+	/// The address of this interrupt's handler.
+	pub const fn handler(&self) -> u64 {
+		self.offset1 as u64 | (self.offset2 as u64) << 16 | (self.offset3 as u64) << 32
+	}
+}
+
+/// What the CPU does with other interrupts while a handler runs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum GateType {
+	/// Disables interrupts while the handler runs. Used for hardware
+	/// interrupts, which shouldn't interrupt each other.
+	Interrupt = 0xE,
+	/// Leaves interrupts enabled while the handler runs. Used for
+	/// exceptions and software interrupts.
+	Trap = 0xF,
+}
+
+/// Settings for an interrupt descriptor: its gate type, privilege level,
+/// and stack. Build it up from [`InterruptOptions::new`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InterruptOptions {
+	/// If this interrupt has a handler. Missing interrupts cause a general
+	/// protection fault.
+	pub present: bool,
+	/// The lowest privilege level that can trigger this interrupt with the
+	/// `int` instruction, where 0 is the kernel and 3 is user mode. Hardware
+	/// interrupts and exceptions ignore this.
+	pub privilege: u8,
+	/// See [`GateType`].
+	pub gate: GateType,
+	/// Which stack from the TSS' interrupt stack table to switch to when
+	/// this interrupt happens, from 1 to 7. 0 means to not switch stacks.
+	pub stack: u8,
+}
+impl InterruptOptions {
+	/// A present interrupt gate for the kernel, that doesn't switch stacks.
+	pub const fn new() -> Self {
+		Self {
+			present: true,
+			privilege: 0,
+			gate: GateType::Interrupt,
+			stack: 0,
+		}
+	}
+
+	/// Sets the gate type.
+	pub const fn gate(mut self, gate: GateType) -> Self {
+		self.gate = gate;
+		self
+	}
+
+	/// Sets the privilege level. Panics if it's more than 3.
+	pub const fn privilege(mut self, privilege: u8) -> Self {
+		if privilege > 3 {
+			panic!("An interrupt's privilege can only be between 0 and 3");
+		}
+
+		self.privilege = privilege;
+		self
+	}
+
+	/// Sets the interrupt stack table index. Panics if it's more than 7.
+	pub const fn stack(mut self, stack: u8) -> Self {
+		if stack > 7 {
+			panic!("The interrupt stack table only has 7 stacks");
+		}
+
+		self.stack = stack;
+		self
+	}
+
+	/// Builds the descriptor's attributes byte.
+	pub const fn build(self) -> u8 {
+		let mut result = self.gate as u8 | (self.privilege << 5);
+		if self.present {
+			result |= 0b1000_0000;
+		}
+
+		result
+	}
+}
+impl Default for InterruptOptions {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 /// Stores a pointer to the IDT. This is stored by the CPU instead
 /// of the actual IDT.
-#[repr(packed)]
+#[repr(C, packed)]
 pub struct IdtDescriptor {
 	pub size: u16,
 	pub offset: u64,
 }
+
+/// Enables interrupts, with `sti`.
+///
+/// # Safety
+/// The IDT must be loaded, with handlers for every interrupt that can arrive.
+pub unsafe fn enable() {
+	// Not `nomem`, so the compiler can't move memory accesses out of (or into) a critical section
+	unsafe { asm!("sti", options(nostack)) };
+}
+
+/// Disables interrupts, with `cli`. Non-maskable interrupts and exceptions still happen.
+pub fn disable() {
+	// Not `nomem`, for the same reason as `enable`
+	unsafe { asm!("cli", options(nostack)) };
+}
+
+/// If interrupts are enabled (the interrupt flag in RFLAGS is set).
+pub fn are_enabled() -> bool {
+	let flags: usize;
+	unsafe { asm!("pushf", "pop {}", out(reg) flags, options(nomem, preserves_flags)) };
+
+	flags & (1 << 9) != 0
+}
+
+/// Runs `f` with interrupts disabled, then re-enables them if they were enabled before.
+pub fn without_interrupts<T>(f: impl FnOnce() -> T) -> T {
+	let _guard = InterruptGuard::new();
+	f()
+}
+
+/// How many stacks can be registered with [`register_stack`] at once.
+pub const MAX_GUARDED_STACKS: usize = 32;
+
+/// Stacks with guard pages, and a name for each one, for reporting stack overflows.
+static GUARDED_STACKS: Spinlock<[Option<(&'static str, GuardedStack)>; MAX_GUARDED_STACKS]> =
+	Spinlock::new([None; MAX_GUARDED_STACKS]);
+
+/// Registers a stack with guard pages, so page faults in its guard pages get reported as a stack
+/// overflow in the stack called `name`. Returns `false` if [`MAX_GUARDED_STACKS`] stacks are
+/// already registered.
+pub fn register_stack(name: &'static str, stack: GuardedStack) -> bool {
+	let mut stacks = GUARDED_STACKS.lock();
+	match stacks.iter_mut().find(|slot| slot.is_none()) {
+		Some(slot) => {
+			*slot = Some((name, stack));
+			true
+		}
+		None => false,
+	}
+}
+
+/// Unregisters a stack from [`register_stack`], eg before it gets unmapped.
+pub fn unregister_stack(stack: GuardedStack) {
+	let mut stacks = GUARDED_STACKS.lock();
+	for slot in stacks.iter_mut() {
+		if slot.is_some_and(|(_, other)| other == stack) {
+			*slot = None;
+		}
+	}
+}
+
+/// If a page fault at `address` was a stack overflow, returns the name of the stack that
+/// overflowed and the stack. Page fault handlers can get the address from [`fault_address`].
+///
+/// This runs in fault handlers, so it doesn't wait for the list of stacks: if the fault happened
+/// while [`register_stack`] or [`unregister_stack`] had it locked, this returns `None`.
+pub fn stack_overflow(address: u64) -> Option<(&'static str, GuardedStack)> {
+	let stacks = GUARDED_STACKS.try_lock()?;
+	stacks
+		.iter()
+		.flatten()
+		.find(|(_, stack)| stack.in_guard(address))
+		.copied()
+}
+
+/// The address that caused the last page fault, from CR2.
+pub fn fault_address() -> u64 {
+	let cr2: usize;
+	unsafe { asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags)) };
+
+	cr2 as u64
+}
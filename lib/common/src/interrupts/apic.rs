@@ -0,0 +1,290 @@
+//! A driver for the local APIC (advanced programmable interrupt controller), which replaces the
+//! PIC on modern hardware.
+//!
+//! Every CPU core has its own local APIC. It receives interrupts (from the I/O APIC, its timer, or
+//! other cores) and hands them to its core, and it's how cores send each other inter-processor
+//! interrupts (IPIs) - which is how the other cores get started in the first place.
+//!
+//! The local APIC's registers are accessed in one of 2 ways:
+//! - xAPIC: Through a 4kib page of memory-mapped I/O, at the physical address in the APIC base MSR
+//! - x2APIC: Through MSRs, if the CPU supports it. This doesn't need any memory mapped, and gives
+//!   the APIC ID 32 bits instead of 8
+//!
+//! Each local APIC also has a timer, which is in [`timer`].
+//!
+//! The PIC should be disabled (see [`super::pic::ChainedPics::disable`]) before using the APIC.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/APIC
+//! - https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html (vol 3, chapter 11)
+
+pub mod timer;
+
+use {
+	crate::{
+		cpuid::Feature,
+		msr::{self, ApicBase},
+		paging::*,
+	},
+	core::ptr,
+};
+
+/// The MSR the x2APIC's registers start at.
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+/// The offsets of the local APIC's registers, in the xAPIC's memory-mapped page.
+mod register {
+	pub const ID: u32 = 0x20;
+	pub const VERSION: u32 = 0x30;
+	pub const END_OF_INTERRUPT: u32 = 0xB0;
+	pub const SPURIOUS_INTERRUPT: u32 = 0xF0;
+	pub const ERROR_STATUS: u32 = 0x280;
+	pub const INTERRUPT_COMMAND_LOW: u32 = 0x300;
+	pub const INTERRUPT_COMMAND_HIGH: u32 = 0x310;
+	pub const TIMER: u32 = 0x320;
+	pub const TIMER_INITIAL_COUNT: u32 = 0x380;
+	pub const TIMER_CURRENT_COUNT: u32 = 0x390;
+	pub const TIMER_DIVIDE: u32 = 0x3E0;
+}
+
+/// How the local APIC's registers are accessed. See the module-level docs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ApicMode {
+	/// The registers are memory-mapped at this virtual address.
+	XApic(u64),
+	/// The registers are MSRs.
+	X2Apic,
+}
+
+/// The current core's local APIC. See the module-level docs.
+pub struct LocalApic {
+	mode: ApicMode,
+}
+impl LocalApic {
+	/// If the CPU has a local APIC (CPUID leaf 1, EDX bit 9).
+	pub fn supported() -> bool {
+		Feature::Apic.supported()
+	}
+
+	/// If the CPU supports x2APIC mode (CPUID leaf 1, ECX bit 21).
+	pub fn x2apic_supported() -> bool {
+		Feature::X2Apic.supported()
+	}
+
+	/// The physical address of the xAPIC's registers, from the APIC base MSR.
+	pub fn physical_address() -> PhysAddr {
+		ApicBase::read().address()
+	}
+
+	/// Uses the local APIC in x2APIC mode if it's supported, or maps its registers at `virt` and
+	/// uses xAPIC mode otherwise. The registers are mapped uncacheable, since they're I/O.
+	///
+	/// # Safety
+	/// Nothing else can be using this core's local APIC, and nothing else can be mapped at `virt`.
+	pub unsafe fn map(mapper: &mut Mapper, virt: u64) -> Result<Self, MapError> {
+		if Self::x2apic_supported() {
+			return Ok(unsafe { Self::new(ApicMode::X2Apic) });
+		}
+
+		let flags = PageFlags {
+			writable: true,
+			executable: false,
+			caching: false,
+			..Default::default()
+		};
+		mapper.map_to(virt, Self::physical_address(), flags)?;
+
+		Ok(unsafe { Self::new(ApicMode::XApic(virt)) })
+	}
+
+	/// Uses the local APIC in `mode`. For x2APIC mode, this switches the APIC into x2APIC mode.
+	///
+	/// # Safety
+	/// - Nothing else can be using this core's local APIC
+	/// - For xAPIC mode, the registers must be mapped (uncacheable) at the given address
+	/// - For x2APIC mode, the CPU must support x2APIC (see [`LocalApic::x2apic_supported`])
+	pub unsafe fn new(mode: ApicMode) -> Self {
+		if mode == ApicMode::X2Apic {
+			// The APIC has to be enabled before it can switch into x2APIC mode
+			unsafe {
+				ApicBase::read()
+					.with(ApicBase::ENABLE | ApicBase::X2APIC_ENABLE)
+					.write();
+			}
+		}
+
+		Self { mode }
+	}
+
+	/// How this APIC's registers are accessed.
+	pub fn mode(&self) -> ApicMode {
+		self.mode
+	}
+
+	/// Enables the APIC, and sends spurious interrupts to `spurious_vector`. The APIC sends
+	/// spurious interrupts when an interrupt goes away before the core handles it; their handler
+	/// shouldn't send an end-of-interrupt.
+	///
+	/// # Safety
+	/// `spurious_vector` needs a handler, and the PIC should be disabled.
+	pub unsafe fn enable(&mut self, spurious_vector: u8) {
+		unsafe {
+			ApicBase::read().with(ApicBase::ENABLE).write();
+
+			// Bit 8 enables the APIC in software
+			self.write(
+				register::SPURIOUS_INTERRUPT,
+				(1 << 8) | spurious_vector as u32,
+			);
+		}
+	}
+
+	/// This core's APIC ID, which is how other cores send it IPIs.
+	pub fn id(&self) -> u32 {
+		let id = unsafe { self.read(register::ID) };
+		match self.mode {
+			// The xAPIC's ID is only 8 bits, in the top byte
+			ApicMode::XApic(_) => id >> 24,
+			ApicMode::X2Apic => id,
+		}
+	}
+
+	/// The APIC's version register. The low byte is the version, and bits 16-23 are how many
+	/// local vector table entries there are, minus 1.
+	pub fn version(&self) -> u32 {
+		unsafe { self.read(register::VERSION) }
+	}
+
+	/// Errors the APIC ran into while sending or receiving interrupts. Reading this clears them.
+	pub fn error_status(&mut self) -> u32 {
+		unsafe {
+			// The register only updates when it's written to
+			self.write(register::ERROR_STATUS, 0);
+			self.read(register::ERROR_STATUS)
+		}
+	}
+
+	/// Tells the APIC the current interrupt has been handled.
+	pub fn end_of_interrupt(&mut self) {
+		unsafe { self.write(register::END_OF_INTERRUPT, 0) };
+	}
+
+	/// Sends an inter-processor interrupt. `destination` is the APIC ID of the core to send it to,
+	/// and is ignored if `ipi` has a shorthand.
+	///
+	/// # Safety
+	/// The receiving core(s) have to be able to handle the IPI. INIT and start-up IPIs restart the
+	/// receiving cores.
+	pub unsafe fn send_ipi(&mut self, destination: u32, ipi: Ipi) {
+		let command = ipi.build();
+		match self.mode {
+			ApicMode::XApic(_) => unsafe {
+				self.write(register::INTERRUPT_COMMAND_HIGH, destination << 24);
+				// Writing the low half sends the IPI
+				self.write(register::INTERRUPT_COMMAND_LOW, command);
+
+				// Wait for the IPI to be sent (the delivery status bit)
+				while self.read(register::INTERRUPT_COMMAND_LOW) & (1 << 12) != 0 {
+					core::hint::spin_loop();
+				}
+			},
+			// x2APIC's interrupt command register is a single 64-bit MSR
+			ApicMode::X2Apic => unsafe {
+				msr::write(
+					Self::msr(register::INTERRUPT_COMMAND_LOW),
+					((destination as u64) << 32) | command as u64,
+				);
+			},
+		}
+	}
+
+	/// Reads the register at `offset`.
+	///
+	/// # Safety
+	/// `offset` must be a readable register.
+	unsafe fn read(&self, offset: u32) -> u32 {
+		match self.mode {
+			ApicMode::XApic(base) => unsafe {
+				ptr::read_volatile((base + offset as u64) as usize as *const u32)
+			},
+			ApicMode::X2Apic => unsafe { msr::read(Self::msr(offset)) as u32 },
+		}
+	}
+
+	/// Writes the register at `offset`.
+	///
+	/// # Safety
+	/// `offset` must be a writable register, and `value` must be valid for it.
+	unsafe fn write(&mut self, offset: u32, value: u32) {
+		match self.mode {
+			ApicMode::XApic(base) => unsafe {
+				ptr::write_volatile((base + offset as u64) as usize as *mut u32, value)
+			},
+			ApicMode::X2Apic => unsafe { msr::write(Self::msr(offset), value as u64) },
+		}
+	}
+
+	/// The x2APIC MSR for the register at `offset`.
+	fn msr(offset: u32) -> u32 {
+		X2APIC_MSR_BASE + (offset >> 4)
+	}
+}
+
+/// An inter-processor interrupt. See [`LocalApic::send_ipi`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Ipi {
+	/// The vector to send. For start-up IPIs, this is the page the receiving cores start running
+	/// at instead (address = vector * 4kib).
+	pub vector: u8,
+	pub delivery: DeliveryMode,
+	pub shorthand: Shorthand,
+}
+impl Ipi {
+	/// A fixed IPI, sending `vector` to the destination core.
+	pub const fn fixed(vector: u8) -> Self {
+		Self {
+			vector,
+			delivery: DeliveryMode::Fixed,
+			shorthand: Shorthand::None,
+		}
+	}
+
+	/// Builds the low half of the interrupt command register.
+	pub const fn build(self) -> u32 {
+		// Bit 14 is the level, which has to be set (asserted) on modern CPUs
+		self.vector as u32 | (self.delivery as u32) << 8 | 1 << 14 | (self.shorthand as u32) << 18
+	}
+}
+
+/// What kind of IPI to send.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum DeliveryMode {
+	/// A normal interrupt, at the IPI's vector.
+	Fixed = 0b000,
+	/// A normal interrupt, at the IPI's vector, to whichever destination core has the lowest
+	/// priority.
+	LowestPriority = 0b001,
+	/// A system management interrupt. The vector must be 0.
+	Smi = 0b010,
+	/// A non-maskable interrupt. The vector is ignored.
+	Nmi = 0b100,
+	/// Resets the destination cores, so they wait for a start-up IPI.
+	Init = 0b101,
+	/// Starts the destination cores at the page the vector points to. See [`Ipi::vector`].
+	StartUp = 0b110,
+}
+
+/// Shortcuts for sending an IPI without a destination APIC ID.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum Shorthand {
+	/// Send it to the destination APIC ID.
+	None = 0b00,
+	/// Send it to this core.
+	OnlySelf = 0b01,
+	/// Send it to every core, including this one.
+	AllIncludingSelf = 0b10,
+	/// Send it to every core but this one.
+	AllExcludingSelf = 0b11,
+}
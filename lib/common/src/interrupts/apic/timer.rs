@@ -0,0 +1,128 @@
+//! The local APIC's timer, which is the per-core timer the scheduler's tick comes from.
+//!
+//! The timer counts down from an initial count, at the core's bus frequency divided by a
+//! configurable divisor, and sends an interrupt when it hits 0. In periodic mode it then reloads
+//! the initial count and starts over. The bus frequency differs between machines, so the timer has
+//! to be calibrated against a timer with a known frequency - the PIT (see [`pit`]) - before it can
+//! be given a frequency in Hz.
+//!
+//! The tick counter only goes up if the timer's interrupt handler calls [`tick`].
+//!
+//! Resources:
+//! - https://wiki.osdev.org/APIC_Timer
+//! - https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html (vol 3, section 11.5.4)
+
+use {
+	super::*,
+	crate::interrupts::pit,
+	core::sync::atomic::{AtomicU32, AtomicU64, Ordering},
+};
+
+/// The divide configuration for dividing the bus frequency by 16. The timer always runs at this
+/// divisor, since it's precise enough without overflowing the count too quickly.
+const DIVIDE_BY_16: u32 = 0b0011;
+/// Masks the timer's interrupt, in its local vector table entry.
+const MASKED: u32 = 1 << 16;
+/// How long to calibrate the timer for, in milliseconds.
+const CALIBRATION_MS: u32 = 10;
+
+/// How many times the timer has ticked. See [`tick`].
+static TICKS: AtomicU64 = AtomicU64::new(0);
+/// How many times per second the timer ticks, or 0 if it hasn't been started.
+static FREQUENCY: AtomicU32 = AtomicU32::new(0);
+
+/// What the timer does when its count hits 0.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum TimerMode {
+	/// Sends one interrupt, then stops.
+	OneShot = 0b00 << 17,
+	/// Sends an interrupt, then starts counting down again.
+	Periodic = 0b01 << 17,
+}
+
+impl LocalApic {
+	/// Measures how many times the timer counts down per millisecond, by timing it against the
+	/// PIT. This takes about 10ms. Pass the result to [`LocalApic::start_tick`].
+	///
+	/// # Safety
+	/// Nothing else can be using PIT channel 2 (see [`pit::sleep_ms`]).
+	pub unsafe fn calibrate_timer(&mut self) -> u32 {
+		unsafe {
+			self.write(register::TIMER_DIVIDE, DIVIDE_BY_16);
+			self.write(register::TIMER, MASKED);
+			self.write(register::TIMER_INITIAL_COUNT, u32::MAX);
+
+			pit::sleep_ms(CALIBRATION_MS);
+
+			let elapsed = u32::MAX - self.read(register::TIMER_CURRENT_COUNT);
+			self.stop_timer();
+
+			elapsed / CALIBRATION_MS
+		}
+	}
+
+	/// Starts the timer, sending `vector` after `count` counts, in `mode`. The timer counts down
+	/// [`LocalApic::calibrate_timer`] times per millisecond.
+	///
+	/// # Safety
+	/// `vector` needs a handler that sends an end-of-interrupt.
+	pub unsafe fn start_timer(&mut self, vector: u8, mode: TimerMode, count: u32) {
+		unsafe {
+			self.write(register::TIMER_DIVIDE, DIVIDE_BY_16);
+			self.write(register::TIMER, mode as u32 | vector as u32);
+			// Writing the initial count starts the timer
+			self.write(register::TIMER_INITIAL_COUNT, count.max(1));
+		}
+	}
+
+	/// Starts a periodic tick, sending `vector` about `hz` times per second. `counts_per_ms` is
+	/// from [`LocalApic::calibrate_timer`]. Returns the actual frequency, which is what
+	/// [`frequency`] returns from then on.
+	///
+	/// # Safety
+	/// `vector` needs a handler that calls [`tick`] and sends an end-of-interrupt.
+	pub unsafe fn start_tick(&mut self, vector: u8, hz: u32, counts_per_ms: u32) -> u32 {
+		let count = ((counts_per_ms as u64 * 1000) / hz.max(1) as u64).clamp(1, u32::MAX as u64);
+		unsafe { self.start_timer(vector, TimerMode::Periodic, count as u32) };
+
+		let hz = (counts_per_ms as u64 * 1000 / count) as u32;
+		FREQUENCY.store(hz, Ordering::Relaxed);
+
+		hz
+	}
+
+	/// Stops the timer.
+	pub fn stop_timer(&mut self) {
+		unsafe {
+			self.write(register::TIMER, MASKED);
+			self.write(register::TIMER_INITIAL_COUNT, 0);
+		}
+	}
+}
+
+/// Counts a tick. The timer's interrupt handler should call this.
+pub fn tick() {
+	TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// How many ticks there have been since [`LocalApic::start_tick`]. This never goes down, so it
+/// can be used as a monotonic clock.
+pub fn ticks() -> u64 {
+	TICKS.load(Ordering::Relaxed)
+}
+
+/// How many times per second the timer ticks, or `None` if [`LocalApic::start_tick`] hasn't been
+/// called.
+pub fn frequency() -> Option<u32> {
+	match FREQUENCY.load(Ordering::Relaxed) {
+		0 => None,
+		hz => Some(hz),
+	}
+}
+
+/// Roughly how many milliseconds have passed since [`LocalApic::start_tick`], from the tick
+/// counter. Returns 0 if the timer hasn't been started.
+pub fn uptime_ms() -> u64 {
+	frequency().map_or(0, |hz| ticks() * 1000 / hz as u64)
+}
@@ -0,0 +1,444 @@
+//! CPU exceptions: interrupts the CPU raises itself when something goes wrong, like dividing by
+//! zero or accessing unmapped memory. They always use vectors 0-31.
+//!
+//! Exception handlers are `extern "x86-interrupt"` functions, which get an [`InterruptStackFrame`]
+//! (what the CPU pushed before calling the handler). Some exceptions also push an error code,
+//! which the handler gets as a second argument - there's a handler type for each combination, and
+//! [`Exception::has_error_code`] says which exceptions push one.
+//!
+//! [`install_default_handlers`] fills an IDT with handlers that panic with a dump of what went
+//! wrong, so exceptions are at least readable before the kernel has its own handlers.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Exceptions
+//! - https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html (vol 3, chapter 6)
+
+use {
+	super::*,
+	core::fmt::{self, Debug, Display, Formatter},
+};
+
+/// What the CPU pushes onto the stack before calling an interrupt handler.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct InterruptStackFrame {
+	/// The instruction that was interrupted. For faults, this is the instruction that caused the
+	/// exception, so returning retries it.
+	pub instruction_pointer: u64,
+	/// The code segment the interrupted code was running in.
+	pub code_segment: u64,
+	/// The interrupted code's RFLAGS register.
+	pub flags: u64,
+	/// The interrupted code's stack pointer.
+	pub stack_pointer: u64,
+	/// The interrupted code's stack segment.
+	pub stack_segment: u64,
+}
+impl Debug for InterruptStackFrame {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		writeln!(
+			f,
+			"RIP: {:#018x}  CS: {:#06x}",
+			self.instruction_pointer, self.code_segment
+		)?;
+		writeln!(
+			f,
+			"RSP: {:#018x}  SS: {:#06x}",
+			self.stack_pointer, self.stack_segment
+		)?;
+		write!(f, "RFLAGS: {:#018x}", self.flags)
+	}
+}
+
+/// A handler for an interrupt without an error code.
+pub type HandlerFunc = extern "x86-interrupt" fn(InterruptStackFrame);
+/// A handler for an exception with an error code.
+pub type HandlerFuncWithErrorCode = extern "x86-interrupt" fn(InterruptStackFrame, u64);
+/// A handler for an exception with a segment selector error code.
+pub type SelectorHandlerFunc = extern "x86-interrupt" fn(InterruptStackFrame, SelectorErrorCode);
+/// A handler for page faults.
+pub type PageFaultHandlerFunc = extern "x86-interrupt" fn(InterruptStackFrame, PageFaultErrorCode);
+/// A handler for an exception that can't be returned from, without an error code (machine checks).
+pub type DivergingHandlerFunc = extern "x86-interrupt" fn(InterruptStackFrame) -> !;
+/// A handler for an exception that can't be returned from, with an error code (double faults).
+pub type DivergingHandlerFuncWithErrorCode =
+	extern "x86-interrupt" fn(InterruptStackFrame, u64) -> !;
+
+/// Any of the handler types, which can be put in an IDT with [`Idt::set_handler`].
+pub trait Handler {
+	/// The handler's address.
+	fn address(self) -> u64;
+}
+macro_rules! handler {
+	($($ty:ty),*) => {
+		$(
+			impl Handler for $ty {
+				fn address(self) -> u64 {
+					self as usize as u64
+				}
+			}
+		)*
+	};
+}
+handler!(
+	HandlerFunc,
+	HandlerFuncWithErrorCode,
+	SelectorHandlerFunc,
+	PageFaultHandlerFunc,
+	DivergingHandlerFunc,
+	DivergingHandlerFuncWithErrorCode
+);
+
+impl<const LEN: usize> Idt<LEN> {
+	/// Installs `handler` for `vector`, in the code segment `segment`.
+	pub fn set_handler(
+		&mut self,
+		vector: u8,
+		handler: impl Handler,
+		segment: u16,
+		options: InterruptOptions,
+	) {
+		self.interrupts[vector as usize] =
+			InterruptDescriptor::new(handler.address(), segment, options);
+	}
+}
+
+/// Every architectural exception, by vector. Vectors that aren't listed are reserved.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum Exception {
+	DivideError = 0,
+	Debug = 1,
+	NonMaskableInterrupt = 2,
+	Breakpoint = 3,
+	Overflow = 4,
+	BoundRangeExceeded = 5,
+	InvalidOpcode = 6,
+	DeviceNotAvailable = 7,
+	DoubleFault = 8,
+	InvalidTss = 10,
+	SegmentNotPresent = 11,
+	StackSegmentFault = 12,
+	GeneralProtectionFault = 13,
+	PageFault = 14,
+	X87FloatingPoint = 16,
+	AlignmentCheck = 17,
+	MachineCheck = 18,
+	SimdFloatingPoint = 19,
+	Virtualization = 20,
+	ControlProtection = 21,
+	HypervisorInjection = 28,
+	VmmCommunication = 29,
+	Security = 30,
+}
+impl Exception {
+	/// Every exception, in order.
+	pub const ALL: [Self; 23] = [
+		Self::DivideError,
+		Self::Debug,
+		Self::NonMaskableInterrupt,
+		Self::Breakpoint,
+		Self::Overflow,
+		Self::BoundRangeExceeded,
+		Self::InvalidOpcode,
+		Self::DeviceNotAvailable,
+		Self::DoubleFault,
+		Self::InvalidTss,
+		Self::SegmentNotPresent,
+		Self::StackSegmentFault,
+		Self::GeneralProtectionFault,
+		Self::PageFault,
+		Self::X87FloatingPoint,
+		Self::AlignmentCheck,
+		Self::MachineCheck,
+		Self::SimdFloatingPoint,
+		Self::Virtualization,
+		Self::ControlProtection,
+		Self::HypervisorInjection,
+		Self::VmmCommunication,
+		Self::Security,
+	];
+
+	/// The exception with this vector, if there is one.
+	pub fn from_vector(vector: u8) -> Option<Self> {
+		Self::ALL
+			.into_iter()
+			.find(|exception| *exception as u8 == vector)
+	}
+
+	/// If the CPU pushes an error code for this exception.
+	pub const fn has_error_code(self) -> bool {
+		matches!(
+			self,
+			Self::DoubleFault
+				| Self::InvalidTss
+				| Self::SegmentNotPresent
+				| Self::StackSegmentFault
+				| Self::GeneralProtectionFault
+				| Self::PageFault
+				| Self::AlignmentCheck
+				| Self::ControlProtection
+				| Self::VmmCommunication
+				| Self::Security
+		)
+	}
+
+	/// A readable name for this exception.
+	pub const fn name(self) -> &'static str {
+		match self {
+			Self::DivideError => "Divide error",
+			Self::Debug => "Debug",
+			Self::NonMaskableInterrupt => "Non-maskable interrupt",
+			Self::Breakpoint => "Breakpoint",
+			Self::Overflow => "Overflow",
+			Self::BoundRangeExceeded => "Bound range exceeded",
+			Self::InvalidOpcode => "Invalid opcode",
+			Self::DeviceNotAvailable => "Device not available",
+			Self::DoubleFault => "Double fault",
+			Self::InvalidTss => "Invalid TSS",
+			Self::SegmentNotPresent => "Segment not present",
+			Self::StackSegmentFault => "Stack-segment fault",
+			Self::GeneralProtectionFault => "General protection fault",
+			Self::PageFault => "Page fault",
+			Self::X87FloatingPoint => "x87 floating-point exception",
+			Self::AlignmentCheck => "Alignment check",
+			Self::MachineCheck => "Machine check",
+			Self::SimdFloatingPoint => "SIMD floating-point exception",
+			Self::Virtualization => "Virtualization exception",
+			Self::ControlProtection => "Control protection exception",
+			Self::HypervisorInjection => "Hypervisor injection exception",
+			Self::VmmCommunication => "VMM communication exception",
+			Self::Security => "Security exception",
+		}
+	}
+}
+
+/// The error code for exceptions caused by a segment selector (invalid TSS, segment not present,
+/// stack-segment fault, and general protection fault). If the exception wasn't caused by a
+/// selector, it's 0.
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SelectorErrorCode(pub u64);
+impl SelectorErrorCode {
+	/// If the exception happened while delivering an external interrupt.
+	pub fn external(&self) -> bool {
+		self.0 & 1 != 0
+	}
+
+	/// Which table the selector indexes into.
+	pub fn table(&self) -> DescriptorTable {
+		match (self.0 >> 1) & 0b11 {
+			0b00 => DescriptorTable::Gdt,
+			0b10 => DescriptorTable::Ldt,
+			_ => DescriptorTable::Idt,
+		}
+	}
+
+	/// The index into that table.
+	pub fn index(&self) -> u16 {
+		((self.0 >> 3) & 0x1FFF) as u16
+	}
+}
+impl Debug for SelectorErrorCode {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		if self.0 == 0 {
+			return write!(f, "not caused by a selector");
+		}
+
+		write!(f, "{:?} index {}", self.table(), self.index())?;
+		if self.external() {
+			write!(f, " (external)")?;
+		}
+
+		Ok(())
+	}
+}
+
+/// The tables a [`SelectorErrorCode`] can point into.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DescriptorTable {
+	Gdt,
+	Idt,
+	Ldt,
+}
+
+/// The error code for page faults, which says why the access faulted. The address that was
+/// accessed is in CR2 (see [`fault_address`]).
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PageFaultErrorCode(pub u64);
+impl PageFaultErrorCode {
+	/// If the page was present. When false, the page wasn't mapped; when true, the access broke
+	/// the page's permissions.
+	pub fn present(&self) -> bool {
+		self.0 & (1 << 0) != 0
+	}
+
+	/// If the access was a write. Otherwise it was a read.
+	pub fn write(&self) -> bool {
+		self.0 & (1 << 1) != 0
+	}
+
+	/// If the access came from user mode.
+	pub fn user_mode(&self) -> bool {
+		self.0 & (1 << 2) != 0
+	}
+
+	/// If a page table entry had a reserved bit set.
+	pub fn reserved_bit(&self) -> bool {
+		self.0 & (1 << 3) != 0
+	}
+
+	/// If the access was an instruction fetch (ie, executing a non-executable page).
+	pub fn instruction_fetch(&self) -> bool {
+		self.0 & (1 << 4) != 0
+	}
+
+	/// If the page's protection key didn't allow the access.
+	pub fn protection_key(&self) -> bool {
+		self.0 & (1 << 5) != 0
+	}
+
+	/// If the access was to a shadow stack.
+	pub fn shadow_stack(&self) -> bool {
+		self.0 & (1 << 6) != 0
+	}
+}
+impl Debug for PageFaultErrorCode {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let access = if self.instruction_fetch() {
+			"execute"
+		} else if self.write() {
+			"write"
+		} else {
+			"read"
+		};
+		let mode = if self.user_mode() { "user" } else { "kernel" };
+		let cause = if self.present() {
+			"protection violation"
+		} else {
+			"page not present"
+		};
+		write!(f, "{mode} {access}, {cause}")?;
+
+		if self.reserved_bit() {
+			write!(f, ", reserved bit set")?;
+		}
+		if self.protection_key() {
+			write!(f, ", protection key")?;
+		}
+		if self.shadow_stack() {
+			write!(f, ", shadow stack")?;
+		}
+
+		Ok(())
+	}
+}
+impl Display for PageFaultErrorCode {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		Debug::fmt(self, f)
+	}
+}
+
+/// Installs a handler for every exception in `idt` that panics with a dump of the exception, in the
+/// code segment `segment`. The page fault and double fault handlers also report stack overflows
+/// (see [`stack_overflow`]), and the NMI and machine check handlers dump the machine-check banks (see
+/// [`machine_check`]).
+pub fn install_default_handlers<const LEN: usize>(idt: &mut Idt<LEN>, segment: u16) {
+	let options = InterruptOptions::new();
+	let trap = options.gate(GateType::Trap);
+
+	macro_rules! install {
+		($($exception:ident => $handler:expr, $options:expr;)*) => {
+			$(idt.set_handler(Exception::$exception as u8, $handler, segment, $options);)*
+		};
+	}
+
+	install! {
+		DivideError => default_handler::<0> as HandlerFunc, trap;
+		Debug => default_handler::<1> as HandlerFunc, trap;
+		NonMaskableInterrupt => machine_check::nmi_handler as HandlerFunc, options;
+		Breakpoint => default_handler::<3> as HandlerFunc, trap;
+		Overflow => default_handler::<4> as HandlerFunc, trap;
+		BoundRangeExceeded => default_handler::<5> as HandlerFunc, trap;
+		InvalidOpcode => default_handler::<6> as HandlerFunc, trap;
+		DeviceNotAvailable => default_handler::<7> as HandlerFunc, trap;
+		DoubleFault => default_double_fault as DivergingHandlerFuncWithErrorCode, options;
+		InvalidTss => default_selector_handler::<10> as SelectorHandlerFunc, trap;
+		SegmentNotPresent => default_selector_handler::<11> as SelectorHandlerFunc, trap;
+		StackSegmentFault => default_selector_handler::<12> as SelectorHandlerFunc, trap;
+		GeneralProtectionFault => default_selector_handler::<13> as SelectorHandlerFunc, trap;
+		PageFault => default_page_fault as PageFaultHandlerFunc, options;
+		X87FloatingPoint => default_handler::<16> as HandlerFunc, trap;
+		AlignmentCheck => default_error_code_handler::<17> as HandlerFuncWithErrorCode, trap;
+		MachineCheck => machine_check::machine_check_handler as DivergingHandlerFunc, options;
+		SimdFloatingPoint => default_handler::<19> as HandlerFunc, trap;
+		Virtualization => default_handler::<20> as HandlerFunc, trap;
+		ControlProtection => default_error_code_handler::<21> as HandlerFuncWithErrorCode, trap;
+		HypervisorInjection => default_handler::<28> as HandlerFunc, trap;
+		VmmCommunication => default_error_code_handler::<29> as HandlerFuncWithErrorCode, trap;
+		Security => default_error_code_handler::<30> as HandlerFuncWithErrorCode, trap;
+	}
+}
+
+/// The name of the exception with this vector, for the default handlers.
+fn name(vector: u8) -> &'static str {
+	Exception::from_vector(vector).map_or("Unknown exception", Exception::name)
+}
+
+extern "x86-interrupt" fn default_handler<const VECTOR: u8>(frame: InterruptStackFrame) {
+	panic!("EXCEPTION: {} (vector {VECTOR})\n{frame:?}", name(VECTOR));
+}
+
+extern "x86-interrupt" fn default_error_code_handler<const VECTOR: u8>(
+	frame: InterruptStackFrame,
+	error_code: u64,
+) {
+	panic!(
+		"EXCEPTION: {} (vector {VECTOR})\nError code: {error_code:#x}\n{frame:?}",
+		name(VECTOR)
+	);
+}
+
+extern "x86-interrupt" fn default_selector_handler<const VECTOR: u8>(
+	frame: InterruptStackFrame,
+	error_code: SelectorErrorCode,
+) {
+	panic!(
+		"EXCEPTION: {} (vector {VECTOR})\nSelector: {error_code:?}\n{frame:?}",
+		name(VECTOR)
+	);
+}
+
+extern "x86-interrupt" fn default_page_fault(
+	frame: InterruptStackFrame,
+	error_code: PageFaultErrorCode,
+) {
+	let address = fault_address();
+	if let Some((stack, guarded)) = stack_overflow(address) {
+		panic!(
+			"EXCEPTION: Stack overflow in the {stack} stack ({:#x}..{:#x})\nAccessed: {address:#x}\n{frame:?}",
+			guarded.bottom, guarded.top
+		);
+	}
+
+	panic!(
+		"EXCEPTION: Page fault\nAccessed: {address:#x} ({error_code})\nCR3: {:#x}\n{frame:?}",
+		crate::paging::Cr3::read().bits()
+	);
+}
+
+extern "x86-interrupt" fn default_double_fault(frame: InterruptStackFrame, _error_code: u64) -> ! {
+	// A stack overflow can't push the page fault's frame on the stack that overflowed, so it turns
+	// into a double fault. CR2 still has the address from that page fault.
+	let address = fault_address();
+	if let Some((stack, guarded)) = stack_overflow(address) {
+		panic!(
+			"EXCEPTION: Double fault from a stack overflow in the {stack} stack ({:#x}..{:#x})\nAccessed: {address:#x}\n{frame:?}",
+			guarded.bottom, guarded.top
+		);
+	}
+
+	// The error code is always 0
+	panic!("EXCEPTION: Double fault\n{frame:?}");
+}
@@ -0,0 +1,299 @@
+//! A driver for the I/O APIC, which routes hardware interrupts to the local APICs (see
+//! [`super::apic`]).
+//!
+//! Each I/O APIC has a few input pins (usually 24), and each pin is a Global System Interrupt
+//! (GSI). Multiple I/O APICs split up the GSIs between them, starting at their GSI base. Each pin
+//! has a redirection table entry, which says which vector to send the interrupt as and which
+//! core's local APIC to send it to.
+//!
+//! Legacy ISA IRQs (like the keyboard, PIT, and IDE) are normally wired so IRQ N is GSI N, but
+//! the MADT can override that with interrupt source overrides - the PIT's IRQ 0 is almost always
+//! on GSI 2, for example. [`route_isa_irq`] takes care of that.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/IOAPIC
+//! - https://pdos.csail.mit.edu/6.828/2016/readings/ia32/ioapic.pdf
+
+use {
+	super::apic::DeliveryMode,
+	crate::paging::*,
+	acpi::madt::{Madt, MadtEntry, Polarity, TriggerMode},
+	core::ptr,
+};
+
+/// The register that selects which register the window reads and writes.
+const REGISTER_SELECT: u64 = 0x00;
+/// The register that reads and writes the selected register.
+const REGISTER_WINDOW: u64 = 0x10;
+
+/// The indirect registers, selected through [`REGISTER_SELECT`].
+mod register {
+	pub const ID: u32 = 0x00;
+	pub const VERSION: u32 = 0x01;
+	/// The first redirection table entry. Each entry is 2 registers.
+	pub const REDIRECTION_TABLE: u32 = 0x10;
+}
+
+/// An I/O APIC. See the module-level docs.
+pub struct IoApic {
+	/// The virtual address of the I/O APIC's registers.
+	base: u64,
+	/// The first GSI this I/O APIC handles.
+	gsi_base: u32,
+}
+impl IoApic {
+	/// Uses the I/O APIC whose registers are mapped at `base`, which handles GSIs starting at
+	/// `gsi_base`. Both come from the MADT's I/O APIC entries.
+	///
+	/// # Safety
+	/// The registers must be mapped (uncacheable) at `base`, and nothing else can be using this
+	/// I/O APIC.
+	pub unsafe fn new(base: u64, gsi_base: u32) -> Self {
+		Self { base, gsi_base }
+	}
+
+	/// Maps the I/O APIC's registers from `address` (its physical address) to `virt`, then uses
+	/// it. See [`IoApic::new`].
+	///
+	/// # Safety
+	/// Nothing else can be using this I/O APIC, and nothing else can be mapped at `virt`.
+	pub unsafe fn map(
+		mapper: &mut Mapper,
+		virt: u64,
+		address: PhysAddr,
+		gsi_base: u32,
+	) -> Result<Self, MapError> {
+		let flags = PageFlags {
+			writable: true,
+			executable: false,
+			caching: false,
+			..Default::default()
+		};
+		mapper.map_to(virt, address, flags)?;
+
+		Ok(unsafe { Self::new(virt, gsi_base) })
+	}
+
+	/// This I/O APIC's ID.
+	pub fn id(&self) -> u8 {
+		(unsafe { self.read(register::ID) } >> 24) as u8 & 0xF
+	}
+
+	/// How many redirection table entries (pins) this I/O APIC has.
+	pub fn entries(&self) -> u32 {
+		// Bits 16-23 are the index of the last entry
+		((unsafe { self.read(register::VERSION) } >> 16) & 0xFF) + 1
+	}
+
+	/// If `gsi` is one of this I/O APIC's pins.
+	pub fn handles(&self, gsi: u32) -> bool {
+		gsi.checked_sub(self.gsi_base)
+			.is_some_and(|index| index < self.entries())
+	}
+
+	/// Reads the redirection table entry for `gsi`. Returns `None` if this I/O APIC doesn't
+	/// handle `gsi`.
+	pub fn entry(&self, gsi: u32) -> Option<RedirectionEntry> {
+		let register = self.entry_register(gsi)?;
+		let (low, high) = unsafe { (self.read(register), self.read(register + 1)) };
+
+		Some(RedirectionEntry::decode(((high as u64) << 32) | low as u64))
+	}
+
+	/// Writes the redirection table entry for `gsi`. Returns `false` if this I/O APIC doesn't
+	/// handle `gsi`.
+	///
+	/// # Safety
+	/// If the entry isn't masked, its vector needs a handler on the destination core.
+	pub unsafe fn set_entry(&mut self, gsi: u32, entry: RedirectionEntry) -> bool {
+		let Some(register) = self.entry_register(gsi) else {
+			return false;
+		};
+
+		let bits = entry.build();
+		unsafe {
+			// Mask the entry while it's half-written, so a half-configured interrupt can't fire
+			self.write(register, RedirectionEntry::MASKED as u32);
+			self.write(register + 1, (bits >> 32) as u32);
+			self.write(register, bits as u32);
+		}
+
+		true
+	}
+
+	/// Masks (disables) `gsi`. Returns `false` if this I/O APIC doesn't handle `gsi`.
+	pub fn mask(&mut self, gsi: u32) -> bool {
+		let Some(mut entry) = self.entry(gsi) else {
+			return false;
+		};
+		entry.masked = true;
+
+		unsafe { self.set_entry(gsi, entry) }
+	}
+
+	/// Unmasks (enables) `gsi`. Returns `false` if this I/O APIC doesn't handle `gsi`.
+	///
+	/// # Safety
+	/// The entry's vector needs a handler on the destination core.
+	pub unsafe fn unmask(&mut self, gsi: u32) -> bool {
+		let Some(mut entry) = self.entry(gsi) else {
+			return false;
+		};
+		entry.masked = false;
+
+		unsafe { self.set_entry(gsi, entry) }
+	}
+
+	/// The first register of the redirection table entry for `gsi`.
+	fn entry_register(&self, gsi: u32) -> Option<u32> {
+		self.handles(gsi)
+			.then(|| register::REDIRECTION_TABLE + (gsi - self.gsi_base) * 2)
+	}
+
+	unsafe fn read(&self, register: u32) -> u32 {
+		unsafe {
+			ptr::write_volatile((self.base + REGISTER_SELECT) as usize as *mut u32, register);
+			ptr::read_volatile((self.base + REGISTER_WINDOW) as usize as *const u32)
+		}
+	}
+
+	unsafe fn write(&mut self, register: u32, value: u32) {
+		unsafe {
+			ptr::write_volatile((self.base + REGISTER_SELECT) as usize as *mut u32, register);
+			ptr::write_volatile((self.base + REGISTER_WINDOW) as usize as *mut u32, value);
+		}
+	}
+}
+
+/// Where an I/O APIC pin sends its interrupt.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RedirectionEntry {
+	/// The vector to send the interrupt as.
+	pub vector: u8,
+	/// How to send the interrupt. Only [`DeliveryMode::Fixed`], [`DeliveryMode::LowestPriority`],
+	/// [`DeliveryMode::Smi`], [`DeliveryMode::Nmi`], and [`DeliveryMode::Init`] are allowed.
+	pub delivery: DeliveryMode,
+	/// If the pin is active when it's low, instead of high.
+	pub active_low: bool,
+	/// If the pin is level-triggered, instead of edge-triggered.
+	pub level_triggered: bool,
+	/// If the pin is masked (disabled).
+	pub masked: bool,
+	/// The APIC ID of the core to send the interrupt to.
+	pub destination: u8,
+}
+impl RedirectionEntry {
+	/// The mask bit.
+	const MASKED: u64 = 1 << 16;
+
+	/// An unmasked, edge-triggered, active-high entry that sends `vector` to the core with APIC ID
+	/// `destination`. That's how ISA IRQs are wired by default.
+	pub const fn new(vector: u8, destination: u8) -> Self {
+		Self {
+			vector,
+			delivery: DeliveryMode::Fixed,
+			active_low: false,
+			level_triggered: false,
+			masked: false,
+			destination,
+		}
+	}
+
+	/// Builds the raw 64-bit entry.
+	pub const fn build(self) -> u64 {
+		let mut bits = self.vector as u64 | (self.delivery as u64) << 8;
+		if self.active_low {
+			bits |= 1 << 13;
+		}
+		if self.level_triggered {
+			bits |= 1 << 15;
+		}
+		if self.masked {
+			bits |= Self::MASKED;
+		}
+
+		bits | (self.destination as u64) << 56
+	}
+
+	/// Decodes a raw 64-bit entry. Unknown delivery modes are decoded as
+	/// [`DeliveryMode::Fixed`].
+	pub const fn decode(bits: u64) -> Self {
+		Self {
+			vector: bits as u8,
+			delivery: match (bits >> 8) & 0b111 {
+				0b001 => DeliveryMode::LowestPriority,
+				0b010 => DeliveryMode::Smi,
+				0b100 => DeliveryMode::Nmi,
+				0b101 => DeliveryMode::Init,
+				_ => DeliveryMode::Fixed,
+			},
+			active_low: bits & (1 << 13) != 0,
+			level_triggered: bits & (1 << 15) != 0,
+			masked: bits & Self::MASKED != 0,
+			destination: (bits >> 56) as u8,
+		}
+	}
+}
+
+/// How a legacy ISA IRQ is wired to the I/O APICs. See [`isa_route`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct IsaRoute {
+	/// The GSI the IRQ is wired to.
+	pub gsi: u32,
+	pub active_low: bool,
+	pub level_triggered: bool,
+}
+
+/// Finds how the ISA IRQ `irq` is wired, using the MADT's interrupt source overrides. Without an
+/// override, IRQ N is GSI N, edge-triggered, and active high.
+pub fn isa_route(madt: &Madt, irq: u8) -> IsaRoute {
+	let mut route = IsaRoute {
+		gsi: irq as u32,
+		active_low: false,
+		level_triggered: false,
+	};
+
+	for entry in madt.entries() {
+		let MadtEntry::InterruptSourceOverride(source_override) = entry else {
+			continue;
+		};
+		if source_override.bus != 0 || source_override.source != irq {
+			continue;
+		}
+
+		route.gsi = source_override.global_system_interrupt;
+		// The ISA bus defaults are active high and edge-triggered
+		route.active_low = source_override.polarity() == Polarity::ActiveLow;
+		route.level_triggered = source_override.trigger_mode() == TriggerMode::Level;
+	}
+
+	route
+}
+
+/// Routes the ISA IRQ `irq` to `vector` on the core with APIC ID `destination`, through whichever
+/// I/O APIC in `io_apics` handles it (see [`isa_route`]). Returns the route, or `None` if none of
+/// the I/O APICs handle its GSI.
+///
+/// # Safety
+/// `vector` needs a handler on the destination core.
+pub unsafe fn route_isa_irq(
+	io_apics: &mut [IoApic],
+	madt: &Madt,
+	irq: u8,
+	vector: u8,
+	destination: u8,
+) -> Option<IsaRoute> {
+	let route = isa_route(madt, irq);
+	let io_apic = io_apics
+		.iter_mut()
+		.find(|io_apic| io_apic.handles(route.gsi))?;
+
+	let entry = RedirectionEntry {
+		active_low: route.active_low,
+		level_triggered: route.level_triggered,
+		..RedirectionEntry::new(vector, destination)
+	};
+	unsafe { io_apic.set_entry(route.gsi, entry) };
+
+	Some(route)
+}
@@ -0,0 +1,107 @@
+//! A registry of hardware interrupt (IRQ) handlers, so drivers can claim IRQs at runtime instead
+//! of every handler being hardcoded into the IDT.
+//!
+//! [`install_irq_stubs`] fills the IDT with a stub for every IRQ. IRQ N arrives at vector
+//! [`IRQ_VECTOR_BASE`] + N, which is where [`pic`] puts them by default, and where the I/O APIC
+//! should route them to (see [`ioapic::route_isa_irq`]). The stub looks up the handler registered
+//! with [`register_irq`], calls it, then sends the end-of-interrupt to whichever
+//! [`InterruptController`] was set with [`set_controller`] - so handlers don't need to.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Interrupt_Service_Routines
+
+use {
+	super::{apic::LocalApic, exceptions::*, pic::ChainedPics, *},
+	crate::sync::Spinlock,
+};
+
+/// How many IRQs the registry has room for. That's every legacy ISA IRQ, plus the extra pins an
+/// I/O APIC usually has.
+pub const IRQ_COUNT: usize = 24;
+/// The vector IRQ 0 arrives at.
+pub const IRQ_VECTOR_BASE: u8 = pic::PRIMARY_OFFSET;
+
+/// A handler for an IRQ. It gets the IRQ number, so one function can handle several IRQs.
+pub type IrqHandler = fn(u8);
+
+/// The interrupt controller IRQs come from, which gets sent an end-of-interrupt after each one.
+pub enum InterruptController {
+	Pic(ChainedPics),
+	Apic(LocalApic),
+}
+
+/// The handler for each IRQ.
+static HANDLERS: Spinlock<[Option<IrqHandler>; IRQ_COUNT]> = Spinlock::new([None; IRQ_COUNT]);
+/// Where end-of-interrupts get sent.
+static CONTROLLER: Spinlock<Option<InterruptController>> = Spinlock::new(None);
+
+/// The vector `irq` arrives at.
+pub const fn vector(irq: u8) -> u8 {
+	IRQ_VECTOR_BASE + irq
+}
+
+/// Fills vectors [`IRQ_VECTOR_BASE`] to [`IRQ_VECTOR_BASE`] + [`IRQ_COUNT`] with stubs that
+/// dispatch to the registered handlers, in the code segment `segment`.
+pub fn install_irq_stubs<const LEN: usize>(idt: &mut Idt<LEN>, segment: u16) {
+	for (irq, stub) in STUBS.into_iter().enumerate() {
+		idt.set_handler(vector(irq as u8), stub, segment, InterruptOptions::new());
+	}
+}
+
+/// Sets the interrupt controller that end-of-interrupts get sent to, returning the old one. Until
+/// this is called, no end-of-interrupts are sent, so each controller only sends one IRQ.
+///
+/// # Safety
+/// The controller has to be the one actually sending IRQs, and nothing else can be using it.
+pub unsafe fn set_controller(controller: InterruptController) -> Option<InterruptController> {
+	CONTROLLER.lock().replace(controller)
+}
+
+/// Makes `handler` handle `irq`. Returns `false` if `irq` already has a handler, or is
+/// [`IRQ_COUNT`] or higher.
+///
+/// The IRQ still has to be unmasked in the interrupt controller afterwards.
+pub fn register_irq(irq: u8, handler: IrqHandler) -> bool {
+	match HANDLERS.lock().get_mut(irq as usize) {
+		Some(slot @ None) => {
+			*slot = Some(handler);
+			true
+		}
+		_ => false,
+	}
+}
+
+/// Removes `irq`'s handler, returning it. The IRQ should be masked first, since any more
+/// interrupts on it will be ignored.
+pub fn unregister_irq(irq: u8) -> Option<IrqHandler> {
+	HANDLERS.lock().get_mut(irq as usize)?.take()
+}
+
+/// Runs `irq`'s handler, then sends the end-of-interrupt. IRQs without a handler are just
+/// acknowledged.
+fn dispatch(irq: u8) {
+	// The lock isn't held while the handler runs, so it can (un)register IRQs
+	let handler = HANDLERS.lock()[irq as usize];
+	if let Some(handler) = handler {
+		handler(irq);
+	}
+
+	match &mut *CONTROLLER.lock() {
+		Some(InterruptController::Pic(pics)) => unsafe { pics.end_of_interrupt(vector(irq)) },
+		Some(InterruptController::Apic(apic)) => apic.end_of_interrupt(),
+		None => {}
+	}
+}
+
+extern "x86-interrupt" fn stub<const IRQ: u8>(_: InterruptStackFrame) {
+	dispatch(IRQ);
+}
+
+macro_rules! stubs {
+	($($irq:literal)*) => {
+		[$(stub::<$irq> as HandlerFunc),*]
+	};
+}
+/// The stub for each IRQ.
+const STUBS: [HandlerFunc; IRQ_COUNT] =
+	stubs!(0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23);
@@ -0,0 +1,113 @@
+//! Dedicated stacks for interrupts that can't trust the current stack, from the TSS' Interrupt
+//! Stack Table (IST).
+//!
+//! Normally an interrupt's handler runs on whatever stack was active when it arrived. That's a
+//! problem for a double fault caused by a stack overflow, since the CPU can't push the stack frame
+//! either and triple faults instead. It's also a problem for NMIs and machine checks, which can
+//! arrive at any point - like halfway through switching stacks. Giving those interrupts an IST
+//! stack makes the CPU always switch to a known-good stack first.
+//!
+//! [`map_interrupt_stack`] does all of it in one call: it maps a stack with a guard page, puts it
+//! in the TSS, points the interrupt's descriptor at it, and registers it (see [`register_stack`])
+//! so an overflow of the interrupt stack itself gets reported. [`map_exception_stacks`] does that
+//! for the double fault, NMI, and machine check handlers.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Task_State_Segment
+//! - https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html (vol 3, section 6.14.5)
+
+use {
+	super::*,
+	crate::{gdt::Tss, paging::*},
+};
+
+/// An interrupt that gets its own stack, and which IST stack it uses.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InterruptStack {
+	/// The interrupt's vector.
+	pub vector: u8,
+	/// Which IST stack it uses, from 1 to 7 (see [`InterruptOptions::stack`]).
+	pub index: u8,
+	/// The stack's name, for reporting stack overflows.
+	pub name: &'static str,
+}
+impl InterruptStack {
+	pub const DOUBLE_FAULT: Self = Self {
+		vector: 8,
+		index: 1,
+		name: "double fault",
+	};
+	pub const NMI: Self = Self {
+		vector: 2,
+		index: 2,
+		name: "NMI",
+	};
+	pub const MACHINE_CHECK: Self = Self {
+		vector: 18,
+		index: 3,
+		name: "machine check",
+	};
+}
+
+impl<const LEN: usize> Idt<LEN> {
+	/// Makes the interrupt at `vector` switch to the IST stack `index` (from 1 to 7), or to no IST
+	/// stack if `index` is 0. Panics if `index` is more than 7.
+	pub fn set_stack(&mut self, vector: u8, index: u8) {
+		assert!(index <= 7, "The interrupt stack table only has 7 stacks");
+		self.interrupts[vector as usize].stack_table = index;
+	}
+}
+
+/// Maps a stack of `pages` pages for `stack`, with a guard page below it starting at `virt`, then
+/// puts it in `tss`, makes `stack`'s vector use it in `idt`, and registers it with
+/// [`register_stack`]. Returns the stack.
+///
+/// The interrupt's handler should be installed first, since installing it resets the descriptor's
+/// stack. Panics if `stack`'s index isn't from 1 to 7, since 0 means it doesn't use the IST.
+///
+/// # Safety
+/// `tss` and `idt` can't be loaded yet, or if they are, interrupts have to be disabled.
+pub unsafe fn map_interrupt_stack<const LEN: usize>(
+	idt: &mut Idt<LEN>,
+	tss: &mut Tss,
+	mapper: &mut Mapper,
+	stack: InterruptStack,
+	virt: u64,
+	pages: u64,
+) -> Result<GuardedStack, MapError> {
+	assert!(
+		(1..=7).contains(&stack.index),
+		"IST stacks are numbered from 1 to 7"
+	);
+	let guarded = mapper.map_guarded_stack(virt, 1, pages)?;
+
+	tss.interrupt_stacks[stack.index as usize - 1] = guarded.top;
+	idt.set_stack(stack.vector, stack.index);
+	register_stack(stack.name, guarded);
+
+	Ok(guarded)
+}
+
+/// Maps a stack for the double fault, NMI, and machine check handlers (see [`InterruptStack`]),
+/// each `pages` pages with a guard page. The stacks are next to each other, starting at `virt`.
+/// See [`map_interrupt_stack`].
+///
+/// # Safety
+/// See [`map_interrupt_stack`].
+pub unsafe fn map_exception_stacks<const LEN: usize>(
+	idt: &mut Idt<LEN>,
+	tss: &mut Tss,
+	mapper: &mut Mapper,
+	virt: u64,
+	pages: u64,
+) -> Result<[GuardedStack; 3], MapError> {
+	let stride = (pages + 1) * PAGE_SIZE;
+	let mut map =
+		|stack, virt| unsafe { map_interrupt_stack(idt, tss, mapper, stack, virt, pages) };
+
+	Ok([
+		map(InterruptStack::DOUBLE_FAULT, virt)?,
+		map(InterruptStack::NMI, virt + stride)?,
+		map(InterruptStack::MACHINE_CHECK, virt + stride * 2)?,
+	])
+}
@@ -0,0 +1,227 @@
+//! Handling for non-maskable interrupts (NMIs) and machine checks (#MC), which is how the hardware
+//! reports errors it can't fix - like memory going bad, or a bus error.
+//!
+//! Machine checks are reported through the machine-check architecture (MCA): a set of "banks" of
+//! MSRs, one per hardware unit (caches, memory controllers, etc). When a unit finds an error, it
+//! fills in its bank's status register (and maybe an address), then raises #MC if the error
+//! couldn't be corrected. Machine checks are off until they're turned on with
+//! [`enable_machine_checks`] - before that, a machine check just shuts down the CPU.
+//!
+//! NMIs come from the chipset (memory parity and I/O channel errors, reported in port 0x61), or
+//! from watchdogs and other cores. They can't be masked, and can arrive in the middle of anything.
+//!
+//! [`nmi_handler`] and [`machine_check_handler`] dump everything they can find, then panic, so a
+//! hardware error shows up as a readable report instead of a silent triple fault. They're what
+//! [`install_default_handlers`] uses.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Machine_Check_Exception
+//! - https://wiki.osdev.org/Non_Maskable_Interrupt
+//! - https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html (vol 3, chapter 16)
+
+use {
+	super::{exceptions::InterruptStackFrame, *},
+	crate::{cpuid::Feature, msr},
+	core::fmt::{self, Display, Formatter},
+};
+
+/// The machine-check enable bit in CR4.
+const CR4_MCE: usize = 1 << 6;
+/// The system control port, which has the chipset's NMI status.
+const SYSTEM_CONTROL: u16 = 0x61;
+
+/// If the CPU supports machine checks and the machine-check architecture (CPUID leaf 1, EDX bits 7
+/// and 14).
+pub fn machine_check_supported() -> bool {
+	Feature::MachineCheck.supported() && Feature::MachineCheckArchitecture.supported()
+}
+
+/// How many machine-check banks the CPU has (from `IA32_MCG_CAP`).
+///
+/// # Safety
+/// The CPU must support machine checks (see [`machine_check_supported`]).
+pub unsafe fn bank_count() -> u8 {
+	unsafe { msr::read(msr::IA32_MCG_CAP) as u8 }
+}
+
+/// Turns on every error report in every bank, clears any old errors, and turns on machine checks in
+/// CR4.
+///
+/// # Safety
+/// The CPU must support machine checks (see [`machine_check_supported`]), and the IDT must have a
+/// machine check handler.
+pub unsafe fn enable_machine_checks() {
+	unsafe {
+		// Bit 8 says `IA32_MCG_CTL` exists
+		if msr::read(msr::IA32_MCG_CAP) & (1 << 8) != 0 {
+			msr::write(msr::IA32_MCG_CTL, u64::MAX);
+		}
+		for bank in 0..bank_count() {
+			msr::write(msr::mc_ctl(bank), u64::MAX);
+			msr::write(msr::mc_status(bank), 0);
+		}
+
+		asm!(
+			"mov {0}, cr4",
+			"or {0}, {1}",
+			"mov cr4, {0}",
+			out(reg) _,
+			const CR4_MCE,
+			options(nomem, nostack, preserves_flags)
+		);
+	}
+}
+
+/// An error reported in one of the machine-check banks.
+#[derive(Clone, Copy, Debug)]
+pub struct MachineCheckBank {
+	/// Which bank reported the error.
+	pub index: u8,
+	/// The bank's status register.
+	pub status: u64,
+	/// The address the error happened at, if the bank saved one.
+	pub address: Option<u64>,
+	/// Model-specific extra information, if the bank saved any.
+	pub misc: Option<u64>,
+}
+impl MachineCheckBank {
+	/// Reads the error in bank `index`, or `None` if it doesn't have one.
+	///
+	/// # Safety
+	/// The CPU must support machine checks, and `index` must be less than [`bank_count`].
+	pub unsafe fn read(index: u8) -> Option<Self> {
+		let status = unsafe { msr::read(msr::mc_status(index)) };
+		// Bit 63 says the bank has an error
+		if status & (1 << 63) == 0 {
+			return None;
+		}
+
+		// Bits 58 and 59 say if the address and misc registers are valid
+		let address = (status & (1 << 58) != 0).then(|| unsafe { msr::read(msr::mc_addr(index)) });
+		let misc = (status & (1 << 59) != 0).then(|| unsafe { msr::read(msr::mc_misc(index)) });
+
+		Some(Self {
+			index,
+			status,
+			address,
+			misc,
+		})
+	}
+
+	/// The architectural error code, which says what kind of error it was.
+	pub fn error_code(&self) -> u16 {
+		self.status as u16
+	}
+
+	/// The model-specific error code.
+	pub fn model_code(&self) -> u16 {
+		(self.status >> 16) as u16
+	}
+
+	/// If the error couldn't be corrected.
+	pub fn uncorrected(&self) -> bool {
+		self.status & (1 << 61) != 0
+	}
+
+	/// If the error left the CPU in a state it can't safely continue from.
+	pub fn context_corrupt(&self) -> bool {
+		self.status & (1 << 57) != 0
+	}
+
+	/// If another error happened before this one was cleared, so some information was lost.
+	pub fn overflow(&self) -> bool {
+		self.status & (1 << 62) != 0
+	}
+}
+impl Display for MachineCheckBank {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"Bank {}: status {:#018x} (error {:#06x}, model {:#06x})",
+			self.index,
+			self.status,
+			self.error_code(),
+			self.model_code()
+		)?;
+
+		if self.uncorrected() {
+			write!(f, ", uncorrected")?;
+		}
+		if self.context_corrupt() {
+			write!(f, ", context corrupt")?;
+		}
+		if self.overflow() {
+			write!(f, ", overflowed")?;
+		}
+		if let Some(address) = self.address {
+			write!(f, ", address {address:#x}")?;
+		}
+		if let Some(misc) = self.misc {
+			write!(f, ", misc {misc:#x}")?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Every bank that has an error in it.
+///
+/// # Safety
+/// The CPU must support machine checks (see [`machine_check_supported`]).
+pub unsafe fn errors() -> impl Iterator<Item = MachineCheckBank> {
+	(0..unsafe { bank_count() }).filter_map(|index| unsafe { MachineCheckBank::read(index) })
+}
+
+/// Prints every bank that has an error in it, one per line.
+struct Errors;
+impl Display for Errors {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		if !machine_check_supported() {
+			return write!(f, "The CPU doesn't support the machine-check architecture");
+		}
+
+		let status = unsafe { msr::read(msr::IA32_MCG_STATUS) };
+		write!(f, "MCG status: {status:#x}")?;
+		if status & 1 == 0 {
+			write!(f, " (can't restart)")?;
+		}
+		for bank in unsafe { errors() } {
+			write!(f, "\n{bank}")?;
+		}
+
+		Ok(())
+	}
+}
+
+/// The chipset's reasons for an NMI, from the system control port.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct NmiReason {
+	/// A memory parity error (or some other memory error, on newer chipsets).
+	pub memory_parity: bool,
+	/// An I/O channel check - an expansion card reported an error.
+	pub io_channel_check: bool,
+}
+impl NmiReason {
+	/// Reads the NMI reason from the system control port.
+	pub fn read() -> Self {
+		let port = unsafe { portio::read_u8(SYSTEM_CONTROL) };
+		Self {
+			memory_parity: port & (1 << 7) != 0,
+			io_channel_check: port & (1 << 6) != 0,
+		}
+	}
+}
+
+/// Reports an NMI, with the chipset's reason for it and any machine-check errors, then panics.
+pub extern "x86-interrupt" fn nmi_handler(frame: InterruptStackFrame) {
+	panic!(
+		"EXCEPTION: Non-maskable interrupt\n{:?}\n{}\n{frame:?}",
+		NmiReason::read(),
+		Errors
+	);
+}
+
+/// Reports a machine check, with every machine-check bank that has an error, then panics.
+pub extern "x86-interrupt" fn machine_check_handler(frame: InterruptStackFrame) -> ! {
+	panic!("EXCEPTION: Machine check\n{}\n{frame:?}", Errors);
+}
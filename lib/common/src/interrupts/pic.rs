@@ -0,0 +1,180 @@
+//! A driver for the 8259 programmable interrupt controller (PIC), the legacy way hardware
+//! interrupts get to the CPU.
+//!
+//! PCs have 2 PICs, each with 8 IRQ lines. The secondary PIC is chained into IRQ 2 of the primary
+//! one, so there are 15 usable IRQs in total. Each PIC gives its IRQs consecutive interrupt
+//! vectors, starting at an offset. By default the offsets are 8 and 0x70, which overlap with the
+//! CPU's exceptions, so the first thing to do with the PICs is remap them (usually to 32 and 40,
+//! right after the exceptions).
+//!
+//! Once an IRQ's handler is done, it has to send an end-of-interrupt (EOI) command to the PIC,
+//! or the PIC won't send any more interrupts at that priority or lower.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/8259_PIC
+//! - https://pdos.csail.mit.edu/6.828/2014/readings/hardware/8259A.pdf
+
+/// The vector the primary PIC's IRQs usually start at, right after the CPU exceptions.
+pub const PRIMARY_OFFSET: u8 = 32;
+/// The vector the secondary PIC's IRQs usually start at, right after the primary PIC's.
+pub const SECONDARY_OFFSET: u8 = PRIMARY_OFFSET + 8;
+
+/// The initialisation command. Bit 0 says the 4th initialisation word will be sent.
+const ICW1_INIT: u8 = 0x11;
+/// Puts the PIC in 8086 mode.
+const ICW4_8086: u8 = 0x01;
+/// The end-of-interrupt command.
+const EOI: u8 = 0x20;
+
+/// A single 8259 PIC.
+struct Pic {
+	/// The vector this PIC's IRQ 0 is sent as.
+	offset: u8,
+	/// The I/O port commands are sent to.
+	command: u16,
+	/// The I/O port data (like the IRQ mask) is sent to.
+	data: u16,
+}
+impl Pic {
+	fn handles(&self, vector: u8) -> bool {
+		(self.offset..self.offset.saturating_add(8)).contains(&vector)
+	}
+
+	unsafe fn end_of_interrupt(&self) {
+		unsafe { portio::write_u8(self.command, EOI) };
+	}
+
+	unsafe fn mask(&self) -> u8 {
+		unsafe { portio::read_u8(self.data) }
+	}
+
+	unsafe fn set_mask(&self, mask: u8) {
+		unsafe { portio::write_u8(self.data, mask) };
+	}
+}
+
+/// The primary and secondary PICs, chained together. See the module-level docs.
+pub struct ChainedPics {
+	primary: Pic,
+	secondary: Pic,
+}
+impl ChainedPics {
+	/// Creates a driver for the PICs, which will remap them to `primary_offset` and
+	/// `secondary_offset` once [`ChainedPics::initialize`] is called.
+	///
+	/// # Safety
+	/// Nothing else can be using the PICs, and the offsets can't overlap with the CPU's exceptions
+	/// or each other.
+	pub const unsafe fn new(primary_offset: u8, secondary_offset: u8) -> Self {
+		Self {
+			primary: Pic {
+				offset: primary_offset,
+				command: 0x20,
+				data: 0x21,
+			},
+			secondary: Pic {
+				offset: secondary_offset,
+				command: 0xA0,
+				data: 0xA1,
+			},
+		}
+	}
+
+	/// Remaps the PICs to their offsets. Every IRQ is masked afterwards, so they have to be
+	/// unmasked with [`ChainedPics::unmask`] once they have handlers.
+	///
+	/// # Safety
+	/// Interrupts should be disabled while this runs.
+	pub unsafe fn initialize(&mut self) {
+		let (primary, secondary) = (&self.primary, &self.secondary);
+
+		// Older PICs need a moment between commands, which `io_wait` gives them
+		unsafe {
+			portio::write_u8(primary.command, ICW1_INIT);
+			portio::io_wait();
+			portio::write_u8(secondary.command, ICW1_INIT);
+			portio::io_wait();
+
+			// ICW2: The vector offsets
+			portio::write_u8(primary.data, primary.offset);
+			portio::io_wait();
+			portio::write_u8(secondary.data, secondary.offset);
+			portio::io_wait();
+
+			// ICW3: Tell the primary PIC the secondary one is on IRQ 2, and tell the secondary PIC
+			// its cascade identity
+			portio::write_u8(primary.data, 1 << 2);
+			portio::io_wait();
+			portio::write_u8(secondary.data, 2);
+			portio::io_wait();
+
+			portio::write_u8(primary.data, ICW4_8086);
+			portio::io_wait();
+			portio::write_u8(secondary.data, ICW4_8086);
+			portio::io_wait();
+		}
+
+		// Everything starts masked, except the cascade to the secondary PIC
+		unsafe { self.set_masks(!(1 << 2)) };
+	}
+
+	/// If `vector` is one of the PICs' IRQs.
+	pub fn handles(&self, vector: u8) -> bool {
+		self.primary.handles(vector) || self.secondary.handles(vector)
+	}
+
+	/// Tells the PICs the interrupt at `vector` has been handled. Does nothing if the PICs don't
+	/// handle `vector`.
+	///
+	/// # Safety
+	/// Should only be called at the end of the handler for `vector`.
+	pub unsafe fn end_of_interrupt(&mut self, vector: u8) {
+		if self.secondary.handles(vector) {
+			// The secondary PIC's interrupts go through the primary one, so they both need an EOI
+			unsafe { self.secondary.end_of_interrupt() };
+		}
+		if self.handles(vector) {
+			unsafe { self.primary.end_of_interrupt() };
+		}
+	}
+
+	/// The masks of both PICs, with the primary PIC's IRQs in the low byte. A set bit means the
+	/// IRQ is masked (disabled).
+	pub fn masks(&self) -> u16 {
+		unsafe { u16::from_le_bytes([self.primary.mask(), self.secondary.mask()]) }
+	}
+
+	/// Sets the masks of both PICs. See [`ChainedPics::masks`].
+	///
+	/// # Safety
+	/// Any IRQ that's unmasked needs a handler.
+	pub unsafe fn set_masks(&mut self, masks: u16) {
+		let [primary, secondary] = masks.to_le_bytes();
+		unsafe {
+			self.primary.set_mask(primary);
+			self.secondary.set_mask(secondary);
+		}
+	}
+
+	/// Masks (disables) `irq`, from 0 to 15.
+	pub fn mask(&mut self, irq: u8) {
+		assert!(irq < 16, "The PICs only have 16 IRQs");
+		let masks = self.masks() | (1 << irq);
+		unsafe { self.set_masks(masks) };
+	}
+
+	/// Unmasks (enables) `irq`, from 0 to 15.
+	///
+	/// # Safety
+	/// `irq` needs a handler.
+	pub unsafe fn unmask(&mut self, irq: u8) {
+		assert!(irq < 16, "The PICs only have 16 IRQs");
+		let masks = self.masks() & !(1 << irq);
+		unsafe { self.set_masks(masks) };
+	}
+
+	/// Masks every IRQ, eg to switch to the APIC instead.
+	pub fn disable(&mut self) {
+		unsafe { self.set_masks(0xFFFF) };
+	}
+}
@@ -0,0 +1,139 @@
+//! A driver for the 8253/8254 programmable interval timer (PIT), the legacy timer every PC has.
+//!
+//! The PIT has an oscillator running at about 1.193182 MHz, and 3 channels that count down from a
+//! reload value at that rate:
+//! - Channel 0 is wired to IRQ 0, and is used for a periodic tick (see [`set_frequency`]).
+//! - Channel 1 used to refresh DRAM, and is usually missing now.
+//! - Channel 2 is wired to the PC speaker. Its output can be read back from port 0x61, so it can
+//!   be polled without interrupts, which is what [`sleep_ms`] uses it for.
+//!
+//! The tick counter only goes up if the IRQ 0 handler calls [`tick`].
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Programmable_Interval_Timer
+//! - http://www.osdever.net/bkerndev/Docs/pit.htm
+
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// How many times per second the PIT's oscillator ticks.
+pub const BASE_FREQUENCY: u32 = 1_193_182;
+/// The IRQ channel 0 is wired to.
+pub const IRQ: u8 = 0;
+
+/// Channel 0's data port.
+const CHANNEL_0: u16 = 0x40;
+/// Channel 2's data port.
+const CHANNEL_2: u16 = 0x42;
+/// The mode/command port.
+const COMMAND: u16 = 0x43;
+/// The PC speaker port. Bit 0 gates channel 2, bit 1 connects it to the speaker, and bit 5 is
+/// channel 2's output.
+const SPEAKER: u16 = 0x61;
+
+/// Command bits: access the reload value's low byte, then its high byte.
+const ACCESS_LOW_HIGH: u8 = 0b11 << 4;
+/// Command bits: mode 0, interrupt on terminal count. The output goes high once the count hits 0.
+const MODE_ONE_SHOT: u8 = 0b000 << 1;
+/// Command bits: mode 2, rate generator. The channel reloads itself every time the count hits 0.
+const MODE_RATE_GENERATOR: u8 = 0b010 << 1;
+
+/// How many ticks channel 0 has sent. See [`tick`].
+static TICKS: AtomicU64 = AtomicU64::new(0);
+/// How many times per second channel 0 ticks, or 0 if it hasn't been set up.
+static FREQUENCY: AtomicU32 = AtomicU32::new(0);
+
+/// Makes channel 0 fire IRQ 0 about `hz` times per second. Returns the actual frequency, which is
+/// as close as the PIT can get (between about 19 Hz and half of [`BASE_FREQUENCY`]).
+///
+/// # Safety
+/// Nothing else can be using the PIT, and IRQ 0 needs a handler if it's unmasked.
+pub unsafe fn set_frequency(hz: u32) -> u32 {
+	let divisor = reload_value(BASE_FREQUENCY / hz.max(1));
+	unsafe { program(CHANNEL_0, 0, MODE_RATE_GENERATOR, divisor) };
+
+	// A reload value of 0 means 65536
+	let hz = BASE_FREQUENCY / if divisor == 0 { 65536 } else { divisor as u32 };
+	FREQUENCY.store(hz, Ordering::Relaxed);
+
+	hz
+}
+
+/// How many times per second channel 0 ticks, or `None` if [`set_frequency`] hasn't been called.
+pub fn frequency() -> Option<u32> {
+	match FREQUENCY.load(Ordering::Relaxed) {
+		0 => None,
+		hz => Some(hz),
+	}
+}
+
+/// Counts a tick. The IRQ 0 handler should call this.
+pub fn tick() {
+	TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// How many ticks there have been since the PIT was set up.
+pub fn ticks() -> u64 {
+	TICKS.load(Ordering::Relaxed)
+}
+
+/// Roughly how many milliseconds have passed since the PIT was set up, from the tick counter.
+/// Returns 0 if [`set_frequency`] hasn't been called.
+pub fn uptime_ms() -> u64 {
+	frequency().map_or(0, |hz| ticks() * 1000 / hz as u64)
+}
+
+/// Waits about `ms` milliseconds, by polling channel 2. This works with interrupts disabled and
+/// doesn't need the tick counter, so it can be used before there's a scheduler (or even an IDT).
+///
+/// # Safety
+/// Nothing else can be using channel 2 (or the PC speaker).
+pub unsafe fn sleep_ms(ms: u32) {
+	for _ in 0..ms {
+		unsafe { sleep_ticks(BASE_FREQUENCY.div_ceil(1000) as u16) };
+	}
+}
+
+/// Waits for `ticks` ticks of the PIT's oscillator (see [`BASE_FREQUENCY`]), by polling channel 2.
+/// The longest wait is 65535 ticks, about 55ms.
+///
+/// # Safety
+/// See [`sleep_ms`].
+pub unsafe fn sleep_ticks(ticks: u16) {
+	unsafe {
+		// Gate channel 2 off until it's programmed, and disconnect it from the speaker so it doesn't
+		// beep
+		let speaker = portio::read_u8(SPEAKER) & !0b11;
+		portio::write_u8(SPEAKER, speaker);
+
+		// Programming the channel resets its output to low, and it goes high once the count runs
+		// out. The count starts when the gate goes high.
+		program(CHANNEL_2, 2, MODE_ONE_SHOT, reload_value(ticks as u32));
+		portio::write_u8(SPEAKER, speaker | 1);
+
+		while portio::read_u8(SPEAKER) & (1 << 5) == 0 {
+			core::hint::spin_loop();
+		}
+
+		portio::write_u8(SPEAKER, speaker);
+	}
+}
+
+/// Clamps a reload value to what fits in the PIT's 16-bit counters, where 0 means 65536. The rate
+/// generator mode can't count down from 1, so the smallest value is 2.
+fn reload_value(value: u32) -> u16 {
+	match value {
+		0 | 1 => 2,
+		65536.. => 0,
+		value => value as u16,
+	}
+}
+
+/// Sends a channel a mode, then its reload value.
+unsafe fn program(port: u16, channel: u8, mode: u8, reload: u16) {
+	let [low, high] = reload.to_le_bytes();
+	unsafe {
+		portio::write_u8(COMMAND, channel << 6 | ACCESS_LOW_HIGH | mode);
+		portio::write_u8(port, low);
+		portio::write_u8(port, high);
+	}
+}
@@ -1,9 +1,27 @@
 #![no_std]
+#![cfg_attr(target_arch = "x86_64", feature(abi_x86_interrupt))]
 
+#[cfg(target_arch = "x86")]
+pub mod a20;
+pub mod boot_info;
+pub mod command_line;
+pub mod cpuid;
+#[cfg(target_arch = "x86")]
+pub mod disks;
+pub mod fpu;
 pub mod gdt;
 pub mod interrupts;
+pub mod mbr;
+pub mod memory_map;
+pub mod msr;
 pub mod paging;
 pub mod printing;
+pub mod random;
+pub mod rtc;
+pub mod stages;
+pub mod sync;
+#[cfg(target_arch = "x86")]
+pub mod vbe;
 
 #[cfg(all(not(test), feature = "panic"))]
 mod panic {
@@ -11,7 +29,6 @@ mod panic {
 
 	#[panic_handler]
 	fn ohgod(info: &PanicInfo) -> ! {
-		println!("\n\n(don't?) PANIC:\n\n{info}");
-		loop {}
+		printing::panic_screen("bs", info)
 	}
 }
@@ -0,0 +1,108 @@
+//! The MBR (Master Boot Record) partition table.
+//!
+//! The first sector of a disk is the MBR: 446 bytes of boot code (the bootstrapper), then a
+//! partition table with 4 entries, then the boot signature (0xAA55). BS' boot programs live in
+//! their own partition, marked active and with type [`BS_PARTITION_TYPE`], so the rest of the disk
+//! can have other partitions (like a FAT partition for data). The bootloader finds the active
+//! partition, then loads the rest of BS from it (see [`crate::stages`]).
+//!
+//! The bootstrapper doesn't have room to do that, so the build also writes where the bootloader is
+//! into the MBR, at [`BOOTLOADER_LOCATION_OFFSET`].
+//!
+//! The BIOS already loaded the MBR (along with the bootstrapper), so there's no need to read it off
+//! the disk again. The bootstrapper moves it to [`MBR_ADDRESS`] before loading the bootloader.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/MBR_(x86)
+//! - https://wiki.osdev.org/Partition_Table
+//! - https://en.wikipedia.org/wiki/Master_boot_record
+
+/// Where the MBR is in memory, once the bootstrapper's moved it. The BIOS loads it to 0x7C00, but
+/// the bootloader gets loaded over that (see [`crate::stages::LOAD_ADDRESS`]).
+pub const MBR_ADDRESS: usize = 0x600;
+/// Where the partition table is in the MBR.
+pub const PARTITION_TABLE_OFFSET: usize = 446;
+/// Where the bootloader's location is in the MBR: a [`StageLocation`](crate::stages::StageLocation),
+/// except its LBA is from the start of the disk, not the boot partition. It's right before the
+/// disk signature (at 440), which other OSes use to tell disks apart, so it's left alone.
+pub const BOOTLOADER_LOCATION_OFFSET: usize = 432;
+/// The partition type BS' boot partition uses. 0x7F is reserved for hobby OSes and experiments, so
+/// other OSes should leave it alone.
+pub const BS_PARTITION_TYPE: u8 = 0x7F;
+
+/// One partition in the partition table.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PartitionEntry {
+	/// 0x80 if the partition is active (bootable), 0 otherwise.
+	pub status: u8,
+	/// The partition's first sector, in CHS. BS only uses the LBA fields.
+	pub first_chs: [u8; 3],
+	/// What's in the partition. 0 means the entry isn't used.
+	pub kind: u8,
+	/// The partition's last sector, in CHS.
+	pub last_chs: [u8; 3],
+	/// The partition's first sector.
+	pub start_lba: u32,
+	/// How many sectors are in the partition.
+	pub sectors: u32,
+}
+impl PartitionEntry {
+	/// The `status` of an active partition.
+	pub const ACTIVE: u8 = 0x80;
+	/// The CHS address used for partitions that can only be found with LBA. It's the highest CHS
+	/// address there is, which tells most software to use the LBA fields instead.
+	const LBA_ONLY_CHS: [u8; 3] = [0xFE, 0xFF, 0xFF];
+
+	/// A partition that starts at `start_lba` and is `sectors` sectors long. The CHS fields are set
+	/// so it can only be found with LBA.
+	pub const fn new(kind: u8, start_lba: u32, sectors: u32, active: bool) -> Self {
+		Self {
+			status: if active { Self::ACTIVE } else { 0 },
+			first_chs: Self::LBA_ONLY_CHS,
+			kind,
+			last_chs: Self::LBA_ONLY_CHS,
+			start_lba,
+			sectors,
+		}
+	}
+
+	/// If the partition is active (bootable).
+	pub fn is_active(&self) -> bool {
+		self.status == Self::ACTIVE
+	}
+
+	/// If this entry is actually a partition.
+	pub fn is_used(&self) -> bool {
+		self.kind != 0 && self.sectors != 0
+	}
+}
+
+/// The 4 entries in the MBR's partition table.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PartitionTable {
+	pub entries: [PartitionEntry; 4],
+}
+impl PartitionTable {
+	/// Gets the partition table in the MBR at `mbr`.
+	///
+	/// # Safety
+	/// `mbr` has to point to a readable 512-byte MBR.
+	pub unsafe fn from_mbr(mbr: *const u8) -> &'static Self {
+		unsafe { &*(mbr.add(PARTITION_TABLE_OFFSET) as *const Self) }
+	}
+
+	/// The active partition. If there's more than one (which isn't allowed, but happens), this is
+	/// the first.
+	pub fn active(&self) -> Option<PartitionEntry> {
+		self.entries
+			.into_iter()
+			.find(|entry| entry.is_used() && entry.is_active())
+	}
+
+	/// The table as bytes, to write into an MBR at [`PARTITION_TABLE_OFFSET`].
+	pub fn to_bytes(&self) -> [u8; 64] {
+		unsafe { core::mem::transmute(*self) }
+	}
+}
@@ -0,0 +1,321 @@
+//! The physical memory map: which parts of physical memory are RAM, and which are reserved for
+//! firmware, ACPI, or devices.
+//!
+//! The map comes from the BIOS, with int 15h, EAX=0xE820 (see [`read_e820`], which only works in
+//! real mode). The BIOS gives the regions in any order, and they can overlap - so
+//! [`MemoryMap::normalize`] sorts them, gives overlapping parts the most restrictive kind, and
+//! merges neighbouring regions of the same kind. Normalizing takes up a lot of code, so it's done
+//! in 64-bit mode, by the ELF loader.
+//!
+//! The boot programs add their own allocations to the map with [`MemoryMap::mark_allocated`], so
+//! each stage (and the kernel) knows which memory is still free.
+//!
+//! There's no heap this early, so the map is a fixed-size array of [`MAX_ENTRIES`] entries.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Detecting_Memory_(x86)#BIOS_Function:_INT_0x15.2C_EAX_.3D_0xE820
+//! - https://uefi.org/specs/ACPI/6.5/15_System_Address_Map_Interfaces.html
+
+use crate::paging::{FrameAllocator, MemoryRegion, PhysAddr, PAGE_SIZE};
+
+/// The most entries a [`MemoryMap`] can hold. Real memory maps usually have less than 20.
+pub const MAX_ENTRIES: usize = 64;
+
+/// What a region of physical memory is used for.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegionKind {
+	/// Free RAM.
+	Usable,
+	/// Used by the firmware or a device. Don't touch it.
+	Reserved,
+	/// Holds ACPI tables. It's usable once they've been read.
+	AcpiReclaimable,
+	/// ACPI non-volatile storage. It has to be kept intact, even across sleeps.
+	AcpiNvs,
+	/// RAM that's broken.
+	BadMemory,
+	/// RAM the boot programs allocated: the boot programs themselves, the boot info, the kernel's
+	/// file and segments, its stack, the initrd, and the page tables. It's all in use when the
+	/// kernel starts, but the kernel can reclaim the parts it's done with.
+	BootAllocated,
+}
+impl RegionKind {
+	/// Converts an E820 region type to a kind. Unknown types are reserved, per the ACPI spec.
+	pub fn from_e820(kind: u32) -> Self {
+		match kind {
+			1 => Self::Usable,
+			3 => Self::AcpiReclaimable,
+			4 => Self::AcpiNvs,
+			5 => Self::BadMemory,
+			_ => Self::Reserved,
+		}
+	}
+
+	/// When regions overlap, the kind with the highest priority wins, so memory is never treated
+	/// as more usable than it is.
+	fn priority(&self) -> u8 {
+		match self {
+			Self::Usable => 0,
+			Self::BootAllocated => 1,
+			Self::AcpiReclaimable => 2,
+			Self::AcpiNvs => 3,
+			Self::Reserved => 4,
+			Self::BadMemory => 5,
+		}
+	}
+}
+
+/// One region in the memory map.
+///
+/// This is `align(8)` so it has the same layout in the 16-bit boot programs and 64-bit code, since
+/// it's passed between them in [`crate::boot_info::BootInfo`].
+#[repr(C, align(8))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryMapEntry {
+	/// The first address in the region.
+	pub start: PhysAddr,
+	/// How many bytes are in the region.
+	pub len: u64,
+	/// What the region is used for.
+	pub kind: RegionKind,
+}
+impl MemoryMapEntry {
+	/// The address right after the end of the region.
+	pub fn end(&self) -> PhysAddr {
+		self.start.saturating_add(self.len)
+	}
+
+	/// The region's address range, for the frame allocator.
+	pub fn region(&self) -> MemoryRegion {
+		MemoryRegion {
+			start: self.start,
+			len: self.len,
+		}
+	}
+}
+
+/// The physical memory map. See the module-level docs.
+#[repr(C, align(8))]
+#[derive(Clone, Copy)]
+pub struct MemoryMap {
+	entries: [MemoryMapEntry; MAX_ENTRIES],
+	/// How many entries are used. It's a `u64`, not a `usize`, so the layout is the same in 16-bit
+	/// and 64-bit code.
+	len: u64,
+}
+impl MemoryMap {
+	/// An empty memory map.
+	pub const fn new() -> Self {
+		Self {
+			entries: [MemoryMapEntry {
+				start: 0,
+				len: 0,
+				kind: RegionKind::Reserved,
+			}; MAX_ENTRIES],
+			len: 0,
+		}
+	}
+
+	/// The entries in the map.
+	pub fn entries(&self) -> &[MemoryMapEntry] {
+		&self.entries[..self.len as usize]
+	}
+
+	/// Adds an entry to the end of the map. Returns `false` if the map is full.
+	pub fn push(&mut self, entry: MemoryMapEntry) -> bool {
+		let Some(slot) = self.entries.get_mut(self.len as usize) else {
+			return false;
+		};
+
+		*slot = entry;
+		self.len += 1;
+		true
+	}
+
+	/// Sorts the entries by address, and makes sure none of them overlap (overlapping parts get
+	/// the most restrictive kind). Neighbouring entries with the same kind are merged, and empty
+	/// ones are removed.
+	pub fn normalize(&mut self) {
+		// Every place a region starts or ends. Between two of these, the kind can't change.
+		let mut boundaries = [0; MAX_ENTRIES * 2];
+		let mut count = 0;
+		for entry in self.entries().iter().filter(|entry| entry.len != 0) {
+			boundaries[count] = entry.start;
+			boundaries[count + 1] = entry.end();
+			count += 2;
+		}
+		let boundaries = &mut boundaries[..count];
+		boundaries.sort_unstable();
+
+		let old = *self;
+		self.len = 0;
+		for pair in boundaries.windows(2) {
+			let (start, end) = (pair[0], pair[1]);
+			if start == end {
+				continue;
+			}
+
+			let kind = old
+				.entries()
+				.iter()
+				.filter(|entry| entry.start <= start && end <= entry.end())
+				.map(|entry| entry.kind)
+				.max_by_key(RegionKind::priority);
+			// Holes in the map aren't kept; they aren't usable
+			let Some(kind) = kind else {
+				continue;
+			};
+
+			match self.entries[..self.len as usize].last_mut() {
+				Some(last) if last.end() == start && last.kind == kind => last.len += end - start,
+				_ => {
+					// If there's too many entries, the rest are dropped. They're treated as
+					// unusable, which is safe.
+					self.push(MemoryMapEntry {
+						start,
+						len: end - start,
+						kind,
+					});
+				}
+			}
+		}
+	}
+
+	/// Marks the usable memory `frames` has handed out (or reserved) as
+	/// [`RegionKind::BootAllocated`]. Only frames below [`FrameAllocator::limit`] are checked.
+	///
+	/// The boot programs do this right before handing off to the next stage, which makes its own
+	/// frame allocator from the map's usable regions - so it won't hand out anything they used.
+	///
+	/// The new entries overlap the usable ones, so the map has to be normalized (see
+	/// [`MemoryMap::normalize`]) before it's used. That's left to the caller, since normalizing takes
+	/// up too much code for the 16-bit bootloader; the ELF loader does it instead.
+	///
+	/// Panics if the map fills up, since the memory that didn't fit would look free.
+	pub fn mark_allocated(&mut self, frames: &FrameAllocator) {
+		// New entries go on the end, so only the ones that were already there are checked. They're
+		// indexed instead of copying the map, which is too big for the 16-bit bootloader's stack.
+		for i in 0..self.len as usize {
+			let entry = self.entries[i];
+			if entry.kind != RegionKind::Usable {
+				continue;
+			}
+			let region = entry.region();
+
+			// Only whole frames are tracked
+			let start = region.start.next_multiple_of(PAGE_SIZE);
+			let end = region.end().min(frames.limit()) / PAGE_SIZE * PAGE_SIZE;
+
+			// The extra frame at `end` ends the last run
+			let mut run_start = None;
+			for frame in (start..end).step_by(PAGE_SIZE as usize).chain([end]) {
+				let allocated = frame < end && !frames.is_free(frame);
+				match (run_start, allocated) {
+					(None, true) => run_start = Some(frame),
+					(Some(run), false) => {
+						let entry = MemoryMapEntry {
+							start: run,
+							len: frame - run,
+							kind: RegionKind::BootAllocated,
+						};
+						assert!(self.push(entry), "The memory map is full");
+						run_start = None;
+					}
+					_ => {}
+				}
+			}
+		}
+	}
+
+	/// The usable regions, eg to give to the frame allocator.
+	pub fn usable(&self) -> impl Iterator<Item = MemoryRegion> + '_ {
+		self.entries()
+			.iter()
+			.filter(|entry| entry.kind == RegionKind::Usable)
+			.map(MemoryMapEntry::region)
+	}
+
+	/// How many bytes of usable RAM there are.
+	pub fn usable_bytes(&self) -> u64 {
+		self.usable().map(|region| region.len).sum()
+	}
+
+	/// The end of the highest region in the map.
+	pub fn max_address(&self) -> PhysAddr {
+		self.entries()
+			.iter()
+			.map(MemoryMapEntry::end)
+			.max()
+			.unwrap_or(0)
+	}
+}
+impl Default for MemoryMap {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// An entry, as int 15h EAX=0xE820 writes it.
+#[cfg(target_arch = "x86")]
+#[repr(C, packed)]
+#[derive(Default)]
+struct E820Entry {
+	start: u64,
+	len: u64,
+	kind: u32,
+	/// ACPI 3 attributes. If bit 0 is clear, the entry should be ignored.
+	attributes: u32,
+}
+
+/// Adds the BIOS' memory map (from int 15h EAX=0xE820) to `map`, as-is, without normalizing it.
+/// Returns `false` if the map is still empty afterwards (eg, because the BIOS doesn't support
+/// E820).
+#[cfg(target_arch = "x86")]
+pub fn read_e820(map: &mut MemoryMap) -> bool {
+	use core::{arch::asm, mem::size_of};
+
+	/// "SMAP", which has to be in EDX, and comes back in EAX if the call worked.
+	const SMAP: u32 = 0x534D4150;
+
+	let mut continuation = 0u32;
+	loop {
+		// Some BIOSes don't write the attributes, so they default to "don't ignore"
+		let mut entry = E820Entry {
+			attributes: 1,
+			..Default::default()
+		};
+		let (carry, signature, size): (u32, u32, u32);
+		unsafe {
+			asm!(
+				"int 0x15",
+				"sbb {carry:e}, {carry:e}",
+				carry = lateout(reg) carry,
+				inout("eax") 0xE820u32 => signature,
+				inout("ebx") continuation,
+				inout("ecx") size_of::<E820Entry>() as u32 => size,
+				inout("edx") SMAP => _,
+				in("edi") &mut entry,
+			);
+		}
+
+		// A failed call after the first one means the end of the map
+		if carry != 0 || signature != SMAP {
+			return map.len != 0;
+		}
+
+		if (size < 24 || entry.attributes & 1 != 0)
+			&& !map.push(MemoryMapEntry {
+				start: entry.start,
+				len: entry.len,
+				kind: RegionKind::from_e820(entry.kind),
+			}) {
+			break;
+		}
+		if continuation == 0 {
+			break;
+		}
+	}
+
+	true
+}
@@ -0,0 +1,238 @@
+//! Reads and writes model-specific registers (MSRs).
+//!
+//! MSRs are registers for CPU settings that don't fit anywhere else - things like 64-bit mode,
+//! the page attribute table, and the local APIC. Technically they can differ between CPU models
+//! (hence the name), but the ones BS uses are "architectural" and always exist on x86_64. Each
+//! one is identified by a 32-bit number.
+//!
+//! [`read`] and [`write`] work with any MSR. The ones with flags also have typed wrappers, so code
+//! doesn't need magic numbers: [`Efer`], [`ApicBase`], [`Star`] (and [`write_lstar`] and
+//! [`write_sfmask`] for the rest of the `syscall` settings), and [`crate::paging::Pat`].
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Model_Specific_Registers
+//! - https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html (vol 4)
+
+use {crate::paging::PhysAddr, core::arch::asm};
+
+/// The APIC base MSR. Holds the local APIC's physical address, and enables it. See [`ApicBase`].
+pub const IA32_APIC_BASE: u32 = 0x1B;
+/// The machine-check capabilities. The low byte is how many machine-check banks there are. See
+/// [`crate::interrupts::machine_check`].
+pub const IA32_MCG_CAP: u32 = 0x179;
+/// The machine-check status, which says if the CPU can continue after a machine check.
+pub const IA32_MCG_STATUS: u32 = 0x17A;
+/// Turns machine-check features on and off, if bit 8 of [`IA32_MCG_CAP`] is set.
+pub const IA32_MCG_CTL: u32 = 0x17B;
+/// The page attribute table. See [`crate::paging::pat`].
+pub const IA32_PAT: u32 = 0x277;
+/// The extended feature enable register, which has the settings for 64-bit mode. See [`Efer`].
+pub const IA32_EFER: u32 = 0xC000_0080;
+/// The segments `syscall` and `sysret` switch to. See [`Star`].
+pub const IA32_STAR: u32 = 0xC000_0081;
+/// Where `syscall` jumps to in 64-bit mode.
+pub const IA32_LSTAR: u32 = 0xC000_0082;
+/// Which RFLAGS bits `syscall` clears.
+pub const IA32_FMASK: u32 = 0xC000_0084;
+
+/// The first machine-check bank's control register. Each bank has 4 MSRs in a row: control,
+/// status, address, and misc.
+const IA32_MC0_CTL: u32 = 0x400;
+
+/// Machine-check bank `bank`'s control register, which turns its error reports on and off.
+pub const fn mc_ctl(bank: u8) -> u32 {
+	IA32_MC0_CTL + bank as u32 * 4
+}
+/// Machine-check bank `bank`'s status register.
+pub const fn mc_status(bank: u8) -> u32 {
+	mc_ctl(bank) + 1
+}
+/// Machine-check bank `bank`'s address register.
+pub const fn mc_addr(bank: u8) -> u32 {
+	mc_ctl(bank) + 2
+}
+/// Machine-check bank `bank`'s misc register.
+pub const fn mc_misc(bank: u8) -> u32 {
+	mc_ctl(bank) + 3
+}
+
+/// Reads the MSR `msr`.
+///
+/// # Safety
+/// The MSR must exist, or this faults.
+pub unsafe fn read(msr: u32) -> u64 {
+	let (low, high): (u32, u32);
+	unsafe {
+		asm!(
+			"rdmsr",
+			in("ecx") msr,
+			out("eax") low,
+			out("edx") high,
+			options(nomem, nostack, preserves_flags)
+		)
+	};
+
+	((high as u64) << 32) | low as u64
+}
+
+/// Writes `value` to the MSR `msr`.
+///
+/// # Safety
+/// The MSR must exist, and `value` must be valid for it. Most MSRs change how the CPU behaves, so
+/// the caller has to make sure that doesn't break anything.
+pub unsafe fn write(msr: u32, value: u64) {
+	unsafe {
+		asm!(
+			"wrmsr",
+			in("ecx") msr,
+			in("eax") value as u32,
+			in("edx") (value >> 32) as u32,
+			options(nostack, preserves_flags)
+		)
+	};
+}
+
+/// The extended feature enable register (EFER), which turns on 64-bit mode, `syscall`, and the
+/// no-execute bit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Efer(u64);
+impl Efer {
+	/// Enables the `syscall` and `sysret` instructions.
+	pub const SYSCALL_ENABLE: u64 = 1 << 0;
+	/// Long Mode Enable: turns on 64-bit mode the next time paging is enabled.
+	pub const LONG_MODE_ENABLE: u64 = 1 << 8;
+	/// Long Mode Active: set by the CPU once it's in 64-bit mode. Read-only.
+	pub const LONG_MODE_ACTIVE: u64 = 1 << 10;
+	/// No-Execute Enable: makes the no-execute bit in page table entries work. Check for it with
+	/// [`crate::cpuid::Feature::Nx`] first.
+	pub const NO_EXECUTE_ENABLE: u64 = 1 << 11;
+
+	/// Reads EFER.
+	pub fn read() -> Self {
+		Self(unsafe { read(IA32_EFER) })
+	}
+
+	/// Writes to EFER.
+	///
+	/// # Safety
+	/// The CPU has to support every flag that's set, and changing them can't break the running
+	/// code - eg, turning off the no-execute bit while page tables use it.
+	pub unsafe fn write(self) {
+		unsafe { write(IA32_EFER, self.0) };
+	}
+
+	/// Sets the bits in `flags`.
+	pub fn with(self, flags: u64) -> Self {
+		Self(self.0 | flags)
+	}
+
+	/// Clears the bits in `flags`.
+	pub fn without(self, flags: u64) -> Self {
+		Self(self.0 & !flags)
+	}
+
+	/// If every bit in `flags` is set.
+	pub fn contains(&self, flags: u64) -> bool {
+		self.0 & flags == flags
+	}
+
+	/// The raw value of EFER.
+	pub fn bits(&self) -> u64 {
+		self.0
+	}
+}
+
+/// The APIC base MSR: the local APIC's physical address, and whether it's enabled. See
+/// [`crate::interrupts::apic`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ApicBase(u64);
+impl ApicBase {
+	/// Set if this is the bootstrap processor (the core that started first). Read-only.
+	pub const BOOTSTRAP_PROCESSOR: u64 = 1 << 8;
+	/// Enables x2APIC mode. The APIC has to be enabled too.
+	pub const X2APIC_ENABLE: u64 = 1 << 10;
+	/// Enables the local APIC.
+	pub const ENABLE: u64 = 1 << 11;
+	/// The bits with the APIC's physical address. The low 12 bits are flags.
+	const ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+	/// Reads the APIC base MSR.
+	pub fn read() -> Self {
+		Self(unsafe { read(IA32_APIC_BASE) })
+	}
+
+	/// Writes to the APIC base MSR.
+	///
+	/// # Safety
+	/// Nothing else can be using the local APIC, and it can't be moved somewhere that's in use.
+	/// x2APIC mode can't be turned off without disabling the APIC first.
+	pub unsafe fn write(self) {
+		unsafe { write(IA32_APIC_BASE, self.0) };
+	}
+
+	/// The physical address of the APIC's registers.
+	pub fn address(&self) -> PhysAddr {
+		self.0 & Self::ADDRESS_MASK
+	}
+
+	/// Sets the bits in `flags`.
+	pub fn with(self, flags: u64) -> Self {
+		Self(self.0 | flags)
+	}
+
+	/// If every bit in `flags` is set.
+	pub fn contains(&self, flags: u64) -> bool {
+		self.0 & flags == flags
+	}
+}
+
+/// The segments `syscall` and `sysret` load, from the STAR MSR.
+///
+/// `syscall` loads CS from `kernel_segment` and SS from `kernel_segment + 8`. `sysret` to 64-bit
+/// code loads CS from `user_segment + 16` and SS from `user_segment + 8`, so the GDT needs the
+/// user data segment right before the 64-bit user code segment.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Star {
+	/// The kernel code segment's selector.
+	pub kernel_segment: u16,
+	/// The base selector for the user segments (see above). Its privilege level should be 3.
+	pub user_segment: u16,
+}
+impl Star {
+	/// Reads the STAR MSR.
+	pub fn read() -> Self {
+		let bits = unsafe { read(IA32_STAR) };
+		Self {
+			kernel_segment: (bits >> 32) as u16,
+			user_segment: (bits >> 48) as u16,
+		}
+	}
+
+	/// Writes to the STAR MSR.
+	///
+	/// # Safety
+	/// The segments have to be laid out in the GDT like `syscall` and `sysret` expect (see
+	/// above).
+	pub unsafe fn write(self) {
+		let bits = ((self.user_segment as u64) << 48) | ((self.kernel_segment as u64) << 32);
+		unsafe { write(IA32_STAR, bits) };
+	}
+}
+
+/// Sets where `syscall` jumps to in 64-bit mode (the LSTAR MSR).
+///
+/// # Safety
+/// `entry` has to be a syscall handler, which switches to a kernel stack before using it - `syscall`
+/// doesn't switch stacks.
+pub unsafe fn write_lstar(entry: u64) {
+	unsafe { write(IA32_LSTAR, entry) };
+}
+
+/// Sets which RFLAGS bits `syscall` clears (the SFMASK MSR). This should at least include the
+/// interrupt flag, so an interrupt can't arrive before the handler switches stacks.
+///
+/// # Safety
+/// The syscall handler has to work with the RFLAGS it's given.
+pub unsafe fn write_sfmask(mask: u64) {
+	unsafe { write(IA32_FMASK, mask) };
+}
@@ -16,9 +16,17 @@
 //! makes that 512 tables of 512 tables of 512 4kib pages of memory, and so on, and so forth. Adding more tables massively
 //! increases how much memory the system can use - and, to this end, there's propositions for level 5 paging that introduces
 //! a *fifth* table called the page map level 5 (at long last, consistent naming!). Some CPUs do support it, but level 4
-//! paging already supports such a ridiculous amount of memory that there's no point in BS trying to utilise level 5 (it'd only
+//! paging already supports such a ridiculous amount of memory that there's no point in BS relying on level 5 (it'd only
 //! break compatibility with CPUs that don't have level 5 paging, or require more code to be compatible with them, with no
-//! benefit since that memory won't even be used).
+//! benefit since that memory won't even be used). There's optional support for it behind the `la57` feature, though - see
+//! [`level5`].
+//!
+//! Not every setting makes sense at every level. Settings that control access (like [`PageTableEntry::set_writable`])
+//! work at every level, and apply to all the memory under that entry. Settings that describe the memory itself only work
+//! in entries that map memory: page table entries, and huge pages. So only page directory pointer table and page
+//! directory entries can be huge pages, and only they and page table entries have a dirty flag, protection key, and
+//! memory type (see [`pat`]). The PAT bit is bit 7 in page table entries, and bit 12 in huge pages, since bit 7 is the
+//! huge page bit.
 //!
 //! Virtual addresses are converted to physical addresses by using them to index into page maps. The implementation depends
 //! on the bitness and how many levels of paging there are, but the general idea is that several bits will index into the
@@ -33,8 +41,52 @@
 //! - https://wiki.osdev.org/Entering_Long_Mode_Directly
 //! - https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html (specifically vol 3, chap 4)
 
+pub mod cr3;
+pub mod frames;
+pub mod guard;
+pub mod layout;
+#[cfg(feature = "la57")]
+pub mod level5;
+pub mod mapper;
+pub mod pat;
+pub mod recursive;
+pub mod tlb;
+pub mod translate;
+
+#[cfg(feature = "la57")]
+pub use level5::*;
+pub use {
+	cr3::*, frames::*, guard::*, layout::*, mapper::*, pat::*, recursive::*, tlb::*, translate::*,
+};
+
 use core::ops::{Deref, DerefMut};
 
+/// A physical memory address. These are always 64 bits, even in 32-bit mode, since page tables can
+/// point to memory past 4gib.
+pub type PhysAddr = u64;
+
+/// The present bit in every page map entry.
+const PRESENT: u64 = 1 << 0;
+/// The writable bit in every page map entry.
+const WRITABLE: u64 = 1 << 1;
+/// The user mode bit in every page map entry.
+const USER_MODE: u64 = 1 << 2;
+/// The huge page bit in page directory pointer table and page directory entries.
+const HUGE_PAGE: u64 = 1 << 7;
+/// The PAT bit in page directory pointer table and page directory entries that are huge pages.
+const HUGE_PAT: u64 = 1 << 12;
+/// The bits of an entry that hold its protection key.
+const PROTECTION_KEY: u64 = 0b1111 << 59;
+/// The bits of an entry that hold the physical address it points to.
+const ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// The size of a page mapped by a page table entry - 4kib.
+pub const PAGE_SIZE: u64 = 0x1000;
+/// The size of a huge page mapped by a page directory entry - 2mib.
+pub const HUGE_PAGE_SIZE: u64 = 0x20_0000;
+/// The size of a huge page mapped by a page directory pointer table entry - 1gib.
+pub const GIANT_PAGE_SIZE: u64 = 0x4000_0000;
+
 /// How all 64-bit page tables are laid out in memory - 512 entries, each one 8 bytes in length.
 #[repr(align(0x1000))]
 pub struct PageMap<E: PageMapEntry>([E; 512]);
@@ -66,7 +118,34 @@ impl<E: PageMapEntry> DerefMut for PageMap<E> {
 }
 
 /// Marker type for all the types of page maps.
-pub trait PageMapEntry: Default + Copy {}
+pub trait PageMapEntry: Default + Copy {
+	/// The raw bits of this entry.
+	fn bits(&self) -> u64;
+	/// Overwrites the raw bits of this entry.
+	fn set_bits(&mut self, bits: u64);
+}
+
+/// The permissions and caching settings for a page. See the setters on the page map entries for
+/// what each one does. The defaults match a new, empty entry.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PageFlags {
+	pub writable: bool,
+	pub user_mode: bool,
+	pub executable: bool,
+	pub write_through_cache: bool,
+	pub caching: bool,
+}
+impl Default for PageFlags {
+	fn default() -> Self {
+		Self {
+			writable: false,
+			user_mode: false,
+			executable: true,
+			write_through_cache: false,
+			caching: true,
+		}
+	}
+}
 
 /// Automates flipping bits based on a boolean. Sets the bit if the boolean is true.
 macro_rules! bitbool {
@@ -74,7 +153,7 @@ macro_rules! bitbool {
 		if $name {
 			$var |= 1 << $pos;
 		} else {
-			$var ^= 1 << $pos;
+			$var &= !(1 << $pos);
 		}
 	};
 }
@@ -84,7 +163,7 @@ macro_rules! inverse_bitbool {
 		if !$name {
 			$var |= 1 << $pos;
 		} else {
-			$var ^= 1 << $pos;
+			$var &= !(1 << $pos);
 		}
 	};
 }
@@ -92,6 +171,13 @@ macro_rules! inverse_bitbool {
 /// Implements properties page maps share.
 macro_rules! page_map_type {
 	($name:ident) => {
+		page_map_type!(@entry $name, false, PAGE_SIZE);
+	};
+	// For entries that can be huge pages (see `huge_page_entry`), and how big those pages are
+	($name:ident, huge: $huge_size:expr) => {
+		page_map_type!(@entry $name, true, $huge_size);
+	};
+	(@entry $name:ident, $can_be_huge:literal, $huge_size:expr) => {
 		#[derive(Clone, Copy)]
 		#[repr(transparent)]
 		pub struct $name(u64);
@@ -167,9 +253,22 @@ macro_rules! page_map_type {
 				self
 			}
 
+			/// Whether the CPU has read from this page since the accessed flag was last
+			/// cleared. See [`Self::set_accessed`].
+			pub fn is_accessed(&self) -> bool {
+				self.0 & (1 << 5) != 0
+			}
+
+			/// Clears the accessed flag, so the next read from this page sets it again. The
+			/// TLB caches the flag, so the page should be flushed afterwards (see [`tlb`]).
+			pub fn clear_accessed(&mut self) -> &mut Self {
+				self.set_accessed(false)
+			}
+
 			/// Allows data in this page to be executed as code. This bit is only used
 			/// if the NXE bit is set in the EFER model-specific register. If the NXE
-			/// bit is not set, this flag should not be set.
+			/// bit is not set, this flag should not be set. Not every CPU has the NXE bit;
+			/// check with [`crate::cpuid::Feature::Nx`].
 			///
 			/// Default value: True, data in this page can be executed.
 			pub fn set_executable(&mut self, executable: bool) -> &mut Self {
@@ -178,15 +277,64 @@ macro_rules! page_map_type {
 				self
 			}
 
-			/// Sets the address this entry points to.
-			pub fn set_address(&mut self, address: u64) -> &mut Self {
+			/// Sets every setting in [`PageFlags`] at once.
+			pub fn set_flags(&mut self, flags: PageFlags) -> &mut Self {
+				self.set_writable(flags.writable)
+					.set_user_mode(flags.user_mode)
+					.set_executable(flags.executable)
+					.set_write_through_cache(flags.write_through_cache)
+					.set_caching(flags.caching)
+			}
+
+			/// Sets the address this entry points to, replacing the old one. Panics if the
+			/// address isn't 4kb-aligned, or is too large to fit in an entry (past 52 bits). If
+			/// this entry is a huge page, the address has to be aligned to its size instead.
+			pub fn set_address(&mut self, address: PhysAddr) -> &mut Self {
 				if (address % 4096) != 0 {
 					panic!("Page table addresses must be 4kb-aligned");
 				}
-				self.0 |= address;
+				if address & !ADDRESS_MASK != 0 {
+					panic!("Page table addresses must fit in 52 bits");
+				}
+				if $can_be_huge && self.0 & HUGE_PAGE != 0 && address % $huge_size != 0 {
+					panic!("Huge page addresses must be aligned to the huge page's size");
+				}
+				let mask = self.address_mask();
+				self.0 = (self.0 & !mask) | address;
 
 				self
 			}
+
+			/// The address this entry points to.
+			pub fn address(&self) -> PhysAddr {
+				self.0 & self.address_mask()
+			}
+
+			/// The bits that hold this entry's address. In huge pages, bit 12 is the PAT bit
+			/// instead (see [`pat`]).
+			fn address_mask(&self) -> u64 {
+				if $can_be_huge && self.0 & HUGE_PAGE != 0 {
+					ADDRESS_MASK & !HUGE_PAT
+				} else {
+					ADDRESS_MASK
+				}
+			}
+
+			/// Whether this entry is marked as present. See [`Self::set_present`].
+			pub fn present(&self) -> bool {
+				self.0 & PRESENT != 0
+			}
+
+			/// Reads every setting in [`PageFlags`] back out of this entry.
+			pub fn flags(&self) -> PageFlags {
+				PageFlags {
+					writable: self.0 & WRITABLE != 0,
+					user_mode: self.0 & USER_MODE != 0,
+					executable: self.0 & (1 << 63) == 0,
+					write_through_cache: self.0 & (1 << 3) != 0,
+					caching: self.0 & (1 << 4) == 0,
+				}
+			}
 		}
 
 		impl Default for $name {
@@ -196,13 +344,98 @@ macro_rules! page_map_type {
 			}
 		}
 
-		impl PageMapEntry for $name {}
+		impl PageMapEntry for $name {
+			fn bits(&self) -> u64 {
+				self.0
+			}
+			fn set_bits(&mut self, bits: u64) {
+				self.0 = bits;
+			}
+		}
 	};
 }
 
+/// Implements the huge page bit, for the entries that can map memory directly instead of pointing
+/// to a lower table.
+macro_rules! huge_page_entry {
+	($name:ident, $size:literal, $size_bytes:expr) => {
+		impl $name {
+			#[doc = concat!("Makes this entry directly map ", $size, " of memory, instead of pointing")]
+			/// to a lower page table. The entry's address is then the address of that memory, and
+			/// must be aligned to its size - this panics if the address already in the entry isn't.
+			///
+			/// Bit 12 is part of the address in entries that point to a lower table, but it's the
+			/// PAT bit in huge pages (see [`pat`]). Turning the huge page bit off clears it, so
+			/// the PAT bit doesn't end up in the address.
+			///
+			/// Default value: False, this entry points to a lower page table.
+			pub fn set_huge_page(&mut self, huge_page: bool) -> &mut Self {
+				if huge_page {
+					if self.0 & HUGE_PAGE == 0 && (self.0 & ADDRESS_MASK) % $size_bytes != 0 {
+						panic!("Huge page addresses must be aligned to the huge page's size");
+					}
+					self.0 |= HUGE_PAGE;
+				} else if self.0 & HUGE_PAGE != 0 {
+					self.0 &= !(HUGE_PAGE | HUGE_PAT);
+				}
+
+				self
+			}
+		}
+	};
+}
+
+/// Implements the settings that only matter in entries that map memory, instead of pointing to a
+/// lower table. Page directory pointer table and page directory entries only use these if they're
+/// huge pages.
+macro_rules! mapping_entry {
+	($name:ident) => {
+		impl $name {
+			/// Whether the CPU has written to this page since the dirty flag was last cleared.
+			/// Like the accessed flag, the CPU sets this but never clears it.
+			pub fn is_dirty(&self) -> bool {
+				self.0 & (1 << 6) != 0
+			}
+
+			/// Clears the dirty flag, so the next write to this page sets it again. The TLB
+			/// caches the flag, so the page should be flushed afterwards (see [`tlb`]).
+			pub fn clear_dirty(&mut self) -> &mut Self {
+				self.0 &= !(1 << 6);
+
+				self
+			}
+
+			/// Sets this page's protection key, which picks the access rights in the PKRU
+			/// register (for user-mode pages) or PKRS MSR (for supervisor pages) that apply to
+			/// it. Only used if protection keys are enabled in CR4. Panics if `key` isn't below
+			/// 16.
+			///
+			/// Default value: 0.
+			pub fn set_protection_key(&mut self, key: u8) -> &mut Self {
+				assert!(key < 16, "Protection keys must be below 16");
+				self.0 = (self.0 & !PROTECTION_KEY) | ((key as u64) << 59);
+
+				self
+			}
+
+			/// This page's protection key. See [`Self::set_protection_key`].
+			pub fn protection_key(&self) -> u8 {
+				((self.0 & PROTECTION_KEY) >> 59) as u8
+			}
+		}
+	};
+}
+
+#[cfg(feature = "la57")]
+page_map_type!(PageMapLevel5Entry);
 page_map_type!(PageMapLevel4Entry);
-page_map_type!(PageDirectoryPointerTableEntry);
-page_map_type!(PageDirectoryEntry);
+page_map_type!(PageDirectoryPointerTableEntry, huge: GIANT_PAGE_SIZE);
+page_map_type!(PageDirectoryEntry, huge: HUGE_PAGE_SIZE);
 page_map_type!(PageTableEntry);
 
-// TODO: There are more page attributes to support, but they aren't standard across all the page map types.
+huge_page_entry!(PageDirectoryPointerTableEntry, "1gib", GIANT_PAGE_SIZE);
+huge_page_entry!(PageDirectoryEntry, "2mib", HUGE_PAGE_SIZE);
+
+mapping_entry!(PageDirectoryPointerTableEntry);
+mapping_entry!(PageDirectoryEntry);
+mapping_entry!(PageTableEntry);
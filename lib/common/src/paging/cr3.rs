@@ -0,0 +1,148 @@
+//! Reads and writes CR3, the register that points to the active page tables.
+//!
+//! CR3 holds the physical address of the top-level page table (the page map level 4, or the page
+//! map level 5 with 5-level paging). Writing to it switches address spaces, and flushes the TLB
+//! (see [`tlb`]).
+//!
+//! With process-context identifiers (PCIDs) enabled, the low 12 bits of CR3 tag every TLB entry
+//! with the address space it came from. Switching address spaces then doesn't have to flush the
+//! TLB, since entries from other address spaces can't be used by accident.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/CPU_Registers_x86-64#CR3
+//! - https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html (vol 3, section 4.10.1)
+
+use {super::*, crate::cpuid::Feature, core::arch::asm};
+
+/// The PCIDE bit in CR4.
+const CR4_PCIDE: usize = 1 << 17;
+/// The bits of CR3 that hold the PCID, when PCIDs are enabled.
+const PCID_MASK: u64 = 0xFFF;
+/// When writing to CR3 with PCIDs enabled, this bit stops the TLB entries for the new PCID from
+/// getting flushed. It's never set when CR3 is read.
+#[cfg(target_arch = "x86_64")]
+const NO_FLUSH: u64 = 1 << 63;
+
+/// A value for CR3: the address of the top-level page table, and optionally a PCID.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cr3(u64);
+impl Cr3 {
+	/// Points CR3 at the top-level page table at `address`, with PCID 0. Panics if `address`
+	/// isn't 4kb-aligned.
+	pub fn new(address: PhysAddr) -> Self {
+		assert!(
+			address & !ADDRESS_MASK == 0,
+			"Page table addresses must be 4kb-aligned"
+		);
+
+		Self(address)
+	}
+
+	/// Tags this address space with `pcid`. Only works if PCIDs are enabled (see [`enable_pcid`]);
+	/// otherwise, these bits are the write-through and caching settings for the top-level page
+	/// table, and should be 0. Panics if `pcid` is 4096 or higher.
+	pub fn with_pcid(self, pcid: u16) -> Self {
+		assert!((pcid as u64) <= PCID_MASK, "PCIDs must be below 4096");
+
+		Self((self.0 & !PCID_MASK) | pcid as u64)
+	}
+
+	/// The physical address of the top-level page table.
+	pub fn address(&self) -> PhysAddr {
+		self.0 & ADDRESS_MASK
+	}
+
+	/// This address space's PCID. Only meaningful if PCIDs are enabled.
+	pub fn pcid(&self) -> u16 {
+		(self.0 & PCID_MASK) as u16
+	}
+
+	/// The raw value of CR3.
+	pub fn bits(&self) -> u64 {
+		self.0
+	}
+
+	/// Reads CR3.
+	pub fn read() -> Self {
+		let cr3: usize;
+		unsafe { asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags)) };
+
+		Self(cr3 as u64)
+	}
+
+	/// Writes to CR3, switching to this address space and flushing the TLB.
+	///
+	/// # Safety
+	/// The page tables must be valid, and must keep mapping the code that's running, its stack, and
+	/// any memory Rust has references to.
+	pub unsafe fn write(self) {
+		unsafe { asm!("mov cr3, {}", in(reg) self.0 as usize, options(nostack, preserves_flags)) };
+	}
+
+	/// Writes to CR3, switching to this address space without flushing its PCID's TLB entries.
+	///
+	/// # Safety
+	/// Same as [`Cr3::write`]. PCIDs also have to be enabled, and the TLB can't have stale entries
+	/// for this PCID (eg, from before its page tables were changed).
+	#[cfg(target_arch = "x86_64")]
+	pub unsafe fn write_without_flush(self) {
+		unsafe { Cr3(self.0 | NO_FLUSH).write() };
+	}
+
+	/// Switches to this address space until the returned guard is dropped, then switches back to
+	/// the one that was active before. See [`with_address_space`].
+	///
+	/// # Safety
+	/// Same as [`Cr3::write`], for both address spaces.
+	pub unsafe fn switch(self) -> AddressSpaceGuard {
+		let previous = Self::read();
+		unsafe { self.write() };
+
+		AddressSpaceGuard { previous }
+	}
+}
+
+/// Switches back to the previous address space when dropped. See [`Cr3::switch`].
+#[must_use = "dropping the guard switches back to the previous address space immediately"]
+pub struct AddressSpaceGuard {
+	previous: Cr3,
+}
+impl Drop for AddressSpaceGuard {
+	fn drop(&mut self) {
+		unsafe { self.previous.write() };
+	}
+}
+
+/// Runs `f` in the address space `cr3` points to, then switches back to the active one. Useful for
+/// editing another address space's memory.
+///
+/// # Safety
+/// Same as [`Cr3::write`], for both address spaces. `f` can't hold onto references to memory
+/// from either address space past the switch.
+pub unsafe fn with_address_space<T>(cr3: Cr3, f: impl FnOnce() -> T) -> T {
+	let _guard = unsafe { cr3.switch() };
+	f()
+}
+
+/// Whether the CPU supports PCIDs (CPUID leaf 1, ECX bit 17).
+pub fn pcid_supported() -> bool {
+	Feature::Pcid.supported()
+}
+
+/// Turns on PCIDs in CR4.
+///
+/// # Safety
+/// - The CPU must be in 64-bit mode, and must support PCIDs (see [`pcid_supported`])
+/// - The active CR3 must have PCID 0
+pub unsafe fn enable_pcid() {
+	unsafe {
+		asm!(
+			"mov {0}, cr4",
+			"or {0}, {1}",
+			"mov cr4, {0}",
+			out(reg) _,
+			const CR4_PCIDE,
+			options(nomem, nostack, preserves_flags)
+		)
+	};
+}
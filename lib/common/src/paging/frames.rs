@@ -0,0 +1,164 @@
+//! Keeps track of which physical frames (4kib chunks of physical memory) are free.
+//!
+//! Every page that gets mapped needs a frame to back it, and so does every page table. The
+//! [`FrameAllocator`] uses a bitmap with one bit per frame: set bits are used (or don't exist),
+//! clear bits are free. It doesn't allocate the bitmap itself - there's no heap yet when it's
+//! created - so the caller gives it some memory to use, sized with [`FrameAllocator::bitmap_len`].
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Page_Frame_Allocation
+
+use super::*;
+
+/// A range of physical memory.
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryRegion {
+	/// The first address in the region.
+	pub start: PhysAddr,
+	/// How many bytes are in the region.
+	pub len: u64,
+}
+impl MemoryRegion {
+	/// The address right after the end of the region.
+	pub fn end(&self) -> PhysAddr {
+		self.start.saturating_add(self.len)
+	}
+}
+
+/// Hands out physical frames. See the module-level docs.
+pub struct FrameAllocator<'a> {
+	/// One bit per frame, starting at physical address 0. Set bits are used.
+	bitmap: &'a mut [u64],
+	/// Where to start looking for a free frame. Every frame before this is used.
+	next: usize,
+}
+impl<'a> FrameAllocator<'a> {
+	/// How many `u64`s the bitmap needs to track every frame below `max_address`.
+	pub const fn bitmap_len(max_address: PhysAddr) -> usize {
+		(max_address.div_ceil(PAGE_SIZE) as usize).div_ceil(64)
+	}
+
+	/// Creates a frame allocator where only the frames entirely inside `usable` regions (eg, the
+	/// usable regions in the memory map) are free. Frames past the end of `bitmap` are never
+	/// handed out.
+	///
+	/// Memory that's usable but already in use (like the kernel, or the bitmap itself) should be
+	/// reserved afterwards with [`FrameAllocator::reserve`].
+	pub fn new(bitmap: &'a mut [u64], usable: impl IntoIterator<Item = MemoryRegion>) -> Self {
+		bitmap.fill(u64::MAX);
+		let mut this = Self { bitmap, next: 0 };
+
+		for region in usable {
+			// Only whole frames are usable
+			let start = region.start.next_multiple_of(PAGE_SIZE);
+			let end = region.end() / PAGE_SIZE * PAGE_SIZE;
+			if start < end {
+				this.set_range(
+					frame_index(start),
+					((end - start) / PAGE_SIZE) as usize,
+					false,
+				);
+			}
+		}
+
+		this
+	}
+
+	/// Marks every frame that overlaps `region` as used.
+	pub fn reserve(&mut self, region: MemoryRegion) {
+		let start = region.start / PAGE_SIZE * PAGE_SIZE;
+		let end = region.end().next_multiple_of(PAGE_SIZE);
+		if start < end {
+			self.set_range(
+				frame_index(start),
+				((end - start) / PAGE_SIZE) as usize,
+				true,
+			);
+		}
+	}
+
+	/// Allocates a single frame, returning its address.
+	pub fn allocate_frame(&mut self) -> Option<PhysAddr> {
+		self.allocate_contiguous(1, PAGE_SIZE)
+	}
+
+	/// Frees a frame from [`FrameAllocator::allocate_frame`].
+	pub fn deallocate_frame(&mut self, frame: PhysAddr) {
+		self.deallocate_contiguous(frame, 1);
+	}
+
+	/// Allocates `count` frames in a row, with the first one aligned to `alignment` bytes (which
+	/// must be a power of 2). Returns the address of the first frame. Useful for huge pages and
+	/// DMA buffers.
+	pub fn allocate_contiguous(&mut self, count: usize, alignment: u64) -> Option<PhysAddr> {
+		let step = (alignment.max(PAGE_SIZE) / PAGE_SIZE) as usize;
+		let frames = self.bitmap.len() * 64;
+
+		let mut start = self.next.next_multiple_of(step);
+		while start.checked_add(count)? <= frames {
+			match (start..start + count).find(|frame| self.is_used(*frame)) {
+				// Skip past the used frame, to the next aligned frame
+				Some(used) => start = (used + 1).next_multiple_of(step),
+				None => {
+					self.set_range(start, count, true);
+					if start == self.next {
+						self.next += count;
+					}
+
+					return Some(start as PhysAddr * PAGE_SIZE);
+				}
+			}
+		}
+
+		None
+	}
+
+	/// Frees `count` frames starting at `start`, from [`FrameAllocator::allocate_contiguous`].
+	pub fn deallocate_contiguous(&mut self, start: PhysAddr, count: usize) {
+		let index = frame_index(start);
+		self.set_range(index, count, false);
+		self.next = self.next.min(index);
+	}
+
+	/// The address right after the last frame the allocator tracks. Frames from here on are never
+	/// handed out.
+	pub fn limit(&self) -> PhysAddr {
+		(self.bitmap.len() * 64) as PhysAddr * PAGE_SIZE
+	}
+
+	/// If the frame at `frame` is free. Frames past [`FrameAllocator::limit`] never are.
+	pub fn is_free(&self, frame: PhysAddr) -> bool {
+		let index = frame_index(frame);
+		index < self.bitmap.len() * 64 && !self.is_used(index)
+	}
+
+	/// How many frames are free.
+	pub fn free_frames(&self) -> usize {
+		self.bitmap
+			.iter()
+			.map(|chunk| chunk.count_zeros() as usize)
+			.sum()
+	}
+
+	fn is_used(&self, frame: usize) -> bool {
+		self.bitmap[frame / 64] & (1 << (frame % 64)) != 0
+	}
+
+	/// Marks frames as used or free. Frames past the end of the bitmap are ignored.
+	fn set_range(&mut self, start: usize, count: usize, used: bool) {
+		let end = start.saturating_add(count).min(self.bitmap.len() * 64);
+		for frame in start..end {
+			let bit = 1 << (frame % 64);
+			if used {
+				self.bitmap[frame / 64] |= bit;
+			} else {
+				self.bitmap[frame / 64] &= !bit;
+			}
+		}
+	}
+}
+
+/// The index of the frame at `address`, in the bitmap.
+fn frame_index(address: PhysAddr) -> usize {
+	(address / PAGE_SIZE) as usize
+}
@@ -0,0 +1,88 @@
+//! Maps stacks with unmapped guard pages below them, so stack overflows fault instead of silently
+//! overwriting whatever's below the stack.
+//!
+//! Stacks grow down. When a stack runs out of space, the next push writes to the page below it -
+//! if that page is mapped, the stack overflows into it without anyone noticing. Leaving a few
+//! pages under the stack unmapped turns that into a page fault at an address right below the
+//! stack, which the page fault handler can recognise (see [`crate::interrupts::stack_overflow`]).
+//!
+//! Resources:
+//! - https://en.wikipedia.org/wiki/Guard_byte
+
+use super::*;
+
+/// A stack mapped by [`Mapper::map_guarded_stack`]. In memory, it looks like this:
+///
+/// ```txt
+/// | guard pages (unmapped) | stack |
+/// ^ guard                  ^ bottom ^ top
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GuardedStack {
+	/// The first guard page.
+	pub guard: u64,
+	/// The lowest address in the stack, right after the guard pages.
+	pub bottom: u64,
+	/// The address right after the end of the stack. This is what the stack pointer should start
+	/// at.
+	pub top: u64,
+}
+impl GuardedStack {
+	/// Whether `address` is in the guard pages. A page fault there means the stack overflowed.
+	pub fn in_guard(&self, address: u64) -> bool {
+		(self.guard..self.bottom).contains(&address)
+	}
+
+	/// The stack's size in bytes, not counting the guard pages.
+	pub fn size(&self) -> u64 {
+		self.top - self.bottom
+	}
+}
+
+impl Mapper<'_, '_> {
+	/// Maps a stack of `stack_pages` pages, with `guard_pages` unmapped pages below it, starting at
+	/// `virt` (the first guard page). The stack is backed by new frames, and is writable and
+	/// non-executable.
+	///
+	/// Errors if any of the guard pages are already mapped, since they wouldn't catch anything. If
+	/// mapping the stack itself fails partway through, the pages mapped so far are left mapped.
+	pub fn map_guarded_stack(
+		&mut self,
+		virt: u64,
+		guard_pages: u64,
+		stack_pages: u64,
+	) -> Result<GuardedStack, MapError> {
+		if !virt.is_multiple_of(PAGE_SIZE) {
+			return Err(MapError::Unaligned);
+		}
+
+		let bottom = virt + guard_pages * PAGE_SIZE;
+		for page in (virt..bottom).step_by(PAGE_SIZE as usize) {
+			match self.mapped_entry(page) {
+				Ok(entry) => return Err(MapError::AlreadyMapped(entry.address())),
+				Err(MapError::NotMapped) => {}
+				Err(other) => return Err(other),
+			}
+		}
+
+		let flags = PageFlags {
+			writable: true,
+			executable: false,
+			..Default::default()
+		};
+		let top = bottom + stack_pages * PAGE_SIZE;
+		for page in (bottom..top).step_by(PAGE_SIZE as usize) {
+			let frame = self
+				.frames()
+				.allocate_frame()
+				.ok_or(MapError::OutOfFrames)?;
+			self.map_to(page, frame, flags)?;
+		}
+
+		Ok(GuardedStack {
+			guard: virt,
+			bottom,
+			top,
+		})
+	}
+}
@@ -0,0 +1,85 @@
+//! Where things go in the virtual address space, and helpers for working with higher-half
+//! addresses.
+//!
+//! x86_64 virtual addresses are only 48 bits, but they're stored in 64-bit registers. The top 16
+//! bits have to be copies of bit 47 - addresses like that are "canonical", and using any other
+//! address faults. That splits the address space in 2, with a giant hole in the middle:
+//!
+//! ```txt
+//! 0x0000_0000_0000_0000..=0x0000_7FFF_FFFF_FFFF - lower half (user mode)
+//! 0x0000_8000_0000_0000..=0xFFFF_7FFF_FFFF_FFFF - non-canonical, unusable
+//! 0xFFFF_8000_0000_0000..=0xFFFF_FFFF_FFFF_FFFF - higher half (kernel)
+//! ```
+//!
+//! The kernel lives in the higher half, so the lower half is free for user-mode programs. Every
+//! address space shares the same higher-half page map level 4 entries, so the kernel stays mapped
+//! no matter which program is running.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Higher_Half_Kernel
+//! - https://en.wikipedia.org/wiki/X86-64#Canonical_form_addresses
+
+use super::*;
+
+/// The first address in the higher half.
+pub const HIGHER_HALF_START: u64 = 0xFFFF_8000_0000_0000;
+/// Where all of physical memory is mapped in the higher half, for the `physical_offset` in
+/// [`translate`] and [`Mapper`]. This is the start of the higher half, so it uses page map level 4
+/// entries 256 and up.
+pub const PHYSICAL_MEMORY_OFFSET: u64 = HIGHER_HALF_START;
+/// Where the kernel is loaded - the last 2gib of the address space. The kernel's code model
+/// expects all of its code to be in the top 2gib, so it can use sign-extended 32-bit addresses.
+/// This is in page map level 4 entry 511, so a [`RecursiveMapping`] should use a different entry.
+pub const KERNEL_BASE: u64 = 0xFFFF_FFFF_8000_0000;
+/// Where the kernel's heap starts: page map level 4 entry 384, halfway between where physical
+/// memory is mapped ([`PHYSICAL_MEMORY_OFFSET`]) and the kernel.
+pub const KERNEL_HEAP_START: u64 = 0xFFFF_C000_0000_0000;
+/// The size of the stack the kernel starts on (128kib).
+pub const KERNEL_STACK_SIZE: u64 = 0x2_0000;
+/// The stack the kernel starts on. The ELF loader maps it right below [`KERNEL_BASE`], in the same
+/// page map level 4 entry, with an unmapped guard page under it (see [`guard`]) - so the kernel
+/// can recognise its own stack overflowing.
+pub const KERNEL_STACK: GuardedStack = GuardedStack {
+	guard: KERNEL_BASE - KERNEL_STACK_SIZE - PAGE_SIZE,
+	bottom: KERNEL_BASE - KERNEL_STACK_SIZE,
+	top: KERNEL_BASE,
+};
+
+/// Sign-extends bit 47 of `virt` into the top 16 bits, making it canonical.
+pub const fn canonicalize(virt: u64) -> u64 {
+	((virt << 16) as i64 >> 16) as u64
+}
+
+/// Whether `virt` is canonical (bits 48-63 are copies of bit 47). See the module-level docs.
+pub const fn is_canonical(virt: u64) -> bool {
+	canonicalize(virt) == virt
+}
+
+/// Whether `virt` is a canonical address in the higher half.
+pub const fn is_higher_half(virt: u64) -> bool {
+	virt >= HIGHER_HALF_START
+}
+
+/// Builds a canonical virtual address from table indices (from the page map level 4 down to the
+/// page table) and an offset into the page. The opposite of [`table_indices`].
+///
+/// Panics if any index is 512 or more, or the offset is bigger than a page.
+pub const fn virtual_address(indices: [usize; 4], offset: u64) -> u64 {
+	let [pml4, pdpt, pd, pt] = indices;
+	assert!(
+		pml4 < 512 && pdpt < 512 && pd < 512 && pt < 512,
+		"Table indices must be below 512"
+	);
+	assert!(
+		offset < PAGE_SIZE,
+		"Page offsets must be below the page size"
+	);
+
+	canonicalize(
+		((pml4 as u64) << 39)
+			| ((pdpt as u64) << 30)
+			| ((pd as u64) << 21)
+			| ((pt as u64) << 12)
+			| offset,
+	)
+}
@@ -0,0 +1,95 @@
+//! Optional support for 5-level paging, behind the `la57` feature.
+//!
+//! 5-level paging (LA57) adds a page map level 5 above the page map level 4, making virtual
+//! addresses 57 bits instead of 48. It has to be turned on in CR4 *before* paging is enabled, and
+//! CR3 then points to a page map level 5 instead of a page map level 4. Only newer CPUs support it,
+//! so check [`la57_supported`] first.
+//!
+//! BS doesn't need the extra address space, so the simplest way to use 5-level paging is to
+//! keep building a 4-level hierarchy and put it under a page map level 5 with [`wrap_level4`].
+//!
+//! Resources:
+//! - https://en.wikipedia.org/wiki/Intel_5-level_paging
+//! - https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html (vol 3, section 4.5)
+
+use {super::*, crate::cpuid::Feature, core::arch::asm};
+
+/// The LA57 bit in CR4.
+const CR4_LA57: usize = 1 << 12;
+
+/// Whether the CPU supports 5-level paging (CPUID leaf 7, ECX bit 16).
+pub fn la57_supported() -> bool {
+	Feature::La57.supported()
+}
+
+/// Whether 5-level paging is turned on in CR4.
+pub fn la57_enabled() -> bool {
+	let cr4: usize;
+	unsafe { asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags)) };
+	cr4 & CR4_LA57 != 0
+}
+
+/// Turns on 5-level paging in CR4. Once paging is enabled, CR3 has to point to a page map level 5.
+///
+/// # Safety
+/// - Paging must be disabled; setting LA57 with paging on faults
+/// - The CPU must support 5-level paging (see [`la57_supported`])
+pub unsafe fn enable_la57() {
+	unsafe {
+		asm!(
+			"mov {0}, cr4",
+			"or {0}, {1}",
+			"mov cr4, {0}",
+			out(reg) _,
+			const CR4_LA57,
+			options(nomem, nostack, preserves_flags)
+		)
+	};
+}
+
+/// Gets the index into each table for a 57-bit virtual address, from the page map level 5 down to
+/// the page table. See [`table_indices`].
+pub fn table_indices_level5(virt: u64) -> [usize; 5] {
+	[48, 39, 30, 21, 12].map(|shift| ((virt >> shift) & 0x1FF) as usize)
+}
+
+/// Sign-extends bit 56 of `virt` into the top 7 bits, making it canonical with 5-level paging. See
+/// [`canonicalize`].
+pub const fn canonicalize_level5(virt: u64) -> u64 {
+	((virt << 7) as i64 >> 7) as u64
+}
+
+/// Puts a 4-level hierarchy under `pml5`, so every address that's canonical with 4-level paging
+/// maps to the same place with 5-level paging. The page map level 4 at `pml4_address` is used for
+/// both the lowest and highest 128pib of the address space, since higher-half 4-level addresses
+/// sign-extend into the last page map level 5 entry.
+pub fn wrap_level4(pml5: &mut PageMap<PageMapLevel5Entry>, pml4_address: PhysAddr) {
+	for index in [0, 511] {
+		let entry = &mut pml5[index];
+		*entry = PageMapLevel5Entry::new();
+		entry
+			.set_present(true)
+			.set_writable(true)
+			.set_address(pml4_address);
+	}
+}
+
+/// Finds the physical address `virt` is mapped to, using the 5-level hierarchy under `pml5`.
+/// Returns `None` if `virt` isn't mapped. See [`translate`].
+///
+/// # Safety
+/// See [`translate`].
+pub unsafe fn translate_level5(
+	pml5: &PageMap<PageMapLevel5Entry>,
+	virt: u64,
+	physical_offset: u64,
+) -> Option<PhysAddr> {
+	let entry = &pml5[table_indices_level5(virt)[0]];
+	if !entry.present() {
+		return None;
+	}
+
+	let pml4 = (entry.address() + physical_offset) as usize as *const PageMap<PageMapLevel4Entry>;
+	// The lower levels index the same way as 4-level paging
+	unsafe { translate(&*pml4, virt, physical_offset) }
+}
@@ -0,0 +1,198 @@
+//! Maps and unmaps individual pages, without having to index into the page tables by hand.
+//!
+//! The [`Mapper`] walks the page tables the same way [`translate`] does, except it creates any
+//! missing tables on the way down, using frames from a [`FrameAllocator`]. Only 4kib pages are
+//! mapped; if the walk runs into a huge page, the mapper gives up with [`MapError::HugePage`].
+//!
+//! Unmapping a page or changing its flags flushes it from the TLB (see [`tlb`]), so the change
+//! takes effect immediately on this CPU.
+
+use super::*;
+
+/// Something went wrong while changing a mapping.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MapError {
+	/// The page is already mapped to this physical address.
+	AlreadyMapped(PhysAddr),
+	/// The page isn't mapped.
+	NotMapped,
+	/// The page is inside a huge page, which the mapper can't edit.
+	HugePage,
+	/// The virtual or physical address isn't aligned to a 4kib page.
+	Unaligned,
+	/// There weren't any free frames for a new page table.
+	OutOfFrames,
+}
+
+/// Edits the page tables under a page map level 4. See the module-level docs.
+pub struct Mapper<'a, 'f> {
+	pml4: &'a mut PageMap<PageMapLevel4Entry>,
+	frames: &'a mut FrameAllocator<'f>,
+	/// Where physical memory is mapped in virtual memory. See [`translate`].
+	physical_offset: u64,
+}
+impl<'a, 'f> Mapper<'a, 'f> {
+	/// Creates a mapper for the page tables under `pml4`. New page tables are allocated from
+	/// `frames`.
+	///
+	/// # Safety
+	/// - Every table under `pml4`, and every frame `frames` hands out, must be mapped at its
+	///   physical address plus `physical_offset`
+	/// - Nothing else can edit the tables while the mapper exists
+	pub unsafe fn new(
+		pml4: &'a mut PageMap<PageMapLevel4Entry>,
+		frames: &'a mut FrameAllocator<'f>,
+		physical_offset: u64,
+	) -> Self {
+		Self {
+			pml4,
+			frames,
+			physical_offset,
+		}
+	}
+
+	/// The frame allocator new page tables come from.
+	pub fn frames(&mut self) -> &mut FrameAllocator<'f> {
+		self.frames
+	}
+
+	/// Maps the page at `virt` to the frame at `phys`, creating page tables as needed. Errors if
+	/// the page is already mapped.
+	pub fn map_to(&mut self, virt: u64, phys: PhysAddr, flags: PageFlags) -> Result<(), MapError> {
+		if !virt.is_multiple_of(PAGE_SIZE) || !phys.is_multiple_of(PAGE_SIZE) {
+			return Err(MapError::Unaligned);
+		}
+
+		let entry = self.page_table_entry(virt, Some(flags.user_mode))?;
+		if entry.present() {
+			return Err(MapError::AlreadyMapped(entry.address()));
+		}
+
+		*entry = PageTableEntry::new();
+		entry.set_present(true).set_flags(flags).set_address(phys);
+
+		Ok(())
+	}
+
+	/// Maps `len` bytes at `virt` to the physical memory at `phys`, one 4kib page at a time - eg, to
+	/// map the kernel at [`KERNEL_BASE`]. Both addresses must be page-aligned; `len` is rounded up
+	/// to a whole page. Stops at the first page that can't be mapped.
+	pub fn map_range(
+		&mut self,
+		virt: u64,
+		phys: PhysAddr,
+		len: u64,
+		flags: PageFlags,
+	) -> Result<(), MapError> {
+		for offset in (0..len).step_by(PAGE_SIZE as usize) {
+			self.map_to(virt + offset, phys + offset, flags)?;
+		}
+
+		Ok(())
+	}
+
+	/// Unmaps the page at `virt`, returning the frame it was mapped to. The frame isn't freed,
+	/// since the mapper doesn't know if anything else uses it.
+	pub fn unmap(&mut self, virt: u64) -> Result<PhysAddr, MapError> {
+		let entry = self.mapped_entry(virt)?;
+		let phys = entry.address();
+		*entry = PageTableEntry::new();
+		invlpg(virt);
+
+		Ok(phys)
+	}
+
+	/// Changes the flags of the page at `virt`.
+	pub fn set_flags(&mut self, virt: u64, flags: PageFlags) -> Result<(), MapError> {
+		self.mapped_entry(virt)?.set_flags(flags);
+		invlpg(virt);
+
+		Ok(())
+	}
+
+	/// Changes the memory type of the page at `virt` to the one at `index` in the PAT. See
+	/// [`PageTableEntry::set_pat_index`].
+	pub fn set_pat_index(&mut self, virt: u64, index: u8) -> Result<(), MapError> {
+		self.mapped_entry(virt)?.set_pat_index(index);
+		invlpg(virt);
+
+		Ok(())
+	}
+
+	/// Gets the page table entry for `virt`, erroring if it isn't mapped.
+	pub(super) fn mapped_entry(&mut self, virt: u64) -> Result<&mut PageTableEntry, MapError> {
+		if !virt.is_multiple_of(PAGE_SIZE) {
+			return Err(MapError::Unaligned);
+		}
+
+		let entry = self.page_table_entry(virt, None)?;
+		if !entry.present() {
+			return Err(MapError::NotMapped);
+		}
+
+		Ok(entry)
+	}
+
+	/// Walks down to the page table entry for `virt`. If `create` is set, missing tables are
+	/// created, and the tables are made accessible to user mode if `create` is `Some(true)`.
+	/// Otherwise, missing tables are an error.
+	fn page_table_entry(
+		&mut self,
+		virt: u64,
+		create: Option<bool>,
+	) -> Result<&mut PageTableEntry, MapError> {
+		let [pml4_index, pdpt_index, pd_index, pt_index] = table_indices(virt);
+		let (frames, offset) = (&mut *self.frames, self.physical_offset);
+
+		let pdpt: &mut PageMap<PageDirectoryPointerTableEntry> =
+			unsafe { next_table(&mut self.pml4[pml4_index], frames, offset, create) }?;
+		let pd: &mut PageMap<PageDirectoryEntry> =
+			unsafe { next_table(&mut pdpt[pdpt_index], frames, offset, create) }?;
+		let pt: &mut PageMap<PageTableEntry> =
+			unsafe { next_table(&mut pd[pd_index], frames, offset, create) }?;
+
+		Ok(&mut pt[pt_index])
+	}
+}
+
+/// Gets the table `entry` points to, creating it if it's missing and `create` is set. See
+/// [`Mapper::page_table_entry`].
+///
+/// # Safety
+/// The table must be mapped at its physical address plus `physical_offset`, and so must every
+/// frame `frames` hands out.
+unsafe fn next_table<'t, E: PageMapEntry, N: PageMapEntry>(
+	entry: &mut E,
+	frames: &mut FrameAllocator,
+	physical_offset: u64,
+	create: Option<bool>,
+) -> Result<&'t mut PageMap<N>, MapError> {
+	let mut bits = entry.bits();
+
+	if bits & PRESENT == 0 {
+		let Some(user_mode) = create else {
+			return Err(MapError::NotMapped);
+		};
+
+		let frame = frames.allocate_frame().ok_or(MapError::OutOfFrames)?;
+		let table = (frame + physical_offset) as usize as *mut PageMap<N>;
+		// An empty table is all zeroes. Zeroing it in place, instead of writing a new one, keeps a
+		// 4kib table off the stack - which the 16-bit bootloader doesn't have room for.
+		unsafe { table.write_bytes(0, 1) };
+
+		// Intermediate tables allow everything; the final page table entry decides the permissions
+		bits = frame | PRESENT | WRITABLE;
+		if user_mode {
+			bits |= USER_MODE;
+		}
+		entry.set_bits(bits);
+	} else if bits & HUGE_PAGE != 0 {
+		return Err(MapError::HugePage);
+	} else if create == Some(true) {
+		bits |= USER_MODE;
+		entry.set_bits(bits);
+	}
+
+	let table = ((bits & ADDRESS_MASK) + physical_offset) as usize as *mut PageMap<N>;
+	Ok(unsafe { &mut *table })
+}
@@ -0,0 +1,184 @@
+//! Sets the memory type (how the CPU caches memory) of individual pages, with the page attribute
+//! table (PAT).
+//!
+//! Each page table entry has 3 bits that pick the memory type: PWT (write-through), PCD (cache
+//! disable), and PAT. Together they form a 3-bit index into the PAT, an MSR holding 8 memory types.
+//! The PAT's default entries only cover the memory types PWT and PCD could already pick, so to get
+//! something like write-combining (which framebuffers need to not be painfully slow), the PAT has
+//! to be reprogrammed first.
+//!
+//! [`Pat::BS`] keeps the first 4 entries the same as the default, so entries that don't set the
+//! PAT bit act the same either way, and puts the other memory types in the last 4.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Paging#PAT
+//! - https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html (vol 3, section 12.12)
+
+use {super::*, crate::msr, core::arch::asm};
+
+/// How the CPU caches a page's memory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum MemoryType {
+	/// Never cached. Used for memory-mapped I/O.
+	Uncacheable = 0,
+	/// Not cached, but writes are buffered and combined into bigger writes. Used for framebuffers.
+	WriteCombining = 1,
+	/// Reads are cached, and writes go to both the cache and memory.
+	WriteThrough = 4,
+	/// Reads are cached, and writes go straight to memory.
+	WriteProtect = 5,
+	/// Reads and writes are cached, and written to memory later. Normal memory uses this.
+	WriteBack = 6,
+	/// Like [`MemoryType::Uncacheable`], but the MTRRs can override it to write-combining.
+	UncacheableMinus = 7,
+}
+impl TryFrom<u8> for MemoryType {
+	type Error = u8;
+
+	fn try_from(value: u8) -> Result<Self, Self::Error> {
+		Ok(match value {
+			0 => Self::Uncacheable,
+			1 => Self::WriteCombining,
+			4 => Self::WriteThrough,
+			5 => Self::WriteProtect,
+			6 => Self::WriteBack,
+			7 => Self::UncacheableMinus,
+			other => return Err(other),
+		})
+	}
+}
+
+/// The 8 memory types in the page attribute table. See the module-level docs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Pat(pub [MemoryType; 8]);
+impl Pat {
+	/// What the PAT is set to when the CPU starts.
+	pub const DEFAULT: Self = Self([
+		MemoryType::WriteBack,
+		MemoryType::WriteThrough,
+		MemoryType::UncacheableMinus,
+		MemoryType::Uncacheable,
+		MemoryType::WriteBack,
+		MemoryType::WriteThrough,
+		MemoryType::UncacheableMinus,
+		MemoryType::Uncacheable,
+	]);
+	/// The PAT BS uses. It's the same as [`Pat::DEFAULT`], except the last 4 entries cover every
+	/// memory type that the first 4 don't.
+	pub const BS: Self = Self([
+		MemoryType::WriteBack,
+		MemoryType::WriteThrough,
+		MemoryType::UncacheableMinus,
+		MemoryType::Uncacheable,
+		MemoryType::WriteCombining,
+		MemoryType::WriteProtect,
+		MemoryType::UncacheableMinus,
+		MemoryType::Uncacheable,
+	]);
+
+	/// Reads the current PAT from its MSR. Returns `None` if it has an invalid memory type.
+	pub fn read() -> Option<Self> {
+		let bits = unsafe { msr::read(msr::IA32_PAT) };
+		let mut types = [MemoryType::Uncacheable; 8];
+		for (index, memory_type) in types.iter_mut().enumerate() {
+			*memory_type = MemoryType::try_from((bits >> (index * 8)) as u8 & 0b111).ok()?;
+		}
+
+		Some(Self(types))
+	}
+
+	/// Writes this PAT to its MSR, then flushes the caches and TLB so the new memory types take
+	/// effect.
+	///
+	/// # Safety
+	/// Any page whose memory type changes must not be in use by anything that relies on the old
+	/// type (eg, memory-mapped I/O that can't be cached).
+	pub unsafe fn load(&self) {
+		unsafe {
+			msr::write(msr::IA32_PAT, self.bits());
+			asm!("wbinvd", options(nostack, preserves_flags));
+		}
+		flush_all();
+	}
+
+	/// The raw value of the PAT's MSR.
+	pub fn bits(&self) -> u64 {
+		self.0
+			.iter()
+			.enumerate()
+			.fold(0, |bits, (index, memory_type)| {
+				bits | ((*memory_type as u64) << (index * 8))
+			})
+	}
+
+	/// The first PAT index with `memory_type`, to pass to `set_pat_index`.
+	pub fn index_of(&self, memory_type: MemoryType) -> Option<u8> {
+		self.0
+			.iter()
+			.position(|other| *other == memory_type)
+			.map(|index| index as u8)
+	}
+}
+
+/// Sets the PWT, PCD, and PAT bits of an entry to `index`. `pat_bit` is where the PAT bit is in
+/// this type of entry.
+fn set_pat_bits(entry: &mut u64, index: u8, pat_bit: u32) {
+	assert!(index < 8, "PAT indices must be below 8");
+
+	*entry &= !((1 << 3) | (1 << 4) | (1 << pat_bit));
+	*entry |= ((index as u64 & 0b011) << 3) | ((index as u64 >> 2) << pat_bit);
+}
+
+/// Reads the PAT index out of an entry. See [`set_pat_bits`].
+fn pat_bits(entry: u64, pat_bit: u32) -> u8 {
+	(((entry >> 3) & 0b011) | (((entry >> pat_bit) & 1) << 2)) as u8
+}
+
+/// Implements the PAT bits, for the entries that map memory. The PAT bit is bit 7 in page table
+/// entries, but bit 7 is the huge page bit in higher tables, so their PAT bit moves to bit 12.
+macro_rules! pat_entry {
+	($name:ident) => {
+		pat_entry!(@entry $name, 7, false);
+	};
+	// For entries that only map memory if they're huge pages. Otherwise, bit 12 is part of the
+	// lower table's address.
+	($name:ident, huge) => {
+		pat_entry!(@entry $name, 12, true);
+	};
+	(@entry $name:ident, $pat_bit:literal, $huge:literal) => {
+		impl $name {
+			/// Sets this page's memory type to the memory type at `index` in the PAT (see
+			/// [`Pat::index_of`]). This overwrites the write-through and caching settings, since
+			/// they're the low 2 bits of the index. Panics if `index` isn't below 8.
+			///
+			/// Page directory and page directory pointer table entries only have a PAT bit if
+			/// they're huge pages, so this also panics if one of them isn't a huge page yet.
+			///
+			/// Default value: 0, which is write-back with the default PAT.
+			pub fn set_pat_index(&mut self, index: u8) -> &mut Self {
+				if $huge && self.0 & HUGE_PAGE == 0 {
+					panic!("Only huge pages can have a PAT index");
+				}
+				set_pat_bits(&mut self.0, index, $pat_bit);
+
+				self
+			}
+
+			/// The index in the PAT of this page's memory type. See [`Self::set_pat_index`]. If
+			/// this entry points to a lower table, its PAT bit is part of the address, so only
+			/// the low 2 bits of the index are read.
+			pub fn pat_index(&self) -> u8 {
+				if $huge && self.0 & HUGE_PAGE == 0 {
+					pat_bits(self.0 & !HUGE_PAT, $pat_bit)
+				} else {
+					pat_bits(self.0, $pat_bit)
+				}
+			}
+		}
+	};
+}
+
+pat_entry!(PageTableEntry);
+pat_entry!(PageDirectoryEntry, huge);
+pat_entry!(PageDirectoryPointerTableEntry, huge);
@@ -0,0 +1,113 @@
+//! Edits page tables through a recursive page map level 4 entry, so physical memory doesn't have
+//! to be mapped anywhere.
+//!
+//! Once paging is on, page tables can only be edited through virtual addresses. One way to get
+//! those is mapping all of physical memory somewhere (see [`translate`]), but another is pointing
+//! one of the page map level 4's entries back at the page map level 4 itself. When the CPU walks
+//! through that entry, it treats the page map level 4 as a page directory pointer table, so
+//! everything one level lower shows up as regular memory. Going through the entry 2, 3, or 4
+//! times exposes the page directories, page directory pointer tables, and page map level 4.
+//!
+//! The catch is the recursive entry takes up 512gib of virtual memory (1/512th of the address
+//! space), and it only exposes the active page tables.
+//!
+//! Resources:
+//! - https://os.phil-opp.com/paging-implementation/#recursive-page-tables
+//! - https://wiki.osdev.org/Page_Tables#Recursive_mapping
+
+use super::*;
+
+/// A recursive entry in the page map level 4. See the module-level docs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecursiveMapping {
+	index: usize,
+}
+impl RecursiveMapping {
+	/// Uses the page map level 4 entry at `index` as the recursive entry. Panics if `index` isn't
+	/// below 512.
+	pub const fn new(index: usize) -> Self {
+		assert!(index < 512, "Page map level 4 indices must be below 512");
+
+		Self { index }
+	}
+
+	/// The index of the recursive entry.
+	pub fn index(&self) -> usize {
+		self.index
+	}
+
+	/// Points the recursive entry in `pml4` back at `pml4`, which is at the physical address
+	/// `pml4_address`. The entry's only writable by the kernel, so user mode can't edit page tables.
+	pub fn install(&self, pml4: &mut PageMap<PageMapLevel4Entry>, pml4_address: PhysAddr) {
+		let entry = &mut pml4[self.index()];
+		*entry = PageMapLevel4Entry::new();
+		entry
+			.set_present(true)
+			.set_writable(true)
+			.set_executable(false)
+			.set_address(pml4_address);
+	}
+
+	/// The virtual address of the page map level 4.
+	pub fn pml4_address(&self) -> u64 {
+		virtual_address([self.index; 4], 0)
+	}
+
+	/// The virtual address of the page directory pointer table that maps `virt`.
+	pub fn pdpt_address(&self, virt: u64) -> u64 {
+		let [pml4, ..] = table_indices(virt);
+		virtual_address([self.index, self.index, self.index, pml4], 0)
+	}
+
+	/// The virtual address of the page directory that maps `virt`.
+	pub fn pd_address(&self, virt: u64) -> u64 {
+		let [pml4, pdpt, ..] = table_indices(virt);
+		virtual_address([self.index, self.index, pml4, pdpt], 0)
+	}
+
+	/// The virtual address of the page table that maps `virt`.
+	pub fn pt_address(&self, virt: u64) -> u64 {
+		let [pml4, pdpt, pd, _] = table_indices(virt);
+		virtual_address([self.index, pml4, pdpt, pd], 0)
+	}
+
+	/// The active page map level 4, through the recursive entry.
+	///
+	/// # Safety
+	/// - The recursive entry must be installed in the active page map level 4
+	/// - Nothing else can edit the table while the reference exists
+	pub unsafe fn pml4<'a>(&self) -> &'a mut PageMap<PageMapLevel4Entry> {
+		unsafe { &mut *(self.pml4_address() as usize as *mut _) }
+	}
+
+	/// The active page directory pointer table that maps `virt`, through the recursive entry.
+	///
+	/// # Safety
+	/// - The recursive entry must be installed in the active page map level 4
+	/// - The page map level 4 entry for `virt` must be present
+	/// - Nothing else can edit the table while the reference exists
+	pub unsafe fn pdpt<'a>(&self, virt: u64) -> &'a mut PageMap<PageDirectoryPointerTableEntry> {
+		unsafe { &mut *(self.pdpt_address(virt) as usize as *mut _) }
+	}
+
+	/// The active page directory that maps `virt`, through the recursive entry.
+	///
+	/// # Safety
+	/// - The recursive entry must be installed in the active page map level 4
+	/// - The page map level 4 and page directory pointer table entries for `virt` must be present,
+	///   and not huge pages
+	/// - Nothing else can edit the table while the reference exists
+	pub unsafe fn pd<'a>(&self, virt: u64) -> &'a mut PageMap<PageDirectoryEntry> {
+		unsafe { &mut *(self.pd_address(virt) as usize as *mut _) }
+	}
+
+	/// The active page table that maps `virt`, through the recursive entry.
+	///
+	/// # Safety
+	/// - The recursive entry must be installed in the active page map level 4
+	/// - Every entry above the page table for `virt` must be present, and not a huge page
+	/// - Nothing else can edit the table while the reference exists
+	pub unsafe fn pt<'a>(&self, virt: u64) -> &'a mut PageMap<PageTableEntry> {
+		unsafe { &mut *(self.pt_address(virt) as usize as *mut _) }
+	}
+}
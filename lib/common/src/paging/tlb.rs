@@ -0,0 +1,27 @@
+//! Clears stale entries out of the TLB.
+//!
+//! The CPU caches page table lookups in the translation lookaside buffer (TLB), and it doesn't
+//! notice when the page tables change. After a page is unmapped or its flags change, the old
+//! entry has to be flushed, or the CPU will keep using the old mapping until it happens to get
+//! evicted. Mapping a page that wasn't present doesn't need a flush, since the CPU never caches
+//! missing pages.
+//!
+//! These only affect the current CPU. They're also privileged instructions, so they'll fault
+//! outside of ring 0.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/TLB
+//! - https://www.felixcloutier.com/x86/invlpg
+
+use {super::*, core::arch::asm};
+
+/// Flushes the TLB entry for the page containing `virt`.
+pub fn invlpg(virt: u64) {
+	unsafe { asm!("invlpg [{}]", in(reg) virt as usize, options(nostack, preserves_flags)) };
+}
+
+/// Flushes every TLB entry, by reloading CR3. Global pages aren't flushed.
+pub fn flush_all() {
+	// Rewriting the same value can't unmap anything
+	unsafe { Cr3::read().write() };
+}
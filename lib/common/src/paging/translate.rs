@@ -0,0 +1,92 @@
+//! Converts virtual addresses to physical addresses by walking the page tables, the same way the
+//! CPU does.
+//!
+//! A 64-bit virtual address is split up like this:
+//!
+//! ```txt
+//! | 63..48 | 47..39 | 38..30 | 29..21 | 20..12 | 11..0  |
+//! | unused |  PML4  |  PDPT  |   PD   |   PT   | offset |
+//! ```
+//!
+//! Each 9-bit chunk indexes into a table, and the entry there points to the next table down. If a
+//! page directory pointer table or page directory entry is a huge page, the walk stops early, and
+//! the rest of the address is the offset into that huge page.
+//!
+//! The tables store physical addresses, but the walker has to read them through virtual
+//! addresses. So every function here takes a `physical_offset`: the virtual address physical
+//! memory is mapped at. When memory is identity-mapped (like in the bootloader), that's just 0.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Paging#64-Bit_Paging
+//! - https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html (vol 3, section 4.5)
+
+use super::*;
+
+/// Gets the index into each table for a virtual address, from the page map level 4 down to the
+/// page table.
+pub fn table_indices(virt: u64) -> [usize; 4] {
+	[39, 30, 21, 12].map(|shift| ((virt >> shift) & 0x1FF) as usize)
+}
+
+/// Finds the physical address `virt` is mapped to, using the page tables under `pml4`. Returns
+/// `None` if `virt` isn't mapped.
+///
+/// # Safety
+/// - Every table under `pml4` must be mapped at its physical address plus `physical_offset`
+/// - The tables can't be modified while they're being walked
+pub unsafe fn translate(
+	pml4: &PageMap<PageMapLevel4Entry>,
+	virt: u64,
+	physical_offset: u64,
+) -> Option<PhysAddr> {
+	let [pml4_index, pdpt_index, pd_index, pt_index] = table_indices(virt);
+
+	let entry = present(pml4[pml4_index].bits())?;
+	let pdpt: &PageMap<PageDirectoryPointerTableEntry> =
+		unsafe { next_table(entry, physical_offset) };
+
+	let entry = present(pdpt[pdpt_index].bits())?;
+	if entry & HUGE_PAGE != 0 {
+		return Some(huge_page_address(entry, virt, GIANT_PAGE_SIZE));
+	}
+	let pd: &PageMap<PageDirectoryEntry> = unsafe { next_table(entry, physical_offset) };
+
+	let entry = present(pd[pd_index].bits())?;
+	if entry & HUGE_PAGE != 0 {
+		return Some(huge_page_address(entry, virt, HUGE_PAGE_SIZE));
+	}
+	let pt: &PageMap<PageTableEntry> = unsafe { next_table(entry, physical_offset) };
+
+	let entry = present(pt[pt_index].bits())?;
+	Some((entry & ADDRESS_MASK) + (virt % PAGE_SIZE))
+}
+
+/// Finds the physical address `virt` is mapped to, using the active page tables (the ones CR3
+/// points to). See [`translate`].
+///
+/// # Safety
+/// See [`translate`].
+pub unsafe fn translate_active(virt: u64, physical_offset: u64) -> Option<PhysAddr> {
+	let pml4 = Cr3::read().address() + physical_offset;
+	unsafe { translate(&*(pml4 as usize as *const _), virt, physical_offset) }
+}
+
+/// Returns the entry, if it's present.
+fn present(entry: u64) -> Option<u64> {
+	(entry & PRESENT != 0).then_some(entry)
+}
+
+/// Gets the table an entry points to.
+///
+/// # Safety
+/// The table must be mapped at its physical address plus `physical_offset`.
+unsafe fn next_table<'a, E: PageMapEntry>(entry: u64, physical_offset: u64) -> &'a PageMap<E> {
+	let address = (entry & ADDRESS_MASK) + physical_offset;
+	unsafe { &*(address as usize as *const PageMap<E>) }
+}
+
+/// Gets the physical address of `virt` in the huge page an entry maps.
+fn huge_page_address(entry: u64, virt: u64, size: u64) -> PhysAddr {
+	// The low bits of a huge page's address hold other flags (like PAT), so they're masked off
+	(entry & ADDRESS_MASK & !(size - 1)) + (virt % size)
+}
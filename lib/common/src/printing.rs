@@ -1,61 +1,447 @@
 //! Adds the `print!` and `println!` macros, just like you'd find in standard Rust.
 //! If using the BIOS feature, this uses int 0x10 to print characters.
 //! Otherwise, this uses VGA text mode.
+//!
+//! The VGA console's blinking cursor follows the text; it can be hidden with
+//! [`Printer::hide_cursor`].
+//!
+//! The macros can also write to the serial port (see [`serial`]), or any other output - they write
+//! to every registered [`sink`]. Without VGA text mode (eg under UEFI), text can be drawn into a
+//! pixel framebuffer instead (see [`framebuffer`]).
+//!
+//! The VGA console understands the common ANSI escape sequences (colours, moving the cursor, and
+//! clearing the screen), so output looks the same as on the serial console. Non-ASCII text is
+//! converted to the VGA's character set (see [`cp437`]).
+//!
+//! Text that scrolls off the top of the VGA console can be kept in a scrollback (see
+//! [`scrollback`]).
+//!
+//! There's also levelled logging macros, like `log_error!`, in [`log`].
+//!
+//! The global printers are behind a [`Spinlock`](crate::sync::Spinlock), so printing from interrupt handlers is safe.
+//! Panic handlers should call [`force_unlock`] first, in case the panic happened mid-print - or
+//! just use [`panic_screen`], which does that and more.
 
-use core::{fmt::Write, ptr::addr_of_mut};
+mod ansi;
+pub mod cp437;
+pub mod framebuffer;
+pub mod log;
+pub mod panic_screen;
+pub mod scrollback;
+pub mod serial;
+pub mod sink;
 
-pub static mut GLOBAL_PRINTER: Printer = Printer { idx: 0 };
+pub use {framebuffer::*, panic_screen::*, scrollback::*, serial::*, sink::*};
+
+use ansi::*;
+
+use {
+	crate::sync::{Spinlock, SpinlockGuard},
+	core::{
+		arch::asm,
+		fmt::{self, Write},
+	},
+};
+
+pub static GLOBAL_PRINTER: Spinlock<Printer> = Spinlock::new(Printer::new());
+
+/// Unlocks the global printers, even if something else has them locked.
+///
+/// # Safety
+/// Only for panic handlers: whatever was printing can't continue.
+pub unsafe fn force_unlock() {
+	unsafe {
+		GLOBAL_PRINTER.force_unlock();
+		GLOBAL_SERIAL.force_unlock();
+		sink::SINKS.force_unlock();
+	}
+}
 
-#[derive(Default)]
 pub struct Printer {
 	pub idx: usize,
+	/// The VGA attribute byte new characters are printed with. See [`VgaColor::attribute`].
+	pub colour: u8,
+	/// How many rows the screen has. See [`Printer::detect_mode`].
+	rows: usize,
+	scrollback: Option<Scrollback>,
+	/// Where the printer is in an ANSI escape sequence.
+	ansi: AnsiParser,
 }
 #[allow(dead_code)] // Some consts are only used with certain crate features
 impl Printer {
 	const BUFFER: *mut [VgaTextChar; 8_000] = 0xB8000 as *mut _;
-	const NUM_ROWS: usize = 25;
-	const NUM_COLUMNS: usize = 80;
-	const LEN: usize = Self::NUM_ROWS * Self::NUM_COLUMNS;
+	/// How many rows the screen has until [`Printer::detect_mode`] says otherwise. This is the BIOS'
+	/// default text mode (80x25).
+	pub const DEFAULT_ROWS: usize = 25;
+	/// The most rows the printer supports. This is the 80x50 text mode, with an 8x8 font.
+	pub const MAX_ROWS: usize = 50;
+	pub const NUM_COLUMNS: usize = 80;
+	/// Tabs move to the next multiple of this many columns.
+	pub const TAB_WIDTH: usize = 8;
+	/// White text on black.
+	const DEFAULT_COLOUR: u8 = VgaColor::attribute(VgaColor::White, VgaColor::Black, false);
+
+	/// A printer at the top of the screen, that prints white text on black.
+	pub const fn new() -> Self {
+		Self {
+			idx: 0,
+			colour: Self::DEFAULT_COLOUR,
+			rows: Self::DEFAULT_ROWS,
+			scrollback: None,
+			ansi: AnsiParser::new(),
+		}
+	}
+
+	/// Reads the current text mode's size from the VGA's CRT controller, so wrapping and scrolling
+	/// use the whole screen (eg in 80x50 mode). Returns `false` and keeps the old size if the mode
+	/// isn't one the printer supports: it has to be [`Printer::NUM_COLUMNS`] wide and at most
+	/// [`Printer::MAX_ROWS`] tall.
+	///
+	/// The BIOS can switch to 80x50 with int 10h before leaving real mode; the bootstrapper does
+	/// that with its `text-80x50` feature.
+	pub fn detect_mode(&mut self) -> bool {
+		let (columns, rows) = unsafe {
+			// The horizontal display end is the last character column
+			let columns = crtc_read(CRTC_HORIZONTAL_DISPLAY_END) as usize + 1;
+
+			// The vertical display end is the last scanline, and is 10 bits. The high bits are
+			// in the overflow register.
+			let overflow = crtc_read(CRTC_OVERFLOW) as usize;
+			let scanlines = (crtc_read(CRTC_VERTICAL_DISPLAY_END) as usize
+				| (overflow & 0b10) << 7
+				| (overflow & 0b100_0000) << 3)
+				+ 1;
+			let char_height = (crtc_read(CRTC_MAXIMUM_SCAN_LINE) & 0x1F) as usize + 1;
+
+			(columns, scanlines / char_height)
+		};
+
+		if columns != Self::NUM_COLUMNS || !(1..=Self::MAX_ROWS).contains(&rows) {
+			return false;
+		}
+
+		self.scroll_to_bottom();
+		self.rows = rows;
+		self.idx = self.idx.min(self.len() - 1);
+		self.update_cursor();
+
+		true
+	}
+
+	/// How many rows the screen has. See [`Printer::detect_mode`].
+	pub fn rows(&self) -> usize {
+		self.rows
+	}
+
+	/// How many characters fit on the screen.
+	fn len(&self) -> usize {
+		self.rows * Self::NUM_COLUMNS
+	}
+
+	/// Locks the global printer. Printing while it's locked (including with `print!`) deadlocks,
+	/// so don't hold onto it.
+	pub fn get_global() -> SpinlockGuard<'static, Self> {
+		GLOBAL_PRINTER.lock()
+	}
 
-	pub fn get_global<'a>() -> &'a mut Self {
-		unsafe { &mut *addr_of_mut!(GLOBAL_PRINTER) }
+	/// Sets the colours new characters are printed with. Blinking is left how it was.
+	pub fn set_colour(&mut self, foreground: VgaColor, background: VgaColor) {
+		self.colour = VgaColor::attribute(foreground, background, self.blink());
 	}
 
-	/// Prints one byte to the screen.
+	/// The colours new characters are printed with, as `(foreground, background)`.
+	pub fn colour(&self) -> (VgaColor, VgaColor) {
+		(
+			VgaColor::from_bits(self.colour & 0xF),
+			VgaColor::from_bits((self.colour >> 4) & 0b111),
+		)
+	}
+
+	/// Makes new characters blink (or stop blinking).
+	pub fn set_blink(&mut self, blink: bool) {
+		if blink {
+			self.colour |= 1 << 7;
+		} else {
+			self.colour &= !(1 << 7);
+		}
+	}
+
+	/// If new characters blink.
+	pub fn blink(&self) -> bool {
+		self.colour & (1 << 7) != 0
+	}
+
+	/// Prints one byte (a CP437 character, not UTF-8) to the screen, and moves the cursor after it.
 	pub fn write_byte(&mut self, byte: u8) {
+		self.put_byte(byte);
+		self.update_cursor();
+	}
+
+	/// Prints one byte to the screen, without moving the cursor.
+	fn put_byte(&mut self, byte: u8) {
+		self.scroll_to_bottom();
+
+		let byte = match self.ansi.feed(byte) {
+			AnsiAction::Print(byte) => byte,
+			AnsiAction::None => return,
+			AnsiAction::Csi(command, params, count) => {
+				self.run_csi(command, params, count);
+				return;
+			}
+		};
+
 		match byte {
 			b'\n' => self.idx += Self::NUM_COLUMNS - (self.idx % Self::NUM_COLUMNS),
 			b'\r' => self.idx -= self.idx % Self::NUM_COLUMNS,
+			b'\t' => {
+				// Blank up to the next tab stop, without going past the end of the line
+				let col = self.idx % Self::NUM_COLUMNS;
+				let stop = (col / Self::TAB_WIDTH + 1) * Self::TAB_WIDTH;
+				let end = self.idx - col + stop.min(Self::NUM_COLUMNS);
+				self.fill(self.idx..end);
+				self.idx = end;
+			}
+			// Backspace: erase the previous character. This can go back to the previous line, so
+			// line editing works on input that wrapped.
+			0x08 => {
+				self.idx = self.idx.saturating_sub(1);
+				self.fill(self.idx..self.idx + 1);
+			}
 			byte => {
 				let buffer = unsafe { &mut *Self::BUFFER };
 				buffer[self.idx].letter = byte;
-				buffer[self.idx].colour = 0b0000_1111;
+				buffer[self.idx].colour = self.colour;
 				self.idx += 1;
 			}
 		}
+
+		if self.idx >= self.len() {
+			self.bump_screen();
+		}
 	}
 
-	/// Clears the whole VGA buffer, making the screen black.
-	pub fn clear(&mut self) {
+	/// The row the next character will be printed in, starting at 0.
+	pub fn row(&self) -> usize {
+		self.idx / Self::NUM_COLUMNS
+	}
+
+	/// The column the next character will be printed in, starting at 0.
+	pub fn col(&self) -> usize {
+		self.idx % Self::NUM_COLUMNS
+	}
+
+	/// Moves to `row` and `col` (starting at 0), so the next character gets printed there. They're
+	/// clamped to the screen. Handy for status lines that update in place.
+	pub fn move_to(&mut self, row: usize, col: usize) {
+		self.idx = row.min(self.rows - 1) * Self::NUM_COLUMNS + col.min(Self::NUM_COLUMNS - 1);
+		self.update_cursor();
+	}
+
+	/// Blanks `row`, with the current colours. The cursor doesn't move.
+	pub fn clear_line(&mut self, row: usize) {
+		self.clear_rows(row..row + 1);
+	}
+
+	/// Blanks every row in `rows`, with the current colours. The cursor doesn't move.
+	pub fn clear_rows(&mut self, rows: core::ops::Range<usize>) {
+		self.fill(rows.start * Self::NUM_COLUMNS..rows.end * Self::NUM_COLUMNS);
+	}
+
+	/// Blanks from the cursor to the end of its line, eg to overwrite a status line with something
+	/// shorter. The cursor doesn't move.
+	pub fn clear_to_end_of_line(&mut self) {
+		let end = (self.row() + 1) * Self::NUM_COLUMNS;
+		self.fill(self.idx..end);
+	}
+
+	/// Moves every line on the screen up one, making room for a new line at the bottom. The top
+	/// line goes into the scrollback, if there is one.
+	pub fn bump_screen(&mut self) {
 		let buffer = unsafe { &mut *Self::BUFFER };
+		if let Some(scrollback) = &mut self.scrollback {
+			scrollback.push(&buffer[..Self::NUM_COLUMNS]);
+		}
+
+		let len = self.len();
+		buffer.copy_within(Self::NUM_COLUMNS..len, 0);
+		self.fill(len - Self::NUM_COLUMNS..len);
+
+		self.idx = self.idx.saturating_sub(Self::NUM_COLUMNS);
+	}
 
-		for char in buffer {
+	/// Blanks the characters in `range` (indices into the screen), with the current colours.
+	fn fill(&mut self, range: core::ops::Range<usize>) {
+		let (buffer, len) = (unsafe { &mut *Self::BUFFER }, self.len());
+		for char in &mut buffer[range.start.min(len)..range.end.min(len)] {
 			char.letter = 0;
-			char.colour = 0;
+			char.colour = self.colour;
 		}
+	}
+
+	/// Moves the blinking hardware cursor to `idx`, where the next character will go.
+	pub fn update_cursor(&self) {
+		let [low, high] = (self.idx.min(self.len() - 1) as u16).to_le_bytes();
+		unsafe {
+			crtc_write(CRTC_CURSOR_LOCATION_LOW, low);
+			crtc_write(CRTC_CURSOR_LOCATION_HIGH, high);
+		}
+	}
+
+	/// Shows the hardware cursor, as an underline.
+	pub fn show_cursor(&mut self) {
+		unsafe {
+			// The cursor covers scanlines 14 to 15 of the character
+			crtc_write(CRTC_CURSOR_START, 14);
+			crtc_write(CRTC_CURSOR_END, 15);
+		}
+		self.update_cursor();
+	}
+
+	/// Hides the hardware cursor.
+	pub fn hide_cursor(&mut self) {
+		// Bit 5 of the cursor start register disables the cursor
+		unsafe { crtc_write(CRTC_CURSOR_START, 1 << 5) };
+	}
+
+	/// Clears the whole VGA buffer, filling it with the background colour. The scrollback is
+	/// kept.
+	pub fn clear(&mut self) {
+		self.scroll_to_bottom();
+		// The cursor is drawn in the cell's foreground colour, so the cells keep the printer's
+		// colours to keep it visible
+		self.fill(0..self.len());
 
 		self.idx = 0;
+		self.update_cursor();
+	}
+}
+impl Default for Printer {
+	fn default() -> Self {
+		Self::new()
 	}
 }
 impl Write for Printer {
 	fn write_str(&mut self, s: &str) -> core::fmt::Result {
-		s.bytes().for_each(|byte| self.write_byte(byte));
+		// VGA text mode uses CP437, not UTF-8
+		s.chars()
+			.for_each(|c| cp437::to_cp437(c, |byte| self.put_byte(byte)));
+		self.update_cursor();
 
 		Ok(())
 	}
 }
 
-#[repr(packed)]
+/// The VGA CRT controller's index port. Writing a register's index here selects it, then it's read
+/// or written through [`CRTC_DATA`].
+const CRTC_INDEX: u16 = 0x3D4;
+const CRTC_DATA: u16 = 0x3D5;
+// CRT controller registers, for the text mode's size and the hardware cursor
+const CRTC_HORIZONTAL_DISPLAY_END: u8 = 0x01;
+const CRTC_OVERFLOW: u8 = 0x07;
+const CRTC_MAXIMUM_SCAN_LINE: u8 = 0x09;
+const CRTC_CURSOR_START: u8 = 0x0A;
+const CRTC_CURSOR_END: u8 = 0x0B;
+const CRTC_CURSOR_LOCATION_HIGH: u8 = 0x0E;
+const CRTC_CURSOR_LOCATION_LOW: u8 = 0x0F;
+const CRTC_VERTICAL_DISPLAY_END: u8 = 0x12;
+
+/// Reads the CRT controller register `register`.
+///
+/// # Safety
+/// There has to be a VGA card, and nothing else can be using the CRT controller.
+unsafe fn crtc_read(register: u8) -> u8 {
+	unsafe {
+		portio::write_u8(CRTC_INDEX, register);
+		portio::read_u8(CRTC_DATA)
+	}
+}
+
+/// Writes `value` to the CRT controller register `register`.
+///
+/// # Safety
+/// `value` has to be valid for `register`.
+unsafe fn crtc_write(register: u8, value: u8) {
+	unsafe {
+		portio::write_u8(CRTC_INDEX, register);
+		portio::write_u8(CRTC_DATA, value);
+	}
+}
+
+/// Runs `f` with the global printer's colours set to `foreground` and `background`, then changes
+/// them back. Handy for making `println!`s stand out, like errors.
+pub fn with_colour<T>(foreground: VgaColor, background: VgaColor, f: impl FnOnce() -> T) -> T {
+	let old = {
+		let mut printer = Printer::get_global();
+		let old = printer.colour;
+		printer.set_colour(foreground, background);
+		old
+	};
+	let result = f();
+	Printer::get_global().colour = old;
+
+	result
+}
+
+/// The 16 colours of VGA text mode. Backgrounds can only use the first 8, since the top bit of the
+/// background is the blink bit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum VgaColor {
+	Black = 0,
+	Blue = 1,
+	Green = 2,
+	Cyan = 3,
+	Red = 4,
+	Magenta = 5,
+	Brown = 6,
+	LightGray = 7,
+	DarkGray = 8,
+	LightBlue = 9,
+	LightGreen = 10,
+	LightCyan = 11,
+	LightRed = 12,
+	Pink = 13,
+	Yellow = 14,
+	White = 15,
+}
+impl VgaColor {
+	/// Builds a VGA attribute byte: the foreground in the low 4 bits, the background in the next 3,
+	/// and blink in the top bit. Only the first 8 colours can be backgrounds; brighter ones are
+	/// dimmed to their first 8 version.
+	pub const fn attribute(foreground: Self, background: Self, blink: bool) -> u8 {
+		let mut attribute = foreground as u8 | (background as u8 & 0b111) << 4;
+		if blink {
+			attribute |= 1 << 7;
+		}
+
+		attribute
+	}
+
+	/// The colour with these 4 bits. The top 4 bits are ignored.
+	pub const fn from_bits(bits: u8) -> Self {
+		match bits & 0xF {
+			0 => Self::Black,
+			1 => Self::Blue,
+			2 => Self::Green,
+			3 => Self::Cyan,
+			4 => Self::Red,
+			5 => Self::Magenta,
+			6 => Self::Brown,
+			7 => Self::LightGray,
+			8 => Self::DarkGray,
+			9 => Self::LightBlue,
+			10 => Self::LightGreen,
+			11 => Self::LightCyan,
+			12 => Self::LightRed,
+			13 => Self::Pink,
+			14 => Self::Yellow,
+			_ => Self::White,
+		}
+	}
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
 pub struct VgaTextChar {
 	pub letter: u8,
 	pub colour: u8,
@@ -64,20 +450,16 @@ pub struct VgaTextChar {
 #[macro_export]
 macro_rules! print {
     () => {};
-    ($($arg:tt)*) => {{
-		use core::fmt::Write;
-
-        $crate::printing::Printer::get_global().write_fmt(format_args!($($arg)*)).unwrap();
-    }};
+    ($($arg:tt)*) => {
+        $crate::printing::_print(format_args!($($arg)*))
+    };
 }
 #[macro_export]
 macro_rules! println {
     () => {
-        $crate::printing::Printer::get_global().write_byte(b'\n')
+        $crate::printing::_print(format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::printing::_print(format_args!("{}\n", format_args!($($arg)*)))
     };
-    ($($arg:tt)*) => {{
-		use core::fmt::Write;
-
-        $crate::printing::Printer::get_global().write_fmt(format_args!("{}\n", format_args!($($arg)*))).unwrap();
-    }};
 }
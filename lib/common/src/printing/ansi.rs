@@ -0,0 +1,187 @@
+//! Support for a subset of ANSI escape sequences in the VGA console, so text written for a normal
+//! terminal (like the serial console) looks the same on both.
+//!
+//! Escape sequences start with ESC (`\x1b`) and `[` (together called the Control Sequence
+//! Introducer, or CSI), then some numeric parameters separated by `;`, then a letter saying what
+//! to do. The supported ones are:
+//! - `m` (SGR): Colours - 0 (reset), 1 (bright), 5 (blink), 22, 25, 30-37, 39, 40-47, 49, 90-97,
+//!   and 100-107
+//! - `H` and `f`: Move the cursor to a row and column (starting at 1)
+//! - `A`, `B`, `C`, `D`: Move the cursor up, down, right, or left
+//! - `J`: Clear the screen - after the cursor (0), before it (1), or all of it (2)
+//! - `K`: Clear the line - after the cursor (0), before it (1), or all of it (2)
+//!
+//! Anything else is ignored.
+//!
+//! Resources:
+//! - https://en.wikipedia.org/wiki/ANSI_escape_code
+//! - https://vt100.net/emu/dec_ansi_parser
+
+use super::*;
+
+/// The most parameters an escape sequence can have. Extra ones are ignored.
+const MAX_PARAMS: usize = 4;
+
+/// The colours ANSI colour codes map to, in order (black, red, green, yellow, blue, magenta, cyan,
+/// white).
+const COLOURS: [VgaColor; 8] = [
+	VgaColor::Black,
+	VgaColor::Red,
+	VgaColor::Green,
+	VgaColor::Brown,
+	VgaColor::Blue,
+	VgaColor::Magenta,
+	VgaColor::Cyan,
+	VgaColor::LightGray,
+];
+
+/// Where the parser is in an escape sequence.
+#[derive(Clone, Copy, Default)]
+pub(super) enum AnsiParser {
+	/// Not in an escape sequence.
+	#[default]
+	Ground,
+	/// Just got an ESC.
+	Escape,
+	/// In a control sequence, with these parameters so far.
+	Csi {
+		params: [u16; MAX_PARAMS],
+		count: usize,
+	},
+}
+
+/// What the printer should do with a byte, after the parser has seen it.
+pub(super) enum AnsiAction {
+	/// Print the byte normally.
+	Print(u8),
+	/// Nothing - the byte was part of an escape sequence.
+	None,
+	/// Run a control sequence, with its final letter and parameters.
+	Csi(u8, [u16; MAX_PARAMS], usize),
+}
+
+impl AnsiParser {
+	pub(super) const fn new() -> Self {
+		Self::Ground
+	}
+
+	/// Feeds the parser one byte.
+	pub(super) fn feed(&mut self, byte: u8) -> AnsiAction {
+		match (*self, byte) {
+			(Self::Ground, 0x1B) => *self = Self::Escape,
+			(Self::Ground, byte) => return AnsiAction::Print(byte),
+			(Self::Escape, b'[') => {
+				*self = Self::Csi {
+					params: [0; MAX_PARAMS],
+					count: 0,
+				}
+			}
+			// Not a control sequence, so it's not supported
+			(Self::Escape, _) => *self = Self::Ground,
+			(Self::Csi { mut params, count }, b'0'..=b'9') => {
+				// The first digit starts the first parameter
+				let count = count.max(1);
+				if let Some(param) = params.get_mut(count - 1) {
+					*param = param
+						.saturating_mul(10)
+						.saturating_add((byte - b'0') as u16);
+				}
+				*self = Self::Csi { params, count };
+			}
+			(Self::Csi { params, count }, b';') => {
+				// An empty first parameter still counts as one
+				*self = Self::Csi {
+					params,
+					count: count.max(1) + 1,
+				}
+			}
+			(Self::Csi { params, count }, 0x40..=0x7E) => {
+				*self = Self::Ground;
+				return AnsiAction::Csi(byte, params, count.min(MAX_PARAMS));
+			}
+			// Intermediate bytes and private parameters (like `?`) aren't supported, so the
+			// sequence is ignored once it ends
+			(Self::Csi { .. }, _) => {}
+		}
+
+		AnsiAction::None
+	}
+}
+
+impl Printer {
+	/// Runs the control sequence ending in `command`.
+	pub(super) fn run_csi(&mut self, command: u8, params: [u16; MAX_PARAMS], count: usize) {
+		let params = &params[..count];
+		// Most parameters default to 1 if they're missing or 0
+		let param = |idx: usize| {
+			params
+				.get(idx)
+				.copied()
+				.filter(|param| *param != 0)
+				.unwrap_or(1)
+		};
+		let (row, col) = (self.idx / Self::NUM_COLUMNS, self.idx % Self::NUM_COLUMNS);
+
+		match command {
+			b'm' => self.select_graphic_rendition(params),
+			b'H' | b'f' => {
+				let row = (param(0) as usize - 1).min(self.rows - 1);
+				let col = (param(1) as usize - 1).min(Self::NUM_COLUMNS - 1);
+				self.idx = row * Self::NUM_COLUMNS + col;
+			}
+			b'A' => self.idx -= row.min(param(0) as usize) * Self::NUM_COLUMNS,
+			b'B' => self.idx += (self.rows - 1 - row).min(param(0) as usize) * Self::NUM_COLUMNS,
+			b'C' => self.idx += (Self::NUM_COLUMNS - 1 - col).min(param(0) as usize),
+			b'D' => self.idx -= col.min(param(0) as usize),
+			b'J' => match params.first().copied().unwrap_or(0) {
+				0 => self.fill(self.idx..self.len()),
+				1 => self.fill(0..self.idx + 1),
+				_ => self.fill(0..self.len()),
+			},
+			b'K' => {
+				let line = row * Self::NUM_COLUMNS;
+				match params.first().copied().unwrap_or(0) {
+					0 => self.fill(self.idx..line + Self::NUM_COLUMNS),
+					1 => self.fill(line..self.idx + 1),
+					_ => self.fill(line..line + Self::NUM_COLUMNS),
+				}
+			}
+			_ => {}
+		}
+	}
+
+	/// Changes the colours, for the `m` control sequence.
+	fn select_graphic_rendition(&mut self, params: &[u16]) {
+		// No parameters means reset
+		if params.is_empty() {
+			self.colour = Self::DEFAULT_COLOUR;
+		}
+
+		for param in params {
+			let bright = self.colour & (1 << 3);
+			match *param {
+				0 => self.colour = Self::DEFAULT_COLOUR,
+				1 => self.colour |= 1 << 3,
+				22 => self.colour &= !(1 << 3),
+				5 => self.set_blink(true),
+				25 => self.set_blink(false),
+				code @ 30..=37 => self.set_foreground(COLOURS[code as usize - 30] as u8 | bright),
+				39 => self.set_foreground(Self::DEFAULT_COLOUR & 0xF),
+				code @ 90..=97 => self.set_foreground(COLOURS[code as usize - 90] as u8 | (1 << 3)),
+				code @ 40..=47 => self.set_background(COLOURS[code as usize - 40] as u8),
+				49 => self.set_background((Self::DEFAULT_COLOUR >> 4) & 0b111),
+				// There's no bright backgrounds (that bit is blink), so these are the normal ones
+				code @ 100..=107 => self.set_background(COLOURS[code as usize - 100] as u8),
+				_ => {}
+			}
+		}
+	}
+
+	fn set_foreground(&mut self, foreground: u8) {
+		self.colour = (self.colour & 0xF0) | foreground;
+	}
+
+	fn set_background(&mut self, background: u8) {
+		self.colour = (self.colour & 0x8F) | (background << 4);
+	}
+}
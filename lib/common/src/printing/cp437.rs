@@ -0,0 +1,54 @@
+//! Converts Unicode characters to code page 437, the character set VGA text mode uses.
+//!
+//! The first 128 characters of CP437 are (mostly) ASCII, so those pass straight through. The other
+//! 128 are accented letters, Greek letters, maths symbols, and box-drawing characters; those are
+//! found in [`HIGH_HALF`]. A few common characters that aren't in CP437 (like `…` and curly quotes)
+//! are spelled out in ASCII instead, and anything else becomes [`REPLACEMENT`].
+//!
+//! CP437's low 32 characters are also glyphs (smileys, card suits, arrows), but VGA only draws
+//! them if they're written to the buffer directly - when printing, they're control characters.
+//!
+//! Resources:
+//! - https://en.wikipedia.org/wiki/Code_page_437
+
+/// The character unmappable characters are printed as: a small square (`■`).
+pub const REPLACEMENT: u8 = 0xFE;
+
+/// The Unicode characters for CP437 0x80 to 0xFF, in order.
+const HIGH_HALF: [char; 128] = [
+	'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+	'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+	'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+	'░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+	'└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+	'╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+	'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+	'≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{A0}',
+];
+
+/// Characters that aren't in CP437, but have a close enough ASCII spelling.
+const FALLBACKS: [(char, &str); 9] = [
+	('…', "..."),
+	('‘', "'"),
+	('’', "'"),
+	('“', "\""),
+	('”', "\""),
+	('–', "-"),
+	('—', "--"),
+	('•', "*"),
+	('→', "->"),
+];
+
+/// Converts `c` to CP437, passing each resulting byte to `out`. Most characters are one byte, but a
+/// few are spelled out with multiple (see the module-level docs).
+pub fn to_cp437(c: char, mut out: impl FnMut(u8)) {
+	if c.is_ascii() {
+		out(c as u8);
+	} else if let Some(index) = HIGH_HALF.iter().position(|high| *high == c) {
+		out(0x80 + index as u8);
+	} else if let Some((_, ascii)) = FALLBACKS.iter().find(|(fallback, _)| *fallback == c) {
+		ascii.bytes().for_each(out);
+	} else {
+		out(REPLACEMENT);
+	}
+}
@@ -0,0 +1,275 @@
+//! A text console that draws into a pixel framebuffer, for when there's no VGA text mode - like
+//! under UEFI (GOP), or in a high-resolution VBE mode.
+//!
+//! The console draws each character with a bitmap font, where each glyph is `height` rows of bits
+//! with the leftmost pixel in the top bit. There's no font built in; the caller provides one,
+//! either from a PSF file ([`Font::from_psf`]) or the VGA BIOS' own font (int 0x10, AX=0x1130),
+//! which is the same format. Glyphs are expected in code page 437 order, like the VGA's own
+//! font, so text is converted to it (see [`cp437`]) before it's drawn.
+//!
+//! The console isn't a sink until one is set up with [`set_framebuffer_console`] and
+//! `register_sink(framebuffer_sink)`.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Drawing_In_a_Linear_Framebuffer
+//! - https://wiki.osdev.org/PC_Screen_Font
+
+use super::*;
+
+/// The framebuffer console `framebuffer_sink` prints to, if there is one.
+pub static GLOBAL_FRAMEBUFFER: Spinlock<Option<FramebufferConsole>> = Spinlock::new(None);
+
+/// Makes `console` the one [`framebuffer_sink`] prints to.
+pub fn set_framebuffer_console(console: FramebufferConsole) {
+	*GLOBAL_FRAMEBUFFER.lock() = Some(console);
+}
+
+/// Prints to the framebuffer console, through [`GLOBAL_FRAMEBUFFER`].
+pub fn framebuffer_sink(args: fmt::Arguments) {
+	if let Some(console) = GLOBAL_FRAMEBUFFER.lock().as_mut() {
+		let _ = console.write_fmt(args);
+	}
+}
+
+/// The order of the colour channels in each pixel.
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PixelFormat {
+	/// Red first, then green, then blue.
+	Rgb,
+	/// Blue first, then green, then red. Most framebuffers are this.
+	Bgr,
+}
+
+/// Where a linear framebuffer is, and how it's laid out.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FramebufferInfo {
+	/// The (virtual) address of the top-left pixel.
+	pub address: u64,
+	/// The width, in pixels.
+	pub width: usize,
+	/// The height, in pixels.
+	pub height: usize,
+	/// How many bytes each row of pixels takes up. Can be more than `width` times the pixel size.
+	pub pitch: usize,
+	/// How many bytes each pixel takes up. Only 3 and 4 are supported.
+	pub bytes_per_pixel: usize,
+	pub format: PixelFormat,
+}
+
+/// A bitmap font. See the module-level docs.
+#[derive(Clone, Copy, Debug)]
+pub struct Font {
+	/// Every glyph, one after another.
+	glyphs: &'static [u8],
+	/// The width of each glyph, in pixels.
+	width: usize,
+	/// The height of each glyph, in pixels.
+	height: usize,
+}
+impl Font {
+	/// A font whose glyphs are `width` by `height` pixels. Each row of a glyph is `width` rounded
+	/// up to a whole byte. Returns `None` if the glyphs are empty or don't fill `glyphs` evenly.
+	pub fn new(glyphs: &'static [u8], width: usize, height: usize) -> Option<Self> {
+		let this = Self {
+			glyphs,
+			width,
+			height,
+		};
+		let size = this.glyph_size();
+
+		(size != 0 && glyphs.len().is_multiple_of(size)).then_some(this)
+	}
+
+	/// Reads a font from a PSF (version 1 or 2) file. Unicode tables are ignored, so the glyphs
+	/// have to be in CP437 order.
+	pub fn from_psf(file: &'static [u8]) -> Option<Self> {
+		let u32_at = |offset: usize| {
+			Some(u32::from_le_bytes(file.get(offset..offset + 4)?.try_into().ok()?) as usize)
+		};
+
+		match file {
+			// PSF1: 256 or 512 glyphs, 8 pixels wide
+			[0x36, 0x04, mode, height, ..] => {
+				let count = if mode & 1 != 0 { 512 } else { 256 };
+				let glyphs = file.get(4..4 + count * *height as usize)?;
+				Self::new(glyphs, 8, *height as usize)
+			}
+			[0x72, 0xB5, 0x4A, 0x86, ..] => {
+				let (header_size, count, glyph_size) = (u32_at(8)?, u32_at(16)?, u32_at(20)?);
+				let (height, width) = (u32_at(24)?, u32_at(28)?);
+				let glyphs = file.get(header_size..header_size + count * glyph_size)?;
+				Self::new(glyphs, width, height)
+			}
+			_ => None,
+		}
+	}
+
+	/// How many bytes each glyph takes up.
+	fn glyph_size(&self) -> usize {
+		self.width.div_ceil(8) * self.height
+	}
+
+	/// The glyph for `byte`, or the first glyph if the font doesn't have that many.
+	fn glyph(&self, byte: u8) -> &'static [u8] {
+		let size = self.glyph_size();
+		self.glyphs
+			.get(byte as usize * size..)
+			.unwrap_or(self.glyphs)
+			.get(..size)
+			.unwrap_or_default()
+	}
+}
+
+/// A text console in a framebuffer. See the module-level docs.
+pub struct FramebufferConsole {
+	info: FramebufferInfo,
+	font: Font,
+	/// The column the next character goes in.
+	col: usize,
+	/// The row the next character goes in.
+	row: usize,
+	/// The text colour, as `0xRRGGBB`.
+	foreground: u32,
+	/// The background colour, as `0xRRGGBB`.
+	background: u32,
+}
+impl FramebufferConsole {
+	/// Creates a console that draws into the framebuffer described by `info` with `font`, and
+	/// clears the framebuffer. Returns `None` if the pixel size isn't supported, or the framebuffer
+	/// can't fit a single character.
+	///
+	/// # Safety
+	/// The framebuffer has to be mapped (and writable) at `info.address`, and nothing else can be
+	/// drawing to it.
+	pub unsafe fn new(info: FramebufferInfo, font: Font) -> Option<Self> {
+		if !matches!(info.bytes_per_pixel, 3 | 4)
+			|| info.width < font.width
+			|| info.height < font.height
+		{
+			return None;
+		}
+
+		let mut this = Self {
+			info,
+			font,
+			col: 0,
+			row: 0,
+			foreground: 0xFFFFFF,
+			background: 0x000000,
+		};
+		this.clear();
+
+		Some(this)
+	}
+
+	/// How many columns of text fit.
+	pub fn columns(&self) -> usize {
+		self.info.width / self.font.width
+	}
+
+	/// How many rows of text fit.
+	pub fn rows(&self) -> usize {
+		self.info.height / self.font.height
+	}
+
+	/// Sets the text and background colours, as `0xRRGGBB`.
+	pub fn set_colours(&mut self, foreground: u32, background: u32) {
+		self.foreground = foreground;
+		self.background = background;
+	}
+
+	/// Clears the framebuffer to the background colour, and moves back to the top-left.
+	pub fn clear(&mut self) {
+		for y in 0..self.info.height {
+			self.fill_row(y, self.background);
+		}
+		self.col = 0;
+		self.row = 0;
+	}
+
+	/// Prints one CP437 byte (see [`cp437`]). Bytes are drawn with the font's glyph at that index.
+	pub fn write_byte(&mut self, byte: u8) {
+		match byte {
+			b'\n' => self.new_line(),
+			b'\r' => self.col = 0,
+			byte => {
+				if self.col >= self.columns() {
+					self.new_line();
+				}
+				self.draw_glyph(byte);
+				self.col += 1;
+			}
+		}
+	}
+
+	fn new_line(&mut self) {
+		self.col = 0;
+		if self.row + 1 < self.rows() {
+			self.row += 1;
+		} else {
+			self.scroll();
+		}
+	}
+
+	/// Moves everything up one row of text, and clears the bottom row.
+	fn scroll(&mut self) {
+		let line = self.info.pitch * self.font.height;
+		let used = line * self.rows();
+		unsafe {
+			let base = self.info.address as usize as *mut u8;
+			core::ptr::copy(base.add(line), base, used - line);
+		}
+
+		let bottom = (self.rows() - 1) * self.font.height;
+		for y in bottom..bottom + self.font.height {
+			self.fill_row(y, self.background);
+		}
+	}
+
+	fn draw_glyph(&mut self, byte: u8) {
+		let (x, y) = (self.col * self.font.width, self.row * self.font.height);
+		let bytes_per_row = self.font.width.div_ceil(8);
+		let glyph = self.font.glyph(byte);
+
+		for (row, bits) in glyph.chunks(bytes_per_row).enumerate() {
+			for col in 0..self.font.width {
+				let set = bits[col / 8] & (0x80 >> (col % 8)) != 0;
+				let colour = if set {
+					self.foreground
+				} else {
+					self.background
+				};
+				self.put_pixel(x + col, y + row, colour);
+			}
+		}
+	}
+
+	fn fill_row(&mut self, y: usize, colour: u32) {
+		for x in 0..self.info.width {
+			self.put_pixel(x, y, colour);
+		}
+	}
+
+	fn put_pixel(&mut self, x: usize, y: usize, colour: u32) {
+		let [r, g, b] = [(colour >> 16) as u8, (colour >> 8) as u8, colour as u8];
+		let bytes = match self.info.format {
+			PixelFormat::Rgb => [r, g, b],
+			PixelFormat::Bgr => [b, g, r],
+		};
+
+		let offset = y * self.info.pitch + x * self.info.bytes_per_pixel;
+		let pixel = (self.info.address as usize + offset) as *mut u8;
+		for (idx, byte) in bytes.into_iter().enumerate() {
+			unsafe { pixel.add(idx).write_volatile(byte) };
+		}
+	}
+}
+impl Write for FramebufferConsole {
+	fn write_str(&mut self, s: &str) -> fmt::Result {
+		// Fonts are laid out in CP437 order, like the VGA's, not UTF-8
+		s.chars()
+			.for_each(|c| cp437::to_cp437(c, |byte| self.write_byte(byte)));
+		Ok(())
+	}
+}
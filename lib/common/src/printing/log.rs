@@ -0,0 +1,110 @@
+//! Levelled logging, with the `log_error!`, `log_warn!`, `log_info!`, and `log_debug!` macros.
+//!
+//! Each message is printed (with `print!`, so it goes to every [`sink`]) with its level
+//! in front, and the level is coloured on the VGA console. Messages below the level set with
+//! [`set_level`] are skipped, so debug messages can be left in without drowning everything else.
+//! Once there's a clock, [`set_clock`] adds a timestamp to each message.
+
+use {super::*, crate::sync::Spinlock};
+
+/// How important a log message is. Later levels are less important.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[repr(u8)]
+pub enum Level {
+	Error,
+	Warn,
+	Info,
+	Debug,
+}
+impl Level {
+	/// The level's name, as it's printed in front of messages.
+	pub const fn name(self) -> &'static str {
+		match self {
+			Self::Error => "ERROR",
+			Self::Warn => "WARN",
+			Self::Info => "INFO",
+			Self::Debug => "DEBUG",
+		}
+	}
+
+	/// The colour the level's name is printed in on the VGA console.
+	pub const fn colour(self) -> VgaColor {
+		match self {
+			Self::Error => VgaColor::LightRed,
+			Self::Warn => VgaColor::Yellow,
+			Self::Info => VgaColor::LightGreen,
+			Self::Debug => VgaColor::LightGray,
+		}
+	}
+}
+
+/// The least important level that's printed.
+static LEVEL: Spinlock<Level> = Spinlock::new(Level::Info);
+/// Gets the current time in milliseconds, for timestamps.
+static CLOCK: Spinlock<Option<fn() -> u64>> = Spinlock::new(None);
+
+/// Only prints messages at `level` or more important.
+pub fn set_level(level: Level) {
+	*LEVEL.lock() = level;
+}
+
+/// The least important level that's printed.
+pub fn level() -> Level {
+	*LEVEL.lock()
+}
+
+/// Adds a timestamp to each message, from `clock`, which returns the time in milliseconds (like
+/// [`crate::interrupts::pit::uptime_ms`]).
+pub fn set_clock(clock: fn() -> u64) {
+	*CLOCK.lock() = Some(clock);
+}
+
+/// Prints a log message. Used by the logging macros.
+#[doc(hidden)]
+pub fn _log(level: Level, args: fmt::Arguments) {
+	if level > self::level() {
+		return;
+	}
+
+	// Copy the clock out, so the lock isn't held while it runs
+	let clock = *CLOCK.lock();
+	if let Some(clock) = clock {
+		let ms = clock();
+		crate::print!("[{:>5}.{:03}] ", ms / 1000, ms % 1000);
+	}
+	with_colour(level.colour(), VgaColor::Black, || {
+		crate::print!("{:<5}", level.name())
+	});
+	crate::println!(" {args}");
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => {
+        $crate::printing::log::_log($level, format_args!($($arg)*))
+    };
+}
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        $crate::log!($crate::printing::log::Level::Error, $($arg)*)
+    };
+}
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        $crate::log!($crate::printing::log::Level::Warn, $($arg)*)
+    };
+}
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        $crate::log!($crate::printing::log::Level::Info, $($arg)*)
+    };
+}
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        $crate::log!($crate::printing::log::Level::Debug, $($arg)*)
+    };
+}
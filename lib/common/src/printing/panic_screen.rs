@@ -0,0 +1,165 @@
+//! A panic screen, for every stage's panic handler to use.
+//!
+//! [`panic_screen`] clears the VGA console to white-on-red (so a panic can't be mistaken for normal
+//! output), then prints the panic message, where it happened, a snapshot of the registers, and a
+//! stack trace. Everything also goes to the other sinks (like serial).
+//!
+//! The stack trace follows the chain of saved frame pointers, so it only works when the code was
+//! compiled with frame pointers (`-C force-frame-pointers=yes`). Without them, it stops as soon as
+//! it finds something that doesn't look like a frame.
+
+use {super::*, core::panic::PanicInfo};
+
+/// The most stack frames the stack trace shows.
+const MAX_FRAMES: usize = 16;
+
+/// The registers [`panic_screen`] shows. They're read inside the panic handler, so the general
+/// purpose registers aren't very useful - but the control registers and stack are.
+#[derive(Clone, Copy, Debug)]
+pub struct Registers {
+	pub stack_pointer: usize,
+	pub frame_pointer: usize,
+	pub flags: usize,
+	pub cr0: usize,
+	/// The last page fault's address.
+	pub cr2: usize,
+	/// The page tables.
+	pub cr3: usize,
+	pub cr4: usize,
+}
+impl Registers {
+	/// Reads the registers.
+	#[inline(always)]
+	pub fn read() -> Self {
+		let (stack_pointer, frame_pointer, flags, cr0, cr2, cr3, cr4): (
+			usize,
+			usize,
+			usize,
+			usize,
+			usize,
+			usize,
+			usize,
+		);
+		unsafe {
+			#[cfg(target_arch = "x86_64")]
+			asm!("mov {}, rsp", "mov {}, rbp", out(reg) stack_pointer, out(reg) frame_pointer);
+			#[cfg(target_arch = "x86")]
+			asm!("mov {}, esp", "mov {}, ebp", out(reg) stack_pointer, out(reg) frame_pointer);
+
+			asm!("pushf", "pop {}", out(reg) flags);
+			asm!(
+				"mov {}, cr0",
+				"mov {}, cr2",
+				"mov {}, cr3",
+				"mov {}, cr4",
+				out(reg) cr0,
+				out(reg) cr2,
+				out(reg) cr3,
+				out(reg) cr4,
+				options(nomem, nostack, preserves_flags)
+			);
+		}
+
+		Self {
+			stack_pointer,
+			frame_pointer,
+			flags,
+			cr0,
+			cr2,
+			cr3,
+			cr4,
+		}
+	}
+}
+impl fmt::Display for Registers {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let width = size_of::<usize>() * 2 + 2;
+		writeln!(
+			f,
+			"SP: {:#0width$x}  FP: {:#0width$x}  FLAGS: {:#0width$x}",
+			self.stack_pointer, self.frame_pointer, self.flags
+		)?;
+		write!(
+			f,
+			"CR0: {:#0width$x}  CR2: {:#0width$x}  CR3: {:#0width$x}  CR4: {:#0width$x}",
+			self.cr0, self.cr2, self.cr3, self.cr4
+		)
+	}
+}
+
+/// Walks the saved frame pointers starting at `frame_pointer`, calling `f` with each return
+/// address. Stops at a null or misaligned frame pointer, or one that goes down the stack instead
+/// of up it.
+///
+/// # Safety
+/// Every frame pointer in the chain has to be readable, which is only guaranteed if everything
+/// on the stack was compiled with frame pointers.
+pub unsafe fn walk_stack(mut frame_pointer: usize, mut f: impl FnMut(usize)) {
+	for _ in 0..MAX_FRAMES {
+		if frame_pointer == 0 || !frame_pointer.is_multiple_of(size_of::<usize>()) {
+			return;
+		}
+
+		// Each frame starts with the caller's frame pointer, then the return address
+		let frame = frame_pointer as *const usize;
+		let (next, return_address) = unsafe { (*frame, *frame.add(1)) };
+		if return_address == 0 {
+			return;
+		}
+		f(return_address);
+
+		if next <= frame_pointer {
+			return;
+		}
+		frame_pointer = next;
+	}
+}
+
+/// Shows the panic screen (see the module-level docs), then halts forever. `stage` is the name of
+/// what panicked, like "bootloader".
+pub fn panic_screen(stage: &str, info: &PanicInfo) -> ! {
+	crate::interrupts::disable();
+	let registers = Registers::read();
+	unsafe { force_unlock() };
+
+	{
+		let mut printer = Printer::get_global();
+		printer.set_colour(VgaColor::White, VgaColor::Red);
+		printer.set_blink(false);
+		printer.clear();
+	}
+
+	crate::println!("{} PANIC\n", Uppercase(stage));
+	crate::println!("{}", info.message());
+	if let Some(location) = info.location() {
+		crate::println!("at {location}");
+	}
+
+	crate::println!("\nRegisters:\n{registers}");
+
+	crate::println!("\nStack trace:");
+	let mut frames = 0;
+	unsafe {
+		walk_stack(registers.frame_pointer, |address| {
+			crate::println!("{frames:>3}: {address:#x}");
+			frames += 1;
+		})
+	};
+	if frames == 0 {
+		crate::println!("  (not available)");
+	}
+
+	loop {
+		unsafe { asm!("cli", "hlt") };
+	}
+}
+
+/// Prints a string in uppercase.
+struct Uppercase<'a>(&'a str);
+impl fmt::Display for Uppercase<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.0
+			.chars()
+			.try_for_each(|char| f.write_char(char.to_ascii_uppercase()))
+	}
+}
@@ -0,0 +1,138 @@
+//! Keeps the lines that scroll off the top of the VGA console, so they can be scrolled back into
+//! view.
+//!
+//! There's no heap when the printer starts, so like the frame allocator's bitmap, the memory for
+//! the scrollback is given to the printer with [`Printer::set_scrollback`]. Its last
+//! [`Printer::MAX_ROWS`] lines hold a copy of the screen while it's scrolled back, and the rest is
+//! a ring buffer of old lines. Size it with [`Printer::scrollback_len`].
+
+use super::*;
+
+/// One line of the VGA console.
+pub type TextLine = [VgaTextChar; Printer::NUM_COLUMNS];
+
+/// The printer's scrollback. See the module-level docs.
+pub struct Scrollback {
+	/// The ring buffer of old lines, followed by the saved screen.
+	lines: &'static mut [TextLine],
+	/// The oldest line in the ring buffer.
+	start: usize,
+	/// How many lines are in the ring buffer.
+	len: usize,
+	/// How many lines back the screen is scrolled. 0 means it's showing the live screen.
+	view: usize,
+}
+impl Scrollback {
+	/// How many old lines the ring buffer can hold.
+	fn capacity(&self) -> usize {
+		self.lines.len() - Printer::MAX_ROWS
+	}
+
+	/// Adds a line that scrolled off the top of the screen, replacing the oldest line if the ring
+	/// buffer is full.
+	pub(super) fn push(&mut self, line: &[VgaTextChar]) {
+		let capacity = self.capacity();
+		if capacity == 0 {
+			return;
+		}
+
+		let idx = (self.start + self.len) % capacity;
+		self.lines[idx].copy_from_slice(line);
+		if self.len == capacity {
+			self.start = (self.start + 1) % capacity;
+		} else {
+			self.len += 1;
+		}
+	}
+
+	/// The `idx`th oldest line in the ring buffer.
+	fn line(&self, idx: usize) -> &TextLine {
+		&self.lines[(self.start + idx) % self.capacity()]
+	}
+}
+
+impl Printer {
+	/// How many lines of memory [`Printer::set_scrollback`] needs to keep `history` old lines.
+	pub const fn scrollback_len(history: usize) -> usize {
+		history + Self::MAX_ROWS
+	}
+
+	/// Starts keeping lines that scroll off the top of the screen in `lines`, which should be
+	/// [`Printer::scrollback_len`] lines long. Any old scrollback is dropped. Does nothing if
+	/// `lines` is too short to hold any history.
+	pub fn set_scrollback(&mut self, lines: &'static mut [TextLine]) {
+		self.scroll_to_bottom();
+		if lines.len() <= Self::MAX_ROWS {
+			return;
+		}
+
+		self.scrollback = Some(Scrollback {
+			lines,
+			start: 0,
+			len: 0,
+			view: 0,
+		});
+	}
+
+	/// How many old lines are in the scrollback.
+	pub fn history(&self) -> usize {
+		self.scrollback
+			.as_ref()
+			.map_or(0, |scrollback| scrollback.len)
+	}
+
+	/// Scrolls the screen `lines` lines back, up to the oldest line in the scrollback.
+	pub fn scroll_up(&mut self, lines: usize) {
+		let Some(scrollback) = &mut self.scrollback else {
+			return;
+		};
+		if scrollback.view == 0 {
+			// Save the live screen, so it can be put back
+			let capacity = scrollback.capacity();
+			let screen = unsafe { &*Self::BUFFER };
+			let rows = scrollback.lines[capacity..].iter_mut().take(self.rows);
+			for (row, line) in rows.enumerate() {
+				line.copy_from_slice(&screen[row * Self::NUM_COLUMNS..][..Self::NUM_COLUMNS]);
+			}
+		}
+
+		scrollback.view = (scrollback.view + lines).min(scrollback.len);
+		self.render_scrollback();
+	}
+
+	/// Scrolls the screen `lines` lines forward, back towards the live screen.
+	pub fn scroll_down(&mut self, lines: usize) {
+		let Some(scrollback) = &mut self.scrollback else {
+			return;
+		};
+		if scrollback.view == 0 {
+			return;
+		}
+
+		scrollback.view = scrollback.view.saturating_sub(lines);
+		self.render_scrollback();
+	}
+
+	/// Scrolls all the way forward, to the live screen. Printing does this automatically.
+	pub fn scroll_to_bottom(&mut self) {
+		self.scroll_down(usize::MAX);
+	}
+
+	/// Draws the scrolled-back screen: the last `view` old lines, then the top of the saved
+	/// screen.
+	fn render_scrollback(&mut self) {
+		let Some(scrollback) = &self.scrollback else {
+			return;
+		};
+		let screen = unsafe { &mut *Self::BUFFER };
+		let saved = &scrollback.lines[scrollback.capacity()..];
+
+		for row in 0..self.rows {
+			let line = match row.checked_sub(scrollback.view) {
+				Some(saved_row) => &saved[saved_row],
+				None => scrollback.line(scrollback.len - scrollback.view + row),
+			};
+			screen[row * Self::NUM_COLUMNS..][..Self::NUM_COLUMNS].copy_from_slice(line);
+		}
+	}
+}
@@ -0,0 +1,132 @@
+//! A driver for the 16550 UART, the serial port every PC (and QEMU) has.
+//!
+//! Serial output is much easier to work with than the VGA console: QEMU can send it straight to a
+//! terminal or a file (with `-serial stdio`), so it can be scrolled, searched, and captured in CI.
+//!
+//! The UART is controlled through 8 I/O ports starting at its base port. COM1 is almost always at
+//! 0x3F8. Before it's used, it needs a baud rate, which is set by dividing its 115200 Hz clock.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Serial_Ports
+//! - https://www.lammertbies.nl/comm/info/serial-uart
+
+use super::*;
+
+/// The base port of the first serial port.
+pub const COM1: u16 = 0x3F8;
+/// The UART's clock, which is divided to get the baud rate.
+const CLOCK: u32 = 115_200;
+
+/// The offsets of the UART's registers from its base port.
+mod register {
+	/// The byte to send or the byte that was received. The low byte of the baud rate divisor when
+	/// the divisor latch is set.
+	pub const DATA: u16 = 0;
+	/// Which interrupts are enabled. The high byte of the baud rate divisor when the divisor latch
+	/// is set.
+	pub const INTERRUPT_ENABLE: u16 = 1;
+	pub const FIFO_CONTROL: u16 = 2;
+	/// The data format, and the divisor latch (bit 7).
+	pub const LINE_CONTROL: u16 = 3;
+	pub const MODEM_CONTROL: u16 = 4;
+	pub const LINE_STATUS: u16 = 5;
+}
+
+/// The serial port `print!` and `println!` write to, once [`serial_sink`] is registered.
+pub static GLOBAL_SERIAL: Spinlock<SerialPort> = Spinlock::new(unsafe { SerialPort::new(COM1) });
+
+/// A 16550 UART. See the module-level docs.
+pub struct SerialPort {
+	/// The first I/O port of the UART.
+	base: u16,
+}
+impl SerialPort {
+	/// Creates a driver for the UART at `base`. It has to be set up with [`SerialPort::init`]
+	/// before it's used.
+	///
+	/// # Safety
+	/// There has to be a UART at `base`, and nothing else can be using it.
+	pub const unsafe fn new(base: u16) -> Self {
+		Self { base }
+	}
+
+	/// Locks the global serial port. See [`Printer::get_global`].
+	pub fn get_global() -> SpinlockGuard<'static, Self> {
+		GLOBAL_SERIAL.lock()
+	}
+
+	/// Sets up the UART to send and receive 8 bits per character, no parity, and 1 stop bit, at
+	/// about `baud` bits per second. Returns `false` if the UART doesn't seem to exist (it failed a
+	/// loopback test).
+	pub fn init(&mut self, baud: u32) -> bool {
+		let divisor = (CLOCK / baud.clamp(1, CLOCK)) as u16;
+		let [low, high] = divisor.to_le_bytes();
+
+		unsafe {
+			// No interrupts; output is polled
+			self.write(register::INTERRUPT_ENABLE, 0);
+
+			// Set the divisor latch, so the first 2 registers set the divisor
+			self.write(register::LINE_CONTROL, 1 << 7);
+			self.write(register::DATA, low);
+			self.write(register::INTERRUPT_ENABLE, high);
+			// 8 bits, no parity, 1 stop bit, and clear the divisor latch
+			self.write(register::LINE_CONTROL, 0b011);
+
+			// Enable and clear the FIFOs, with a 14-byte threshold
+			self.write(register::FIFO_CONTROL, 0xC7);
+
+			// Loopback mode: whatever's sent gets received, so we can check the UART works
+			self.write(register::MODEM_CONTROL, 0x1E);
+			self.write(register::DATA, 0xAE);
+			if self.read(register::DATA) != 0xAE {
+				return false;
+			}
+
+			// Normal mode, with data terminal ready, request to send, and OUT1/OUT2 set
+			self.write(register::MODEM_CONTROL, 0x0F);
+		}
+
+		true
+	}
+
+	/// Sends one byte, waiting for the UART to be ready for it first.
+	pub fn write_byte(&mut self, byte: u8) {
+		unsafe {
+			// Bit 5 says the transmitter holding register is empty
+			while self.read(register::LINE_STATUS) & (1 << 5) == 0 {
+				core::hint::spin_loop();
+			}
+			self.write(register::DATA, byte);
+		}
+	}
+
+	/// Gets one byte that's been received, if there is one.
+	pub fn read_byte(&mut self) -> Option<u8> {
+		unsafe {
+			// Bit 0 says there's data ready
+			(self.read(register::LINE_STATUS) & 1 != 0).then(|| self.read(register::DATA))
+		}
+	}
+
+	unsafe fn read(&self, register: u16) -> u8 {
+		unsafe { portio::read_u8(self.base + register) }
+	}
+
+	unsafe fn write(&mut self, register: u16, value: u8) {
+		unsafe { portio::write_u8(self.base + register, value) };
+	}
+}
+impl Write for SerialPort {
+	fn write_str(&mut self, s: &str) -> core::fmt::Result {
+		for byte in s.bytes() {
+			// Terminals expect a carriage return before each newline
+			if byte == b'\n' {
+				self.write_byte(b'\r');
+			}
+			self.write_byte(byte);
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,67 @@
+//! Output sinks: everywhere `print!` and `println!` write to.
+//!
+//! A sink is just a function that gets the printed text. Every registered sink gets every print,
+//! so output can go to the VGA console, the serial port, and anything else (like an in-memory log)
+//! at once. Only [`vga_sink`] is registered at first; add the serial port with
+//! `register_sink(serial_sink)` once it's set up (see [`SerialPort::init`]), or a framebuffer with
+//! [`framebuffer_sink`].
+
+use super::*;
+
+/// A function that gets printed text. It gets everything from one `print!` at once, so it can
+/// keep its output locked while writing it, and other prints can't end up in the middle of it.
+pub type Sink = fn(fmt::Arguments);
+
+/// How many sinks can be registered at once.
+pub const MAX_SINKS: usize = 8;
+
+/// A registered sink, from [`register_sink`]. Pass it to [`unregister_sink`] to remove the sink.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SinkId(usize);
+
+/// Every registered sink.
+pub(super) static SINKS: Spinlock<[Option<Sink>; MAX_SINKS]> = {
+	let mut sinks: [Option<Sink>; MAX_SINKS] = [None; MAX_SINKS];
+	sinks[0] = Some(vga_sink);
+	Spinlock::new(sinks)
+};
+
+/// The ID of [`vga_sink`], which is registered by default.
+pub const VGA_SINK: SinkId = SinkId(0);
+
+/// Makes `sink` get everything that's printed from now on. Returns `None` if [`MAX_SINKS`] sinks
+/// are already registered.
+pub fn register_sink(sink: Sink) -> Option<SinkId> {
+	let mut sinks = SINKS.lock();
+	let (idx, slot) = sinks
+		.iter_mut()
+		.enumerate()
+		.find(|(_, slot)| slot.is_none())?;
+	*slot = Some(sink);
+
+	Some(SinkId(idx))
+}
+
+/// Stops a sink from getting anything else that's printed, returning it.
+pub fn unregister_sink(id: SinkId) -> Option<Sink> {
+	SINKS.lock()[id.0].take()
+}
+
+/// Prints to the VGA console, through [`GLOBAL_PRINTER`].
+pub fn vga_sink(args: fmt::Arguments) {
+	let _ = Printer::get_global().write_fmt(args);
+}
+
+/// Prints to the serial port, through [`GLOBAL_SERIAL`].
+pub fn serial_sink(args: fmt::Arguments) {
+	let _ = SerialPort::get_global().write_fmt(args);
+}
+
+/// Prints to every registered sink. Used by the `print!` and `println!` macros.
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+	// Copy the sinks, so they aren't locked while printing (a sink might print, or the print could
+	// be interrupted by one that does)
+	let sinks = *SINKS.lock();
+	sinks.iter().flatten().for_each(|sink| sink(args));
+}
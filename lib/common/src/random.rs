@@ -0,0 +1,72 @@
+//! Random numbers from the CPU, for things like KASLR that need a little unpredictability early
+//! in boot.
+//!
+//! There's two sources:
+//! - `rdrand`, which reads from a hardware random number generator in the CPU. It's actually
+//!   random, but not every CPU has it (see [`Feature::Rdrand`]), and it can fail if the generator
+//!   runs out of entropy - Intel recommends retrying it 10 times before giving up.
+//! - `rdtsc`, which reads the timestamp counter (how many cycles the CPU has run). Every x86_64 CPU
+//!   has it, but it's only a little unpredictable: the low bits depend on exactly how long booting
+//!   took, but the high bits are the same every boot.
+//!
+//! [`entropy`] mixes both, so it works everywhere, and is actually random where it can be. None of
+//! this is cryptographically secure.
+//!
+//! Resources:
+//! - https://www.intel.com/content/www/us/en/developer/articles/guide/intel-digital-random-number-generator-drng-software-implementation-guide.html
+//! - https://wiki.osdev.org/Random_Number_Generator
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::_rdtsc;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::_rdtsc;
+use {crate::cpuid::Feature, core::arch::asm};
+
+/// How many times [`rdrand`] retries before giving up.
+const RDRAND_RETRIES: usize = 10;
+
+/// Gets a random number with `rdrand`. Returns `None` if the CPU doesn't have it, or it failed
+/// [`RDRAND_RETRIES`] times in a row.
+pub fn rdrand() -> Option<u32> {
+	if !Feature::Rdrand.supported() {
+		return None;
+	}
+
+	(0..RDRAND_RETRIES).find_map(|_| {
+		let (value, success): (u32, u8);
+		unsafe {
+			asm!(
+				"rdrand {value:e}",
+				"setc {success}",
+				value = out(reg) value,
+				success = out(reg_byte) success,
+				options(nomem, nostack),
+			)
+		}
+		(success != 0).then_some(value)
+	})
+}
+
+/// Reads the timestamp counter with `rdtsc`.
+pub fn rdtsc() -> u64 {
+	unsafe { _rdtsc() }
+}
+
+/// Gets 64 random bits, from [`rdrand`] if it works, mixed with [`rdtsc`]. See the module-level
+/// docs.
+pub fn entropy() -> u64 {
+	let mut value = rdtsc();
+	if let (Some(high), Some(low)) = (rdrand(), rdrand()) {
+		value ^= ((high as u64) << 32) | low as u64;
+	}
+
+	mix(value)
+}
+
+/// The splitmix64 finalizer, which spreads every input bit across the output - so the timestamp
+/// counter's unpredictable low bits affect all of the result.
+fn mix(value: u64) -> u64 {
+	let value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+	let value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+	value ^ (value >> 31)
+}
@@ -0,0 +1,171 @@
+//! Reads the date and time from the real-time clock (RTC), which lives in the CMOS.
+//!
+//! The CMOS is a tiny bit of battery-backed memory, read by writing a register number to port 0x70
+//! and then reading port 0x71. The RTC keeps the time in a few of those registers - but there's a
+//! few quirks:
+//! - The RTC updates its registers once a second, and reading them mid-update can give a mix of
+//!   the old and new time. So [`read`] waits for the update-in-progress flag to clear, and reads
+//!   until it gets the same time twice in a row.
+//! - The values can be in binary or BCD, and the hour can be 12- or 24-hour, depending on status
+//!   register B.
+//! - There's no standard century register. ACPI's FADT says where it is, if there is one (see
+//!   [`acpi::fadt::Fadt::century`]); otherwise, [`read`] assumes it's the 2000s.
+//!
+//! The RTC doesn't know what timezone it's in; it's usually UTC, but some computers (mostly ones
+//! that dual-boot Windows) keep local time in it.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/CMOS
+//! - https://wiki.osdev.org/RTC
+
+use core::fmt::{self, Display, Formatter};
+
+/// The CMOS's register index port. Bit 7 of the index disables NMIs, so it's always left clear.
+const CMOS_INDEX: u16 = 0x70;
+/// The CMOS's data port, which reads or writes the register selected with [`CMOS_INDEX`].
+const CMOS_DATA: u16 = 0x71;
+
+const SECONDS: u8 = 0x00;
+const MINUTES: u8 = 0x02;
+const HOURS: u8 = 0x04;
+const DAY: u8 = 0x07;
+const MONTH: u8 = 0x08;
+const YEAR: u8 = 0x09;
+/// Status register A. Bit 7 is set while the RTC is updating its registers.
+const STATUS_A: u8 = 0x0A;
+/// Status register B. Bit 1 is set if the hour is 24-hour, and bit 2 is set if the values are
+/// binary instead of BCD.
+const STATUS_B: u8 = 0x0B;
+
+const UPDATE_IN_PROGRESS: u8 = 1 << 7;
+const HOUR_24: u8 = 1 << 1;
+const BINARY: u8 = 1 << 2;
+/// In 12-hour mode, this bit of the hour is set for PM.
+const PM: u8 = 1 << 7;
+
+/// A date and time from the RTC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+	/// The full year, eg 2024.
+	pub year: u16,
+	/// The month, from 1 to 12.
+	pub month: u8,
+	/// The day of the month, from 1 to 31.
+	pub day: u8,
+	/// The hour, from 0 to 23.
+	pub hour: u8,
+	/// The minute, from 0 to 59.
+	pub minute: u8,
+	/// The second, from 0 to 59.
+	pub second: u8,
+}
+impl DateTime {
+	/// Seconds since the Unix epoch (1970-01-01 00:00:00), assuming the RTC is in UTC.
+	pub fn unix_timestamp(&self) -> u64 {
+		// Days since the epoch, from Howard Hinnant's `days_from_civil`. The year starts in March
+		// here, so the leap day is at the end of it.
+		let year = self.year as i64 - (self.month <= 2) as i64;
+		let era = year.div_euclid(400);
+		let year_of_era = year - era * 400;
+		let month = (self.month as i64 + 9) % 12;
+		let day_of_year = (153 * month + 2) / 5 + self.day as i64 - 1;
+		let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+		let days = era * 146_097 + day_of_era - 719_468;
+
+		let seconds =
+			days * 86_400 + self.hour as i64 * 3_600 + self.minute as i64 * 60 + self.second as i64;
+		seconds.max(0) as u64
+	}
+}
+impl Display for DateTime {
+	/// Formats the date and time like `2024-07-15 13:05:09`.
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+			self.year, self.month, self.day, self.hour, self.minute, self.second
+		)
+	}
+}
+
+/// Reads the date and time from the RTC. `century` is the CMOS index of the century register, from
+/// [`acpi::fadt::Fadt::century`]; if it's `None`, the year is assumed to be in the 2000s.
+///
+/// This uses the CMOS ports, so it shouldn't run while something else (like an interrupt handler)
+/// is using them.
+pub fn read(century: Option<u8>) -> DateTime {
+	// Keep reading until two reads match, so an update can't land in the middle of one
+	let mut last = read_raw(century);
+	loop {
+		let current = read_raw(century);
+		if current == last {
+			break;
+		}
+		last = current;
+	}
+	let [second, minute, hour, day, month, year, century] = last;
+
+	let status_b = unsafe { read_register(STATUS_B) };
+	let decode = |value: u8| match status_b & BINARY {
+		0 => bcd_to_binary(value),
+		_ => value,
+	};
+
+	let pm = hour & PM != 0;
+	let mut hour = decode(hour & !PM);
+	if status_b & HOUR_24 == 0 {
+		// 12am is hour 0, and 12pm is hour 12
+		hour %= 12;
+		if pm {
+			hour += 12;
+		}
+	}
+
+	let century = match century {
+		0 => 20,
+		century => decode(century) as u16,
+	};
+
+	DateTime {
+		year: century * 100 + decode(year) as u16,
+		month: decode(month),
+		day: decode(day),
+		hour,
+		minute: decode(minute),
+		second: decode(second),
+	}
+}
+
+/// Waits for any update to finish, then reads the time registers, without decoding them. The
+/// century is 0 if there's no century register.
+fn read_raw(century: Option<u8>) -> [u8; 7] {
+	unsafe {
+		while read_register(STATUS_A) & UPDATE_IN_PROGRESS != 0 {}
+
+		[
+			read_register(SECONDS),
+			read_register(MINUTES),
+			read_register(HOURS),
+			read_register(DAY),
+			read_register(MONTH),
+			read_register(YEAR),
+			century.map(|register| read_register(register)).unwrap_or(0),
+		]
+	}
+}
+
+/// Reads a CMOS register.
+///
+/// # Safety
+/// Nothing else can be using the CMOS ports at the same time.
+unsafe fn read_register(register: u8) -> u8 {
+	unsafe {
+		portio::write_u8(CMOS_INDEX, register & 0x7F);
+		portio::read_u8(CMOS_DATA)
+	}
+}
+
+/// Converts a binary-coded decimal byte (eg 0x59) to binary (59).
+fn bcd_to_binary(bcd: u8) -> u8 {
+	(bcd >> 4) * 10 + (bcd & 0x0F)
+}
@@ -0,0 +1,127 @@
+//! The stage table, which says where each boot program is on the disk.
+//!
+//! The boot programs are in BS' boot partition (see [`crate::mbr`]). It starts with the stage table,
+//! then every stage (each one starting on a new sector). Besides the boot programs and the kernel,
+//! the initial ramdisk and the configuration sector (see [`crate::command_line`]) are also stages. Stage locations are relative to the start
+//! of the partition, so the partition can be anywhere on the disk. The build writes the table when
+//! it puts the image together, and the bootloader reads it (see [`crate::disks::stage_location`])
+//! to load exactly the sectors a stage is in. That's instead of scanning for a marker at the end of
+//! each stage, which breaks if a stage happens to contain the marker's bytes.
+//!
+//! The table is the same in 16-bit and 64-bit code (it's only `u32`s and bytes), and it's used by
+//! the build scripts on the host, so this module doesn't use any real-mode-only code.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Rolling_Your_Own_Bootloader
+
+use core::{mem::size_of, slice};
+
+/// The sector the stage table is in, relative to the start of the boot partition.
+pub const STAGE_TABLE_LBA: u64 = 0;
+/// Where boot programs get loaded, and called, in memory. Their link script (`boot-program.ld`)
+/// links them here. They have to end by 0x10000, since real-mode code can only reach the first
+/// 64kib with the segment registers at 0 - so this is as low as it can be: right above the stack
+/// (see [`STACK_TOP`]).
+pub const LOAD_ADDRESS: u16 = 0x3000;
+/// The top of the stack the bootstrapper and the bootloader run on (8kib). It grows down towards
+/// the [`BootInfo`](crate::boot_info::BootInfo).
+pub const STACK_TOP: u16 = 0x3000;
+/// Where the bootloader loads the ELF loader, and calls it, in memory (1mib). It's 64-bit, so it
+/// doesn't have to fit in conventional memory like the other boot programs; its link script
+/// (`elf-loader/link.ld`) links it here.
+pub const ELF_LOADER_ADDRESS: u64 = 0x10_0000;
+/// The biggest the ELF loader can be (1mib), so it always ends by 2mib.
+pub const ELF_LOADER_MAX_SIZE: u64 = 0x10_0000;
+/// Where the ELF loader's memory ends (2mib). Everything below this is either the BIOS' or the boot
+/// programs', so it's never handed out as free memory, and it's mapped as executable.
+pub const BOOT_PROGRAMS_END: u64 = ELF_LOADER_ADDRESS + ELF_LOADER_MAX_SIZE;
+
+/// A stage of the boot process that's loaded from the disk. The bootstrapper isn't here, since the
+/// BIOS loads it.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+	Bootloader,
+	ElfLoader,
+	Kernel,
+	/// The initial ramdisk, an archive the kernel uses as its first filesystem. It's optional.
+	Initrd,
+	/// The configuration sector, with the kernel command line. It's optional.
+	Config,
+}
+impl Stage {
+	/// Every stage, in the order they're stored on the disk.
+	pub const ALL: [Self; STAGE_COUNT] = [
+		Self::Bootloader,
+		Self::ElfLoader,
+		Self::Kernel,
+		Self::Initrd,
+		Self::Config,
+	];
+}
+/// How many [`Stage`]s there are.
+pub const STAGE_COUNT: usize = 5;
+
+/// Where a stage is on the disk.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StageLocation {
+	/// The first sector of the stage, relative to the start of the boot partition.
+	pub lba: u32,
+	/// How many sectors the stage takes up.
+	pub sectors: u32,
+}
+
+/// Where every stage is on the disk. See the module-level docs.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StageTable {
+	/// Always [`StageTable::MAGIC`].
+	pub magic: [u8; 8],
+	/// Each stage's location, indexed by [`Stage`]. A stage with 0 sectors isn't on the disk.
+	pub stages: [StageLocation; STAGE_COUNT],
+}
+impl StageTable {
+	/// The first 8 bytes of the stage table's sector.
+	pub const MAGIC: [u8; 8] = *b"BSSTAGES";
+
+	/// A stage table with no stages.
+	pub const fn new() -> Self {
+		Self {
+			magic: Self::MAGIC,
+			stages: [StageLocation { lba: 0, sectors: 0 }; STAGE_COUNT],
+		}
+	}
+
+	/// Where `stage` is. Returns `None` if this isn't actually a stage table (the magic is wrong),
+	/// or if the stage isn't on the disk.
+	pub fn get(&self, stage: Stage) -> Option<StageLocation> {
+		let location = self.stages[stage as usize];
+		(self.magic == Self::MAGIC && location.sectors != 0).then_some(location)
+	}
+
+	/// Sets where `stage` is.
+	pub fn set(&mut self, stage: Stage, location: StageLocation) {
+		self.stages[stage as usize] = location;
+	}
+
+	/// Reads the table from the sector it's in. The opposite of [`StageTable::to_sector`].
+	pub fn from_sector(sector: &[u8; 512]) -> Self {
+		// `StageTable` is only `u32`s and bytes, so any bytes are valid for it
+		unsafe { (sector.as_ptr() as *const Self).read_unaligned() }
+	}
+
+	/// The table as a sector, to write to the disk. The rest of the sector is zeroed.
+	pub fn to_sector(&self) -> [u8; 512] {
+		let bytes =
+			unsafe { slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) };
+		let mut sector = [0; 512];
+		sector[..bytes.len()].copy_from_slice(bytes);
+		sector
+	}
+}
+impl Default for StageTable {
+	fn default() -> Self {
+		Self::new()
+	}
+}
@@ -0,0 +1,301 @@
+//! Locks and lazy initialization for sharing state without `std`.
+//!
+//! There's no scheduler to put a thread to sleep, so everything here spins:
+//! - [`Mutex`] is a plain spinlock.
+//! - [`Spinlock`] is a spinlock that also disables interrupts while it's held. Anything that's
+//!   used by an interrupt handler (like the printers, since `print!` can be called from anywhere)
+//!   needs this: if a handler tried to take a lock the code it interrupted was holding, it'd spin
+//!   forever, since that code can't continue until the handler returns.
+//! - [`InterruptGuard`] disables interrupts until it's dropped, for code that doesn't need a lock
+//!   but can't be interrupted.
+//! - [`Once`] runs an initializer exactly once, and [`Lazy`] wraps it up into a value that's
+//!   initialized the first time it's used.
+//!
+//! These are all safe to put in a `static`, which is what they're for - instead of `static mut`s.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Spinlock
+//! - https://marabos.nl/atomics/building-spinlock.html
+
+use {
+	crate::interrupts,
+	core::{
+		cell::UnsafeCell,
+		mem::MaybeUninit,
+		ops::{Deref, DerefMut},
+		sync::atomic::{AtomicBool, AtomicU8, Ordering},
+	},
+};
+
+/// A spinlock. This doesn't disable interrupts, so it can't be used from interrupt handlers - see
+/// [`Spinlock`] for that.
+pub struct Mutex<T> {
+	locked: AtomicBool,
+	value: UnsafeCell<T>,
+}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+unsafe impl<T: Send> Send for Mutex<T> {}
+impl<T> Mutex<T> {
+	pub const fn new(value: T) -> Self {
+		Self {
+			locked: AtomicBool::new(false),
+			value: UnsafeCell::new(value),
+		}
+	}
+
+	/// Waits for the lock, then locks it until the guard is dropped.
+	pub fn lock(&self) -> MutexGuard<'_, T> {
+		loop {
+			if let Some(guard) = self.try_lock() {
+				return guard;
+			}
+			// Wait for it to look unlocked before trying again, so the cache line isn't bounced
+			// between cores by failed writes
+			while self.locked.load(Ordering::Relaxed) {
+				core::hint::spin_loop();
+			}
+		}
+	}
+
+	/// Locks the lock, or returns `None` if something else has it locked.
+	pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+		self.locked
+			.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+			.ok()
+			.map(|_| MutexGuard { lock: self })
+	}
+
+	/// If something has the lock locked.
+	pub fn is_locked(&self) -> bool {
+		self.locked.load(Ordering::Relaxed)
+	}
+
+	/// Gets the value without locking, since having `&mut self` means nothing else has it.
+	pub fn get_mut(&mut self) -> &mut T {
+		self.value.get_mut()
+	}
+
+	/// Takes the value out of the lock.
+	pub fn into_inner(self) -> T {
+		self.value.into_inner()
+	}
+
+	/// Unlocks the lock, even though something else has it locked. Panic handlers use this so
+	/// they can still print if the panic happened while the printer was locked.
+	///
+	/// # Safety
+	/// Whatever had the lock can't use it anymore.
+	pub unsafe fn force_unlock(&self) {
+		self.locked.store(false, Ordering::Release);
+	}
+}
+impl<T: Default> Default for Mutex<T> {
+	fn default() -> Self {
+		Self::new(T::default())
+	}
+}
+
+/// Access to a [`Mutex`]'s value, while it's locked.
+pub struct MutexGuard<'a, T> {
+	lock: &'a Mutex<T>,
+}
+impl<T> Deref for MutexGuard<'_, T> {
+	type Target = T;
+
+	fn deref(&self) -> &T {
+		unsafe { &*self.lock.value.get() }
+	}
+}
+impl<T> DerefMut for MutexGuard<'_, T> {
+	fn deref_mut(&mut self) -> &mut T {
+		unsafe { &mut *self.lock.value.get() }
+	}
+}
+impl<T> Drop for MutexGuard<'_, T> {
+	fn drop(&mut self) {
+		self.lock.locked.store(false, Ordering::Release);
+	}
+}
+
+/// Disables interrupts until it's dropped, then re-enables them if they were enabled before. Guards
+/// can be nested; only the outermost one re-enables interrupts.
+pub struct InterruptGuard {
+	/// If interrupts were enabled before the guard was made.
+	enabled: bool,
+}
+impl InterruptGuard {
+	pub fn new() -> Self {
+		let enabled = interrupts::are_enabled();
+		interrupts::disable();
+
+		Self { enabled }
+	}
+}
+impl Default for InterruptGuard {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+impl Drop for InterruptGuard {
+	fn drop(&mut self) {
+		if self.enabled {
+			unsafe { interrupts::enable() };
+		}
+	}
+}
+
+/// A spinlock that disables interrupts while it's held, so it can be shared with interrupt
+/// handlers. See the module-level docs.
+pub struct Spinlock<T> {
+	mutex: Mutex<T>,
+}
+impl<T> Spinlock<T> {
+	pub const fn new(value: T) -> Self {
+		Self {
+			mutex: Mutex::new(value),
+		}
+	}
+
+	/// Disables interrupts and waits for the lock. Interrupts are re-enabled (if they were enabled
+	/// before) when the guard is dropped.
+	pub fn lock(&self) -> SpinlockGuard<'_, T> {
+		let interrupts = InterruptGuard::new();
+		SpinlockGuard {
+			guard: self.mutex.lock(),
+			_interrupts: interrupts,
+		}
+	}
+
+	/// Like [`Spinlock::lock`], but returns `None` instead of waiting if something else has it
+	/// locked.
+	pub fn try_lock(&self) -> Option<SpinlockGuard<'_, T>> {
+		let interrupts = InterruptGuard::new();
+		Some(SpinlockGuard {
+			guard: self.mutex.try_lock()?,
+			_interrupts: interrupts,
+		})
+	}
+
+	/// Gets the value without locking, since having `&mut self` means nothing else has it.
+	pub fn get_mut(&mut self) -> &mut T {
+		self.mutex.get_mut()
+	}
+
+	/// Unlocks the lock, even though something else has it locked. See [`Mutex::force_unlock`].
+	///
+	/// # Safety
+	/// Whatever had the lock can't use it anymore.
+	pub unsafe fn force_unlock(&self) {
+		unsafe { self.mutex.force_unlock() };
+	}
+}
+
+/// Access to a [`Spinlock`]'s value, while it's locked.
+pub struct SpinlockGuard<'a, T> {
+	// Fields are dropped in order, so this unlocks before interrupts are re-enabled
+	guard: MutexGuard<'a, T>,
+	_interrupts: InterruptGuard,
+}
+impl<T> Deref for SpinlockGuard<'_, T> {
+	type Target = T;
+
+	fn deref(&self) -> &T {
+		&self.guard
+	}
+}
+impl<T> DerefMut for SpinlockGuard<'_, T> {
+	fn deref_mut(&mut self) -> &mut T {
+		&mut self.guard
+	}
+}
+
+const INCOMPLETE: u8 = 0;
+const RUNNING: u8 = 1;
+const COMPLETE: u8 = 2;
+
+/// A value that's initialized once, by whichever caller of [`Once::call_once`] gets there first.
+pub struct Once<T> {
+	state: AtomicU8,
+	value: UnsafeCell<MaybeUninit<T>>,
+}
+unsafe impl<T: Send + Sync> Sync for Once<T> {}
+unsafe impl<T: Send> Send for Once<T> {}
+impl<T> Once<T> {
+	pub const fn new() -> Self {
+		Self {
+			state: AtomicU8::new(INCOMPLETE),
+			value: UnsafeCell::new(MaybeUninit::uninit()),
+		}
+	}
+
+	/// Initializes the value with `init`, if it hasn't been already, and returns it. If another
+	/// core is running its initializer, this waits for it to finish.
+	///
+	/// `init` can't call `call_once` on the same `Once`, or it'll spin forever.
+	pub fn call_once(&self, init: impl FnOnce() -> T) -> &T {
+		if self
+			.state
+			.compare_exchange(INCOMPLETE, RUNNING, Ordering::Acquire, Ordering::Acquire)
+			.is_ok()
+		{
+			unsafe { (*self.value.get()).write(init()) };
+			self.state.store(COMPLETE, Ordering::Release);
+		} else {
+			while self.state.load(Ordering::Acquire) != COMPLETE {
+				core::hint::spin_loop();
+			}
+		}
+
+		unsafe { (*self.value.get()).assume_init_ref() }
+	}
+
+	/// The value, or `None` if it hasn't been initialized yet.
+	pub fn get(&self) -> Option<&T> {
+		self.is_completed()
+			.then(|| unsafe { (*self.value.get()).assume_init_ref() })
+	}
+
+	/// If the value has been initialized.
+	pub fn is_completed(&self) -> bool {
+		self.state.load(Ordering::Acquire) == COMPLETE
+	}
+}
+impl<T> Default for Once<T> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+impl<T> Drop for Once<T> {
+	fn drop(&mut self) {
+		if *self.state.get_mut() == COMPLETE {
+			unsafe { self.value.get_mut().assume_init_drop() };
+		}
+	}
+}
+
+/// A value that's initialized by `init` the first time it's used.
+pub struct Lazy<T, F = fn() -> T> {
+	once: Once<T>,
+	init: F,
+}
+unsafe impl<T: Send + Sync, F: Send> Sync for Lazy<T, F> {}
+impl<T, F: Fn() -> T> Lazy<T, F> {
+	pub const fn new(init: F) -> Self {
+		Self {
+			once: Once::new(),
+			init,
+		}
+	}
+
+	/// Initializes the value, if it hasn't been already, and returns it.
+	pub fn force(&self) -> &T {
+		self.once.call_once(&self.init)
+	}
+}
+impl<T, F: Fn() -> T> Deref for Lazy<T, F> {
+	type Target = T;
+
+	fn deref(&self) -> &T {
+		self.force()
+	}
+}
@@ -0,0 +1,246 @@
+//! Sets a linear framebuffer graphics mode with the VESA BIOS Extensions (VBE).
+//!
+//! VBE is a set of int 10h functions (AH=0x4F) for finding and setting graphics modes. The BIOS
+//! lists the modes the graphics card supports (int 10h, AX=0x4F00), then describes each one
+//! (AX=0x4F01) - its resolution, pixel layout, and where its linear framebuffer is. [`find_mode`]
+//! goes through them and picks the biggest direct-colour mode with a linear framebuffer that fits
+//! in a given resolution, then [`VbeMode::set`] switches to it (AX=0x4F02).
+//!
+//! Only 24- and 32-bit modes are used, since those are the only pixel sizes the framebuffer console
+//! supports (see `printing::framebuffer`).
+//!
+//! This uses BIOS calls, so it's only available in the 16-bit boot programs. It's also the last
+//! thing that should use the BIOS' text output: once a graphics mode is set, the VGA text buffer
+//! doesn't show up on the screen anymore.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/VESA_Video_Modes
+//! - https://wiki.osdev.org/User:Omarrx024/VESA_Tutorial
+
+use {
+	crate::printing::framebuffer::{FramebufferInfo, PixelFormat},
+	core::{arch::asm, mem::MaybeUninit},
+};
+
+/// What a VBE function returns in AX when it worked.
+const VBE_SUCCESS: u16 = 0x004F;
+/// Marks the end of [`VbeInfo::video_modes`].
+const END_OF_MODES: u16 = 0xFFFF;
+/// Set in the mode number given to AX=0x4F02 to use the mode's linear framebuffer.
+const USE_LINEAR_FRAMEBUFFER: u16 = 1 << 14;
+
+/// [`ModeInfo::attributes`]: the mode is supported by the hardware.
+const ATTRIBUTE_SUPPORTED: u16 = 1 << 0;
+/// [`ModeInfo::attributes`]: the mode is a graphics mode, not a text mode.
+const ATTRIBUTE_GRAPHICS: u16 = 1 << 4;
+/// [`ModeInfo::attributes`]: the mode has a linear framebuffer.
+const ATTRIBUTE_LINEAR_FRAMEBUFFER: u16 = 1 << 7;
+/// [`ModeInfo::memory_model`] for direct-colour modes, where each pixel is its own colour instead
+/// of an index into a palette.
+const MEMORY_MODEL_DIRECT_COLOUR: u8 = 6;
+
+/// The controller information AX=0x4F00 writes.
+#[allow(dead_code)] // This is the BIOS' layout, so not every field is used
+#[repr(C, packed)]
+struct VbeInfo {
+	/// "VBE2" going in, to ask for the VBE 2+ fields; "VESA" coming out.
+	signature: [u8; 4],
+	/// The VBE version, as BCD (eg 0x0300 for 3.0).
+	version: u16,
+	oem: u32,
+	capabilities: u32,
+	/// A real-mode far pointer (segment:offset) to the mode numbers, ending with
+	/// [`END_OF_MODES`].
+	video_modes: u32,
+	/// How much video memory there is, in 64kib blocks.
+	total_memory: u16,
+	_reserved: [u8; 492],
+}
+
+/// The mode information AX=0x4F01 writes. Only the fields BS uses are documented.
+#[allow(dead_code)] // This is the BIOS' layout, so not every field is used
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct ModeInfo {
+	/// Bitflags; see the `ATTRIBUTE_` constants.
+	pub attributes: u16,
+	window_a: u8,
+	window_b: u8,
+	granularity: u16,
+	window_size: u16,
+	segment_a: u16,
+	segment_b: u16,
+	window_function: u32,
+	/// How many bytes each row of pixels takes up.
+	pub pitch: u16,
+	/// The width, in pixels.
+	pub width: u16,
+	/// The height, in pixels.
+	pub height: u16,
+	char_width: u8,
+	char_height: u8,
+	planes: u8,
+	/// How many bits each pixel takes up.
+	pub bits_per_pixel: u8,
+	banks: u8,
+	/// How pixels are stored; see [`MEMORY_MODEL_DIRECT_COLOUR`].
+	pub memory_model: u8,
+	bank_size: u8,
+	image_pages: u8,
+	_reserved0: u8,
+	red_mask: u8,
+	/// Which bit red starts at in each pixel.
+	pub red_position: u8,
+	green_mask: u8,
+	green_position: u8,
+	blue_mask: u8,
+	/// Which bit blue starts at in each pixel.
+	pub blue_position: u8,
+	reserved_mask: u8,
+	reserved_position: u8,
+	direct_colour_attributes: u8,
+	/// The physical address of the linear framebuffer.
+	pub framebuffer: u32,
+	off_screen_memory: u32,
+	off_screen_memory_size: u16,
+	_reserved1: [u8; 206],
+}
+impl ModeInfo {
+	/// The order of the colour channels, or `None` if they aren't laid out as whole bytes in
+	/// either order.
+	pub fn pixel_format(&self) -> Option<PixelFormat> {
+		match (self.red_position, self.blue_position) {
+			(0, 16) => Some(PixelFormat::Rgb),
+			(16, 0) => Some(PixelFormat::Bgr),
+			_ => None,
+		}
+	}
+
+	/// If BS can draw to this mode: it's a supported direct-colour graphics mode with a linear
+	/// framebuffer, 24 or 32-bit pixels, and a known [`PixelFormat`].
+	fn usable(&self) -> bool {
+		let required = ATTRIBUTE_SUPPORTED | ATTRIBUTE_GRAPHICS | ATTRIBUTE_LINEAR_FRAMEBUFFER;
+		self.attributes & required == required
+			&& self.memory_model == MEMORY_MODEL_DIRECT_COLOUR
+			&& matches!(self.bits_per_pixel, 24 | 32)
+			&& self.framebuffer != 0
+			&& self.pixel_format().is_some()
+	}
+
+	/// How [`find_mode`] compares modes: more pixels is better, then more bits per pixel.
+	fn rank(&self) -> (u32, u8) {
+		(self.width as u32 * self.height as u32, self.bits_per_pixel)
+	}
+}
+
+/// A video mode, from [`find_mode`].
+#[derive(Clone, Copy)]
+pub struct VbeMode {
+	/// The mode's number, for AX=0x4F02.
+	pub number: u16,
+	pub info: ModeInfo,
+}
+impl VbeMode {
+	/// Switches to this mode, with its linear framebuffer. Returns `false` if the BIOS couldn't set
+	/// it.
+	pub fn set(&self) -> bool {
+		let status: u16;
+		unsafe {
+			asm!(
+				"push bx",
+				"mov bx, {mode:x}",
+				"int 0x10",
+				"pop bx",
+				mode = in(reg) self.number | USE_LINEAR_FRAMEBUFFER,
+				inout("ax") 0x4F02u16 => status,
+			)
+		}
+		status == VBE_SUCCESS
+	}
+
+	/// Where this mode's framebuffer is, and how it's laid out. The address is physical.
+	pub fn framebuffer(&self) -> FramebufferInfo {
+		FramebufferInfo {
+			address: self.info.framebuffer as u64,
+			width: self.info.width as usize,
+			height: self.info.height as usize,
+			pitch: self.info.pitch as usize,
+			bytes_per_pixel: self.info.bits_per_pixel as usize / 8,
+			// `find_mode` only returns modes with a pixel format
+			format: self.info.pixel_format().unwrap_or(PixelFormat::Bgr),
+		}
+	}
+}
+
+/// Finds the biggest mode BS can draw to (see the module-level docs) that's at most `max_width` by
+/// `max_height` pixels. If two modes have the same resolution, the 32-bit one wins, since its pixels
+/// are aligned. Returns `None` if the BIOS doesn't support VBE 2+, or doesn't have a mode that
+/// fits.
+pub fn find_mode(max_width: u16, max_height: u16) -> Option<VbeMode> {
+	let info = controller_info()?;
+
+	// The list is behind a real-mode far pointer, which has to be turned into a linear address
+	let modes = info.video_modes;
+	let mut mode_ptr = (((modes >> 16) << 4) + (modes & 0xFFFF)) as usize as *const u16;
+	let mut best: Option<VbeMode> = None;
+	loop {
+		let number = unsafe { mode_ptr.read_unaligned() };
+		if number == END_OF_MODES {
+			break;
+		}
+		mode_ptr = mode_ptr.wrapping_add(1);
+
+		let Some(info) = mode_info(number) else {
+			continue;
+		};
+		if !info.usable() || info.width > max_width || info.height > max_height {
+			continue;
+		}
+
+		if best.is_none_or(|best| info.rank() > best.info.rank()) {
+			best = Some(VbeMode { number, info });
+		}
+	}
+
+	best
+}
+
+/// Gets the controller information (int 10h, AX=0x4F00). Returns `None` if the BIOS doesn't
+/// support VBE 2+.
+fn controller_info() -> Option<VbeInfo> {
+	let mut info = MaybeUninit::<VbeInfo>::uninit();
+	// Ask for the VBE 2+ fields
+	unsafe { (info.as_mut_ptr() as *mut [u8; 4]).write(*b"VBE2") };
+
+	let status: u16;
+	unsafe {
+		asm!(
+			"int 0x10",
+			inout("ax") 0x4F00u16 => status,
+			in("edi") info.as_mut_ptr(),
+		)
+	}
+	if status != VBE_SUCCESS {
+		return None;
+	}
+
+	let info = unsafe { info.assume_init() };
+	let version = info.version;
+	(info.signature == *b"VESA" && version >= 0x0200).then_some(info)
+}
+
+/// Gets the information for mode `number` (int 10h, AX=0x4F01).
+fn mode_info(number: u16) -> Option<ModeInfo> {
+	let mut info = MaybeUninit::<ModeInfo>::uninit();
+	let status: u16;
+	unsafe {
+		asm!(
+			"int 0x10",
+			inout("ax") 0x4F01u16 => status,
+			in("cx") number,
+			in("edi") info.as_mut_ptr(),
+		)
+	}
+
+	(status == VBE_SUCCESS).then(|| unsafe { info.assume_init() })
+}
@@ -0,0 +1,177 @@
+//! Parses the dynamic section, which holds the information needed for dynamic linking: where
+//! the relocations are, where the dynamic symbol and string tables are, which shared libraries
+//! the ELF needs, etc.
+//!
+//! The dynamic section is pointed to by the `PT_DYNAMIC` program header, and is a list of
+//! [`DynamicEntry`]s ending with a [`DT_NULL`] entry. Unlike section headers, dynamic entries are
+//! always present in loadable ELFs (section headers can be stripped), so they're what loaders
+//! should use. Most entries hold virtual addresses; [`FileHeader::address_to_offset`] converts
+//! those to offsets in the file.
+//!
+//! Resources:
+//! - https://refspecs.linuxfoundation.org/elf/gabi4+/ch5.dynamic.html#dynamic_section
+
+use crate::*;
+
+/// Marks the end of the dynamic section.
+pub const DT_NULL: i64 = 0;
+/// The name of a needed shared library, as an offset into the [`DT_STRTAB`] string table.
+pub const DT_NEEDED: i64 = 1;
+/// The size, in bytes, of the relocations at [`DT_JMPREL`].
+pub const DT_PLTRELSZ: i64 = 2;
+/// The address of the global offset table.
+pub const DT_PLTGOT: i64 = 3;
+/// The address of the symbol hash table.
+pub const DT_HASH: i64 = 4;
+/// The address of the dynamic string table.
+pub const DT_STRTAB: i64 = 5;
+/// The address of the dynamic symbol table.
+pub const DT_SYMTAB: i64 = 6;
+/// The address of the relocations with addends.
+pub const DT_RELA: i64 = 7;
+/// The size, in bytes, of the relocations at [`DT_RELA`].
+pub const DT_RELASZ: i64 = 8;
+/// The size, in bytes, of each relocation at [`DT_RELA`].
+pub const DT_RELAENT: i64 = 9;
+/// The size, in bytes, of the dynamic string table.
+pub const DT_STRSZ: i64 = 10;
+/// The size, in bytes, of each symbol in the dynamic symbol table.
+pub const DT_SYMENT: i64 = 11;
+/// The address of the initialisation function.
+pub const DT_INIT: i64 = 12;
+/// The address of the termination function.
+pub const DT_FINI: i64 = 13;
+/// The name of this shared object, as an offset into the [`DT_STRTAB`] string table.
+pub const DT_SONAME: i64 = 14;
+/// The library search path, as an offset into the [`DT_STRTAB`] string table.
+pub const DT_RPATH: i64 = 15;
+/// The address of the relocations without addends.
+pub const DT_REL: i64 = 17;
+/// The size, in bytes, of the relocations at [`DT_REL`].
+pub const DT_RELSZ: i64 = 18;
+/// What type of relocations are at [`DT_JMPREL`] - either [`DT_RELA`] or [`DT_REL`].
+pub const DT_PLTREL: i64 = 20;
+/// If present, relocations may modify read-only segments.
+pub const DT_TEXTREL: i64 = 22;
+/// The address of the procedure linkage table's relocations.
+pub const DT_JMPREL: i64 = 23;
+/// If present, every relocation should be applied before the program runs.
+pub const DT_BIND_NOW: i64 = 24;
+/// Flags for the object.
+pub const DT_FLAGS: i64 = 30;
+/// The address of the GNU-style symbol hash table.
+pub const DT_GNU_HASH: i64 = 0x6FFF_FEF5;
+/// How many of the relocations at [`DT_RELA`] are `R_X86_64_RELATIVE` relocations.
+pub const DT_RELACOUNT: i64 = 0x6FFF_FFF9;
+/// More flags for the object.
+pub const DT_FLAGS_1: i64 = 0x6FFF_FFFB;
+
+/// An entry in the dynamic section.
+#[repr(C, packed)]
+pub struct DynamicEntry {
+	/// What this entry is. See the `DT_*` consts.
+	pub tag: i64,
+	/// This entry's value. Depending on the tag, this is either an address or a plain integer.
+	pub value: u64,
+}
+
+/// The dynamic entries a loader actually needs, collected from the dynamic section. See
+/// [`FileHeader::dynamic_info`].
+#[derive(Default, Debug)]
+pub struct DynamicInfo {
+	/// [`DT_RELA`]
+	pub rela: Option<u64>,
+	/// [`DT_RELASZ`]
+	pub rela_size: u64,
+	/// [`DT_JMPREL`]
+	pub jmprel: Option<u64>,
+	/// [`DT_PLTRELSZ`]
+	pub plt_rel_size: u64,
+	/// [`DT_PLTREL`]
+	pub plt_rel: Option<i64>,
+	/// [`DT_SYMTAB`]
+	pub symtab: Option<u64>,
+	/// [`DT_STRTAB`]
+	pub strtab: Option<u64>,
+	/// [`DT_STRSZ`]
+	pub strtab_size: u64,
+}
+impl DynamicInfo {
+	/// Collects the values of the entries in a dynamic section.
+	pub fn from_entries<'a>(entries: impl Iterator<Item = &'a DynamicEntry>) -> Self {
+		let mut info = Self::default();
+
+		for entry in entries {
+			let value = entry.value;
+			match entry.tag {
+				DT_RELA => info.rela = Some(value),
+				DT_RELASZ => info.rela_size = value,
+				DT_JMPREL => info.jmprel = Some(value),
+				DT_PLTRELSZ => info.plt_rel_size = value,
+				DT_PLTREL => info.plt_rel = Some(value as i64),
+				DT_SYMTAB => info.symtab = Some(value),
+				DT_STRTAB => info.strtab = Some(value),
+				DT_STRSZ => info.strtab_size = value,
+				_ => {}
+			}
+		}
+
+		info
+	}
+}
+
+impl FileHeader {
+	/// Iterates over the entries in the dynamic section, stopping at [`DT_NULL`]. Returns `None`
+	/// if the ELF doesn't have a `PT_DYNAMIC` segment (ie, it's statically linked).
+	///
+	/// `elf` is the entire ELF file, starting at this header.
+	pub fn dynamic_entries<'a>(
+		&self,
+		elf: &'a [u8],
+	) -> Result<Option<impl Iterator<Item = &'a DynamicEntry> + 'a>, ElfError> {
+		let Some(segment) = self
+			.program_headers(elf)?
+			.find(|segment| segment.is_type(ProgramType::Dynamic))
+		else {
+			return Ok(None);
+		};
+
+		let data = elf.get(segment.file_range()?).ok_or(ElfError::Truncated)?;
+		let entries: &[DynamicEntry] = cast_slice(data);
+
+		Ok(Some(
+			entries.iter().take_while(|entry| entry.tag != DT_NULL),
+		))
+	}
+
+	/// Collects the dynamic entries a loader needs. See [`DynamicInfo`]. Returns `None` if the ELF
+	/// doesn't have a `PT_DYNAMIC` segment.
+	pub fn dynamic_info(&self, elf: &[u8]) -> Result<Option<DynamicInfo>, ElfError> {
+		Ok(self.dynamic_entries(elf)?.map(DynamicInfo::from_entries))
+	}
+
+	/// Converts a virtual address to an offset in the ELF file, using the `PT_LOAD` segments.
+	/// Returns `None` if the address isn't backed by bytes in the file.
+	pub fn address_to_offset(&self, elf: &[u8], address: u64) -> Option<usize> {
+		self.program_headers(elf)
+			.ok()?
+			.filter(|segment| segment.is_type(ProgramType::Load))
+			.find_map(|segment| {
+				let start = segment.address;
+				let offset = address.checked_sub(start)?;
+				if offset >= segment.file_size {
+					return None;
+				}
+
+				usize::try_from(segment.offset + offset).ok()
+			})
+	}
+
+	/// Gets `len` bytes at a virtual address. See [`FileHeader::address_to_offset`].
+	pub(crate) fn bytes_at<'a>(&self, elf: &'a [u8], address: u64, len: u64) -> Option<&'a [u8]> {
+		let start = self.address_to_offset(elf, address)?;
+		let end = start.checked_add(usize::try_from(len).ok()?)?;
+
+		elf.get(start..end)
+	}
+}
@@ -0,0 +1,266 @@
+//! A safe entry point for parsing ELF files that are already in memory.
+//!
+//! [`FileHeader::try_from_raw`] only checks the file header, and trusts that the rest of the file
+//! is wherever the header says it is. [`Elf::parse`] takes the whole file as a slice instead, and
+//! checks that every table the header points to is actually inside it, so a truncated or
+//! malicious file can't cause out-of-bounds reads.
+//!
+//! [`Elf`] can read both 32-bit and 64-bit ELFs. 32-bit headers are widened to the 64-bit
+//! layout, so the same [`FileHeader`], [`ProgramHeader`], and [`SectionHeader`] types work for
+//! both. Loading and symbols are only supported for 64-bit ELFs.
+//!
+//! With the `endian-compat` feature, [`Elf`] can also read ELFs with the opposite endianness. Their
+//! headers, sections, and symbols are byte-swapped as they're read, but they can't be loaded.
+
+use {crate::*, core::mem};
+
+/// A parsed, bounds-checked ELF file. See the module-level docs.
+pub struct Elf<'a> {
+	/// The entire ELF file.
+	pub data: &'a [u8],
+	/// If the ELF is 32-bit or 64-bit.
+	pub bitness: Bitness,
+	/// If the ELF's endianness is the opposite of the native endianness. See
+	/// [`Endianess::NATIVE`].
+	swapped: bool,
+	/// The file header. If the ELF is 32-bit, this has been widened to the 64-bit layout. If the
+	/// ELF has the opposite endianness, its fields have been byte-swapped (but `endianess` still
+	/// says what the file's endianness is).
+	pub header: FileHeader,
+	/// The bytes of the program header table.
+	program_table: &'a [u8],
+	/// The bytes of the section header table.
+	section_table: &'a [u8],
+	/// The string table with the sections' names, if the ELF has one.
+	pub section_names: Option<StringTable<'a>>,
+}
+impl<'a> Elf<'a> {
+	/// Parses and validates an ELF file.
+	pub fn parse(data: &'a [u8]) -> Result<Self, ElfError> {
+		if data.len() < mem::size_of::<FileHeader32>() {
+			return Err(ElfError::Truncated);
+		}
+		// The bitness and endianness are enums, which can't be read from arbitrary bytes. Check
+		// those bytes before casting.
+		let [bitness, endianess] = [data[4], data[5]];
+		if data[..4] != [0x7F, 0x45, 0x4C, 0x46] {
+			return Err(ElfError::NoMagicBytes);
+		}
+		let endianess = match endianess {
+			1 => Endianess::Little,
+			2 => Endianess::Big,
+			_ => return Err(ElfError::BadEndianness),
+		};
+		let swapped = endianess != Endianess::NATIVE;
+		if swapped && !cfg!(feature = "endian-compat") {
+			return Err(ElfError::BadEndianness);
+		}
+
+		// The header is read into a native-endian copy, so it's validated as if it were native
+		let (bitness, mut header, program_header_size, section_header_size) = match bitness {
+			1 => {
+				let mut header: FileHeader32 = read(data, swapped);
+				header.endianess = Endianess::NATIVE;
+				unsafe { FileHeader32::try_from_raw(&header) }?;
+				(
+					Bitness::X32,
+					FileHeader::from(&header),
+					mem::size_of::<ProgramHeader32>(),
+					mem::size_of::<SectionHeader32>(),
+				)
+			}
+			2 if data.len() >= mem::size_of::<FileHeader>() => {
+				let mut header: FileHeader = read(data, swapped);
+				header.endianess = Endianess::NATIVE;
+				unsafe { FileHeader::try_from_raw(&header) }?;
+				(
+					Bitness::X64,
+					header,
+					mem::size_of::<ProgramHeader>(),
+					mem::size_of::<SectionHeader>(),
+				)
+			}
+			2 => return Err(ElfError::Truncated),
+			_ => return Err(ElfError::BadBitness),
+		};
+		header.endianess = endianess;
+
+		let program_table = table_bytes(
+			data,
+			header.program_table_offset,
+			header.program_table_entries,
+			program_header_size,
+		)?;
+		let section_table = table_bytes(
+			data,
+			header.section_table_offset,
+			header.section_table_entries,
+			section_header_size,
+		)?;
+
+		let mut elf = Self {
+			data,
+			bitness,
+			swapped,
+			header,
+			program_table,
+			section_table,
+			section_names: None,
+		};
+
+		for segment in elf.program_headers() {
+			data.get(segment.file_range()?).ok_or(ElfError::Truncated)?;
+		}
+
+		// Index 0 means the ELF doesn't have a section name table
+		let names_index = elf.header.section_names_index as usize;
+		if names_index != 0 {
+			let section = elf
+				.section_headers()
+				.nth(names_index)
+				.ok_or(ElfError::Truncated)?;
+			let bytes = data.get(section.file_range()?).ok_or(ElfError::Truncated)?;
+			elf.section_names = Some(StringTable { bytes });
+		}
+
+		Ok(elf)
+	}
+
+	/// Iterates over the program headers. 32-bit program headers are widened to
+	/// [`ProgramHeader`]s.
+	pub fn program_headers(&self) -> impl Iterator<Item = ProgramHeader> + 'a {
+		let swapped = self.swapped;
+		let (wide, narrow) = match self.bitness {
+			Bitness::X64 => (self.program_table, &[][..]),
+			Bitness::X32 => (&[][..], self.program_table),
+		};
+
+		let (wide, _) = wide.as_chunks::<{ mem::size_of::<ProgramHeader>() }>();
+		let (narrow, _) = narrow.as_chunks::<{ mem::size_of::<ProgramHeader32>() }>();
+		wide.iter().map(move |bytes| read(bytes, swapped)).chain(
+			narrow
+				.iter()
+				.map(move |bytes| ProgramHeader::from(&read::<ProgramHeader32>(bytes, swapped))),
+		)
+	}
+	/// Iterates over the section headers. 32-bit section headers are widened to
+	/// [`SectionHeader`]s.
+	pub fn section_headers(&self) -> impl Iterator<Item = SectionHeader> + Clone + 'a {
+		let swapped = self.swapped;
+		let (wide, narrow) = match self.bitness {
+			Bitness::X64 => (self.section_table, &[][..]),
+			Bitness::X32 => (&[][..], self.section_table),
+		};
+
+		let (wide, _) = wide.as_chunks::<{ mem::size_of::<SectionHeader>() }>();
+		let (narrow, _) = narrow.as_chunks::<{ mem::size_of::<SectionHeader32>() }>();
+		wide.iter().map(move |bytes| read(bytes, swapped)).chain(
+			narrow
+				.iter()
+				.map(move |bytes| SectionHeader::from(&read::<SectionHeader32>(bytes, swapped))),
+		)
+	}
+
+	/// Gets a section's name.
+	pub fn section_name(&self, section: &SectionHeader) -> Option<&'a str> {
+		self.section_names?.get_str(section.name_offset)
+	}
+	/// Finds a section by its name (eg `.text`). Returns `None` if there's no section with that
+	/// name, or the ELF doesn't have section names.
+	pub fn section_by_name(&self, name: &str) -> Option<SectionHeader> {
+		let names = self.section_names?;
+		self.section_headers()
+			.find(|section| names.get(section.name_offset) == Some(name.as_bytes()))
+	}
+
+	/// Gets the bytes of a segment in the file.
+	pub fn segment_data(&self, segment: &ProgramHeader) -> Option<&'a [u8]> {
+		self.data.get(segment.file_range().ok()?)
+	}
+	/// Gets the bytes of a section in the file. Returns `None` for [`SectionType::NoBits`]
+	/// sections, which don't take up any space in the file.
+	pub fn section_data(&self, section: &SectionHeader) -> Option<&'a [u8]> {
+		if section.is_type(SectionType::NoBits) {
+			return None;
+		}
+
+		self.data.get(section.file_range().ok()?)
+	}
+
+	/// See [`FileHeader::notes`].
+	pub fn notes(&self) -> impl Iterator<Item = Note<'a>> + 'a {
+		notes::notes(
+			self.data,
+			self.program_headers(),
+			self.section_headers(),
+			self.swapped,
+		)
+	}
+	/// See [`FileHeader::build_id`].
+	pub fn build_id(&self) -> Option<BuildId<'a>> {
+		notes::build_id(self.notes())
+	}
+
+	/// Gets the TLS template from the `PT_TLS` segment, or `None` if the ELF doesn't use TLS. See
+	/// [`FileHeader::tls_template`].
+	pub fn tls_template(&self) -> Result<Option<TlsTemplate>, ElfError> {
+		self.program_headers()
+			.find(|segment| segment.is_type(ProgramType::ThreadLocal))
+			.map(|segment| TlsTemplate::from_segment(&segment))
+			.transpose()
+	}
+
+	/// The file header, if this is a native-endian 64-bit ELF. Loading is only supported for
+	/// those ELFs.
+	fn native_header(&self) -> Result<&FileHeader, ElfError> {
+		if self.swapped {
+			return Err(ElfError::BadEndianness);
+		}
+
+		match self.bitness {
+			Bitness::X64 => Ok(&self.header),
+			Bitness::X32 => Err(ElfError::Bitness32),
+		}
+	}
+
+	/// See [`FileHeader::load`].
+	pub fn load(&self, base: u64, writer: &mut impl SegmentWriter) -> Result<(), ElfError> {
+		self.native_header()?.load(self.data, base, writer)
+	}
+
+	/// See [`FileHeader::entry_point`].
+	pub fn entry_point<Args>(&self, base: u64) -> Result<EntryPoint<Args>, ElfError> {
+		self.native_header()?.entry_point(self.data, base)
+	}
+
+	/// See [`FileHeader::symbol_table`]. Symbols are only supported for 64-bit ELFs.
+	pub fn symbol_table(&self) -> Result<Option<SymbolTable<'a>>, ElfError> {
+		self.find_symbol_table(SectionType::SymbolTable)
+	}
+	/// See [`FileHeader::dynamic_symbol_table`]. Symbols are only supported for 64-bit ELFs.
+	pub fn dynamic_symbol_table(&self) -> Result<Option<SymbolTable<'a>>, ElfError> {
+		self.find_symbol_table(SectionType::DynamicSymbols)
+	}
+	fn find_symbol_table(
+		&self,
+		section_type: SectionType,
+	) -> Result<Option<SymbolTable<'a>>, ElfError> {
+		if self.bitness != Bitness::X64 {
+			return Err(ElfError::Bitness32);
+		}
+
+		find_symbol_table(
+			self.data,
+			self.section_headers(),
+			section_type,
+			self.swapped,
+		)
+	}
+
+	/// See [`FileHeader::dynamic_entries`].
+	pub fn dynamic_entries(
+		&self,
+	) -> Result<Option<impl Iterator<Item = &'a DynamicEntry> + 'a>, ElfError> {
+		self.native_header()?.dynamic_entries(self.data)
+	}
+}
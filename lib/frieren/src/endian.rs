@@ -0,0 +1,133 @@
+//! Reads ELF structures out of the file, optionally swapping their byte order.
+//!
+//! ELFs are stored in the endianness of the CPU they're for, which isn't always the endianness of
+//! the CPU reading them. With the `endian-compat` feature, [`Elf`] can read ELFs with the opposite
+//! endianness by reversing the bytes of every multi-byte field as it reads each structure. This
+//! is only for inspecting those ELFs - they can't be loaded. Bootloaders only ever load native
+//! ELFs, so they can leave the feature off.
+
+use {crate::*, core::mem};
+
+/// A structure [`Elf`] reads out of the file. See [`read`].
+pub(crate) trait ElfStruct: Copy {
+	/// Reverses the bytes of every multi-byte field in `bytes`, which holds one of this structure.
+	#[cfg(feature = "endian-compat")]
+	fn swap_fields(bytes: &mut [u8]);
+}
+
+/// Implements [`ElfStruct`], given the size of every multi-byte field in the structure.
+macro_rules! elf_struct {
+	($ty:ty { $($field:ident: $size:literal),* $(,)? }) => {
+		impl ElfStruct for $ty {
+			#[cfg(feature = "endian-compat")]
+			fn swap_fields(bytes: &mut [u8]) {
+				$(bytes[mem::offset_of!($ty, $field)..][..$size].reverse();)*
+			}
+		}
+	};
+}
+
+elf_struct!(FileHeader {
+	object_type: 2,
+	instruction_set: 2,
+	elf_version: 4,
+	entry_point: 8,
+	program_table_offset: 8,
+	section_table_offset: 8,
+	flags: 4,
+	size: 2,
+	program_header_size: 2,
+	program_table_entries: 2,
+	section_header_size: 2,
+	section_table_entries: 2,
+	section_names_index: 2,
+});
+elf_struct!(FileHeader32 {
+	object_type: 2,
+	instruction_set: 2,
+	elf_version: 4,
+	entry_point: 4,
+	program_table_offset: 4,
+	section_table_offset: 4,
+	flags: 4,
+	size: 2,
+	program_header_size: 2,
+	program_table_entries: 2,
+	section_header_size: 2,
+	section_table_entries: 2,
+	section_names_index: 2,
+});
+elf_struct!(ProgramHeader {
+	program_type: 4,
+	flags: 4,
+	offset: 8,
+	address: 8,
+	physical_address: 8,
+	file_size: 8,
+	memory_size: 8,
+	alignment: 8,
+});
+elf_struct!(ProgramHeader32 {
+	program_type: 4,
+	offset: 4,
+	address: 4,
+	physical_address: 4,
+	file_size: 4,
+	memory_size: 4,
+	flags: 4,
+	alignment: 4,
+});
+elf_struct!(SectionHeader {
+	name_offset: 4,
+	section_type: 4,
+	flags: 8,
+	address: 8,
+	offset: 8,
+	size: 8,
+	link: 4,
+	info: 4,
+	alignment: 8,
+	entry_size: 8,
+});
+elf_struct!(SectionHeader32 {
+	name_offset: 4,
+	section_type: 4,
+	flags: 4,
+	address: 4,
+	offset: 4,
+	size: 4,
+	link: 4,
+	info: 4,
+	alignment: 4,
+	entry_size: 4,
+});
+elf_struct!(Symbol {
+	name_offset: 4,
+	section_index: 2,
+	value: 8,
+	size: 8,
+});
+
+/// Reads a `T` from the start of `bytes`. If `swapped` is set, `bytes` has the opposite
+/// endianness, and the fields are byte-swapped as they're read.
+///
+/// Panics if `bytes` is too short.
+pub(crate) fn read<T: ElfStruct>(bytes: &[u8], swapped: bool) -> T {
+	let bytes = &bytes[..mem::size_of::<T>()];
+
+	#[cfg(feature = "endian-compat")]
+	if swapped {
+		// Every ELF structure fits in 64 bytes
+		let mut buffer = [0; 64];
+		let buffer = &mut buffer[..bytes.len()];
+		buffer.copy_from_slice(bytes);
+		T::swap_fields(buffer);
+
+		return unsafe { buffer.as_ptr().cast::<T>().read_unaligned() };
+	}
+	#[cfg(not(feature = "endian-compat"))]
+	let _ = swapped;
+
+	// The ELF structures are packed, so they don't need to be aligned
+	unsafe { bytes.as_ptr().cast::<T>().read_unaligned() }
+}
@@ -0,0 +1,68 @@
+//! Jumps to a loaded ELF's entry point.
+//!
+//! The entry point in the file header is just an address. Calling it means turning that address
+//! into a function pointer, which is easy to get wrong - the address has to be relocated, the
+//! function has to take the right arguments, and it should never return. [`EntryPoint`] does that
+//! once, so loaders don't each have to `mem::transmute` a raw address.
+
+use {crate::*, core::marker::PhantomData};
+
+/// A loaded ELF's entry point, which takes `Args` as its only argument and never returns. For
+/// example, the kernel's entry point is an `EntryPoint<&BootInfo>`.
+///
+/// The entry point must be an `extern "C"` function.
+pub struct EntryPoint<Args> {
+	address: u64,
+	_args: PhantomData<fn(Args)>,
+}
+impl<Args> EntryPoint<Args> {
+	/// Makes an entry point from an address that's already been relocated. Prefer
+	/// [`FileHeader::entry_point`], which checks the address.
+	///
+	/// # Safety
+	/// `address` must be the address of an `extern "C" fn(Args) -> !`.
+	pub unsafe fn new(address: u64) -> Self {
+		Self {
+			address,
+			_args: PhantomData,
+		}
+	}
+
+	/// The relocated address of the entry point.
+	pub fn address(&self) -> u64 {
+		self.address
+	}
+
+	/// Jumps to the entry point, passing it `args`.
+	///
+	/// # Safety
+	/// The ELF must be loaded and relocated (see [`FileHeader::load`]) at the base this entry point
+	/// was made with, and its entry point must be an `extern "C" fn(Args) -> !`.
+	pub unsafe fn jump(self, args: Args) -> ! {
+		let entry: extern "C" fn(Args) -> ! =
+			unsafe { core::mem::transmute(self.address as usize) };
+		entry(args)
+	}
+}
+
+impl FileHeader {
+	/// The ELF's entry point, relocated to `base`. Errors if the ELF doesn't have an entry point,
+	/// or it isn't in an executable `PT_LOAD` segment.
+	///
+	/// `elf` is the entire ELF file, starting at this header.
+	pub fn entry_point<Args>(&self, elf: &[u8], base: u64) -> Result<EntryPoint<Args>, ElfError> {
+		let entry = self.entry_point;
+		let executable = self.program_headers(elf)?.any(|segment| {
+			segment.is_type(ProgramType::Load)
+				&& segment.executable()
+				&& segment.address <= entry
+				&& entry - segment.address < segment.memory_size
+		});
+		if entry == 0 || !executable {
+			return Err(ElfError::BadEntryPoint);
+		}
+
+		let address = base.checked_add(entry).ok_or(ElfError::BadEntryPoint)?;
+		Ok(unsafe { EntryPoint::new(address) })
+	}
+}
@@ -1,29 +1,82 @@
 #![no_std]
 
+pub mod dynamic;
+pub mod elf;
+mod endian;
+pub mod entry;
+pub mod load;
+pub mod notes;
+pub mod relocate;
 pub mod structs;
-pub use structs::*;
+pub mod symbols;
+pub mod tls;
+pub use {
+	dynamic::DynamicEntry,
+	elf::Elf,
+	entry::EntryPoint,
+	load::*,
+	notes::{BuildId, Note},
+	structs::*,
+	symbols::*,
+	tls::{TlsLayout, TlsTemplate},
+};
 
-use core::mem;
+use {
+	core::{mem, slice},
+	endian::read,
+};
 
 /// Frieren failed to cast a spell
+#[derive(Debug)]
 pub enum ElfError {
 	/// Couldn't find the magic bytes in the ELF file
 	NoMagicBytes,
-	/// ELF is 32-bit, not 64-bit
-	/// TODO: There's probably a way to use 32-bit compat...
+	/// ELF is 32-bit, and this only supports 64-bit ELFs. [`Elf`] can read 32-bit ELFs, but
+	/// Frieren can only load 64-bit ones.
 	Bitness32,
-	/// ELF's endianness didn't match the native endianness
-	/// TODO: Is this actually an error? Maybe there's compat
+	/// The ELF's bitness byte was neither 32-bit nor 64-bit.
+	BadBitness,
+	/// ELF's endianness didn't match the native endianness. With the `endian-compat` feature,
+	/// [`Elf`] can still read these ELFs, but they can't be loaded.
 	BadEndianness,
 	/// The ABI wasn't SystemV
 	BadABI,
 	/// The ELF wasn't v1
 	BadVersion,
+	/// The ELF's object type isn't one of the types in [`ObjectType`], and isn't in the OS-specific
+	/// or processor-specific ranges
+	BadObjectType,
 	/// The reported size of a header in the file header didn't match the size of our structs
-	/// (ie `FileHeader.size` != `mem::size_of::<FileHeader>()`).
+	/// (ie `FileHeader.size` != `mem::size_of::<FileHeader>()`). Empty tables (like the program
+	/// header table in object files) can have any header size.
 	BadHeaderSize(Header),
+	/// A table or segment is (at least partially) past the end of the ELF file.
+	Truncated,
+	/// A segment's alignment wasn't a power of 2, or its address and offset weren't congruent
+	/// modulo its alignment.
+	BadAlignment,
+	/// A segment is bigger in the file than it is in memory.
+	BadSegmentSize,
+	/// The [`SegmentWriter`] couldn't provide memory for a segment.
+	SegmentMemory,
+	/// A relocation has a type Frieren doesn't support.
+	UnsupportedRelocation(u32),
+	/// A relocation uses a symbol that isn't defined in the ELF. Frieren doesn't load shared
+	/// libraries, so it can't resolve those.
+	UndefinedSymbol(u32),
+	/// A relocation's target address isn't in a loaded segment.
+	BadRelocation,
+	/// The ELF is for an instruction set Frieren can't load. Frieren can read ELFs for any
+	/// instruction set, but can only load [`Arch::LOADABLE`] ones.
+	UnsupportedArch(Arch),
+	/// The ELF's entry point is 0, or isn't in an executable segment.
+	BadEntryPoint,
+	/// The `PT_TLS` segment's alignment isn't a power of 2, or it's bigger in the file than in
+	/// memory.
+	BadTls,
 }
 
+#[derive(Debug)]
 pub enum Header {
 	Section,
 	Program,
@@ -47,11 +100,17 @@ impl FileHeader {
 			ElfError::BadEndianness
 		} else if header.elf_version != 1 || header.header_version != 1 {
 			ElfError::BadVersion
-		} else if header.abi != ABI::SystemV {
+		} else if header.abi != ABI::SystemV as u8 {
 			ElfError::BadABI
-		} else if header.section_header_size != mem::size_of::<SectionHeader>() as u16 {
+		} else if ObjectType::try_from(header.object_type).is_err() {
+			ElfError::BadObjectType
+		} else if header.section_table_entries != 0
+			&& header.section_header_size != mem::size_of::<SectionHeader>() as u16
+		{
 			ElfError::BadHeaderSize(Header::Section)
-		} else if header.program_header_size != mem::size_of::<ProgramHeader>() as u16 {
+		} else if header.program_table_entries != 0
+			&& header.program_header_size != mem::size_of::<ProgramHeader>() as u16
+		{
 			ElfError::BadHeaderSize(Header::Program)
 		} else if header.size != mem::size_of::<FileHeader>() as u16 {
 			ElfError::BadHeaderSize(Header::File)
@@ -60,6 +119,20 @@ impl FileHeader {
 		})
 	}
 
+	/// The ABI this ELF targets, or `None` if it's an unknown or architecture-specific ABI.
+	pub fn abi(&self) -> Option<ABI> {
+		ABI::try_from(self.abi).ok()
+	}
+	/// The type of this ELF, or `None` if it's not a known type and isn't in the OS-specific or
+	/// processor-specific ranges.
+	pub fn object_type(&self) -> Option<ObjectType> {
+		ObjectType::try_from(self.object_type).ok()
+	}
+	/// The instruction set this ELF targets.
+	pub fn arch(&self) -> Arch {
+		Arch::from(self.instruction_set)
+	}
+
 	/// Returns the (inclusive start, exclusive end) range that holds the section table.
 	pub fn section_table_range(&self) -> (usize, usize) {
 		let start = self.section_table_offset as usize;
@@ -67,6 +140,146 @@ impl FileHeader {
 
 		(start, start + len)
 	}
+
+	/// Returns the (inclusive start, exclusive end) range that holds the program header table.
+	pub fn program_table_range(&self) -> (usize, usize) {
+		let start = self.program_table_offset as usize;
+		let len = mem::size_of::<ProgramHeader>() * self.program_table_entries as usize;
+
+		(start, start + len)
+	}
+
+	/// Iterates over the program headers in the ELF. `elf` is the entire ELF file, starting at
+	/// this header. Errors if the program header table isn't inside `elf`.
+	pub fn program_headers<'a>(
+		&self,
+		elf: &'a [u8],
+	) -> Result<impl Iterator<Item = &'a ProgramHeader> + 'a, ElfError> {
+		Ok(self.program_table(elf)?.iter())
+	}
+	/// The program header table, as a slice.
+	pub(crate) fn program_table<'a>(&self, elf: &'a [u8]) -> Result<&'a [ProgramHeader], ElfError> {
+		table(elf, self.program_table_offset, self.program_table_entries)
+	}
+
+	/// Iterates over the section headers in the ELF. `elf` is the entire ELF file, starting at
+	/// this header. Errors if the section header table isn't inside `elf`.
+	pub fn section_headers<'a>(
+		&self,
+		elf: &'a [u8],
+	) -> Result<impl Iterator<Item = &'a SectionHeader> + 'a, ElfError> {
+		Ok(self.section_table(elf)?.iter())
+	}
+	/// The section header table, as a slice.
+	pub(crate) fn section_table<'a>(&self, elf: &'a [u8]) -> Result<&'a [SectionHeader], ElfError> {
+		table(elf, self.section_table_offset, self.section_table_entries)
+	}
+
+	/// The string table with the sections' names, or `None` if the ELF doesn't have one.
+	///
+	/// `elf` is the entire ELF file, starting at this header.
+	pub fn section_names<'a>(&self, elf: &'a [u8]) -> Result<Option<StringTable<'a>>, ElfError> {
+		// Index 0 means the ELF doesn't have a section name table
+		let index = self.section_names_index as usize;
+		if index == 0 {
+			return Ok(None);
+		}
+
+		let section = self
+			.section_table(elf)?
+			.get(index)
+			.ok_or(ElfError::Truncated)?;
+		let bytes = elf.get(section.file_range()?).ok_or(ElfError::Truncated)?;
+
+		Ok(Some(StringTable { bytes }))
+	}
+
+	/// Finds a section by its name (eg `.text`). Returns `None` if there's no section with that
+	/// name, or the ELF doesn't have section names.
+	///
+	/// `elf` is the entire ELF file, starting at this header.
+	pub fn section_by_name<'a>(
+		&self,
+		elf: &'a [u8],
+		name: &str,
+	) -> Result<Option<&'a SectionHeader>, ElfError> {
+		let Some(names) = self.section_names(elf)? else {
+			return Ok(None);
+		};
+
+		Ok(self
+			.section_table(elf)?
+			.iter()
+			.find(|section| names.get(section.name_offset) == Some(name.as_bytes())))
+	}
+}
+
+/// Gets a table of `entries` `T`s at `offset` in the ELF file, erroring if any of it is out of
+/// bounds.
+fn table<T>(elf: &[u8], offset: u64, entries: u16) -> Result<&[T], ElfError> {
+	table_bytes(elf, offset, entries, mem::size_of::<T>()).map(cast_slice)
+}
+
+/// Gets the bytes of a table of `entries` entries that are each `entry_size` bytes, at `offset` in
+/// the ELF file. Errors if any of it is out of bounds.
+pub(crate) fn table_bytes(
+	elf: &[u8],
+	offset: u64,
+	entries: u16,
+	entry_size: usize,
+) -> Result<&[u8], ElfError> {
+	let start = usize::try_from(offset).map_err(|_| ElfError::Truncated)?;
+	let end = (entries as usize)
+		.checked_mul(entry_size)
+		.and_then(|len| start.checked_add(len))
+		.ok_or(ElfError::Truncated)?;
+
+	elf.get(start..end).ok_or(ElfError::Truncated)
+}
+
+/// Reinterprets bytes from the ELF file as a slice of `T`s. Any bytes left over at the end are
+/// ignored. `T` must be one of the packed ELF structs, so it doesn't need to be aligned and any
+/// bytes are valid for it.
+pub(crate) fn cast_slice<T>(bytes: &[u8]) -> &[T] {
+	unsafe { slice::from_raw_parts(bytes.as_ptr().cast(), bytes.len() / mem::size_of::<T>()) }
+}
+
+impl FileHeader32 {
+	/// Takes a raw pointer to a 32-bit file header, verifies its contents, and errors if anything
+	/// is wrong. See [`FileHeader::try_from_raw`].
+	///
+	/// # Safety
+	/// - `ptr` must be a non-null, aligned pointer
+	/// - `ptr` must live for at least `'a`
+	pub unsafe fn try_from_raw<'a>(ptr: *const FileHeader32) -> Result<&'a Self, ElfError> {
+		let header = unsafe { &*ptr };
+
+		Err(if header.magic_bytes != [0x7F, 0x45, 0x4C, 0x46] {
+			ElfError::NoMagicBytes
+		} else if header.bitness != Bitness::X32 {
+			ElfError::BadBitness
+		} else if header.endianess != Endianess::NATIVE {
+			ElfError::BadEndianness
+		} else if header.elf_version != 1 || header.header_version != 1 {
+			ElfError::BadVersion
+		} else if header.abi != ABI::SystemV as u8 {
+			ElfError::BadABI
+		} else if ObjectType::try_from(header.object_type).is_err() {
+			ElfError::BadObjectType
+		} else if header.section_table_entries != 0
+			&& header.section_header_size != mem::size_of::<SectionHeader32>() as u16
+		{
+			ElfError::BadHeaderSize(Header::Section)
+		} else if header.program_table_entries != 0
+			&& header.program_header_size != mem::size_of::<ProgramHeader32>() as u16
+		{
+			ElfError::BadHeaderSize(Header::Program)
+		} else if header.size != mem::size_of::<FileHeader32>() as u16 {
+			ElfError::BadHeaderSize(Header::File)
+		} else {
+			return Ok(header);
+		})
+	}
 }
 
 // Old code, just here for when I implement ELF loading
@@ -0,0 +1,179 @@
+//! Loads an ELF's segments into memory.
+//!
+//! Only `PT_LOAD` segments get loaded. Frieren doesn't know how memory is managed wherever it's
+//! being used - in the bootloader everything's identity mapped, but the kernel will have its own
+//! page tables - so the caller decides where each segment actually goes with a [`SegmentWriter`].
+
+use {
+	crate::*,
+	core::{ops::Range, slice},
+};
+
+/// Decides where [`FileHeader::load_segments`] copies each segment to.
+pub trait SegmentWriter {
+	/// Returns the memory to load `segment` into. The slice must be at least
+	/// [`ProgramHeader::memory_size`] bytes long, and its start must be congruent to
+	/// [`ProgramHeader::address`] modulo [`ProgramHeader::alignment`] (if the memory will be mapped
+	/// at `address`, this just means it has to be in the right spot in its page).
+	///
+	/// Returns `None` if the memory can't be provided.
+	fn segment_memory(&mut self, segment: &ProgramHeader) -> Option<&mut [u8]>;
+
+	/// Called after `segment` has been copied, so its permissions can be applied (see
+	/// [`ProgramHeader::flags`]). Does nothing by default.
+	fn protect(&mut self, _segment: &ProgramHeader) {}
+
+	/// Writes a relocated 64-bit value to `address` (a virtual address in the ELF). See
+	/// [`FileHeader::apply_relocations`]. Relocations are applied after every segment is loaded
+	/// and protected, so this has to work even if `address` is in a read-only segment.
+	///
+	/// Returns `None` if `address` isn't in a loaded segment.
+	fn relocate(&mut self, address: u64, value: u64) -> Option<()>;
+}
+
+/// A [`SegmentWriter`] that loads every segment at a fixed offset from its address: a segment at
+/// `address` is loaded to `base + address`. Position-independent ELFs usually start at address 0,
+/// so `base` is just where the ELF gets loaded.
+///
+/// It can load at most [`OffsetWriter::MAX_SEGMENTS`] segments, since it remembers where each
+/// one is to check relocations.
+pub struct OffsetWriter {
+	base: usize,
+	/// The addresses (in the ELF) of the segments that have been loaded.
+	segments: [Range<u64>; Self::MAX_SEGMENTS],
+	segment_count: usize,
+}
+impl OffsetWriter {
+	/// The most `PT_LOAD` segments this can load.
+	pub const MAX_SEGMENTS: usize = 16;
+
+	/// # Safety
+	/// The memory every segment will be loaded to must be writable, and can't be used by anything
+	/// else.
+	pub unsafe fn new(base: usize) -> Self {
+		Self {
+			base,
+			segments: [const { 0..0 }; Self::MAX_SEGMENTS],
+			segment_count: 0,
+		}
+	}
+}
+impl SegmentWriter for OffsetWriter {
+	fn segment_memory(&mut self, segment: &ProgramHeader) -> Option<&mut [u8]> {
+		if self.segment_count == Self::MAX_SEGMENTS {
+			return None;
+		}
+
+		let address = self
+			.base
+			.checked_add(usize::try_from(segment.address).ok()?)?;
+		let size = usize::try_from(segment.memory_size).ok()?;
+		let end = segment.address.checked_add(segment.memory_size)?;
+
+		self.segments[self.segment_count] = segment.address..end;
+		self.segment_count += 1;
+
+		Some(unsafe { slice::from_raw_parts_mut(address as *mut u8, size) })
+	}
+
+	fn relocate(&mut self, address: u64, value: u64) -> Option<()> {
+		// The whole value has to land in one loaded segment, or this would write over memory the
+		// ELF doesn't own
+		let end = address.checked_add(size_of::<u64>() as u64)?;
+		self.segments[..self.segment_count]
+			.iter()
+			.find(|segment| segment.start <= address && end <= segment.end)?;
+
+		let address = self.base.checked_add(usize::try_from(address).ok()?)?;
+		unsafe { (address as *mut u64).write_unaligned(value) };
+
+		Some(())
+	}
+}
+
+impl FileHeader {
+	/// Loads the ELF at `base`: copies its segments with [`FileHeader::load_segments`], then
+	/// applies its relocations with [`FileHeader::apply_relocations`].
+	pub fn load(
+		&self,
+		elf: &[u8],
+		base: u64,
+		writer: &mut impl SegmentWriter,
+	) -> Result<(), ElfError> {
+		self.load_segments(elf, writer)?;
+		self.apply_relocations(elf, base, writer)
+	}
+
+	/// Copies every `PT_LOAD` segment in the ELF to the memory given by `writer`. `elf` is the
+	/// entire ELF file, starting at this header.
+	///
+	/// The bytes that are in the file are copied, and the rest of each segment (its
+	/// [`ProgramHeader::memory_size`] past its [`ProgramHeader::file_size`]) is zeroed. Errors if
+	/// the ELF isn't for [`Arch::LOADABLE`].
+	pub fn load_segments(
+		&self,
+		elf: &[u8],
+		writer: &mut impl SegmentWriter,
+	) -> Result<(), ElfError> {
+		let arch = self.arch();
+		if arch != Arch::LOADABLE {
+			return Err(ElfError::UnsupportedArch(arch));
+		}
+
+		for segment in self.program_headers(elf)? {
+			if !segment.is_type(ProgramType::Load) {
+				continue;
+			}
+
+			let alignment = segment.alignment;
+			if alignment > 1
+				&& (!alignment.is_power_of_two()
+					|| segment.address % alignment != segment.offset % alignment)
+			{
+				return Err(ElfError::BadAlignment);
+			}
+
+			if segment.file_size > segment.memory_size {
+				return Err(ElfError::BadSegmentSize);
+			}
+			let memory_size =
+				usize::try_from(segment.memory_size).map_err(|_| ElfError::SegmentMemory)?;
+
+			let src = elf.get(segment.file_range()?).ok_or(ElfError::Truncated)?;
+			let dest = writer
+				.segment_memory(segment)
+				.and_then(|dest| dest.get_mut(..memory_size))
+				.ok_or(ElfError::SegmentMemory)?;
+			let (data, bss) = dest.split_at_mut(src.len());
+			data.copy_from_slice(src);
+			// The rest of the segment (usually `.bss`) isn't in the file, and has to be zeroed
+			bss.fill(0);
+
+			writer.protect(segment);
+		}
+
+		Ok(())
+	}
+}
+
+impl ProgramHeader {
+	/// The range of bytes this segment occupies in the ELF file.
+	pub fn file_range(&self) -> Result<Range<usize>, ElfError> {
+		file_range(self.offset, self.file_size)
+	}
+}
+impl SectionHeader {
+	/// The range of bytes this section occupies in the ELF file. Meaningless for
+	/// [`SectionType::NoBits`] sections, which don't take up any space in the file.
+	pub fn file_range(&self) -> Result<Range<usize>, ElfError> {
+		file_range(self.offset, self.size)
+	}
+}
+
+fn file_range(offset: u64, size: u64) -> Result<Range<usize>, ElfError> {
+	let start = usize::try_from(offset).map_err(|_| ElfError::Truncated)?;
+	let len = usize::try_from(size).map_err(|_| ElfError::Truncated)?;
+	let end = start.checked_add(len).ok_or(ElfError::Truncated)?;
+
+	Ok(start..end)
+}
@@ -0,0 +1,184 @@
+//! Reads notes, which are small, tagged blobs of extra information about an ELF.
+//!
+//! Notes live in `PT_NOTE` segments (and `SHT_NOTE` sections, for object files that don't have
+//! segments). Each note has a name that says who defined it (eg `GNU`), a type that's specific to
+//! that name, and a descriptor with the actual data. The one BS cares about is the GNU build ID -
+//! a hash the linker generates, which uniquely identifies a build of a binary.
+//!
+//! Resources:
+//! - https://refspecs.linuxfoundation.org/elf/gabi4+/ch5.pheader.html#note_section
+//! - https://man7.org/linux/man-pages/man5/elf.5.html (see "Notes")
+
+use {
+	crate::*,
+	core::fmt::{self, Display, Formatter},
+};
+
+/// The note type of a GNU build ID. Its name is [`GNU_NOTE_NAME`].
+pub const NT_GNU_BUILD_ID: u32 = 3;
+/// The name of notes defined by GNU.
+pub const GNU_NOTE_NAME: &[u8] = b"GNU";
+
+/// A single note.
+#[derive(Clone, Copy)]
+pub struct Note<'a> {
+	/// Who defined this note, without its NUL terminator.
+	pub name: &'a [u8],
+	/// The note's type. What this means depends on the name.
+	pub kind: u32,
+	/// The note's data.
+	pub desc: &'a [u8],
+}
+
+/// Iterates over the notes in a note segment or section. Stops early if a note is malformed.
+#[derive(Clone)]
+pub struct Notes<'a> {
+	bytes: &'a [u8],
+	/// What the name and descriptor are padded to.
+	alignment: usize,
+	/// If the note's integers have the opposite endianness.
+	swapped: bool,
+}
+impl<'a> Notes<'a> {
+	/// Reads the notes in a note segment or section. `alignment` is the segment's or section's
+	/// alignment - notes are usually padded to 4 bytes, but some 64-bit ones are padded to 8.
+	pub fn new(bytes: &'a [u8], alignment: u64) -> Self {
+		Self {
+			bytes,
+			alignment: if alignment == 8 { 8 } else { 4 },
+			swapped: false,
+		}
+	}
+
+	/// Reads a `u32` from the start of `bytes`.
+	fn u32(&self, bytes: &[u8]) -> Option<u32> {
+		let value = u32::from_ne_bytes(bytes.get(..4)?.try_into().ok()?);
+		Some(if self.swapped {
+			value.swap_bytes()
+		} else {
+			value
+		})
+	}
+
+	/// Rounds `len` up to the note alignment.
+	fn pad(&self, len: usize) -> Option<usize> {
+		len.checked_next_multiple_of(self.alignment)
+	}
+}
+impl<'a> Iterator for Notes<'a> {
+	type Item = Note<'a>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let note = (|| {
+			let name_size = self.u32(self.bytes)? as usize;
+			let desc_size = self.u32(self.bytes.get(4..)?)? as usize;
+			let kind = self.u32(self.bytes.get(8..)?)?;
+
+			// The name comes right after the 12-byte header, and the descriptor and next note are
+			// aligned relative to the start of this note
+			let name_start = 12;
+			let desc_start = self.pad(name_start + name_size)?;
+			let end = self.pad(desc_start.checked_add(desc_size)?)?;
+
+			let name = self.bytes.get(name_start..name_start + name_size)?;
+			let desc = self.bytes.get(desc_start..desc_start + desc_size)?;
+			// The descriptor's padding can be missing on the last note
+			let end = end.min(self.bytes.len());
+
+			Some((
+				Note {
+					// The name's size includes its NUL terminator
+					name: name.strip_suffix(&[0]).unwrap_or(name),
+					kind,
+					desc,
+				},
+				end,
+			))
+		})();
+
+		match note {
+			Some((note, end)) => {
+				self.bytes = &self.bytes[end..];
+				Some(note)
+			}
+			None => {
+				self.bytes = &[];
+				None
+			}
+		}
+	}
+}
+
+/// A GNU build ID. Displays as a hex string, like `readelf` and `file` show it.
+#[derive(Clone, Copy)]
+pub struct BuildId<'a>(pub &'a [u8]);
+impl Display for BuildId<'_> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		for byte in self.0 {
+			write!(f, "{byte:02x}")?;
+		}
+
+		Ok(())
+	}
+}
+
+impl FileHeader {
+	/// Iterates over the notes in the ELF's `PT_NOTE` segments. If the ELF doesn't have any (ie,
+	/// it's an object file), this uses its `SHT_NOTE` sections instead.
+	///
+	/// `elf` is the entire ELF file, starting at this header.
+	pub fn notes<'a>(
+		&self,
+		elf: &'a [u8],
+	) -> Result<impl Iterator<Item = Note<'a>> + 'a, ElfError> {
+		Ok(notes(
+			elf,
+			self.program_table(elf)?.iter().copied(),
+			self.section_table(elf)?.iter().copied(),
+			false,
+		))
+	}
+
+	/// Finds the GNU build ID, or `None` if the ELF wasn't linked with one.
+	///
+	/// `elf` is the entire ELF file, starting at this header.
+	pub fn build_id<'a>(&self, elf: &'a [u8]) -> Result<Option<BuildId<'a>>, ElfError> {
+		Ok(build_id(self.notes(elf)?))
+	}
+}
+
+/// Finds the notes in an ELF's segments, or its sections if it doesn't have any note segments.
+/// Segments and sections that aren't inside `elf` are skipped. `swapped` is set if the ELF has the
+/// opposite endianness.
+pub(crate) fn notes<'a>(
+	elf: &'a [u8],
+	segments: impl Iterator<Item = ProgramHeader> + 'a,
+	sections: impl Iterator<Item = SectionHeader> + 'a,
+	swapped: bool,
+) -> impl Iterator<Item = Note<'a>> + 'a {
+	let mut segments = segments
+		.filter(|segment| segment.is_type(ProgramType::Note))
+		.map(|segment| (segment.file_range(), segment.alignment))
+		.peekable();
+	let use_sections = segments.peek().is_none();
+	let sections = sections
+		.filter(move |section| use_sections && section.is_type(SectionType::Note))
+		.map(|section| (section.file_range(), section.alignment));
+
+	segments
+		.chain(sections)
+		.flat_map(move |(range, alignment)| {
+			let bytes = range.ok().and_then(|range| elf.get(range)).unwrap_or(&[]);
+			Notes {
+				swapped,
+				..Notes::new(bytes, alignment)
+			}
+		})
+}
+
+/// Finds the GNU build ID in a list of notes.
+pub(crate) fn build_id<'a>(mut notes: impl Iterator<Item = Note<'a>>) -> Option<BuildId<'a>> {
+	notes
+		.find(|note| note.name == GNU_NOTE_NAME && note.kind == NT_GNU_BUILD_ID)
+		.map(|note| BuildId(note.desc))
+}
@@ -0,0 +1,108 @@
+//! Applies relocations, so position-independent ELFs can be loaded at any address.
+//!
+//! Position-independent code still has some absolute addresses in it, like pointers stored in
+//! statics and the global offset table. The linker can't know those addresses ahead of time, so
+//! it leaves relocations that say how to compute each one once the ELF's load address (its
+//! "base") is known.
+//!
+//! Resources:
+//! - https://gitlab.com/x86-psABIs/x86-64-ABI (section 4.4, "Relocation")
+//! - https://wiki.osdev.org/ELF_Tutorial#Relocation_Sections
+
+use {
+	crate::{dynamic::*, *},
+	core::mem,
+};
+
+/// Does nothing.
+pub const R_X86_64_NONE: u32 = 0;
+/// The symbol's address, plus the addend.
+pub const R_X86_64_64: u32 = 1;
+/// The symbol's address. Used for global offset table entries.
+pub const R_X86_64_GLOB_DAT: u32 = 6;
+/// The symbol's address. Used for procedure linkage table entries.
+pub const R_X86_64_JUMP_SLOT: u32 = 7;
+/// The base address, plus the addend. This is most of the relocations in a position-independent
+/// executable.
+pub const R_X86_64_RELATIVE: u32 = 8;
+
+impl FileHeader {
+	/// Applies every relocation listed in the dynamic section ([`DT_RELA`] and [`DT_JMPREL`]),
+	/// assuming the ELF was loaded at `base`. Relocated values are written with
+	/// [`SegmentWriter::relocate`], so this should be called after
+	/// [`FileHeader::load_segments`]. ELFs without a dynamic section don't have any relocations to
+	/// apply.
+	///
+	/// `elf` is the entire ELF file, starting at this header.
+	pub fn apply_relocations(
+		&self,
+		elf: &[u8],
+		base: u64,
+		writer: &mut impl SegmentWriter,
+	) -> Result<(), ElfError> {
+		let Some(info) = self.dynamic_info(elf)? else {
+			return Ok(());
+		};
+
+		let symbol = |index: u32| -> Option<&Symbol> {
+			let size = mem::size_of::<Symbol>() as u64;
+			let address = info.symtab? + index as u64 * size;
+			let bytes = self.bytes_at(elf, address, size)?;
+			cast_slice(bytes).first()
+		};
+		let mut tables = [(info.rela, info.rela_size), (None, 0)];
+		// The PLT's relocations can be either REL or RELA; only RELA is used on x86_64
+		if info.plt_rel == Some(DT_RELA) {
+			tables[1] = (info.jmprel, info.plt_rel_size);
+		}
+
+		for (address, size) in tables {
+			let Some(address) = address else {
+				continue;
+			};
+			let bytes = self
+				.bytes_at(elf, address, size)
+				.ok_or(ElfError::Truncated)?;
+
+			for relocation in cast_slice::<Relocation>(bytes) {
+				apply(relocation, symbol, base, writer)?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Applies a single relocation.
+fn apply<'a>(
+	relocation: &Relocation,
+	symbol: impl Fn(u32) -> Option<&'a Symbol>,
+	base: u64,
+	writer: &mut impl SegmentWriter,
+) -> Result<(), ElfError> {
+	let symbol_address = || {
+		let index = relocation.symbol();
+		let symbol = symbol(index).ok_or(ElfError::UndefinedSymbol(index))?;
+
+		if symbol.is_defined() {
+			Ok(base.wrapping_add(symbol.value))
+		} else if symbol.binding() == Symbol::BINDING_WEAK {
+			Ok(0)
+		} else {
+			Err(ElfError::UndefinedSymbol(index))
+		}
+	};
+	let addend = relocation.addend as u64;
+
+	let value = match relocation.kind() {
+		R_X86_64_NONE => return Ok(()),
+		R_X86_64_RELATIVE => base.wrapping_add(addend),
+		R_X86_64_64 => symbol_address()?.wrapping_add(addend),
+		R_X86_64_GLOB_DAT | R_X86_64_JUMP_SLOT => symbol_address()?,
+		kind => return Err(ElfError::UnsupportedRelocation(kind)),
+	};
+
+	writer
+		.relocate(relocation.offset, value)
+		.ok_or(ElfError::BadRelocation)
+}
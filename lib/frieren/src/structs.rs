@@ -2,8 +2,9 @@
 //! This also defines several enums present in those headers.
 
 /// The first few bytes of an ELF file. Contains general file information. Note that this structure
-/// looks somewhat different for 32-bit ELFs.
-#[repr(packed)]
+/// looks somewhat different for 32-bit ELFs; see [`FileHeader32`].
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
 pub struct FileHeader {
 	// This is technically in the identifier, a substructure in the header,
 	// but having all of these inside another field is annoying to work with.
@@ -15,21 +16,21 @@ pub struct FileHeader {
 	pub endianess: Endianess,
 	/// The version of the ELF header - should be 1 for the current version.
 	pub header_version: u8,
-	/// The ABI this file targets.
-	pub abi: ABI,
+	/// The ABI this file targets. See [`FileHeader::abi`].
+	pub abi: u8,
 	/// The version of the ABI this file targets.
 	pub abi_version: u8,
 	/// Unused bytes. I think this is here for alignment.
 	pub padding: [u8; 7],
 
 	// Back to header fields
-	/// The type of this ELF file - library, executable, etc.
-	pub object_type: ObjectType,
-	/// The targeted instruction set. There's so many values here, I didn't bother
-	/// making an enum for it.
+	/// The type of this ELF file - library, executable, etc. See [`FileHeader::object_type`].
+	pub object_type: u16,
+	/// The targeted instruction set. This is kept as a plain integer, since there's way more
+	/// values than [`Arch`] has variants; use [`FileHeader::arch`] to get it as an [`Arch`].
 	pub instruction_set: u16,
 	/// The version of this ELF file - should be 1 for the current version.
-	pub elf_version: u8,
+	pub elf_version: u32,
 	/// An offset to the entry point of this ELF file.
 	pub entry_point: u64,
 	/// An offset to the program header table of this ELF file.
@@ -55,10 +56,11 @@ pub struct FileHeader {
 
 /// Each program header describes a segment of an ELF file. These are only needed for executables
 /// and shared objects. A segment contains one or more sections.
-#[repr(packed)]
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
 pub struct ProgramHeader {
-	/// Defines the type for this segment.
-	pub program_type: ProgramType,
+	/// Defines the type for this segment. See [`ProgramHeader::program_type`].
+	pub program_type: u32,
 	/// Permissions for this segment. 1 = execute, 2 = write, 4 = read.
 	pub flags: u32,
 	/// Where the actual segment is in the file.
@@ -76,14 +78,43 @@ pub struct ProgramHeader {
 	/// should be positive and a power of 2, and then `address` should equal `offset % alignment`.
 	pub alignment: u64,
 }
+impl ProgramHeader {
+	/// Set in [`ProgramHeader::flags`] if the segment is executable.
+	pub const FLAG_EXECUTE: u32 = 1;
+	/// Set in [`ProgramHeader::flags`] if the segment is writable.
+	pub const FLAG_WRITE: u32 = 2;
+	/// Set in [`ProgramHeader::flags`] if the segment is readable.
+	pub const FLAG_READ: u32 = 4;
+
+	pub fn executable(&self) -> bool {
+		self.flags & Self::FLAG_EXECUTE != 0
+	}
+	pub fn writable(&self) -> bool {
+		self.flags & Self::FLAG_WRITE != 0
+	}
+	pub fn readable(&self) -> bool {
+		self.flags & Self::FLAG_READ != 0
+	}
+
+	/// The type of this segment. Returns `None` if the type isn't a known type, and isn't in the
+	/// OS-specific or processor-specific ranges.
+	pub fn program_type(&self) -> Option<ProgramType> {
+		ProgramType::try_from(self.program_type).ok()
+	}
+	/// If this header has the given type.
+	pub fn is_type(&self, program_type: ProgramType) -> bool {
+		self.program_type == u32::from(program_type)
+	}
+}
 
 /// Each section header describes a section of the ELF file.
-#[repr(packed)]
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
 pub struct SectionHeader {
 	/// An offset into the string table, representing this section's name.
 	pub name_offset: u32,
-	/// The type of this section.
-	pub section_type: SectionType,
+	/// The type of this section. See [`SectionHeader::section_type`].
+	pub section_type: u32,
 	/// Flags for this section.
 	pub flags: u64,
 	/// If this section should be loaded in memory, the address it should be loaded to.
@@ -103,65 +134,315 @@ pub struct SectionHeader {
 	/// this is 0.
 	pub entry_size: u64,
 }
+impl SectionHeader {
+	/// The type of this section. Returns `None` if the type isn't a known type, and isn't in the
+	/// OS-specific or processor-specific ranges.
+	pub fn section_type(&self) -> Option<SectionType> {
+		SectionType::try_from(self.section_type).ok()
+	}
+	/// If this header has the given type.
+	pub fn is_type(&self, section_type: SectionType) -> bool {
+		self.section_type == u32::from(section_type)
+	}
+}
+
+/// The 32-bit version of [`FileHeader`]. The fields are the same, except the entry point and table
+/// offsets are 32 bits wide. Converts to a [`FileHeader`] with [`From`].
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct FileHeader32 {
+	pub magic_bytes: [u8; 4],
+	pub bitness: Bitness,
+	pub endianess: Endianess,
+	pub header_version: u8,
+	pub abi: u8,
+	pub abi_version: u8,
+	pub padding: [u8; 7],
+	pub object_type: u16,
+	pub instruction_set: u16,
+	pub elf_version: u32,
+	pub entry_point: u32,
+	pub program_table_offset: u32,
+	pub section_table_offset: u32,
+	pub flags: u32,
+	pub size: u16,
+	pub program_header_size: u16,
+	pub program_table_entries: u16,
+	pub section_header_size: u16,
+	pub section_table_entries: u16,
+	pub section_names_index: u16,
+}
+impl From<&FileHeader32> for FileHeader {
+	fn from(header: &FileHeader32) -> Self {
+		Self {
+			magic_bytes: header.magic_bytes,
+			bitness: header.bitness,
+			endianess: header.endianess,
+			header_version: header.header_version,
+			abi: header.abi,
+			abi_version: header.abi_version,
+			padding: header.padding,
+			object_type: header.object_type,
+			instruction_set: header.instruction_set,
+			elf_version: header.elf_version,
+			entry_point: header.entry_point as u64,
+			program_table_offset: header.program_table_offset as u64,
+			section_table_offset: header.section_table_offset as u64,
+			flags: header.flags,
+			size: header.size,
+			program_header_size: header.program_header_size,
+			program_table_entries: header.program_table_entries,
+			section_header_size: header.section_header_size,
+			section_table_entries: header.section_table_entries,
+			section_names_index: header.section_names_index,
+		}
+	}
+}
 
-/// The type of a program header in the ELF file.
-#[repr(u32)]
-#[derive(PartialEq, Eq, Clone, Debug)]
-pub enum ProgramType {
-	/// An unused segment.
-	Null = 0,
-	/// A loadable segment. These must be loaded into memory.
-	Load = 1,
-	/// Info for dynamic linking.
-	Dynamic = 2,
-	/// Contains the path to an interpreter for the program.
-	Interpreter = 3,
-	/// Generic information.
-	Note = 4,
-	/// Reserved. Sections with this type don't conform to the ABI.
-	Lib = 5,
-	/// A segment with the program header table.
-	ProgramHeader = 6,
-	/// For thread-local storage.
-	ThreadLocal = 7,
-	// Others are OS/processor specific
-}
-
-/// The type of a section header in the ELF file.
-#[repr(u32)]
-#[derive(PartialEq, Eq, Clone, Debug)]
-pub enum SectionType {
-	/// Unused.
-	Null = 0,
-	/// Information defined by and for the program.
-	ProgramData = 1,
-	/// The symbol table.
-	SymbolTable = 2,
-	/// The string table, which holds all of the text in the ELF.
-	StringTable = 3,
-	/// Holds relocation entries with explicit addends.
-	RelocationsAddend = 4,
-	/// A symbol hash table.
-	HashTable = 5,
-	/// Information for dynamic linking.
-	Dynamic = 6,
-	/// Information that marks the file in some way.
-	Note = 7,
-	/// Just like `ProgramData`, except it holds no data in the actual file.
-	NoBits = 8,
-	/// Holds relocation entries without explicit addends.
-	Relocations = 9,
-	/// Reserved. Sections with this type don't conform to the ABI.
-	Lib = 10,
-	/// Similar to `SymbolTable`, but with less symbols - just the ones needed
-	/// for dynamic linking.
-	DynamicSymbols = 11,
-	// Others are program/processor specific
+/// The 32-bit version of [`ProgramHeader`]. Note that `flags` is in a different spot than in the
+/// 64-bit version. Converts to a [`ProgramHeader`] with [`From`].
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct ProgramHeader32 {
+	pub program_type: u32,
+	pub offset: u32,
+	pub address: u32,
+	pub physical_address: u32,
+	pub file_size: u32,
+	pub memory_size: u32,
+	pub flags: u32,
+	pub alignment: u32,
+}
+impl From<&ProgramHeader32> for ProgramHeader {
+	fn from(header: &ProgramHeader32) -> Self {
+		Self {
+			program_type: header.program_type,
+			flags: header.flags,
+			offset: header.offset as u64,
+			address: header.address as u64,
+			physical_address: header.physical_address as u64,
+			file_size: header.file_size as u64,
+			memory_size: header.memory_size as u64,
+			alignment: header.alignment as u64,
+		}
+	}
+}
+
+/// The 32-bit version of [`SectionHeader`]. Converts to a [`SectionHeader`] with [`From`].
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct SectionHeader32 {
+	pub name_offset: u32,
+	pub section_type: u32,
+	pub flags: u32,
+	pub address: u32,
+	pub offset: u32,
+	pub size: u32,
+	pub link: u32,
+	pub info: u32,
+	pub alignment: u32,
+	pub entry_size: u32,
+}
+impl From<&SectionHeader32> for SectionHeader {
+	fn from(header: &SectionHeader32) -> Self {
+		Self {
+			name_offset: header.name_offset,
+			section_type: header.section_type,
+			flags: header.flags as u64,
+			address: header.address as u64,
+			offset: header.offset as u64,
+			size: header.size as u64,
+			link: header.link,
+			info: header.info,
+			alignment: header.alignment as u64,
+			entry_size: header.entry_size as u64,
+		}
+	}
+}
+
+/// A relocation with an explicit addend, from a [`SectionType::RelocationsAddend`] section.
+#[repr(C, packed)]
+pub struct Relocation {
+	/// The address to apply the relocation at.
+	pub offset: u64,
+	/// The relocation's type (low 32 bits) and symbol index (high 32 bits).
+	pub info: u64,
+	/// A constant used to compute the relocated value.
+	pub addend: i64,
+}
+impl Relocation {
+	/// The type of this relocation. These are architecture-specific; see the `R_X86_64_*` consts
+	/// in [`crate::relocate`].
+	pub fn kind(&self) -> u32 {
+		self.info as u32
+	}
+	/// The index of the symbol this relocation uses in the symbol table.
+	pub fn symbol(&self) -> u32 {
+		(self.info >> 32) as u32
+	}
+}
+
+/// An entry in a symbol table.
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct Symbol {
+	/// An offset into the string table, representing this symbol's name.
+	pub name_offset: u32,
+	/// The symbol's type (low 4 bits) and binding (high 4 bits).
+	pub info: u8,
+	/// The symbol's visibility.
+	pub other: u8,
+	/// The index of the section this symbol is defined in, or 0 if it's undefined.
+	pub section_index: u16,
+	/// The symbol's value - usually its address.
+	pub value: u64,
+	/// The symbol's size, in bytes.
+	pub size: u64,
+}
+impl Symbol {
+	/// The symbol's binding when it's only visible in the file it's defined in.
+	pub const BINDING_LOCAL: u8 = 0;
+	/// The symbol's binding when it's visible to every file being linked.
+	pub const BINDING_GLOBAL: u8 = 1;
+	/// The symbol's binding when it's a weak symbol. Undefined weak symbols resolve to 0.
+	pub const BINDING_WEAK: u8 = 2;
+
+	/// The symbol's type when it doesn't have one.
+	pub const TYPE_NONE: u8 = 0;
+	/// The symbol's type when it's a variable, array, etc.
+	pub const TYPE_OBJECT: u8 = 1;
+	/// The symbol's type when it's a function.
+	pub const TYPE_FUNC: u8 = 2;
+	/// The symbol's type when it's a section.
+	pub const TYPE_SECTION: u8 = 3;
+	/// The symbol's type when it's the name of a source file.
+	pub const TYPE_FILE: u8 = 4;
+	/// The symbol's type when it's a thread-local variable.
+	pub const TYPE_TLS: u8 = 6;
+
+	/// If this symbol is defined in this ELF.
+	pub fn is_defined(&self) -> bool {
+		self.section_index != 0
+	}
+	/// The symbol's binding. See the `BINDING_*` consts.
+	pub fn binding(&self) -> u8 {
+		self.info >> 4
+	}
+	/// The symbol's type. See the `TYPE_*` consts.
+	pub fn kind(&self) -> u8 {
+		self.info & 0xF
+	}
+}
+
+/// Defines an enum for a type that's stored as a raw integer in the ELF, since values that aren't
+/// variants of the enum can't be read straight from the file. Every value in the `os` and
+/// `processor` ranges is valid, and becomes an `OsSpecific` or `ProcessorSpecific` variant.
+/// Conversions are done with [`TryFrom`] and [`From`].
+macro_rules! raw_enum {
+	(
+		$(#[$meta:meta])*
+		pub enum $name:ident($raw:ty) {
+			$($(#[$variant_meta:meta])* $variant:ident = $value:literal,)*
+		}
+		os: $os:pat,
+		processor: $processor:pat,
+	) => {
+		$(#[$meta])*
+		#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+		pub enum $name {
+			$($(#[$variant_meta])* $variant,)*
+			/// A type defined by the OS.
+			OsSpecific($raw),
+			/// A type defined by the processor.
+			ProcessorSpecific($raw),
+		}
+		impl TryFrom<$raw> for $name {
+			/// The raw value, if it isn't a known value or in the OS-specific or
+			/// processor-specific ranges.
+			type Error = $raw;
+
+			fn try_from(raw: $raw) -> Result<Self, Self::Error> {
+				match raw {
+					$($value => Ok(Self::$variant),)*
+					$os => Ok(Self::OsSpecific(raw)),
+					$processor => Ok(Self::ProcessorSpecific(raw)),
+					other => Err(other),
+				}
+			}
+		}
+		impl From<$name> for $raw {
+			fn from(value: $name) -> Self {
+				match value {
+					$($name::$variant => $value,)*
+					$name::OsSpecific(raw) | $name::ProcessorSpecific(raw) => raw,
+				}
+			}
+		}
+	};
+}
+
+raw_enum! {
+	/// The type of a program header in the ELF file.
+	pub enum ProgramType(u32) {
+		/// An unused segment.
+		Null = 0,
+		/// A loadable segment. These must be loaded into memory.
+		Load = 1,
+		/// Info for dynamic linking.
+		Dynamic = 2,
+		/// Contains the path to an interpreter for the program.
+		Interpreter = 3,
+		/// Generic information.
+		Note = 4,
+		/// Reserved. Sections with this type don't conform to the ABI.
+		Lib = 5,
+		/// A segment with the program header table.
+		ProgramHeader = 6,
+		/// For thread-local storage.
+		ThreadLocal = 7,
+	}
+	// Eg `PT_GNU_STACK`, which is in basically every ELF
+	os: 0x6000_0000..=0x6FFF_FFFF,
+	processor: 0x7000_0000..=0x7FFF_FFFF,
+}
+
+raw_enum! {
+	/// The type of a section header in the ELF file.
+	pub enum SectionType(u32) {
+		/// Unused.
+		Null = 0,
+		/// Information defined by and for the program.
+		ProgramData = 1,
+		/// The symbol table.
+		SymbolTable = 2,
+		/// The string table, which holds all of the text in the ELF.
+		StringTable = 3,
+		/// Holds relocation entries with explicit addends.
+		RelocationsAddend = 4,
+		/// A symbol hash table.
+		HashTable = 5,
+		/// Information for dynamic linking.
+		Dynamic = 6,
+		/// Information that marks the file in some way.
+		Note = 7,
+		/// Just like `ProgramData`, except it holds no data in the actual file.
+		NoBits = 8,
+		/// Holds relocation entries without explicit addends.
+		Relocations = 9,
+		/// Reserved. Sections with this type don't conform to the ABI.
+		Lib = 10,
+		/// Similar to `SymbolTable`, but with less symbols - just the ones needed
+		/// for dynamic linking.
+		DynamicSymbols = 11,
+	}
+	// Eg `SHT_GNU_HASH`
+	os: 0x6000_0000..=0x6FFF_FFFF,
+	processor: 0x7000_0000..=0x7FFF_FFFF,
 }
 
 /// If an ELF file is 32-bit or 64-bit.
 #[repr(u8)]
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum Bitness {
 	X32 = 1,
 	X64 = 2,
@@ -169,7 +450,7 @@ pub enum Bitness {
 
 /// If an ELF file is little endian or big endian.
 #[repr(u8)]
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum Endianess {
 	Little = 1,
 	Big = 2,
@@ -182,27 +463,98 @@ impl Endianess {
 	pub const NATIVE: Self = Self::Big;
 }
 
-/// The ELF file's type.
-#[repr(u16)]
-#[derive(PartialEq, Eq, Clone, Debug)]
-pub enum ObjectType {
-	None = 0,
-	/// I'm not sure, but think this is for compiler intermediaries.
-	Relocatable = 1,
-	/// A normal, executable program.
-	Exectuable = 2,
-	/// Wikipedia describes this as a shared object. `readelf` uses this for position-independent code.
-	/// Maybe it represents both. The spec is unfortunately quite vague.
-	Dyn = 3,
-	/// Unsure what core means.
-	Core = 4,
-	// Other values are OS-specific or processor-specific
+raw_enum! {
+	/// The ELF file's type.
+	pub enum ObjectType(u16) {
+		None = 0,
+		/// I'm not sure, but think this is for compiler intermediaries.
+		Relocatable = 1,
+		/// A normal, executable program.
+		Exectuable = 2,
+		/// Wikipedia describes this as a shared object. `readelf` uses this for position-independent code.
+		/// Maybe it represents both. The spec is unfortunately quite vague.
+		Dyn = 3,
+		/// Unsure what core means.
+		Core = 4,
+	}
+	os: 0xFE00..=0xFEFF,
+	processor: 0xFF00..=0xFFFF,
+}
+
+/// The instruction set an ELF targets. Only the common ones have variants - there's hundreds of
+/// others, which are [`Arch::Unknown`].
+///
+/// Resources:
+/// - https://refspecs.linuxfoundation.org/elf/gabi4+/ch4.eheader.html (see `e_machine`)
+/// - https://en.wikipedia.org/wiki/Executable_and_Linkable_Format#File_header
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Arch {
+	/// No specific instruction set.
+	None,
+	Sparc,
+	/// 32-bit x86.
+	X86,
+	Mips,
+	PowerPC,
+	PowerPC64,
+	S390,
+	/// 32-bit ARM.
+	Arm,
+	X86_64,
+	/// 64-bit ARM.
+	AArch64,
+	RiscV,
+	LoongArch,
+	/// Any other instruction set.
+	Unknown(u16),
+}
+impl Arch {
+	/// The instruction set Frieren can load ELFs for.
+	pub const LOADABLE: Self = Self::X86_64;
+}
+impl From<u16> for Arch {
+	fn from(value: u16) -> Self {
+		match value {
+			0 => Self::None,
+			2 => Self::Sparc,
+			3 => Self::X86,
+			8 => Self::Mips,
+			20 => Self::PowerPC,
+			21 => Self::PowerPC64,
+			22 => Self::S390,
+			40 => Self::Arm,
+			62 => Self::X86_64,
+			183 => Self::AArch64,
+			243 => Self::RiscV,
+			258 => Self::LoongArch,
+			other => Self::Unknown(other),
+		}
+	}
+}
+impl From<Arch> for u16 {
+	fn from(arch: Arch) -> Self {
+		match arch {
+			Arch::None => 0,
+			Arch::Sparc => 2,
+			Arch::X86 => 3,
+			Arch::Mips => 8,
+			Arch::PowerPC => 20,
+			Arch::PowerPC64 => 21,
+			Arch::S390 => 22,
+			Arch::Arm => 40,
+			Arch::X86_64 => 62,
+			Arch::AArch64 => 183,
+			Arch::RiscV => 243,
+			Arch::LoongArch => 258,
+			Arch::Unknown(other) => other,
+		}
+	}
 }
 
 /// The ABI the ELF targets. Taken from the list on Wikipedia:
 /// https://en.wikipedia.org/wiki/Executable_and_Linkable_Format#File_header
 #[repr(u8)]
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum ABI {
 	SystemV = 0,
 	HPUX = 1,
@@ -223,3 +575,32 @@ pub enum ABI {
 	NuxiCloud = 17,
 	OpenVOS = 18,
 }
+impl TryFrom<u8> for ABI {
+	/// The raw value, if it isn't one of the ABIs above. Values from 64 up are
+	/// architecture-specific.
+	type Error = u8;
+
+	fn try_from(raw: u8) -> Result<Self, Self::Error> {
+		Ok(match raw {
+			0 => Self::SystemV,
+			1 => Self::HPUX,
+			2 => Self::NetBSD,
+			3 => Self::Linux,
+			4 => Self::Hurd,
+			6 => Self::Solaris,
+			7 => Self::AIX,
+			8 => Self::IRIX,
+			9 => Self::FreeBSD,
+			10 => Self::Tru64,
+			11 => Self::NovellModesto,
+			12 => Self::OpenBSD,
+			13 => Self::OpenVMS,
+			14 => Self::NonStopKernel,
+			15 => Self::AROS,
+			16 => Self::FenixOS,
+			17 => Self::NuxiCloud,
+			18 => Self::OpenVOS,
+			other => return Err(other),
+		})
+	}
+}
@@ -0,0 +1,159 @@
+//! Reads the symbol tables and string tables in an ELF.
+//!
+//! An ELF can have two symbol tables: `.symtab`, which has every symbol (but is often stripped),
+//! and `.dynsym`, which only has the symbols needed for dynamic linking. Symbols don't store their
+//! names directly; instead, they store an offset into a string table, which is just a bunch of
+//! NUL-terminated strings packed together. Each symbol table's section header links to the string
+//! table its names are in.
+//!
+//! Resources:
+//! - https://refspecs.linuxfoundation.org/elf/gabi4+/ch4.symtab.html
+//! - https://refspecs.linuxfoundation.org/elf/gabi4+/ch4.strtab.html
+
+use {crate::*, core::mem};
+
+/// A string table - a list of NUL-terminated strings.
+#[derive(Clone, Copy)]
+pub struct StringTable<'a> {
+	pub bytes: &'a [u8],
+}
+impl<'a> StringTable<'a> {
+	/// Gets the string at `offset`, without its NUL terminator. Returns `None` if the offset is
+	/// out of bounds or the string isn't terminated.
+	pub fn get(&self, offset: u32) -> Option<&'a [u8]> {
+		let bytes = self.bytes.get(offset as usize..)?;
+		let len = bytes.iter().position(|byte| *byte == 0)?;
+
+		Some(&bytes[..len])
+	}
+
+	/// Gets the string at `offset` as a `str`. Returns `None` if it isn't valid UTF-8.
+	pub fn get_str(&self, offset: u32) -> Option<&'a str> {
+		core::str::from_utf8(self.get(offset)?).ok()
+	}
+}
+
+/// A symbol table and the string table its names are in.
+#[derive(Clone, Copy)]
+pub struct SymbolTable<'a> {
+	/// The raw bytes of the symbols. Use [`SymbolTable::iter`] or [`SymbolTable::get`] to read
+	/// them, since they may need to be byte-swapped.
+	symbols: &'a [u8],
+	pub strings: StringTable<'a>,
+	/// If the symbols have the opposite endianness.
+	swapped: bool,
+}
+impl<'a> SymbolTable<'a> {
+	/// How many symbols are in the table.
+	pub fn len(&self) -> usize {
+		self.symbols.len() / mem::size_of::<Symbol>()
+	}
+	/// If the table has no symbols.
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Gets the symbol at `index`.
+	pub fn get(&self, index: usize) -> Option<Symbol> {
+		let offset = index.checked_mul(mem::size_of::<Symbol>())?;
+		let bytes = self.symbols.get(offset..)?;
+		if bytes.len() < mem::size_of::<Symbol>() {
+			return None;
+		}
+
+		Some(read(bytes, self.swapped))
+	}
+
+	/// Iterates over the symbols.
+	pub fn symbols(&self) -> impl Iterator<Item = Symbol> + 'a {
+		let swapped = self.swapped;
+		let (symbols, _) = self.symbols.as_chunks::<{ mem::size_of::<Symbol>() }>();
+		symbols.iter().map(move |bytes| read(bytes, swapped))
+	}
+
+	/// Iterates over the symbols, along with their names. Symbols with unreadable names have an
+	/// empty name.
+	pub fn iter(&self) -> impl Iterator<Item = (Symbol, &'a [u8])> + 'a {
+		let strings = self.strings;
+		self.symbols()
+			.map(move |symbol| (symbol, strings.get(symbol.name_offset).unwrap_or(&[])))
+	}
+
+	/// Gets a symbol's name.
+	pub fn name(&self, symbol: &Symbol) -> Option<&'a str> {
+		self.strings.get_str(symbol.name_offset)
+	}
+
+	/// Finds a defined symbol by its name.
+	pub fn by_name(&self, name: &str) -> Option<Symbol> {
+		self.iter()
+			.find(|(symbol, symbol_name)| symbol.is_defined() && *symbol_name == name.as_bytes())
+			.map(|(symbol, _)| symbol)
+	}
+
+	/// Finds the symbol that contains `address` (ie, `address` is between the symbol's value and
+	/// its value plus its size). Function and object symbols are preferred over other symbols, so
+	/// this can be used to symbolize addresses in backtraces.
+	///
+	/// Addresses are the ones in the ELF. If the ELF was relocated, subtract its base first.
+	pub fn by_address(&self, address: u64) -> Option<Symbol> {
+		let contains = |symbol: &Symbol| {
+			let (start, size) = (symbol.value, symbol.size);
+			symbol.is_defined() && start <= address && address < start.saturating_add(size)
+		};
+
+		self.symbols()
+			.filter(contains)
+			.max_by_key(|symbol| matches!(symbol.kind(), Symbol::TYPE_FUNC | Symbol::TYPE_OBJECT))
+	}
+}
+
+impl FileHeader {
+	/// The full symbol table (`.symtab`), or `None` if the ELF was stripped.
+	///
+	/// `elf` is the entire ELF file, starting at this header.
+	pub fn symbol_table<'a>(&self, elf: &'a [u8]) -> Result<Option<SymbolTable<'a>>, ElfError> {
+		let sections = self.section_table(elf)?.iter().copied();
+		find_symbol_table(elf, sections, SectionType::SymbolTable, false)
+	}
+
+	/// The dynamic symbol table (`.dynsym`), or `None` if the ELF doesn't have one.
+	///
+	/// `elf` is the entire ELF file, starting at this header.
+	pub fn dynamic_symbol_table<'a>(
+		&self,
+		elf: &'a [u8],
+	) -> Result<Option<SymbolTable<'a>>, ElfError> {
+		let sections = self.section_table(elf)?.iter().copied();
+		find_symbol_table(elf, sections, SectionType::DynamicSymbols, false)
+	}
+}
+
+/// Finds the first symbol table of type `section_type` in `sections`, and the string table it
+/// links to. `swapped` is set if the ELF has the opposite endianness.
+pub(crate) fn find_symbol_table<'a>(
+	elf: &'a [u8],
+	mut sections: impl Iterator<Item = SectionHeader> + Clone,
+	section_type: SectionType,
+	swapped: bool,
+) -> Result<Option<SymbolTable<'a>>, ElfError> {
+	let Some(section) = sections
+		.clone()
+		.find(|section| section.is_type(section_type))
+	else {
+		return Ok(None);
+	};
+
+	let symbols = elf.get(section.file_range()?).ok_or(ElfError::Truncated)?;
+	// The symbol table's link is the string table its names are in
+	let strings = sections
+		.nth(section.link as usize)
+		.ok_or(ElfError::Truncated)?;
+	let strings = elf.get(strings.file_range()?).ok_or(ElfError::Truncated)?;
+
+	Ok(Some(SymbolTable {
+		symbols,
+		strings: StringTable { bytes: strings },
+		swapped,
+	}))
+}
@@ -0,0 +1,128 @@
+//! Finds an ELF's thread-local storage (TLS) template, and lays out TLS blocks for it.
+//!
+//! Thread-local variables are described by the `PT_TLS` segment. That segment isn't loaded on its
+//! own - it's a template that every thread gets a copy of. The first part of it is `.tdata` (the
+//! initial values of thread-locals, which are in the file) and the rest is `.tbss` (thread-locals
+//! that start zeroed).
+//!
+//! x86_64 uses "variant II" TLS: each thread's TLS block sits right below its thread pointer (the
+//! FS base), and code accesses thread-locals at negative offsets from it. The thread pointer points
+//! to the thread control block (TCB), whose first word has to be a pointer to itself, so code can
+//! read the thread pointer with `mov %fs:0`.
+//!
+//! Resources:
+//! - https://www.akkadia.org/drepper/tls.pdf (see section 3.4.6 and figure 2)
+//! - https://refspecs.linuxfoundation.org/elf/gabi4+/ch5.pheader.html (see "Thread-Local Storage")
+
+use {crate::*, core::mem};
+
+/// The size of the thread control block that [`TlsTemplate::initialize`] sets up. It only has
+/// the TCB's pointer to itself.
+pub const TCB_SIZE: u64 = mem::size_of::<u64>() as u64;
+
+/// The TLS template described by an ELF's `PT_TLS` segment.
+#[derive(Clone, Copy, Debug)]
+pub struct TlsTemplate {
+	/// The address of the initial values (`.tdata`) in the ELF. If the ELF was relocated, add its
+	/// base to this.
+	pub image_address: u64,
+	/// The size of `.tdata`, in bytes.
+	pub data_size: u64,
+	/// The size of the whole template (`.tdata` and `.tbss`), in bytes.
+	pub memory_size: u64,
+	/// The alignment every TLS block needs. Always a power of 2.
+	pub alignment: u64,
+}
+impl TlsTemplate {
+	/// Gets the TLS template from a `PT_TLS` segment.
+	pub fn from_segment(segment: &ProgramHeader) -> Result<Self, ElfError> {
+		// 0 means no alignment
+		let alignment = segment.alignment.max(1);
+		if !alignment.is_power_of_two() || segment.file_size > segment.memory_size {
+			return Err(ElfError::BadTls);
+		}
+
+		Ok(Self {
+			image_address: segment.address,
+			data_size: segment.file_size,
+			memory_size: segment.memory_size,
+			alignment,
+		})
+	}
+
+	/// The size of `.tbss`, in bytes.
+	pub fn bss_size(&self) -> u64 {
+		self.memory_size - self.data_size
+	}
+
+	/// Lays out a thread's TLS area. See [`TlsLayout`].
+	pub fn layout(&self) -> TlsLayout {
+		// The thread pointer has to be aligned, so the block below it is padded to the alignment.
+		// The linker puts the start of the block at the thread pointer minus this size.
+		let block_size = self.memory_size.next_multiple_of(self.alignment);
+
+		TlsLayout {
+			size: block_size + TCB_SIZE,
+			alignment: self.alignment.max(mem::align_of::<u64>() as u64),
+			thread_pointer_offset: block_size,
+		}
+	}
+
+	/// Sets up a thread's TLS area: copies `.tdata` from `image`, zeroes `.tbss`, and points the
+	/// TCB at itself. Returns the thread pointer, which should be written to the FS base.
+	///
+	/// `area` must be at least [`TlsLayout::size`] bytes and aligned to [`TlsLayout::alignment`].
+	/// `image` is the template's initial values - either from the loaded ELF (at
+	/// [`TlsTemplate::image_address`]) or from the file. Returns `None` if `area` is too small or
+	/// misaligned, or `image` is too small.
+	pub fn initialize(&self, area: &mut [u8], image: &[u8]) -> Option<u64> {
+		let layout = self.layout();
+		let address = area.as_ptr() as u64;
+		if (area.len() as u64) < layout.size || !address.is_multiple_of(layout.alignment) {
+			return None;
+		}
+
+		let data_size = usize::try_from(self.data_size).ok()?;
+		let thread_pointer_offset = usize::try_from(layout.thread_pointer_offset).ok()?;
+
+		// Zero `.tbss` and the padding after it
+		let block = &mut area[..thread_pointer_offset];
+		block[..data_size].copy_from_slice(image.get(..data_size)?);
+		block[data_size..].fill(0);
+
+		let thread_pointer = address + layout.thread_pointer_offset;
+		area[thread_pointer_offset..][..TCB_SIZE as usize]
+			.copy_from_slice(&thread_pointer.to_ne_bytes());
+
+		Some(thread_pointer)
+	}
+}
+
+/// Where everything goes in a thread's TLS area, for x86_64's TLS layout. The area holds the TLS
+/// block, followed by the TCB:
+///
+/// ```txt
+/// | .tdata | .tbss | padding | TCB |
+///                             ^ thread pointer
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct TlsLayout {
+	/// How many bytes to allocate for the area.
+	pub size: u64,
+	/// What the area has to be aligned to.
+	pub alignment: u64,
+	/// Where the thread pointer (and TCB) is, as an offset from the start of the area.
+	pub thread_pointer_offset: u64,
+}
+
+impl FileHeader {
+	/// Gets the ELF's TLS template from its `PT_TLS` segment, or `None` if it doesn't use TLS.
+	///
+	/// `elf` is the entire ELF file, starting at this header.
+	pub fn tls_template(&self, elf: &[u8]) -> Result<Option<TlsTemplate>, ElfError> {
+		self.program_headers(elf)?
+			.find(|segment| segment.is_type(ProgramType::ThreadLocal))
+			.map(TlsTemplate::from_segment)
+			.transpose()
+	}
+}
@@ -1,7 +1,12 @@
 //! Allows specifying a PCI device via [`PciDeviceAddress`], and reading from that
 //! device's PCI configuration address space.
 
-use core::arch::asm;
+use portio::Port;
+
+/// The port [`PciDeviceAddress`]es are written to.
+const CONFIG_ADDRESS: Port<u32> = Port::new(0xCF8);
+/// The port the configuration is read from, after writing a [`PciDeviceAddress`].
+const CONFIG_DATA: Port<u32> = Port::new(0xCFC);
 
 /// Specifies an address in a PCI device's configuration space to be read.
 ///
@@ -58,27 +63,10 @@ impl PciDeviceAddress {
 	/// configuration from I/O port `0xCFC`. The result will always be
 	/// little-endian.
 	pub fn read(self) -> u32 {
-		let mut result = self.0;
 		unsafe {
-			asm!(
-				"push dx",
-
-				"mov dx, 0xCF8",
-				"out dx, eax",
-				"mov dx, 0xCFC",
-				"in eax, dx",
-
-				"pop dx",
-				// inout reads `result` into eax at the start
-				// of the assembly and then reads eax to `result`
-				// at the end of the assembly.
-				//
-				// input("sex") - Toast, 2024
-				inout("eax") result,
-			)
+			CONFIG_ADDRESS.write(self.0);
+			CONFIG_DATA.read()
 		}
-
-		result
 	}
 }
 impl Default for PciDeviceAddress {
@@ -0,0 +1,67 @@
+//! Reads PCI configuration spaces through ECAM, the "Enhanced Configuration Access Mechanism".
+//!
+//! Legacy PCI reads configuration spaces one register at a time through I/O ports (see
+//! [`crate::address_space`]). PCIe maps every function's configuration space into memory instead:
+//! each bus gets 1mib, each device on the bus gets 32kib of that, and each function on the device
+//! gets 4kib of that. Some computers (like QEMU's q35 machine) only have some devices there.
+//!
+//! ACPI's MCFG table says where the configuration spaces are mapped. Each of its allocations covers
+//! a range of buses in one segment group, and becomes one [`Ecam`].
+//!
+//! Resources:
+//! - https://wiki.osdev.org/PCI_Express
+//! - https://wiki.osdev.org/PCI#Enhanced_Configuration_Mechanism
+
+/// The memory-mapped configuration spaces of a range of PCI buses. See the module-level docs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ecam {
+	/// Where [`Ecam::start_bus`]'s configuration spaces start in memory.
+	pub base_address: u64,
+	/// The PCI segment group the buses are in.
+	pub segment_group: u16,
+	/// The first bus that's mapped.
+	pub start_bus: u8,
+	/// The last bus that's mapped (inclusive).
+	pub end_bus: u8,
+}
+impl Ecam {
+	/// The memory-mapped configuration spaces for `start_bus..=end_bus`, in `segment_group`,
+	/// starting at `base_address`. This is usually the physical address from the MCFG, but it can
+	/// be wherever they've been mapped in virtual memory.
+	pub const fn new(base_address: u64, segment_group: u16, start_bus: u8, end_bus: u8) -> Self {
+		Self {
+			base_address,
+			segment_group,
+			start_bus,
+			end_bus,
+		}
+	}
+
+	/// If `bus` is mapped.
+	pub fn contains_bus(&self, bus: u8) -> bool {
+		(self.start_bus..=self.end_bus).contains(&bus)
+	}
+
+	/// Where a function's configuration space is in memory. Returns `None` if its bus isn't mapped,
+	/// or the device or function number is out of range.
+	pub fn function_address(&self, bus: u8, device: u8, function: u8) -> Option<u64> {
+		if !self.contains_bus(bus) || device >= 32 || function >= 8 {
+			return None;
+		}
+
+		let bus = (bus - self.start_bus) as u64;
+		Some(self.base_address + (bus << 20) + ((device as u64) << 15) + ((function as u64) << 12))
+	}
+
+	/// Reads a register from a function's configuration space, which starts at `address` (from
+	/// [`Ecam::function_address`]). ECAM configuration spaces are 4kib, so there's 1024 registers.
+	///
+	/// # Safety
+	/// `address` must be a function's configuration space, and it has to be accessible at that
+	/// address.
+	pub unsafe fn read(address: u64, register: u16) -> u32 {
+		let register = address + register as u64 * 4;
+		// Configuration space is MMIO, so every read has to actually happen
+		unsafe { (register as usize as *const u32).read_volatile() }
+	}
+}
@@ -2,29 +2,73 @@
 
 pub mod address_space;
 pub mod classification;
+pub mod ecam;
 
-use {address_space::*, classification::*};
+use {address_space::*, classification::*, ecam::Ecam};
 
-/// A wrapper around [`PciDeviceAddress`] and the classification types in [`classification`] that
-/// makes it easy to read a PCI device's configuration.
+/// How PCI configuration spaces are read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigAccess {
+	/// Legacy PCI's I/O ports (see [`address_space`]). Every computer has these.
+	Ports,
+	/// PCIe's memory-mapped configuration spaces (see [`ecam`]), for the buses an MCFG allocation
+	/// covers.
+	Ecam(Ecam),
+}
+
+/// Where a function's configuration space is.
+#[derive(Clone)]
+enum ConfigSpace {
+	Ports(PciDeviceAddress),
+	Ecam {
+		/// Where the configuration space starts in memory.
+		address: u64,
+		bus: u8,
+		device: u8,
+		function: u8,
+	},
+}
+
+/// A wrapper around [`PciDeviceAddress`] (or an [`Ecam`] configuration space) and the
+/// classification types in [`classification`] that makes it easy to read a PCI device's
+/// configuration.
 pub struct PciDevice {
 	/// Used to access the PCI device's address space.
-	address: PciDeviceAddress,
+	space: ConfigSpace,
 	/// Caches values from the PCI configuration space. There are 256 bytes in the configuration
 	/// space. Only 32 bits can be read at a time, so it's split into 64 4-byte registers.
 	cache: [Option<[u8; 4]>; 64],
 }
 impl PciDevice {
-	/// Attempts to access a PCI function on a PCI device on a PCI bus. Will return `None` if no device
-	/// exists at that bus/device/function.
+	/// Attempts to access a PCI function on a PCI device on a PCI bus, through the legacy I/O
+	/// ports. Will return `None` if no device exists at that bus/device/function.
 	pub fn new(bus: u8, device: u8, function: u8) -> Option<Self> {
-		let address = PciDeviceAddress::new()
-			.with_bus(bus)
-			.with_device(device)
-			.with_function(function);
+		Self::open(ConfigAccess::Ports, bus, device, function)
+	}
+
+	/// Like [`PciDevice::new`], but reads the configuration space with `access`. Also returns `None`
+	/// if `access` can't reach that bus/device/function.
+	///
+	/// With [`ConfigAccess::Ecam`], the configuration spaces have to be accessible at the
+	/// [`Ecam`]'s base address.
+	pub fn open(access: ConfigAccess, bus: u8, device: u8, function: u8) -> Option<Self> {
+		let space = match access {
+			ConfigAccess::Ports => ConfigSpace::Ports(
+				PciDeviceAddress::new()
+					.with_bus(bus)
+					.with_device(device)
+					.with_function(function),
+			),
+			ConfigAccess::Ecam(ecam) => ConfigSpace::Ecam {
+				address: ecam.function_address(bus, device, function)?,
+				bus,
+				device,
+				function,
+			},
+		};
 
 		let mut this = Self {
-			address,
+			space,
 			cache: [None; 64],
 		};
 
@@ -82,7 +126,12 @@ impl PciDevice {
 	/// Read a register from the PCI configuration space. This will always read from PCI, and never
 	/// reads from or writes to the cache. Returns `None` if the value is `0xFFFFFFFF`.
 	pub fn read_register_uncached(&self, register: u8) -> Option<[u8; 4]> {
-		match self.address.clone().with_register(register).read() {
+		let value = match &self.space {
+			ConfigSpace::Ports(address) => address.clone().with_register(register).read(),
+			ConfigSpace::Ecam { address, .. } => unsafe { Ecam::read(*address, register as u16) },
+		};
+
+		match value {
 			0xFFFFFFFF => None,
 			val => Some(val.to_ne_bytes()),
 		}
@@ -91,12 +140,18 @@ impl PciDevice {
 	/// Get the PCI bus this device is on.
 	#[inline(always)]
 	pub fn bus(&self) -> u8 {
-		self.address.bus()
+		match &self.space {
+			ConfigSpace::Ports(address) => address.bus(),
+			ConfigSpace::Ecam { bus, .. } => *bus,
+		}
 	}
 	/// Get the ID of this device on its PCI bus.
 	#[inline(always)]
 	pub fn device(&self) -> u8 {
-		self.address.device()
+		match &self.space {
+			ConfigSpace::Ports(address) => address.device(),
+			ConfigSpace::Ecam { device, .. } => *device,
+		}
 	}
 	/// Get the ID of this function on its device in the PCI bus.
 	///
@@ -105,6 +160,9 @@ impl PciDevice {
 	/// so it's easiest to just treat each function as a separate device.
 	#[inline(always)]
 	pub fn function(&self) -> u8 {
-		self.address.function()
+		match &self.space {
+			ConfigSpace::Ports(address) => address.function(),
+			ConfigSpace::Ecam { function, .. } => *function,
+		}
 	}
 }
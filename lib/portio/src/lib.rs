@@ -0,0 +1,176 @@
+//! CPU I/O ports, read and written with the `in` and `out` instructions.
+//!
+//! Ports can be 8, 16, or 32 bits wide. The free functions ([`read_u8`], [`write_u16`], etc) are
+//! the simplest way to use them. [`Port`] wraps a port number with its size, for drivers that
+//! keep ports around, and [`PortSize`] lets code be generic over the size.
+//!
+//! All of these are `unsafe`, because reading or writing a port can do pretty much anything -
+//! acknowledge an interrupt, reset the computer, or send a byte to a disk.
+//!
+//! Resources:
+//! - https://wiki.osdev.org/Port_IO
+//! - https://wiki.osdev.org/I/O_Ports
+
+#![no_std]
+
+use core::{arch::asm, marker::PhantomData};
+
+/// An unused port (it's for POST codes), which [`io_wait`] writes to.
+pub const WAIT_PORT: u16 = 0x80;
+
+/// Reads a byte from a port.
+///
+/// # Safety
+/// Reading a port can have side effects, depending on the device behind it.
+#[inline(always)]
+pub unsafe fn read_u8(port: u16) -> u8 {
+	let val;
+	unsafe {
+		asm!("in al, dx", in("dx") port, out("al") val, options(nomem, nostack, preserves_flags))
+	}
+	val
+}
+/// Reads 2 bytes from a port.
+///
+/// # Safety
+/// Reading a port can have side effects, depending on the device behind it.
+#[inline(always)]
+pub unsafe fn read_u16(port: u16) -> u16 {
+	let val;
+	unsafe {
+		asm!("in ax, dx", in("dx") port, out("ax") val, options(nomem, nostack, preserves_flags))
+	}
+	val
+}
+/// Reads 4 bytes from a port.
+///
+/// # Safety
+/// Reading a port can have side effects, depending on the device behind it.
+#[inline(always)]
+pub unsafe fn read_u32(port: u16) -> u32 {
+	let val;
+	unsafe {
+		asm!("in eax, dx", in("dx") port, out("eax") val, options(nomem, nostack, preserves_flags))
+	}
+	val
+}
+
+/// Writes a byte to a port.
+///
+/// # Safety
+/// Writing to a port can do pretty much anything, depending on the device behind it.
+#[inline(always)]
+pub unsafe fn write_u8(port: u16, data: u8) {
+	unsafe {
+		asm!("out dx, al", in("dx") port, in("al") data, options(nomem, nostack, preserves_flags))
+	}
+}
+/// Writes 2 bytes to a port.
+///
+/// # Safety
+/// Writing to a port can do pretty much anything, depending on the device behind it.
+#[inline(always)]
+pub unsafe fn write_u16(port: u16, data: u16) {
+	unsafe {
+		asm!("out dx, ax", in("dx") port, in("ax") data, options(nomem, nostack, preserves_flags))
+	}
+}
+/// Writes 4 bytes to a port.
+///
+/// # Safety
+/// Writing to a port can do pretty much anything, depending on the device behind it.
+#[inline(always)]
+pub unsafe fn write_u32(port: u16, data: u32) {
+	unsafe {
+		asm!("out dx, eax", in("dx") port, in("eax") data, options(nomem, nostack, preserves_flags))
+	}
+}
+
+/// Waits a tiny amount of time (around a microsecond), by writing to [`WAIT_PORT`]. Old devices
+/// (like the PIC) need a moment between writes, and this is the traditional way to give it to
+/// them.
+#[inline(always)]
+pub fn io_wait() {
+	// Nothing listens to port 0x80 after boot, so this is harmless
+	unsafe { write_u8(WAIT_PORT, 0) };
+}
+
+/// This trait allows functions that work with CPU ports to work with ports of different sizes. The
+/// idea is that a function can take or return a [`PortSize`] as a generic, and use that generic to
+/// read from/write to a CPU port. The generic will then handle the port's size (8 bits, 16 bits,
+/// etc) automatically.
+pub trait PortSize: Copy {
+	/// Read from a CPU port.
+	///
+	/// # Safety
+	/// See [`read_u8`].
+	unsafe fn read(port: u16) -> Self;
+	/// Write to a CPU port.
+	///
+	/// # Safety
+	/// See [`write_u8`].
+	unsafe fn write(port: u16, data: Self);
+}
+impl PortSize for u8 {
+	unsafe fn read(port: u16) -> Self {
+		unsafe { read_u8(port) }
+	}
+	unsafe fn write(port: u16, data: Self) {
+		unsafe { write_u8(port, data) }
+	}
+}
+impl PortSize for u16 {
+	unsafe fn read(port: u16) -> Self {
+		unsafe { read_u16(port) }
+	}
+	unsafe fn write(port: u16, data: Self) {
+		unsafe { write_u16(port, data) }
+	}
+}
+impl PortSize for u32 {
+	unsafe fn read(port: u16) -> Self {
+		unsafe { read_u32(port) }
+	}
+	unsafe fn write(port: u16, data: Self) {
+		unsafe { write_u32(port, data) }
+	}
+}
+
+/// A CPU port that's read and written `T` at a time - eg, a `Port<u8>` is an 8-bit port.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Port<T: PortSize> {
+	port: u16,
+	_size: PhantomData<T>,
+}
+impl<T: PortSize> Port<T> {
+	/// Makes a new port. Nothing is read or written until [`Port::read`] or [`Port::write`].
+	pub const fn new(port: u16) -> Self {
+		Self {
+			port,
+			_size: PhantomData,
+		}
+	}
+
+	/// The port's number.
+	pub const fn number(&self) -> u16 {
+		self.port
+	}
+
+	/// Reads from the port.
+	///
+	/// # Safety
+	/// See [`read_u8`].
+	#[inline(always)]
+	pub unsafe fn read(&self) -> T {
+		unsafe { T::read(self.port) }
+	}
+
+	/// Writes to the port.
+	///
+	/// # Safety
+	/// See [`write_u8`].
+	#[inline(always)]
+	pub unsafe fn write(&self, data: T) {
+		unsafe { T::write(self.port, data) }
+	}
+}
@@ -1,19 +1,48 @@
 ```cargo
 package.edition = "2021"
+[dependencies.common]
+path = "../lib/common"
 ```
 
 //! Builds BS into a bootable disk. This is implemented as a postbuild because postbuilds will always run
 //! after a crate has compiled, but normal builds will not be run if a crate isn't recompiled.
 
-use std::{
-	env,
-	fs::{self, File},
-	io::Write,
-	path::PathBuf,
+use {
+	common::{
+		boot_info::COMMAND_LINE_LEN,
+		mbr::{
+			PartitionEntry, PartitionTable, BOOTLOADER_LOCATION_OFFSET, BS_PARTITION_TYPE,
+			PARTITION_TABLE_OFFSET,
+		},
+		stages::{Stage, StageLocation, StageTable, STAGE_TABLE_LBA},
+	},
+	std::{
+		env,
+		fs::{self, File},
+		io::Write,
+		path::PathBuf,
+	},
 };
 
+const SECTOR_SIZE: usize = 512;
+/// The first sector of BS' boot partition.
+const BOOT_PARTITION_LBA: u32 = 1;
+/// The environment variable with the kernel command line to put in the configuration sector.
+const COMMAND_LINE_VAR: &str = "BS_COMMAND_LINE";
+/// The environment variable with the path to the initial ramdisk.
+const INITRD_VAR: &str = "BS_INITRD";
+
 /// Thanks to Bargo's binary dependencies and post-build scripts, BS is already built. This just has to copy
 /// the final binaries into one file that will act like a disk, then load that file in QEMU.
+///
+/// The disk is laid out like this: the MBR (the bootstrapper, the bootloader's location, and the partition
+/// table) in sector 0, then
+/// BS' boot partition. The partition has the stage table, then each stage, padded to a whole number of
+/// sectors. See `common::mbr` and `common::stages`.
+///
+/// If `BS_INITRD` is set, the file at that path is put right after the kernel, as the initial ramdisk. If
+/// `BS_COMMAND_LINE` is set, it's written to the configuration sector (see `common::command_line`) as the
+/// kernel command line.
 fn main() {
 	let target = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap())
 		.parent()
@@ -21,21 +50,62 @@ fn main() {
 		.join("target");
 	let profile = env::var("PROFILE").unwrap();
 	let bs_bins = target.join("bs-bins");
-	let mut output = File::create(target.join("bs.bin")).unwrap();
-
-	output
-		.write_all(&fs::read(bs_bins.join("bootstrapper.bin")).unwrap())
-		.unwrap();
-	output
-		.write_all(&fs::read(bs_bins.join("bootloader.bin")).unwrap())
-		.unwrap();
-	output
-		.write_all(&fs::read(bs_bins.join("elf-loader.bin")).unwrap())
-		.unwrap();
-
 	let kernel_path = target
 		.join("x86_64-unknown-none")
 		.join(profile)
 		.join("kernel");
-	output.write_all(&fs::read(kernel_path).unwrap()).unwrap();
+
+	let mut bootstrapper = fs::read(bs_bins.join("bootstrapper.bin")).unwrap();
+	assert_eq!(
+		bootstrapper.len(),
+		SECTOR_SIZE,
+		"The bootstrapper has to be exactly one sector"
+	);
+	let mut stages = vec![
+		(Stage::Bootloader, fs::read(bs_bins.join("bootloader.bin")).unwrap()),
+		(Stage::ElfLoader, fs::read(bs_bins.join("elf-loader.bin")).unwrap()),
+		(Stage::Kernel, fs::read(kernel_path).unwrap()),
+	];
+	if let Ok(initrd) = env::var(INITRD_VAR) {
+		stages.push((Stage::Initrd, fs::read(initrd).unwrap()));
+	}
+	if let Ok(command_line) = env::var(COMMAND_LINE_VAR) {
+		assert!(
+			command_line.len() <= COMMAND_LINE_LEN,
+			"The kernel command line can't be longer than {COMMAND_LINE_LEN} bytes"
+		);
+		stages.push((Stage::Config, command_line.into_bytes()));
+	}
+
+	// Stages start right after the stage table
+	let mut table = StageTable::new();
+	let mut lba = STAGE_TABLE_LBA as u32 + 1;
+	for (stage, binary) in &stages {
+		let sectors = binary.len().div_ceil(SECTOR_SIZE) as u32;
+		table.set(*stage, StageLocation { lba, sectors });
+		lba += sectors;
+	}
+
+	// The boot partition starts right after the MBR, and `lba` is now its size
+	let mut partitions = PartitionTable::default();
+	partitions.entries[0] = PartitionEntry::new(BS_PARTITION_TYPE, BOOT_PARTITION_LBA, lba, true);
+	bootstrapper[PARTITION_TABLE_OFFSET..PARTITION_TABLE_OFFSET + 64]
+		.copy_from_slice(&partitions.to_bytes());
+
+	// The bootstrapper doesn't look at the partition table or the stage table, so it gets the
+	// bootloader's location (from the start of the disk) straight from the MBR
+	let bootloader = table.get(Stage::Bootloader).unwrap();
+	let location = [BOOT_PARTITION_LBA + bootloader.lba, bootloader.sectors];
+	for (i, field) in location.into_iter().enumerate() {
+		let offset = BOOTLOADER_LOCATION_OFFSET + i * 4;
+		bootstrapper[offset..offset + 4].copy_from_slice(&field.to_le_bytes());
+	}
+
+	let mut output = File::create(target.join("bs.bin")).unwrap();
+	output.write_all(&bootstrapper).unwrap();
+	output.write_all(&table.to_sector()).unwrap();
+	for (_, mut binary) in stages {
+		binary.resize(binary.len().next_multiple_of(SECTOR_SIZE), 0);
+		output.write_all(&binary).unwrap();
+	}
 }